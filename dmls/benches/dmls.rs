@@ -0,0 +1,97 @@
+//! Criterion benchmarks for the DMLS agent.
+//!
+//! Run with `cargo bench`; every benchmark runs once per storage backend in `Backend::ALL`.
+
+#![allow(unused_crate_dependencies)]
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use dmls::{
+    bench::{Backend, BenchGroup, new_agent},
+    helpers::gen_kp_base64,
+};
+use openmls_traits::types::Ciphersuite;
+use std::cell::RefCell;
+
+const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+const EXPORTER_LENGTH: usize = 32;
+const GROUP_SIZES: &[usize] = &[1, 8, 32];
+
+fn key_package(c: &mut Criterion) {
+    let mut group = c.benchmark_group("key-package");
+    for backend in Backend::ALL {
+        let agent = new_agent(*backend, CIPHERSUITE).unwrap();
+        group.bench_function(backend.name(), |b| {
+            b.iter(|| gen_kp_base64(&agent, CIPHERSUITE).unwrap())
+        });
+    }
+    group.finish();
+}
+
+fn create_group(c: &mut Criterion) {
+    let mut group = c.benchmark_group("create-group");
+    group.sample_size(10);
+    for backend in Backend::ALL {
+        for size in GROUP_SIZES {
+            group.bench_with_input(BenchmarkId::new(backend.name(), size), size, |b, size| {
+                b.iter(|| BenchGroup::new(*backend, CIPHERSUITE, *size, EXPORTER_LENGTH).unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn self_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("self-update");
+    for backend in Backend::ALL {
+        for size in GROUP_SIZES {
+            let mut bg = BenchGroup::new(*backend, CIPHERSUITE, *size, EXPORTER_LENGTH).unwrap();
+            group.bench_with_input(BenchmarkId::new(backend.name(), size), size, |b, _| {
+                b.iter(|| bg.self_update().unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn inject_psk(c: &mut Criterion) {
+    let mut group = c.benchmark_group("inject-psk");
+    for backend in Backend::ALL {
+        for size in GROUP_SIZES {
+            let mut bg = BenchGroup::new(*backend, CIPHERSUITE, *size, EXPORTER_LENGTH).unwrap();
+            group.bench_with_input(BenchmarkId::new(backend.name(), size), size, |b, _| {
+                b.iter(|| bg.inject_psk().unwrap())
+            });
+        }
+    }
+    group.finish();
+}
+
+fn messages(c: &mut Criterion) {
+    let mut group = c.benchmark_group("messages");
+    for backend in Backend::ALL {
+        // encrypting and decrypting both need the group, so share it between the closures
+        let bg = RefCell::new(BenchGroup::new(*backend, CIPHERSUITE, 1, EXPORTER_LENGTH).unwrap());
+        group.bench_function(BenchmarkId::new("encrypt", backend.name()), |b| {
+            b.iter(|| bg.borrow_mut().encrypt("hello").unwrap())
+        });
+        // every message can only be decrypted once, so encrypt a fresh one per iteration
+        group.bench_function(BenchmarkId::new("decrypt", backend.name()), |b| {
+            b.iter_batched(
+                || bg.borrow_mut().encrypt("hello").unwrap(),
+                |msg| bg.borrow_mut().decrypt(0, &msg).unwrap(),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    key_package,
+    create_group,
+    self_update,
+    inject_psk,
+    messages
+);
+criterion_main!(benches);
@@ -0,0 +1,7 @@
+//! Build script: compiles the gRPC service definition when the `grpc` feature is enabled.
+
+fn main() {
+    println!("cargo:rerun-if-changed=proto/dmls.proto");
+    #[cfg(feature = "grpc")]
+    tonic_build::compile_protos("proto/dmls.proto").unwrap();
+}
@@ -11,7 +11,7 @@ echo "Inspecting messages for (${participants[@]})."
 for p1 in "${participants[@]}"; do
   inName="from_${p1}.mlsmsg"
   echo "Inspecting messages from ${p1}"
-  cat $inName | RUST_LOG=warn $binary inspect-messages
+  cat $inName | $binary inspect-messages
 done
 
 echo "Done."
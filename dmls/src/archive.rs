@@ -0,0 +1,121 @@
+//! Storage export/import archives.
+//!
+//! `export` dumps the OpenMLS values of an agent into a zstd-compressed tar archive with one file
+//! per entry, laid out like `dir_storage` (`<label>/<key>.json`, the key being the URL-safe base64
+//! encoding of the serialized key and version). `import` restores such an archive into another
+//! agent's store. This allows backing up the OpenMLS values, or moving them (or a single group's
+//! share of them) to another machine, without copying the whole state file blindly.
+//!
+//! With a group id filter, only entries whose key mentions the group are exported; key packages,
+//! signature keys, encryption keys and PSKs are not group-scoped and are left out.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let count = export(provider.state().openmls_values(), "backup.tar.zst", None)?;
+//! // ... on the other machine
+//! let count = import(provider.state().openmls_values(), "backup.tar.zst")?;
+//! ```
+
+use super::{
+    openmls_kvstore::{LABELS, OpenMlsKeyValueStore},
+    perms::private_options,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as Base64Url};
+use core::error::Error;
+use openmls::group::GroupId;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Component, Path},
+};
+
+/// Export the entries of a store (optionally only those of one group) into an archive.
+///
+/// Returns the number of exported entries.
+///
+/// Example:
+///
+/// ```ignore
+/// let count = export(provider.state().openmls_values(), "group.tar.zst", Some(&group_id))?;
+/// ```
+pub fn export(
+    kv: &OpenMlsKeyValueStore,
+    path: &str,
+    group_id: Option<&GroupId>,
+) -> Result<usize, Box<dyn Error>> {
+    let needle = group_id.map(serde_json::to_vec).transpose()?;
+    let mut archive = tar::Builder::new(zstd::Encoder::new(
+        // the archive holds the group secrets in plaintext
+        private_options().truncate(true).open(path)?,
+        0,
+    )?);
+    let mut count = 0;
+    for (label, key, value) in kv.raw_entries()? {
+        let Some(value) = value else {
+            continue;
+        };
+        if let Some(needle) = &needle
+            && !key.windows(needle.len()).any(|window| window == needle)
+        {
+            continue;
+        }
+        let mut header = tar::Header::new_gnu();
+        header.set_size(value.len() as u64);
+        header.set_mode(0o600);
+        let name = format!(
+            "{}/{}.json",
+            String::from_utf8_lossy(label),
+            Base64Url.encode(&key)
+        );
+        archive.append_data(&mut header, name, value.as_slice())?;
+        count += 1;
+    }
+    archive.into_inner()?.finish()?;
+    Ok(count)
+}
+
+/// Import the entries of an archive (see `export`) into a store, replacing existing entries with
+/// the same keys.
+///
+/// Returns the number of imported entries.
+///
+/// Example:
+///
+/// ```ignore
+/// let count = import(provider.state().openmls_values(), "backup.tar.zst")?;
+/// ```
+pub fn import(kv: &OpenMlsKeyValueStore, path: &str) -> Result<usize, Box<dyn Error>> {
+    let mut archive = tar::Archive::new(zstd::Decoder::new(File::open(path)?)?);
+    let mut entries = Vec::new();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = entry.path()?.into_owned();
+        let (label, key) = parse_name(&name)
+            .ok_or_else(|| format!("Unexpected archive entry {}", name.display()))?;
+        let mut value = Vec::new();
+        entry.read_to_end(&mut value)?;
+        entries.push((label, key, Some(value)));
+    }
+    let count = entries.len();
+    kv.insert_raw_entries(entries);
+    Ok(count)
+}
+
+/// Parse an archive entry name (`<label>/<key>.json`) into its label and key.
+fn parse_name(name: &Path) -> Option<(&'static [u8], Vec<u8>)> {
+    let mut components = name.components();
+    let (Some(Component::Normal(label)), Some(Component::Normal(file)), None) =
+        (components.next(), components.next(), components.next())
+    else {
+        return None;
+    };
+    let label = LABELS
+        .iter()
+        .copied()
+        .find(|l| label.to_str() == std::str::from_utf8(l).ok())?;
+    let key = Base64Url
+        .decode(file.to_str()?.strip_suffix(".json")?)
+        .ok()?;
+    Some((label, key))
+}
@@ -0,0 +1,169 @@
+//! PGP-style ASCII armor for artifacts.
+//!
+//! Single-line base64 blobs are easily mangled when pasted into email or chat (wrapped, quoted,
+//! truncated). Armored artifacts are framed by labelled header and footer lines, wrap their
+//! base64 body at 64 columns and carry a CRC-24 checksum (as in RFC 4880, section 6), so damage is
+//! detected rather than silently producing garbage:
+//!
+//! ```text
+//! -----BEGIN DMLS KEY PACKAGE-----
+//! AAEABSAAAAAA...
+//! =njUN
+//! -----END DMLS KEY PACKAGE-----
+//! ```
+//!
+//! The body is always standard base64, independent of the agent-wide `Encoding`. `Dearmor` turns
+//! a stream of lines that mixes armored blocks and plain encoded blobs into a stream of blobs, so
+//! every reader of artifacts accepts both.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let text = armor("WELCOME", &welcome_bytes);
+//! let (label, bytes) = dearmor(&text)?;
+//! for blob in Dearmor::new(stdin().lock().lines()) { /* ... */ }
+//! ```
+
+use super::encoding::Blob;
+use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use core::error::Error;
+
+/// Prefix of an armor header line.
+const BEGIN: &str = "-----BEGIN DMLS ";
+
+/// Prefix of an armor footer line.
+const END: &str = "-----END DMLS ";
+
+/// Suffix of armor header and footer lines.
+const DASHES: &str = "-----";
+
+/// Width at which the armored body is wrapped.
+const LINE_WIDTH: usize = 64;
+
+/// CRC-24 as used by OpenPGP armor (RFC 4880, section 6.1).
+pub fn crc24(data: &[u8]) -> u32 {
+    let mut crc: u32 = 0x00b7_04ce;
+    for byte in data {
+        crc ^= u32::from(*byte) << 16;
+        for _ in 0..8 {
+            crc <<= 1;
+            if crc & 0x0100_0000 != 0 {
+                crc ^= 0x0186_4cfb;
+            }
+        }
+    }
+    crc & 0x00ff_ffff
+}
+
+/// Armor `data` with the given label (e.g. `KEY PACKAGE`).
+///
+/// Example:
+///
+/// ```ignore
+/// println!("{}", armor("COMMIT", &commit_bytes));
+/// ```
+pub fn armor(label: &str, data: &[u8]) -> String {
+    let body = Base64.encode(data);
+    let mut text = format!("{BEGIN}{label}{DASHES}\n");
+    for chunk in body.as_bytes().chunks(LINE_WIDTH) {
+        // base64 is ASCII, so chunks are valid UTF-8
+        text.push_str(&String::from_utf8_lossy(chunk));
+        text.push('\n');
+    }
+    let crc = crc24(data).to_be_bytes();
+    text += "=";
+    text += &Base64.encode(&crc[1..]);
+    text += "\n";
+    text + END + label + DASHES
+}
+
+/// Returns the label of an armor header line, if `line` is one.
+pub fn armor_label(line: &str) -> Option<&str> {
+    line.trim().strip_prefix(BEGIN)?.strip_suffix(DASHES)
+}
+
+/// Parse an armored artifact, checking its footer and checksum.
+///
+/// Returns the label and the data. Text before the header and after the footer is ignored.
+///
+/// Example:
+///
+/// ```ignore
+/// let (label, bytes) = dearmor(&text)?;
+/// ```
+pub fn dearmor(text: &str) -> Result<(String, Vec<u8>), Box<dyn Error>> {
+    let mut lines = text
+        .lines()
+        .map(str::trim)
+        .skip_while(|l| armor_label(l).is_none());
+    let label = lines
+        .next()
+        .and_then(armor_label)
+        .ok_or("Missing armor header")?
+        .to_string();
+    let mut body = String::new();
+    let mut checksum = None;
+    for line in lines {
+        if let Some(footer) = line.strip_prefix(END).and_then(|l| l.strip_suffix(DASHES)) {
+            if footer != label {
+                return Err(format!("Armor footer {footer} does not match header {label}").into());
+            }
+            let data = Base64.decode(&body)?;
+            if let Some(checksum) = checksum {
+                let crc = crc24(&data).to_be_bytes();
+                if Base64.decode(checksum)? != crc[1..] {
+                    return Err("Armor checksum mismatch".into());
+                }
+            }
+            return Ok((label, data));
+        }
+        match line.strip_prefix('=') {
+            Some(crc) => checksum = Some(crc),
+            None => body.push_str(line),
+        }
+    }
+    Err("Missing armor footer".into())
+}
+
+/// Iterator adapter turning lines of plain encoded blobs and armored blocks into blobs.
+#[derive(Debug)]
+pub struct Dearmor<I> {
+    /// The underlying lines.
+    lines: I,
+}
+
+impl<I> Dearmor<I> {
+    /// Wrap an iterator over lines (e.g. `stdin().lock().lines()`).
+    pub fn new(lines: I) -> Self {
+        Self { lines }
+    }
+}
+
+impl<I: Iterator<Item = std::io::Result<String>>> Iterator for Dearmor<I> {
+    type Item = Result<Vec<u8>, Box<dyn Error>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = match self.lines.next()? {
+            Ok(line) => line,
+            Err(e) => return Some(Err(e.into())),
+        };
+        let Some(label) = armor_label(&line).map(str::to_string) else {
+            return Some(Blob.decode(line));
+        };
+        // collect the block up to its footer
+        let mut block = line;
+        for line in self.lines.by_ref() {
+            match line {
+                Err(e) => return Some(Err(e.into())),
+                Ok(line) => {
+                    let footer = line.trim() == format!("{END}{label}{DASHES}");
+                    block.push('\n');
+                    block.push_str(&line);
+                    if footer {
+                        break;
+                    }
+                }
+            }
+        }
+        Some(dearmor(&block).map(|(_, data)| data))
+    }
+}
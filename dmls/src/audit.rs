@@ -0,0 +1,211 @@
+//! Tamper-evident audit log.
+//!
+//! Every commit merged into a local group (own or received) appends entries to an append-only
+//! log kept in `DmlsState`: one `CommitApplied` entry, plus `MembersAdded`, `MembersRemoved` and
+//! `PsksInjected` entries for the membership changes and PSK injections the commit carried.
+//!
+//! Entries are chained: each entry stores the hash of its predecessor (empty for the first entry)
+//! and its own SHA-256 hash over its contents and that previous hash. Modifying, removing or
+//! reordering entries therefore breaks the chain, which `verify` detects. The hashes are unkeyed,
+//! so whoever can write the state can also recompute the chain; with an integrity key set (see
+//! `integrity`), the state also keeps a MAC of the last entry's hash, which `verify` checks too.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let events = commit_events(&group, group.pending_commit().unwrap());
+//! group.merge_pending_commit(&provider)?;
+//! append(&mut provider, &group, events)?;
+//! verify(provider.state().audit_log(), provider.state().audit_head_mac(), provider.crypto())?;
+//! ```
+
+use super::{
+    helpers::kp_identity,
+    integrity::{audit_head_mac, check_audit_head},
+    provider::DmlsProvider,
+};
+use core::error::Error;
+use openmls::{
+    group::{MlsGroup, StagedCommit},
+    schedule::Psk,
+};
+use openmls_traits::{OpenMlsProvider, crypto::OpenMlsCrypto, types::HashType};
+use serde::{Deserialize, Serialize};
+use serde_with::{base64::Base64, serde_as};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// An audited event.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub enum AuditEvent {
+    /// A commit was merged into the group.
+    CommitApplied,
+    /// Members were added by a commit.
+    MembersAdded {
+        /// Credential identities of the added members.
+        #[serde_as(as = "Vec<Base64>")]
+        identities: Vec<Vec<u8>>,
+    },
+    /// Members were removed by a commit.
+    MembersRemoved {
+        /// Credential identities of the removed members.
+        #[serde_as(as = "Vec<Base64>")]
+        identities: Vec<Vec<u8>>,
+    },
+    /// External PSKs were injected by a commit.
+    PsksInjected {
+        /// Ids of the injected PSKs.
+        #[serde_as(as = "Vec<Base64>")]
+        psk_ids: Vec<Vec<u8>>,
+    },
+}
+
+/// An entry of the audit log.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Position in the log, starting at 0.
+    pub seq: u64,
+    /// Unix time (in seconds) the entry was recorded.
+    pub time: u64,
+    /// Group the event happened in.
+    #[serde_as(as = "Base64")]
+    pub group_id: Vec<u8>,
+    /// Epoch of the group after the event.
+    pub epoch: u64,
+    /// The event.
+    pub event: AuditEvent,
+    /// Hash of the previous entry (empty for the first entry).
+    #[serde_as(as = "Base64")]
+    pub prev_hash: Vec<u8>,
+    /// Hash of this entry, covering all other fields.
+    #[serde_as(as = "Base64")]
+    pub hash: Vec<u8>,
+}
+
+impl AuditEntry {
+    /// Compute the hash of this entry from all fields but `hash`.
+    pub fn digest(&self, crypto: &impl OpenMlsCrypto) -> Result<Vec<u8>, Box<dyn Error>> {
+        let content = serde_json::to_vec(&(
+            self.seq,
+            self.time,
+            &self.group_id,
+            self.epoch,
+            &self.event,
+            &self.prev_hash,
+        ))?;
+        Ok(crypto.hash(HashType::Sha2_256, &content)?)
+    }
+}
+
+/// Summarize a staged commit of the group as audit events.
+///
+/// Must be called before the commit is merged, so removed members can still be looked up.
+///
+/// Example:
+///
+/// ```ignore
+/// let events = commit_events(&group, &staged_commit);
+/// ```
+pub fn commit_events(group: &MlsGroup, commit: &StagedCommit) -> Vec<AuditEvent> {
+    let mut events = vec![AuditEvent::CommitApplied];
+    let added = commit
+        .add_proposals()
+        .map(|p| kp_identity(p.add_proposal().key_package()))
+        .collect::<Vec<_>>();
+    if !added.is_empty() {
+        events.push(AuditEvent::MembersAdded { identities: added });
+    }
+    let removed = commit
+        .remove_proposals()
+        .filter_map(|p| group.member(p.remove_proposal().removed()))
+        .map(|credential| credential.serialized_content().to_vec())
+        .collect::<Vec<_>>();
+    if !removed.is_empty() {
+        events.push(AuditEvent::MembersRemoved {
+            identities: removed,
+        });
+    }
+    let psk_ids = commit
+        .psk_proposals()
+        .filter_map(|p| match p.psk_proposal().clone().into_psk_id().psk() {
+            Psk::External(external_psk) => Some(external_psk.psk_id().to_vec()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    if !psk_ids.is_empty() {
+        events.push(AuditEvent::PsksInjected { psk_ids });
+    }
+    events
+}
+
+/// Append the events, which happened in the group's current epoch, to the audit log, and MAC
+/// its new head if an integrity key is set.
+///
+/// Example:
+///
+/// ```ignore
+/// append(&mut provider, &group, vec![AuditEvent::CommitApplied])?;
+/// ```
+pub fn append(
+    provider: &mut DmlsProvider,
+    group: &MlsGroup,
+    events: Vec<AuditEvent>,
+) -> Result<(), Box<dyn Error>> {
+    let time = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    for event in events {
+        let (seq, prev_hash) = match provider.state().audit_log().last() {
+            None => (0, Vec::new()),
+            Some(last) => (last.seq + 1, last.hash.clone()),
+        };
+        let mut entry = AuditEntry {
+            seq,
+            time,
+            group_id: group.group_id().to_vec(),
+            epoch: group.epoch().as_u64(),
+            event,
+            prev_hash,
+            hash: Vec::new(),
+        };
+        entry.hash = entry.digest(provider.crypto())?;
+        let head_mac = audit_head_mac(&entry.hash)?;
+        provider.state_mut().push_audit_entry(entry, head_mac);
+    }
+    Ok(())
+}
+
+/// Verify the hash chain of an audit log and, if an integrity key is set, the MAC of its head.
+///
+/// Fails at the first entry whose position, previous hash or hash does not match.
+///
+/// Example:
+///
+/// ```ignore
+/// verify(provider.state().audit_log(), provider.state().audit_head_mac(), provider.crypto())?;
+/// ```
+pub fn verify(
+    entries: &[AuditEntry],
+    head_mac: Option<&[u8]>,
+    crypto: &impl OpenMlsCrypto,
+) -> Result<(), Box<dyn Error>> {
+    let mut prev_hash = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.seq != i as u64 {
+            return Err(format!("Audit entry {i} has sequence number {}", entry.seq).into());
+        }
+        if entry.prev_hash != prev_hash {
+            return Err(format!("Audit entry {i} does not chain to its predecessor").into());
+        }
+        if entry.digest(crypto)? != entry.hash {
+            return Err(format!("Audit entry {i} was modified").into());
+        }
+        prev_hash = entry.hash.clone();
+    }
+    if !entries.is_empty() {
+        check_audit_head(&prev_hash, head_mac)?;
+    }
+    Ok(())
+}
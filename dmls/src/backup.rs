@@ -0,0 +1,167 @@
+//! Encrypted state backups.
+//!
+//! `seal` turns a state into a compressed, encrypted snapshot for disaster recovery; `open`
+//! turns it back into a state. The snapshot is the JSON state, zstd-compressed and encrypted with
+//! ChaCha20-Poly1305 under a key derived (HKDF-Extract) from a backup key and a random salt:
+//!
+//! ```text
+//! "DMLSBAK1" || salt (32 bytes) || nonce (12 bytes) || ciphertext
+//! ```
+//!
+//! Snapshots can leave out the OpenMLS entries holding secrets (private keys, epoch and message
+//! secrets, PSKs, key packages, group states with their pending commits); such a snapshot keeps
+//! the group structure but cannot decrypt or commit. The agent's signature key is always kept,
+//! since a state can't be loaded without it.
+//!
+//! Restoring an old snapshot over a state that has since moved on would roll groups back to
+//! epochs whose secrets the other members have deleted; `newer_groups` finds such groups so the
+//! caller can refuse.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let snapshot = seal(&crypto, provider.state(), &key, false)?;
+//! // ... later
+//! let state = open(&crypto, &snapshot, &key)?;
+//! ```
+
+use super::{
+    openmls_kvstore::{OpenMlsKeyValueStore, SECRET_LABELS},
+    state::{DmlsState, StateFormat},
+};
+use core::error::Error;
+use openmls::group::{GroupId, MlsGroup};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{
+    crypto::OpenMlsCrypto,
+    random::OpenMlsRand,
+    types::{AeadType, HashType},
+};
+
+/// Magic prefix (and format version) of snapshots.
+const MAGIC: &[u8] = b"DMLSBAK1";
+
+/// Length of the key derivation salt.
+const SALT_LEN: usize = 32;
+
+/// Length of the AEAD nonce.
+const NONCE_LEN: usize = 12;
+
+/// Minimum length of a backup key.
+pub const MIN_KEY_LEN: usize = 16;
+
+/// Derive the AEAD key from the backup key and the salt.
+fn derive_key(crypto: &RustCrypto, key: &[u8], salt: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    Ok(crypto
+        .hkdf_extract(HashType::Sha2_256, salt, key)?
+        .as_slice()
+        .to_vec())
+}
+
+/// Produce an encrypted, compressed snapshot of the state; the key must be at least
+/// `MIN_KEY_LEN` bytes long.
+///
+/// Example:
+///
+/// ```ignore
+/// std::fs::write("alice.bak", seal(&crypto, provider.state(), &key, true)?)?;
+/// ```
+pub fn seal(
+    crypto: &RustCrypto,
+    state: &DmlsState,
+    key: &[u8],
+    exclude_secrets: bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if key.is_empty() {
+        return Err("Empty backup key".into());
+    }
+    if key.len() < MIN_KEY_LEN {
+        return Err(format!("Backup key shorter than {MIN_KEY_LEN} bytes").into());
+    }
+    let plaintext = if exclude_secrets {
+        let stripped = state.without_openmls_values();
+        stripped.openmls_values().apply_raw_entries(
+            state
+                .openmls_values()
+                .raw_entries()?
+                .into_iter()
+                .filter(|(label, _, _)| !SECRET_LABELS.contains(label))
+                .collect(),
+        );
+        stripped.to_bytes(StateFormat::Json)?
+    } else {
+        state.to_bytes(StateFormat::Json)?
+    };
+    let compressed = zstd::encode_all(plaintext.as_slice(), 0)?;
+    let salt = crypto.random_vec(SALT_LEN)?;
+    let nonce = crypto.random_vec(NONCE_LEN)?;
+    let ciphertext = crypto.aead_encrypt(
+        AeadType::ChaCha20Poly1305,
+        &derive_key(crypto, key, &salt)?,
+        &compressed,
+        &nonce,
+        MAGIC,
+    )?;
+    Ok([MAGIC, &salt, &nonce, &ciphertext].concat())
+}
+
+/// Decrypt a snapshot produced by `seal` back into a state.
+///
+/// Example:
+///
+/// ```ignore
+/// let state = open(&crypto, &std::fs::read("alice.bak")?, &key)?;
+/// ```
+pub fn open(crypto: &RustCrypto, snapshot: &[u8], key: &[u8]) -> Result<DmlsState, Box<dyn Error>> {
+    let rest = snapshot.strip_prefix(MAGIC).ok_or("Not a DMLS backup")?;
+    if rest.len() < SALT_LEN + NONCE_LEN {
+        return Err("Truncated backup".into());
+    }
+    let (salt, rest) = rest.split_at(SALT_LEN);
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    let compressed = crypto
+        .aead_decrypt(
+            AeadType::ChaCha20Poly1305,
+            &derive_key(crypto, key, salt)?,
+            ciphertext,
+            nonce,
+            MAGIC,
+        )
+        .map_err(|_| "Wrong backup key or corrupted backup")?;
+    let (state, _) = DmlsState::from_bytes(&zstd::decode_all(compressed.as_slice())?)?;
+    Ok(state)
+}
+
+/// Returns the epochs of the groups stored in `kv`.
+fn group_epochs(kv: &OpenMlsKeyValueStore) -> Result<Vec<(GroupId, u64)>, Box<dyn Error>> {
+    let mut epochs = Vec::new();
+    for key in kv.keys_for_label(b"GroupContext") {
+        let group_id: GroupId = serde_json::from_slice(&key)?;
+        if let Some(group) = MlsGroup::load(kv, &group_id)? {
+            epochs.push((group_id, group.epoch().as_u64()));
+        }
+    }
+    Ok(epochs)
+}
+
+/// Returns the groups that are at a later epoch in `current` than in `restored`, with both
+/// epochs.
+///
+/// Example:
+///
+/// ```ignore
+/// for (group_id, current, restored) in newer_groups(&current, &restored)? { /* ... */ }
+/// ```
+pub fn newer_groups(
+    current: &DmlsState,
+    restored: &DmlsState,
+) -> Result<Vec<(GroupId, u64, u64)>, Box<dyn Error>> {
+    let restored = group_epochs(restored.openmls_values())?;
+    Ok(group_epochs(current.openmls_values())?
+        .into_iter()
+        .filter_map(|(group_id, current)| {
+            let (_, restored) = restored.iter().find(|(g, _)| *g == group_id)?;
+            (current > *restored).then_some((group_id, current, *restored))
+        })
+        .collect())
+}
@@ -0,0 +1,199 @@
+//! Benchmark harness.
+//!
+//! This module sets up in-process agents and groups so that the hot paths of the agent (key
+//! package generation, group creation, self-updates, PSK-injection commits and application
+//! messages) can be measured with `cargo bench` (see `benches/dmls.rs`). Agents are created for a
+//! storage `Backend`, so new storage implementations can be compared against the current
+//! in-memory store by adding a variant and running the same benchmarks.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let mut group = BenchGroup::new(Backend::InMemory, ciphersuite, 8, 32)?;
+//! let msg = group.encrypt("hello")?;
+//! group.decrypt(0, &msg)?;
+//! ```
+
+use super::{
+    encoding::Blob,
+    helpers::{
+        GroupConfigOptions, add_members, gen_kp_base64, gen_send_group, process_body_main,
+        send_group, send_group_inject_psks_base64, send_group_update_base64, stdin_base64_extract,
+        stdin_base64_to_kp, stdin_create_message_base64, store_exporter_psk,
+    },
+    openmls_keys::SignatureKeyPair,
+    provider::DmlsProvider,
+    state::{DmlsState, ExporterParams},
+};
+use core::error::Error;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::Ciphersuite;
+use tls_codec::Serialize;
+
+/// Storage backend to run benchmarks against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Backend {
+    /// The in-memory key-value store persisted with the JSON state.
+    InMemory,
+}
+
+impl Backend {
+    /// All backends, for benchmarks that compare them.
+    pub const ALL: &[Backend] = &[Backend::InMemory];
+    /// Short name used in benchmark ids.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::InMemory => "in-memory",
+        }
+    }
+}
+
+/// Create a fresh agent with a new signature key pair on the given backend.
+///
+/// Example:
+///
+/// ```ignore
+/// let agent = new_agent(Backend::InMemory, ciphersuite)?;
+/// ```
+pub fn new_agent(
+    backend: Backend,
+    ciphersuite: Ciphersuite,
+) -> Result<DmlsProvider, Box<dyn Error>> {
+    match backend {
+        Backend::InMemory => {
+            let crypto = RustCrypto::default();
+            let state = DmlsState::new(SignatureKeyPair::from_crypto(
+                &crypto,
+                ciphersuite.signature_algorithm(),
+            )?);
+            Ok(DmlsProvider::new(state, crypto))
+        }
+    }
+}
+
+/// A send group owned by one agent, with the given number of other members joined.
+#[derive(Debug)]
+pub struct BenchGroup {
+    /// Owner of the send group.
+    owner: DmlsProvider,
+    /// Other members, all joined via the Welcome.
+    members: Vec<DmlsProvider>,
+    /// Ciphersuite to use.
+    ciphersuite: Ciphersuite,
+    /// Exporter length to use for DMLS exporter PSK.
+    exporter_length: usize,
+}
+
+impl BenchGroup {
+    /// Create an owner with a send group and add `members` fresh agents to it.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let group = BenchGroup::new(Backend::InMemory, ciphersuite, 32, 32)?;
+    /// ```
+    pub fn new(
+        backend: Backend,
+        ciphersuite: Ciphersuite,
+        members: usize,
+        exporter_length: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut owner = new_agent(backend, ciphersuite)?;
+        let mut sg = gen_send_group(
+            &mut owner,
+            ciphersuite,
+            &GroupConfigOptions {
+                exporter: ExporterParams::new(exporter_length),
+                ..Default::default()
+            },
+        )?;
+        let mut agents = Vec::with_capacity(members);
+        for _ in 0..members {
+            agents.push(new_agent(backend, ciphersuite)?);
+        }
+        let mut group = Self {
+            owner,
+            members: Vec::new(),
+            ciphersuite,
+            exporter_length,
+        };
+        if !agents.is_empty() {
+            let mut kps = Vec::with_capacity(agents.len());
+            for agent in &agents {
+                let kp = gen_kp_base64(agent, ciphersuite)?;
+                kps.push(stdin_base64_to_kp(&group.owner, Ok(kp))?);
+            }
+            let (_, welcome) = add_members(
+                &mut group.owner,
+                &mut sg,
+                &kps,
+                ciphersuite,
+                exporter_length,
+            )?;
+            group.members = agents;
+            group.deliver(&Blob.encode(welcome.tls_serialize_detached()?))?;
+        }
+        Ok(group)
+    }
+    /// Returns the owner of the send group.
+    pub fn owner(&self) -> &DmlsProvider {
+        &self.owner
+    }
+    /// Returns the number of members besides the owner.
+    pub fn len(&self) -> usize {
+        self.members.len()
+    }
+    /// Returns whether the owner is alone in the group.
+    pub fn is_empty(&self) -> bool {
+        self.members.is_empty()
+    }
+    /// Self-update in the owner's send group and return the commit as base64.
+    pub fn self_update(&mut self) -> Result<String, Box<dyn Error>> {
+        send_group_update_base64(
+            &mut self.owner,
+            false,
+            self.ciphersuite,
+            self.exporter_length,
+        )
+    }
+    /// Queue an exporter PSK of the current epoch and commit it into the owner's send group.
+    ///
+    /// This stands in for the PSK a member would have received from another send group.
+    pub fn inject_psk(&mut self) -> Result<String, Box<dyn Error>> {
+        let sg = send_group(&self.owner)?;
+        let psk_id =
+            store_exporter_psk(&mut self.owner, &sg, self.ciphersuite, self.exporter_length)?;
+        self.owner.state_mut().push_exporter_psk_id(psk_id)?;
+        send_group_inject_psks_base64(&mut self.owner, self.ciphersuite, self.exporter_length)
+    }
+    /// Encrypt an application message in the owner's send group and return it as base64.
+    pub fn encrypt(&mut self, plaintext: &str) -> Result<String, Box<dyn Error>> {
+        let mut sg = send_group(&self.owner)?;
+        stdin_create_message_base64(&mut self.owner, &mut sg, Ok(plaintext.to_string()))
+    }
+    /// Process a base64 message at the member with the given index.
+    ///
+    /// Returns the decrypted plaintext, if the message was an application message.
+    pub fn decrypt(
+        &mut self,
+        member: usize,
+        message: &str,
+    ) -> Result<Option<String>, Box<dyn Error>> {
+        let body = stdin_base64_extract(Ok(message.to_string()))?;
+        let agent = self
+            .members
+            .get_mut(member)
+            .ok_or_else(|| format!("No member with index {member}"))?;
+        Ok(
+            process_body_main(agent, body, self.ciphersuite, self.exporter_length)
+                .map(|message| message.plaintext),
+        )
+    }
+    /// Process a base64 message at every member.
+    pub fn deliver(&mut self, message: &str) -> Result<(), Box<dyn Error>> {
+        for member in 0..self.members.len() {
+            drop(self.decrypt(member, message)?);
+        }
+        Ok(())
+    }
+}
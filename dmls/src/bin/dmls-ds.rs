@@ -0,0 +1,175 @@
+//! Minimal DMLS delivery service.
+//!
+//! `dmls-ds` is a small, in-memory HTTP delivery service (DS) for testing DMLS agents end-to-end
+//! over a network without external infrastructure. It keeps one mailbox per client identity and a
+//! key-package directory; all bodies are newline-separated base64 blobs, exactly as the `dmls`
+//! agent prints them.
+//!
+//! Routes (identities are unpadded base64url credential identities):
+//!
+//! - `POST /mailboxes/{identity}` appends messages (commits, welcomes, application messages)
+//! - `GET /mailboxes/{identity}?wait={secs}` returns and removes all queued messages, holding the
+//!   request up to `wait` seconds while the mailbox is empty
+//! - `POST /key-packages/{identity}` publishes key packages for the identity
+//! - `GET /key-packages/{identity}` returns and removes one key package (404 if none)
+//!
+//! Welcomes are distributed like any other message: the group creator posts them to the
+//! mailboxes of the new members (`dmls use-state ... --transport http:<url> gen-send-group`).
+//!
+//! Example:
+//!
+//! ```text
+//! cargo run --bin dmls-ds -- --listen 127.0.0.1:8080
+//! ```
+
+// the agent's dependencies are shared by this binary but mostly unused here
+#![allow(unused_crate_dependencies)]
+
+use clap::Parser;
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{Read, stderr},
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+use tiny_http::{Method, Request, Response, Server};
+use tracing_subscriber::EnvFilter;
+
+/// Maximum time a `GET /mailboxes/...` request may be held open.
+const MAX_WAIT: Duration = Duration::from_secs(60);
+
+/// Command-line arguments for the delivery service.
+#[derive(Parser, Debug)]
+#[command(author, version, about = "Minimal DMLS delivery service.", long_about = None)]
+struct DsArgs {
+    /// Address to listen on (optional)
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    listen: String,
+}
+
+/// In-memory mailboxes and key-package directory, keyed by identity.
+#[derive(Debug, Default)]
+struct DsState {
+    /// Queued messages per identity.
+    mailboxes: HashMap<String, Vec<String>>,
+    /// Published key packages per identity; each is handed out once.
+    key_packages: HashMap<String, VecDeque<String>>,
+}
+
+/// Shared delivery service state; the condition variable is notified on every mailbox post.
+type SharedDsState = Arc<(Mutex<DsState>, Condvar)>;
+
+/// Split a request URL into its path segments and `wait` query parameter.
+fn parse_url(url: &str) -> (Vec<String>, u64) {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let wait = query
+        .split('&')
+        .filter_map(|pair| pair.strip_prefix("wait="))
+        .find_map(|wait| wait.parse().ok())
+        .unwrap_or(0);
+    let segments = path
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| segment.to_string())
+        .collect();
+    (segments, wait)
+}
+
+/// Read the request body as a list of non-empty lines.
+fn body_lines(request: &mut Request) -> std::io::Result<Vec<String>> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    Ok(body
+        .lines()
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+/// Handle a single request against the shared state.
+fn handle(state: SharedDsState, mut request: Request) {
+    let method = request.method().clone();
+    let (segments, wait) = parse_url(request.url());
+    let segments: Vec<&str> = segments.iter().map(|s| s.as_str()).collect();
+    tracing::info!("{method} {}", request.url());
+    let (lock, cvar) = &*state;
+    let response = match (&method, segments.as_slice()) {
+        (Method::Post, ["mailboxes", identity]) => match body_lines(&mut request) {
+            Err(e) => Response::from_string(format!("{e}")).with_status_code(400),
+            Ok(lines) => {
+                let mut ds = lock.lock().unwrap_or_else(|e| e.into_inner());
+                ds.mailboxes
+                    .entry(identity.to_string())
+                    .or_default()
+                    .extend(lines);
+                cvar.notify_all();
+                Response::from_string("").with_status_code(204)
+            }
+        },
+        (Method::Get, ["mailboxes", identity]) => {
+            let ds = lock.lock().unwrap_or_else(|e| e.into_inner());
+            let timeout = Duration::from_secs(wait).min(MAX_WAIT);
+            let (mut ds, _) = cvar
+                .wait_timeout_while(ds, timeout, |ds| {
+                    ds.mailboxes.get(*identity).is_none_or(|m| m.is_empty())
+                })
+                .unwrap_or_else(|e| e.into_inner());
+            let messages = ds.mailboxes.remove(*identity).unwrap_or_default();
+            Response::from_string(messages.join("\n"))
+        }
+        (Method::Post, ["key-packages", identity]) => match body_lines(&mut request) {
+            Err(e) => Response::from_string(format!("{e}")).with_status_code(400),
+            Ok(lines) => {
+                let mut ds = lock.lock().unwrap_or_else(|e| e.into_inner());
+                ds.key_packages
+                    .entry(identity.to_string())
+                    .or_default()
+                    .extend(lines);
+                Response::from_string("").with_status_code(204)
+            }
+        },
+        (Method::Get, ["key-packages", identity]) => {
+            let mut ds = lock.lock().unwrap_or_else(|e| e.into_inner());
+            match ds
+                .key_packages
+                .get_mut(*identity)
+                .and_then(|kps| kps.pop_front())
+            {
+                Some(kp) => Response::from_string(kp),
+                None => Response::from_string("No key package").with_status_code(404),
+            }
+        }
+        _ => Response::from_string("Not found").with_status_code(404),
+    };
+    if let Err(e) = request.respond(response) {
+        tracing::error!("Error responding to request: {e}");
+    }
+}
+
+/// Entry point for the delivery service binary.
+fn main() {
+    // logging
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(stderr)
+        .init();
+    // command-line args
+    let args = DsArgs::parse();
+    tracing::info!("Command-line arguments: {args:?}");
+    // serve
+    let server = match Server::http(&args.listen) {
+        Ok(server) => server,
+        Err(e) => {
+            tracing::error!("Error listening on {}: {e}", args.listen);
+            return;
+        }
+    };
+    tracing::warn!("Delivery service listening on {}", args.listen);
+    let state: SharedDsState = Arc::new((Mutex::new(DsState::default()), Condvar::new()));
+    for request in server.incoming_requests() {
+        let state = state.clone();
+        // one thread per request so long-polls don't block other clients
+        thread::spawn(move || handle(state, request));
+    }
+}
@@ -0,0 +1,121 @@
+//! Persistence backends over a plain byte store.
+//!
+//! A `ByteStore` gets, puts and deletes byte values by label and key, so new persistence backends
+//! (see `dir_storage` and `redb_storage`) only need those operations. A byte store holds the
+//! OpenMLS values of an agent's `DmlsState` (see `attach` and `write_entries`): the values are
+//! loaded into the in-memory `OpenMlsKeyValueStore`, which remains the only OpenMLS
+//! `StorageProvider`, when the state is loaded, and only the changed entries are written back,
+//! while the state file keeps the agent's bookkeeping. Keys and values are laid out exactly as in
+//! `OpenMlsKeyValueStore`: keys are the JSON-serialized OpenMLS keys followed by the storage
+//! version, values are JSON, and lists are stored as JSON arrays of serialized items.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let store = DirStore::open("./alice-storage")?;
+//! attach(&store, state.openmls_values())?;
+//! // ... run OpenMLS operations against the provider ...
+//! write_entries(&store, decode_changes(state.openmls_values().take_changes())?)?;
+//! ```
+
+use super::openmls_kvstore::{LABELS, OpenMlsKeyValueStore, OpenMlsKeyValueStoreError, RawEntry};
+
+/// A store of byte values, addressed by storage label and key.
+pub trait ByteStore {
+    /// Returns the value stored under `label` and `key`, if any.
+    fn get(&self, label: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>;
+    /// Stores `value` under `label` and `key`, replacing any previous value.
+    fn put(&self, label: &[u8], key: &[u8], value: &[u8]) -> Result<(), StorageError>;
+    /// Deletes the value stored under `label` and `key`; deleting a missing value is not an error.
+    fn delete(&self, label: &[u8], key: &[u8]) -> Result<(), StorageError>;
+    /// Returns all keys and values stored under `label`.
+    fn entries(&self, label: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StorageError>;
+}
+
+/// Load the OpenMLS values of a state from a byte store into its in-memory store.
+///
+/// Values already in the in-memory store (i.e. a state file from before the byte store was used)
+/// are first migrated into the byte store. Returns the number of loaded entries.
+///
+/// Example:
+///
+/// ```ignore
+/// attach(&store, state.openmls_values())?;
+/// ```
+pub fn attach(store: &dyn ByteStore, kv: &OpenMlsKeyValueStore) -> Result<usize, StorageError> {
+    let migrated = kv.raw_entries()?;
+    if !migrated.is_empty() {
+        tracing::info!("Migrating {} entries into storage", migrated.len());
+        write_entries(store, migrated)?;
+    }
+    let mut entries = Vec::new();
+    for label in LABELS {
+        for (key, value) in store.entries(label)? {
+            entries.push((*label, key, Some(value)));
+        }
+    }
+    let count = entries.len();
+    kv.apply_raw_entries(entries);
+    Ok(count)
+}
+
+/// Write decoded entries (see `OpenMlsKeyValueStore::take_changes` and `decode_changes`) to a
+/// byte store, deleting those without a value.
+///
+/// Example:
+///
+/// ```ignore
+/// write_entries(&store, decode_changes(kv.take_changes())?)?;
+/// ```
+pub fn write_entries(store: &dyn ByteStore, entries: Vec<RawEntry>) -> Result<(), StorageError> {
+    for (label, key, value) in entries {
+        match value {
+            Some(value) => store.put(label, &key, &value)?,
+            None => store.delete(label, &key)?,
+        }
+    }
+    Ok(())
+}
+
+/// Errors that can be returned by byte stores.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// A key or value could not be (de)serialized.
+    SerializationError,
+    /// The byte store failed.
+    Backend(String),
+}
+
+/// Implements Display for StorageError for readable error messages.
+impl core::fmt::Display for StorageError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::SerializationError => write!(f, "SerializationError"),
+            Self::Backend(e) => write!(f, "Storage backend error: {e}"),
+        }
+    }
+}
+
+/// Implements the standard Error trait for StorageError.
+impl core::error::Error for StorageError {}
+
+/// Converts Serde JSON errors into StorageError::SerializationError.
+impl From<serde_json::Error> for StorageError {
+    fn from(_: serde_json::Error) -> Self {
+        Self::SerializationError
+    }
+}
+
+/// Converts key store errors into StorageError::SerializationError.
+impl From<OpenMlsKeyValueStoreError> for StorageError {
+    fn from(_: OpenMlsKeyValueStoreError) -> Self {
+        Self::SerializationError
+    }
+}
+
+/// Converts I/O errors into StorageError::Backend.
+impl From<std::io::Error> for StorageError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Backend(e.to_string())
+    }
+}
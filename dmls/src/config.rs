@@ -0,0 +1,166 @@
+//! Configuration files and profiles.
+//!
+//! The command-line agent reads defaults from `dmls.toml` files before parsing its arguments, so
+//! options given on the command line still win. A per-user file (`$XDG_CONFIG_HOME/dmls/dmls.toml`,
+//! `~/.config/dmls/dmls.toml` or `%APPDATA%\dmls\dmls.toml`) is read first, then a per-directory
+//! `./dmls.toml`, whose settings take precedence. Besides the default ciphersuite, exporter length,
+//! blob encoding and storage backend, a file can define named profiles, each resolving to a state
+//! path and optionally overriding the defaults:
+//!
+//! ```toml
+//! ciphersuite = "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519"
+//! exporter-length = 32
+//!
+//! [profiles.alice]
+//! state = "./alice_state.json"
+//! storage = "dir:./alice-storage"
+//! ```
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let (defaults, state_path) = Config::load()?.resolve(Some("alice"))?;
+//! ```
+
+use core::error::Error;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    env::var_os,
+    fs::read_to_string,
+    path::{Path, PathBuf},
+};
+
+/// Name of configuration files.
+pub const CONFIG_FILE_NAME: &str = "dmls.toml";
+
+/// Defaults for command-line options; unset ones keep the built-in defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Defaults {
+    /// Ciphersuite name (see `use-state --ciphersuite`).
+    pub ciphersuite: Option<String>,
+    /// Exporter PSK length (see `use-state --exporter-length`).
+    pub exporter_length: Option<usize>,
+    /// Blob encoding (see `--encoding`).
+    pub encoding: Option<String>,
+    /// Storage backend specification (see `use-state --storage`).
+    pub storage: Option<String>,
+}
+
+impl Defaults {
+    /// Returns these defaults, falling back to `other` for unset ones.
+    pub fn or(self, other: Self) -> Self {
+        Self {
+            ciphersuite: self.ciphersuite.or(other.ciphersuite),
+            exporter_length: self.exporter_length.or(other.exporter_length),
+            encoding: self.encoding.or(other.encoding),
+            storage: self.storage.or(other.storage),
+        }
+    }
+}
+
+/// A named profile: a state path and defaults overriding the file-wide ones.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct Profile {
+    /// Path of the profile's state file.
+    pub state: String,
+    /// Defaults used with this profile.
+    #[serde(flatten)]
+    pub defaults: Defaults,
+}
+
+/// Contents of one or more merged configuration files.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default, rename_all = "kebab-case")]
+pub struct Config {
+    /// File-wide defaults.
+    #[serde(flatten)]
+    pub defaults: Defaults,
+    /// Named profiles.
+    pub profiles: BTreeMap<String, Profile>,
+}
+
+impl Config {
+    /// Read the per-user and per-directory configuration files, if they exist, and merge them.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let config = Config::load()?;
+    /// ```
+    pub fn load() -> Result<Self, Box<dyn Error>> {
+        let user = match user_config_path() {
+            Some(path) => Self::read(&path)?,
+            None => Self::default(),
+        };
+        Ok(Self::read(Path::new(CONFIG_FILE_NAME))?.or(user))
+    }
+
+    /// Read a configuration file; a missing file is an empty configuration.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let config = Config::read(Path::new("./dmls.toml"))?;
+    /// ```
+    pub fn read(path: &Path) -> Result<Self, Box<dyn Error>> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        toml::from_str(&read_to_string(path)?)
+            .map_err(|e| format!("Invalid configuration file {}: {e}", path.display()).into())
+    }
+
+    /// Returns this configuration, falling back to `other` for unset defaults and profiles.
+    pub fn or(self, other: Self) -> Self {
+        let mut profiles = other.profiles;
+        profiles.extend(self.profiles);
+        Self {
+            defaults: self.defaults.or(other.defaults),
+            profiles,
+        }
+    }
+
+    /// Returns the defaults to apply and, with a profile, its state path.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let (defaults, state_path) = config.resolve(Some("alice"))?;
+    /// ```
+    pub fn resolve(
+        self,
+        profile: Option<&str>,
+    ) -> Result<(Defaults, Option<String>), Box<dyn Error>> {
+        match profile {
+            None => Ok((self.defaults, None)),
+            Some(name) => match self.profiles.get(name) {
+                None => Err(format!("Unknown profile: {name}").into()),
+                Some(profile) => Ok((
+                    profile.defaults.clone().or(self.defaults),
+                    Some(profile.state.clone()),
+                )),
+            },
+        }
+    }
+}
+
+/// Returns the path of the per-user configuration file, if the configuration directory is known.
+///
+/// Example:
+///
+/// ```ignore
+/// if let Some(path) = user_config_path() { println!("{}", path.display()); }
+/// ```
+pub fn user_config_path() -> Option<PathBuf> {
+    let dir = if cfg!(windows) {
+        var_os("APPDATA").map(PathBuf::from)
+    } else {
+        var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| var_os("HOME").map(|home| Path::new(&home).join(".config")))
+    }?;
+    Some(dir.join("dmls").join(CONFIG_FILE_NAME))
+}
@@ -0,0 +1,268 @@
+//! Credential continuity across signature key rotations.
+//!
+//! An agent's credential is derived from its signature public key, so rotating the key also
+//! changes the identity peers see. To let members tell a legitimate rotation from an impersonation,
+//! `rotate_key` updates the send group's leaf with a continuity proof: a leaf node extension
+//! (`CONTINUITY_EXTENSION_TYPE`) holding the old public key and its signature over the new one.
+//! `check_commit` is run by `helpers::apply_commit` on received commits: a commit whose path
+//! changes the sender's signature key is rejected unless it carries a valid proof by the key it
+//! replaces.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! if let Some(commit) = rotate_key(&mut provider, ciphersuite, 32)? {
+//!     println!("{}", Blob.encode(commit.tls_serialize_detached()?));
+//! }
+//! ```
+
+use super::{
+    crypto::CryptoBackend,
+    encoding::Blob,
+    helpers::{
+        capabilities, credential_for, discard_pending_commit, merge_pending_commit, send_group,
+        store_exporter_psk,
+    },
+    openmls_keys::{SignatureKeyPair, SignaturePublicKey, labeled_payload},
+    provider::DmlsProvider,
+};
+use core::error::Error;
+use openmls::{
+    extensions::{Extension, Extensions, UnknownExtension},
+    framing::{MlsMessageOut, Sender},
+    group::{MlsGroup, StagedCommit},
+    prelude::{LeafNode, NewSignerBundle},
+    treesync::LeafNodeParameters,
+};
+use openmls_traits::{
+    OpenMlsProvider,
+    crypto::OpenMlsCrypto,
+    signatures::{Signer, SignerError},
+    types::{Ciphersuite, SignatureScheme},
+};
+use tls_codec::{Deserialize as _, Serialize as _, TlsDeserialize, TlsSerialize, TlsSize, VLBytes};
+
+/// Extension type of continuity proofs (private-use range).
+pub const CONTINUITY_EXTENSION_TYPE: u16 = 0xff0b;
+
+/// Label of the old key's signature over the new public key.
+const CONTINUITY_LABEL: &str = "DMLS continuity";
+
+/// The content of the continuity extension.
+#[derive(TlsSerialize, TlsDeserialize, TlsSize)]
+struct ContinuityProof {
+    /// The signature public key being replaced.
+    old_public_key: VLBytes,
+    /// Signature by the old key over the new public key.
+    signature: VLBytes,
+}
+
+/// Signs with a key pair that isn't the agent's own (yet).
+struct KeySigner<'a> {
+    crypto: &'a CryptoBackend,
+    key: &'a SignatureKeyPair,
+}
+
+impl Signer for KeySigner<'_> {
+    fn sign(&self, payload: &[u8]) -> Result<Vec<u8>, SignerError> {
+        self.crypto
+            .sign(
+                self.key.signature_scheme(),
+                payload,
+                self.key.private_key_raw(),
+            )
+            .map_err(SignerError::CryptoError)
+    }
+    fn signature_scheme(&self) -> SignatureScheme {
+        self.key.signature_scheme()
+    }
+}
+
+/// Build the continuity extension for a new key pair, signed by the agent's current key.
+///
+/// Example:
+///
+/// ```ignore
+/// let extension = proof_extension(&provider, &new_key_pair)?;
+/// ```
+pub fn proof_extension(
+    provider: &DmlsProvider,
+    new_key_pair: &SignatureKeyPair,
+) -> Result<Extension, Box<dyn Error>> {
+    let payload = labeled_payload(CONTINUITY_LABEL, new_key_pair.public_key_raw())?;
+    let proof = ContinuityProof {
+        old_public_key: VLBytes::new(
+            provider
+                .state()
+                .signature_key_pair()
+                .public_key_raw()
+                .to_vec(),
+        ),
+        signature: VLBytes::new(provider.sign(&payload)?),
+    };
+    Ok(Extension::Unknown(
+        CONTINUITY_EXTENSION_TYPE,
+        UnknownExtension(proof.tls_serialize_detached()?),
+    ))
+}
+
+/// Verify that a leaf node carries a continuity proof by `old_public_key` over its signature key.
+///
+/// Example:
+///
+/// ```ignore
+/// verify_proof(provider.crypto(), scheme, leaf_node, &member.signature_key)?;
+/// ```
+pub fn verify_proof(
+    crypto: &impl OpenMlsCrypto,
+    signature_scheme: SignatureScheme,
+    leaf_node: &LeafNode,
+    old_public_key: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let Some(bytes) = leaf_node
+        .extensions()
+        .iter()
+        .find_map(|extension| match extension {
+            Extension::Unknown(CONTINUITY_EXTENSION_TYPE, UnknownExtension(bytes)) => Some(bytes),
+            _ => None,
+        })
+    else {
+        return Err("No continuity proof".into());
+    };
+    let proof = ContinuityProof::tls_deserialize_exact(bytes)?;
+    if proof.old_public_key.as_slice() != old_public_key {
+        return Err("Continuity proof is by a different key".into());
+    }
+    let payload = labeled_payload(CONTINUITY_LABEL, leaf_node.signature_key().as_slice())?;
+    SignaturePublicKey::from(old_public_key.to_vec())
+        .verify(
+            crypto,
+            signature_scheme,
+            &payload,
+            proof.signature.as_slice(),
+        )
+        .map_err(|e| format!("Invalid continuity proof: {e:?}").into())
+}
+
+/// Reject a received commit whose path changes the sender's signature key without a valid
+/// continuity proof (before the commit is merged).
+///
+/// Example:
+///
+/// ```ignore
+/// check_commit(provider.crypto(), &group, &sender, &staged_commit)?;
+/// ```
+pub fn check_commit(
+    crypto: &impl OpenMlsCrypto,
+    group: &MlsGroup,
+    sender: &Sender,
+    commit: &StagedCommit,
+) -> Result<(), Box<dyn Error>> {
+    let (Sender::Member(leaf_index), Some(leaf_node)) = (sender, commit.update_path_leaf_node())
+    else {
+        return Ok(());
+    };
+    let Some(member) = group.member_at(*leaf_index) else {
+        return Ok(());
+    };
+    if member.signature_key == leaf_node.signature_key().as_slice() {
+        return Ok(());
+    }
+    verify_proof(
+        crypto,
+        group.ciphersuite().signature_algorithm(),
+        leaf_node,
+        &member.signature_key,
+    )
+    .map_err(|e| format!("Signature key of leaf {leaf_index} changed: {e}").into())
+}
+
+/// Replace the agent's signature key pair with a new one of the same scheme.
+///
+/// If the agent has a send group, its leaf is updated to the new credential with a continuity
+/// proof, and the commit is returned. Groups joined through others' send groups keep the old
+/// leaf, which the agent never signs with again; new key packages use the new credential. Unless
+/// an identity was chosen (see `helpers::update_identity_base64`), the credential identity
+/// changes too, so an explicit admin list (see `roles`) naming the old identity has to be
+/// updated by another admin.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = rotate_key(&mut provider, ciphersuite, 32)?;
+/// ```
+pub fn rotate_key(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<MlsMessageOut>, Box<dyn Error>> {
+    let new_key_pair = SignatureKeyPair::from_crypto(
+        provider.crypto(),
+        provider.state().signature_key_pair().signature_scheme(),
+    )?;
+    let commit = match provider.state().send_group_id() {
+        None => None,
+        Some(_) => {
+            let mut sg = send_group(provider)?;
+            let credential_with_key = credential_for(&new_key_pair, provider.state().identity());
+            let leaf_node_parameters = LeafNodeParameters::builder()
+                .with_credential_with_key(credential_with_key.clone())
+                .with_capabilities(capabilities(
+                    provider.state().capabilities(),
+                    sg.ciphersuite(),
+                )?)
+                .with_extensions(Extensions::single(proof_extension(
+                    provider,
+                    &new_key_pair,
+                )?))
+                .build();
+            discard_pending_commit(provider, &mut sg)?;
+            sg.clear_pending_proposals(provider.storage())?;
+            let signer = KeySigner {
+                crypto: provider.crypto(),
+                key: &new_key_pair,
+            };
+            let (commit, _, _) = sg
+                .self_update_with_new_signer(
+                    provider,
+                    provider,
+                    NewSignerBundle {
+                        signer: &signer,
+                        credential_with_key,
+                    },
+                    leaf_node_parameters,
+                )?
+                .into_messages();
+            merge_pending_commit(provider, &mut sg)?;
+            drop(store_exporter_psk(
+                provider,
+                &sg,
+                ciphersuite,
+                exporter_length,
+            )?);
+            Some(commit)
+        }
+    };
+    provider.state_mut().set_signature_key_pair(new_key_pair);
+    Ok(commit)
+}
+
+/// Rotate the agent's signature key pair (see `rotate_key`) and return the send group's commit, if
+/// any, as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// if let Some(commit_b64) = rotate_key_base64(&mut provider, ciphersuite, 32)? {
+///     println!("{}", commit_b64);
+/// }
+/// ```
+pub fn rotate_key_base64(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<String>, Box<dyn Error>> {
+    rotate_key(provider, ciphersuite, exporter_length)?
+        .map(|commit| Ok(Blob.encode(commit.tls_serialize_detached()?)))
+        .transpose()
+}
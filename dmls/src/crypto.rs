@@ -0,0 +1,246 @@
+//! Compiled-in crypto backends.
+//!
+//! `CryptoBackend` is the `OpenMlsCrypto` implementation used by `DmlsProvider`. It dispatches to
+//! one of the backends compiled into the binary: RustCrypto (always available) or, with the
+//! `libcrux` feature, the formally verified libcrux primitives. Other providers (e.g. ring-based or
+//! FIPS-validated ones) can be plugged in by adding a variant. The backend is chosen at run time,
+//! e.g. with the `--crypto-backend` CLI flag; every agent of a group may use a different one.
+//!
+//! Randomness drawn by OpenMLS comes from `DmlsRand`, not from the backend.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let crypto: CryptoBackend = "libcrux".parse()?;
+//! let provider = DmlsProvider::new(state, crypto);
+//! ```
+
+use core::str::FromStr;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{
+    crypto::OpenMlsCrypto,
+    types::{
+        AeadType, Ciphersuite, CryptoError, ExporterSecret, HashType, HpkeCiphertext, HpkeConfig,
+        HpkeKeyPair, KemOutput, SignatureScheme,
+    },
+};
+use tls_codec::SecretVLBytes;
+
+/// Names of the backends compiled into this build, as accepted by `CryptoBackend::from_str`.
+pub const BACKEND_NAMES: &[&str] = &[
+    "rust-crypto",
+    #[cfg(feature = "libcrux")]
+    "libcrux",
+];
+
+/// A crypto backend compiled into this build.
+pub enum CryptoBackend {
+    /// Pure-Rust primitives from the RustCrypto project.
+    RustCrypto(RustCrypto),
+    /// Formally verified primitives from libcrux.
+    #[cfg(feature = "libcrux")]
+    Libcrux(openmls_libcrux_crypto::CryptoProvider),
+}
+
+impl Default for CryptoBackend {
+    fn default() -> Self {
+        Self::RustCrypto(RustCrypto::default())
+    }
+}
+
+impl From<RustCrypto> for CryptoBackend {
+    fn from(crypto: RustCrypto) -> Self {
+        Self::RustCrypto(crypto)
+    }
+}
+
+impl CryptoBackend {
+    /// Returns the name of the backend (see `BACKEND_NAMES`).
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::RustCrypto(_) => "rust-crypto",
+            #[cfg(feature = "libcrux")]
+            Self::Libcrux(_) => "libcrux",
+        }
+    }
+
+    /// Returns the first compiled-in backend supporting the ciphersuite, trying RustCrypto first.
+    ///
+    /// Hybrid post-quantum ciphersuites (`pq` feature) are only provided by libcrux.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let crypto = CryptoBackend::for_ciphersuite(ciphersuite)?;
+    /// ```
+    pub fn for_ciphersuite(ciphersuite: Ciphersuite) -> Result<Self, String> {
+        for name in BACKEND_NAMES {
+            let backend: Self = name.parse()?;
+            if backend.supports(ciphersuite).is_ok() {
+                return Ok(backend);
+            }
+        }
+        Err(format!(
+            "No crypto backend in this build supports {ciphersuite:?}"
+        ))
+    }
+}
+
+impl core::fmt::Debug for CryptoBackend {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("CryptoBackend").field(&self.name()).finish()
+    }
+}
+
+impl FromStr for CryptoBackend {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rust-crypto" => Ok(Self::default()),
+            #[cfg(feature = "libcrux")]
+            "libcrux" => openmls_libcrux_crypto::CryptoProvider::new()
+                .map(Self::Libcrux)
+                .map_err(|e| format!("Error initializing libcrux: {e:?}")),
+            _ => Err(format!(
+                "Unknown crypto backend {s}; this build supports: {}",
+                BACKEND_NAMES.join(", ")
+            )),
+        }
+    }
+}
+
+/// Calls the same method on whichever backend is selected.
+macro_rules! dispatch {
+    ($self:ident, $crypto:ident => $call:expr) => {
+        match $self {
+            Self::RustCrypto($crypto) => $call,
+            #[cfg(feature = "libcrux")]
+            Self::Libcrux($crypto) => $call,
+        }
+    };
+}
+
+impl OpenMlsCrypto for CryptoBackend {
+    fn supports(&self, ciphersuite: Ciphersuite) -> Result<(), CryptoError> {
+        dispatch!(self, crypto => crypto.supports(ciphersuite))
+    }
+    fn supported_ciphersuites(&self) -> Vec<Ciphersuite> {
+        dispatch!(self, crypto => crypto.supported_ciphersuites())
+    }
+    fn hkdf_extract(
+        &self,
+        hash_type: HashType,
+        salt: &[u8],
+        ikm: &[u8],
+    ) -> Result<SecretVLBytes, CryptoError> {
+        dispatch!(self, crypto => crypto.hkdf_extract(hash_type, salt, ikm))
+    }
+    fn hkdf_expand(
+        &self,
+        hash_type: HashType,
+        prk: &[u8],
+        info: &[u8],
+        okm_len: usize,
+    ) -> Result<SecretVLBytes, CryptoError> {
+        dispatch!(self, crypto => crypto.hkdf_expand(hash_type, prk, info, okm_len))
+    }
+    fn hash(&self, hash_type: HashType, data: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        dispatch!(self, crypto => crypto.hash(hash_type, data))
+    }
+    fn aead_encrypt(
+        &self,
+        alg: AeadType,
+        key: &[u8],
+        data: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        dispatch!(self, crypto => crypto.aead_encrypt(alg, key, data, nonce, aad))
+    }
+    fn aead_decrypt(
+        &self,
+        alg: AeadType,
+        key: &[u8],
+        ct_tag: &[u8],
+        nonce: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        dispatch!(self, crypto => crypto.aead_decrypt(alg, key, ct_tag, nonce, aad))
+    }
+    fn signature_key_gen(&self, alg: SignatureScheme) -> Result<(Vec<u8>, Vec<u8>), CryptoError> {
+        dispatch!(self, crypto => crypto.signature_key_gen(alg))
+    }
+    fn verify_signature(
+        &self,
+        alg: SignatureScheme,
+        data: &[u8],
+        pk: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CryptoError> {
+        dispatch!(self, crypto => crypto.verify_signature(alg, data, pk, signature))
+    }
+    fn sign(&self, alg: SignatureScheme, data: &[u8], key: &[u8]) -> Result<Vec<u8>, CryptoError> {
+        dispatch!(self, crypto => crypto.sign(alg, data, key))
+    }
+    fn hpke_seal(
+        &self,
+        config: HpkeConfig,
+        pk_r: &[u8],
+        info: &[u8],
+        aad: &[u8],
+        ptxt: &[u8],
+    ) -> Result<HpkeCiphertext, CryptoError> {
+        dispatch!(self, crypto => crypto.hpke_seal(config, pk_r, info, aad, ptxt))
+    }
+    fn hpke_open(
+        &self,
+        config: HpkeConfig,
+        input: &HpkeCiphertext,
+        sk_r: &[u8],
+        info: &[u8],
+        aad: &[u8],
+    ) -> Result<Vec<u8>, CryptoError> {
+        dispatch!(self, crypto => crypto.hpke_open(config, input, sk_r, info, aad))
+    }
+    fn hpke_setup_sender_and_export(
+        &self,
+        config: HpkeConfig,
+        pk_r: &[u8],
+        info: &[u8],
+        exporter_context: &[u8],
+        exporter_length: usize,
+    ) -> Result<(KemOutput, ExporterSecret), CryptoError> {
+        dispatch!(self, crypto => crypto.hpke_setup_sender_and_export(
+            config,
+            pk_r,
+            info,
+            exporter_context,
+            exporter_length,
+        ))
+    }
+    fn hpke_setup_receiver_and_export(
+        &self,
+        config: HpkeConfig,
+        enc: &[u8],
+        sk_r: &[u8],
+        info: &[u8],
+        exporter_context: &[u8],
+        exporter_length: usize,
+    ) -> Result<ExporterSecret, CryptoError> {
+        dispatch!(self, crypto => crypto.hpke_setup_receiver_and_export(
+            config,
+            enc,
+            sk_r,
+            info,
+            exporter_context,
+            exporter_length,
+        ))
+    }
+    fn derive_hpke_keypair(
+        &self,
+        config: HpkeConfig,
+        ikm: &[u8],
+    ) -> Result<HpkeKeyPair, CryptoError> {
+        dispatch!(self, crypto => crypto.derive_hpke_keypair(config, ikm))
+    }
+}
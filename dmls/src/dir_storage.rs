@@ -0,0 +1,147 @@
+//! File-per-key directory storage.
+//!
+//! `DirStore` persists every OpenMLS entity as its own file, so each write only touches the
+//! entries that changed (no whole-state rewrite, no database dependency) and individual entities
+//! can be inspected with ordinary tools:
+//!
+//! ```text
+//! <root>/GroupState/<key>.json
+//! <root>/Tree/<key>.json
+//! <root>/QueuedProposal/<first 128 chars of key>/<rest of key>.json
+//! ```
+//!
+//! The directory is named after the storage label; the file name is the URL-safe base64 encoding
+//! of the serialized key (and version), split into 128-character directory components so long
+//! keys stay within file name limits. Files hold the JSON value as written by OpenMLS.
+//!
+//! Writes are crash-safe: values are written to a temporary file, synced and atomically renamed
+//! over the old file, so after a crash each entry holds either its old or its new value.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let store = DirStore::open("./alice-storage")?;
+//! attach(&store, state.openmls_values())?;
+//! // cat ./alice-storage/GroupState/*.json
+//! ```
+
+use super::{
+    byte_store::{ByteStore, StorageError},
+    perms::{create_private_dir, private_options},
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as Base64Url};
+use std::{
+    fs::{read, read_dir, remove_file, rename},
+    io::{ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+/// Maximum length of one file name component.
+const COMPONENT_LEN: usize = 128;
+
+/// A byte store keeping one file per (label, key) pair under a root directory.
+#[derive(Clone, Debug)]
+pub struct DirStore {
+    /// Root directory of the store.
+    root: PathBuf,
+}
+
+impl DirStore {
+    /// Opens the store at the given directory, creating it if necessary.
+    pub fn open(root: impl AsRef<Path>) -> Result<Self, StorageError> {
+        create_private_dir(root.as_ref())?;
+        Ok(Self {
+            root: root.as_ref().to_path_buf(),
+        })
+    }
+    /// Returns the root directory of the store.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+    /// Returns the path of the file holding the value for `label` and `key`.
+    fn path(&self, label: &[u8], key: &[u8]) -> PathBuf {
+        let mut path = self.root.join(String::from_utf8_lossy(label).as_ref());
+        let name = Base64Url.encode(key);
+        let mut components = name.as_bytes().chunks(COMPONENT_LEN).peekable();
+        while let Some(component) = components.next() {
+            // base64 is ASCII, so components are valid UTF-8
+            let component = String::from_utf8_lossy(component);
+            if components.peek().is_some() {
+                path.push(component.as_ref());
+            } else {
+                // the extension keeps files and directories with the same prefix apart
+                path.push(format!("{component}.json"));
+            }
+        }
+        path
+    }
+}
+
+/// Collect the files under `dir` (recursively), with their names as the concatenation of their
+/// path components below the label directory, without the extension.
+fn collect_files(
+    dir: &Path,
+    prefix: &str,
+    files: &mut Vec<(String, PathBuf)>,
+) -> Result<(), StorageError> {
+    let entries = match read_dir(dir) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        entries => entries?,
+    };
+    for entry in entries {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        if path.is_dir() {
+            collect_files(&path, &format!("{prefix}{name}"), files)?;
+        } else if let Some(name) = name.strip_suffix(".json") {
+            files.push((format!("{prefix}{name}"), path.clone()));
+        }
+    }
+    Ok(())
+}
+
+impl ByteStore for DirStore {
+    fn get(&self, label: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        match read(self.path(label, key)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            value => Ok(Some(value?)),
+        }
+    }
+    fn put(&self, label: &[u8], key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let path = self.path(label, key);
+        if let Some(dir) = path.parent() {
+            create_private_dir(dir)?;
+        }
+        let tmp = path.with_extension("json.tmp");
+        let mut file = private_options().truncate(true).open(&tmp)?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        rename(&tmp, &path)?;
+        Ok(())
+    }
+    fn delete(&self, label: &[u8], key: &[u8]) -> Result<(), StorageError> {
+        match remove_file(self.path(label, key)) {
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => Ok(result?),
+        }
+    }
+    fn entries(&self, label: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StorageError> {
+        let mut files = Vec::new();
+        collect_files(
+            &self.root.join(String::from_utf8_lossy(label).as_ref()),
+            "",
+            &mut files,
+        )?;
+        files
+            .into_iter()
+            .map(|(name, path)| {
+                let key = Base64Url
+                    .decode(&name)
+                    .map_err(|e| StorageError::Backend(format!("Invalid file name {name}: {e}")))?;
+                Ok((key, read(path)?))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,165 @@
+//! State diagnosis and repair.
+//!
+//! A state can become inconsistent when a run is interrupted, a state file is edited or restored
+//! by hand, or a bug slips through. OpenMLS then fails with errors that don't point at the cause.
+//! `diagnose` checks the invariants the agent relies on:
+//!
+//! - the send group id (if any) refers to a stored group
+//! - every queued exporter or external PSK id has a stored PSK bundle
+//! - every set of epoch key pairs belongs to a stored group
+//! - every stored key package is stored under its own hash reference
+//!
+//! and `repair` fixes the problems found by clearing or pruning the broken entries.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let problems = diagnose(&provider)?;
+//! repair(&mut provider, &problems);
+//! ```
+
+use super::{
+    openmls_kvstore::{EPOCH_KEY_PAIRS_LABEL, GROUP_CONTEXT_LABEL, KEY_PACKAGE_LABEL, PSK_LABEL},
+    provider::DmlsProvider,
+};
+use core::error::Error;
+use openmls::{
+    group::GroupId,
+    key_packages::KeyPackageBundle,
+    schedule::{ExternalPsk, Psk},
+};
+use openmls_traits::OpenMlsProvider;
+
+/// An inconsistency found in a state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+    /// The send group id doesn't refer to a stored group.
+    MissingSendGroup(GroupId),
+    /// A queued exporter or external PSK id has no stored PSK bundle.
+    MissingPsk(Vec<u8>),
+    /// Epoch key pairs of a group that isn't stored, with their storage key (including the
+    /// version).
+    OrphanedEpochKeyPairs(Vec<u8>),
+    /// A key package that isn't stored under its hash reference, with its storage key (including
+    /// the version).
+    UnresolvedKeyPackage(Vec<u8>),
+}
+
+impl core::fmt::Display for Problem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            Self::MissingSendGroup(group_id) => {
+                write!(f, "send group {:?} is not stored", group_id.as_slice())
+            }
+            Self::MissingPsk(psk_id) => {
+                write!(f, "queued PSK {psk_id:?} has no stored secret")
+            }
+            Self::OrphanedEpochKeyPairs(key) => write!(
+                f,
+                "epoch key pairs {} belong to no stored group",
+                String::from_utf8_lossy(&key[..key.len().saturating_sub(2)])
+            ),
+            Self::UnresolvedKeyPackage(key) => write!(
+                f,
+                "key package {} is not stored under its hash reference",
+                String::from_utf8_lossy(&key[..key.len().saturating_sub(2)])
+            ),
+        }
+    }
+}
+
+/// Check a state for inconsistencies (see the module docs).
+///
+/// Example:
+///
+/// ```ignore
+/// for problem in diagnose(&provider)? {
+///     println!("{problem}");
+/// }
+/// ```
+pub fn diagnose(provider: &DmlsProvider) -> Result<Vec<Problem>, Box<dyn Error>> {
+    let kv = provider.state().openmls_values();
+    let mut problems = Vec::new();
+    let groups = kv
+        .keys_for_label(GROUP_CONTEXT_LABEL)
+        .iter()
+        .map(|key| serde_json::from_slice::<GroupId>(key))
+        .collect::<Result<Vec<_>, _>>()?;
+    // send group
+    if let Some(group_id) = provider.state().send_group_id()
+        && !groups.contains(&group_id)
+    {
+        problems.push(Problem::MissingSendGroup(group_id));
+    }
+    // exporter PSKs
+    let psks = kv.keys_for_label(PSK_LABEL);
+    let state = provider.state();
+    for psk_id in state
+        .exporter_psk_ids()
+        .iter()
+        .chain(state.external_psk_ids())
+    {
+        let key = serde_json::to_vec(&Psk::External(ExternalPsk::new(psk_id.clone())))?;
+        if !psks.contains(&key) {
+            problems.push(Problem::MissingPsk(psk_id.clone()));
+        }
+    }
+    // epoch key pairs and key packages
+    for (label, key, value) in kv.raw_entries()? {
+        // strip the storage version
+        let (Some(value), Some(openmls_key)) = (value, key.get(..key.len().saturating_sub(2)))
+        else {
+            continue;
+        };
+        if label == EPOCH_KEY_PAIRS_LABEL {
+            // the key starts with the group id
+            let group_id = serde_json::Deserializer::from_slice(openmls_key)
+                .into_iter::<GroupId>()
+                .next()
+                .and_then(Result::ok);
+            if !group_id.is_some_and(|group_id| groups.contains(&group_id)) {
+                problems.push(Problem::OrphanedEpochKeyPairs(key));
+            }
+        } else if label == KEY_PACKAGE_LABEL {
+            let resolves = serde_json::from_slice::<KeyPackageBundle>(&value)
+                .ok()
+                .and_then(|bundle| bundle.key_package().hash_ref(provider.crypto()).ok())
+                .and_then(|hash_ref| serde_json::to_vec(&hash_ref).ok())
+                .is_some_and(|hash_ref| hash_ref == openmls_key);
+            if !resolves {
+                problems.push(Problem::UnresolvedKeyPackage(key));
+            }
+        }
+    }
+    Ok(problems)
+}
+
+/// Fix problems found by `diagnose`: clear a dangling send group id, drop queued PSK ids without
+/// secrets, and delete orphaned epoch key pairs and unresolvable key packages.
+///
+/// Example:
+///
+/// ```ignore
+/// repair(&mut provider, &diagnose(&provider)?);
+/// ```
+pub fn repair(provider: &mut DmlsProvider, problems: &[Problem]) {
+    let mut deleted = Vec::new();
+    for problem in problems {
+        match problem {
+            Problem::MissingSendGroup(_) => provider.state_mut().clear_send_group_id(),
+            Problem::MissingPsk(psk_id) => provider
+                .state_mut()
+                .remove_exporter_psk_ids(core::slice::from_ref(psk_id)),
+            Problem::OrphanedEpochKeyPairs(key) => {
+                deleted.push((EPOCH_KEY_PAIRS_LABEL, key.clone(), None));
+            }
+            Problem::UnresolvedKeyPackage(key) => {
+                deleted.push((KEY_PACKAGE_LABEL, key.clone(), None));
+            }
+        }
+    }
+    provider
+        .state()
+        .openmls_values()
+        .insert_raw_entries(deleted);
+}
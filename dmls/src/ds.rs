@@ -0,0 +1,153 @@
+//! HTTP delivery service client.
+//!
+//! This module implements a small client for a delivery service (DS) that keeps one mailbox per
+//! client identity. Producing commands POST their base64 artifacts into the mailboxes of the
+//! intended recipients, and `Process` fetches (and thereby drains) the agent's own mailbox,
+//! optionally long-polling until messages arrive.
+//!
+//! The protocol is deliberately minimal and text-based; every body is a list of newline-separated
+//! base64 blobs, exactly as they would be piped between agents on stdin/stdout:
+//!
+//! - `POST {url}/mailboxes/{identity}` appends the blobs in the body to the mailbox
+//! - `GET {url}/mailboxes/{identity}?wait={secs}` returns and removes all queued blobs
+//! - `POST {url}/key-packages/{identity}` publishes key packages for the identity
+//! - `GET {url}/key-packages/{identity}` returns and removes one key package (404 if none)
+//!
+//! Identities are credential identities encoded as unpadded base64url, so they are safe to use as
+//! path segments.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let ds = HttpDeliveryService::new("http://localhost:8080");
+//! ds.post_messages(&bob_identity, &[commit_b64])?;
+//! let incoming = ds.fetch_messages(&alice_identity, 30)?;
+//! ```
+
+use super::transport::KeyPackageDirectory;
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as Base64Url};
+use core::error::Error;
+
+/// Client for an HTTP delivery service with per-identity mailboxes.
+#[derive(Clone, Debug)]
+pub struct HttpDeliveryService {
+    /// Base URL of the delivery service, without trailing slash.
+    url: String,
+}
+
+impl HttpDeliveryService {
+    /// Creates a client for the delivery service at the given base URL.
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.trim_end_matches('/').to_string(),
+        }
+    }
+    /// Returns the base URL of the delivery service.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+    /// Returns the URL of the mailbox belonging to the given credential identity.
+    fn mailbox_url(&self, identity: &[u8]) -> String {
+        format!("{}/mailboxes/{}", self.url, Base64Url.encode(identity))
+    }
+    /// Returns the key-package directory URL of the given credential identity.
+    fn key_packages_url(&self, identity: &[u8]) -> String {
+        format!("{}/key-packages/{}", self.url, Base64Url.encode(identity))
+    }
+}
+
+impl HttpDeliveryService {
+    /// Append base64 artifacts to the mailbox of the given credential identity.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// ds.post_messages(&identity, &[commit_b64])?;
+    /// ```
+    pub fn post_messages(
+        &self,
+        identity: &[u8],
+        messages: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        if messages.is_empty() {
+            return Ok(());
+        }
+        ureq::post(&self.mailbox_url(identity))
+            .header("Content-Type", "text/plain")
+            .send(messages.join("\n"))?;
+        Ok(())
+    }
+
+    /// Fetch and remove all base64 artifacts queued in the mailbox of the given identity.
+    ///
+    /// If `wait` is non-zero, the delivery service holds the request for up to `wait` seconds
+    /// until at least one message is available (long-polling).
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// for msg in ds.fetch_messages(&identity, 30)? {
+    ///     println!("{}", msg);
+    /// }
+    /// ```
+    pub fn fetch_messages(
+        &self,
+        identity: &[u8],
+        wait: u64,
+    ) -> Result<Vec<String>, Box<dyn Error>> {
+        let body = ureq::get(&self.mailbox_url(identity))
+            .query("wait", wait.to_string())
+            .call()?
+            .body_mut()
+            .read_to_string()?;
+        Ok(body
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| line.to_string())
+            .collect())
+    }
+}
+
+impl KeyPackageDirectory for HttpDeliveryService {
+    /// Publish base64 key packages in the directory under the given credential identity.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// ds.publish_key_packages(&own_identity, &[kp_b64])?;
+    /// ```
+    fn publish_key_packages(
+        &self,
+        identity: &[u8],
+        key_packages: &[String],
+    ) -> Result<(), Box<dyn Error>> {
+        if key_packages.is_empty() {
+            return Ok(());
+        }
+        ureq::post(&self.key_packages_url(identity))
+            .header("Content-Type", "text/plain")
+            .send(key_packages.join("\n"))?;
+        Ok(())
+    }
+
+    /// Fetch (and thereby consume) one base64 key package published for the given identity.
+    ///
+    /// Returns `None` if the directory has no key package for the identity.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// if let Some(kp_b64) = ds.fetch_key_package(&bob_identity)? {
+    ///     println!("{}", kp_b64);
+    /// }
+    /// ```
+    fn fetch_key_package(&self, identity: &[u8]) -> Result<Option<String>, Box<dyn Error>> {
+        match ureq::get(&self.key_packages_url(identity)).call() {
+            Err(ureq::Error::StatusCode(404)) => Ok(None),
+            Err(e) => Err(e.into()),
+            Ok(mut response) => Ok(Some(
+                response.body_mut().read_to_string()?.trim().to_string(),
+            )),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+//! Text encodings for wire blobs.
+//!
+//! MLS artifacts (key packages, welcomes, commits, messages), group ids and identities are
+//! exchanged as text. By default they are standard base64; the agent-wide `Encoding` can be
+//! switched to base64url (e.g. to embed blobs in URLs) or hex (for hex-oriented tooling) with
+//! `set_encoding`, which the CLI does for its global `--encoding` option. Every helper that
+//! encodes or decodes wire blobs goes through `Blob`, so they all honor the setting. The
+//! persisted state is not affected.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! set_encoding(Encoding::Hex);
+//! let text = Blob.encode(kp.tls_serialize_detached()?);
+//! let bytes = Blob.decode(&text)?;
+//! ```
+
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD as Base64, URL_SAFE_NO_PAD as Base64Url},
+};
+use core::{error::Error, str::FromStr};
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Text encoding of wire blobs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    /// Standard base64 with padding.
+    #[default]
+    Base64,
+    /// URL-safe base64 without padding.
+    Base64Url,
+    /// Lowercase hex (uppercase is accepted when decoding).
+    Hex,
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(Self::Base64),
+            "base64url" => Ok(Self::Base64Url),
+            "hex" => Ok(Self::Hex),
+            _ => Err(format!("Unknown encoding: {s}")),
+        }
+    }
+}
+
+/// The agent-wide encoding, as the index of an `Encoding` variant.
+static ENCODING: AtomicU8 = AtomicU8::new(0);
+
+/// Set the agent-wide encoding of wire blobs.
+pub fn set_encoding(encoding: Encoding) {
+    ENCODING.store(encoding as u8, Ordering::Relaxed);
+}
+
+/// Returns the agent-wide encoding of wire blobs.
+pub fn encoding() -> Encoding {
+    match ENCODING.load(Ordering::Relaxed) {
+        1 => Encoding::Base64Url,
+        2 => Encoding::Hex,
+        _ => Encoding::Base64,
+    }
+}
+
+/// Encoder/decoder for wire blobs using the agent-wide encoding.
+#[derive(Clone, Copy, Debug)]
+pub struct Blob;
+
+impl Blob {
+    /// Encode bytes as text.
+    pub fn encode(&self, data: impl AsRef<[u8]>) -> String {
+        match encoding() {
+            Encoding::Base64 => Base64.encode(data),
+            Encoding::Base64Url => Base64Url.encode(data),
+            Encoding::Hex => hex_encode(data.as_ref()),
+        }
+    }
+    /// Decode text into bytes; surrounding whitespace is ignored.
+    pub fn decode(&self, data: impl AsRef<[u8]>) -> Result<Vec<u8>, Box<dyn Error>> {
+        let data = data.as_ref().trim_ascii();
+        match encoding() {
+            Encoding::Base64 => Ok(Base64.decode(data)?),
+            Encoding::Base64Url => Ok(Base64Url.decode(data)?),
+            Encoding::Hex => hex_decode(core::str::from_utf8(data)?),
+        }
+    }
+}
+
+/// Encode bytes as lowercase hex.
+pub fn hex_encode(data: &[u8]) -> String {
+    data.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Decode a hex string.
+pub fn hex_decode(s: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    if s.len() % 2 != 0 || !s.is_ascii() {
+        return Err("Invalid hex string".into());
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| Ok(u8::from_str_radix(&s[i..i + 2], 16)?))
+        .collect()
+}
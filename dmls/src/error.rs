@@ -0,0 +1,390 @@
+//! Error codes.
+//!
+//! Most failures are reported as boxed errors with a message. Those that users and scripts are
+//! likely to run into, and can do something about, are `DmlsError`s instead: each has a stable
+//! code (e.g. `DMLS-0007`) printed with its message, and `explain` (`dmls explain DMLS-0007`)
+//! describes what it means and how to remedy it. Codes are never renumbered or reused, so scripts
+//! can match on them.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let sg = send_group(&provider).map_err(|e| match e.downcast_ref::<DmlsError>() {
+//!     Some(DmlsError::NoSendGroup) => /* create one */,
+//!     _ => /* give up */,
+//! });
+//! println!("{}", explain("DMLS-0007").unwrap());
+//! ```
+
+use core::fmt::{Display, Formatter, Result as FmtResult};
+
+/// An error with a stable code (see `explain`).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DmlsError {
+    /// No local group has the requested group id.
+    GroupNotFound,
+    /// A pending commit was expected but there is none.
+    NoPendingCommit,
+    /// A new commit would discard a pending one that may already have been sent.
+    PendingCommit,
+    /// The agent already has a send group.
+    SendGroupExists,
+    /// None of the requested members are in the group.
+    NoMatchingMembers,
+    /// A message in another member's send group wasn't sent by its owner.
+    NotSentByOwner,
+    /// The agent has no send group yet.
+    NoSendGroup,
+    /// A Welcome was expected but the message is something else.
+    NotAWelcome,
+    /// No resumption PSK is retained for the requested epoch.
+    ResumptionPskNotFound,
+    /// A fetched key package belongs to another identity than requested.
+    KeyPackageMismatch,
+    /// The signing key's scheme differs from the ciphersuite's, both named.
+    SignatureSchemeMismatch {
+        /// Scheme of the signing key.
+        key: String,
+        /// Scheme of the ciphersuite, followed by the ciphersuite.
+        ciphersuite: String,
+    },
+    /// The exporter label isn't on the allow-list.
+    LabelNotAllowed(String),
+    /// Only admins may change the admin list.
+    NotAdmin,
+    /// The ciphersuite (named) or its signature scheme isn't accepted by the suite policy.
+    CiphersuiteNotAllowed(String),
+    /// A membership change exceeds a limit of the membership policy, described.
+    MembershipLimitExceeded(String),
+    /// A membership change adds a banned identity (base64).
+    BannedIdentity(String),
+    /// A key package or leaf node lifetime ended this many seconds ago.
+    LifetimeExpired(u64),
+    /// A key package or leaf node lifetime only starts in this many seconds.
+    LifetimeNotYetValid(u64),
+    /// The credential validator (see `validator`) rejected a credential, for this reason.
+    CredentialRejected(String),
+    /// The exporter PSK queue holds this many PSKs, its cap, and refuses more.
+    PskQueueFull(u64),
+    /// A Welcome is addressed to a key package (described) whose private part isn't stored.
+    KeyPackageMissing(String),
+    /// The group retains resumption PSKs of only this many epochs, fewer than dmls keeps.
+    ResumptionPskNotRetained(usize),
+    /// The integrity MAC of the named state doesn't match it.
+    IntegrityCheckFailed(String),
+    /// A file or directory holding secrets is accessible by others, described.
+    InsecurePermissions(String),
+}
+
+/// Code, name and explanation (with remedy) of every `DmlsError`.
+const EXPLANATIONS: &[(u16, &str, &str)] = &[
+    (
+        1,
+        "GroupNotFound",
+        "No group with the given group id is stored in the state. The id may be mistyped or use \
+         another encoding (see --encoding), or the group was never joined with this state.\n\n\
+         Remedy: list the stored groups with `group-stats` and check the id; join the group by \
+         processing its Welcome first.",
+    ),
+    (
+        2,
+        "NoPendingCommit",
+        "The command works on a staged commit (see `--stage`), but the send group has none: it \
+         was never staged, or was already confirmed or aborted.\n\n\
+         Remedy: check with `pending show`; stage a commit first.",
+    ),
+    (
+        3,
+        "PendingCommit",
+        "The send group has a staged commit that may already have been sent to members. \
+         Creating a new commit would discard it, and members who applied it could no longer be \
+         reached.\n\n\
+         Remedy: `confirm-commit` once it was delivered, `abort-commit` if it wasn't, or rerun \
+         with `use-state --force` to discard it anyway.",
+    ),
+    (
+        4,
+        "SendGroupExists",
+        "The agent already has a send group, and each agent owns exactly one.\n\n\
+         Remedy: move the send group to new members with `branch` or to a new ciphersuite with \
+         `reinit`, or use a separate state for another send group.",
+    ),
+    (
+        5,
+        "NoMatchingMembers",
+        "None of the requested identities are members of the group.\n\n\
+         Remedy: check the identities (base64 of the credential identity) with `tree`.",
+    ),
+    (
+        6,
+        "NotSentByOwner",
+        "A message in another member's send group was sent by someone other than the group's \
+         owner. In DMLS only the owner sends in their send group, so the message is rejected.\n\n\
+         Remedy: none needed; investigate the sender if it keeps happening.",
+    ),
+    (
+        7,
+        "NoSendGroup",
+        "The command works on the agent's send group, but none was created yet.\n\n\
+         Remedy: create it with `gen-send-group`, passing members' key packages on stdin.",
+    ),
+    (
+        8,
+        "NotAWelcome",
+        "A Welcome message was expected, but the input holds another kind of message.\n\n\
+         Remedy: check the input with `inspect-messages`.",
+    ),
+    (
+        9,
+        "ResumptionPskNotFound",
+        "No resumption PSK is retained for the requested epoch of the group; only the most \
+         recent epochs are kept.\n\n\
+         Remedy: list the retained epochs with `resumption-psks` and pick one of them.",
+    ),
+    (
+        10,
+        "KeyPackageMismatch",
+        "The key package fetched for an identity has a credential for another identity, so the \
+         directory served the wrong (or a forged) key package.\n\n\
+         Remedy: do not use it; check the key-package directory.",
+    ),
+    (
+        11,
+        "SignatureSchemeMismatch",
+        "The signing key's signature scheme differs from the one of the ciphersuite, so OpenMLS \
+         cannot sign with it in groups of that ciphersuite.\n\n\
+         Remedy: pass a matching `--ciphersuite`, or create a state with a matching \
+         `gen-state --signature-scheme`.",
+    ),
+    (
+        12,
+        "LabelNotAllowed",
+        "Secrets can only be exported with labels on the allow-list, so that applications don't \
+         accidentally derive the same secrets.\n\n\
+         Remedy: allow the label with `export-labels allow <label>`.",
+    ),
+    (
+        13,
+        "NotAdmin",
+        "Only admins of a group can grant or revoke the admin role.\n\n\
+         Remedy: ask an admin to make the change (list them with `admin list`).",
+    ),
+    (
+        14,
+        "CiphersuiteNotAllowed",
+        "The key package, Welcome or new group uses a ciphersuite, or a signature scheme, that the \
+         agent's suite policy doesn't accept, e.g. because it is weaker than policy permits. \
+         Accepting it could downgrade the security of the group.\n\n\
+         Remedy: ask the peer for a key package or group with an accepted ciphersuite; change the \
+         policy with `suite-policy` only if the suite is acceptable after all.",
+    ),
+    (
+        15,
+        "MembershipLimitExceeded",
+        "A membership change, own or in a received commit, would exceed the maximum group size or \
+         the maximum number of members added by one commit set in the agent's membership policy. \
+         Received commits exceeding the limits are rejected, so giant commits can't exhaust the \
+         agent's resources.\n\n\
+         Remedy: add fewer members per commit; raise the limits with `membership-policy` if the \
+         group legitimately needs them.",
+    ),
+    (
+        16,
+        "BannedIdentity",
+        "A membership change, own or in a received commit, adds an identity on the agent's ban \
+         list.\n\n\
+         Remedy: don't add the identity; lift the ban with `membership-policy --unban <identity>` \
+         if it was a mistake.",
+    ),
+    (
+        17,
+        "LifetimeExpired",
+        "A key package (or the key package a Welcome is addressed to) has expired: its lifetime \
+         ended longer ago than the clock skew tolerance. Its owner may have deleted the private \
+         keys, and accepting it weakens the guarantee that old key packages stop being used.\n\n\
+         Remedy: fetch a fresh key package from its owner; if only the clocks differ, raise the \
+         tolerance with `use-state --clock-skew <seconds>` (or skip the check with \
+         `--ignore-lifetime` for testing).",
+    ),
+    (
+        18,
+        "LifetimeNotYetValid",
+        "A key package (or the key package a Welcome is addressed to) has a lifetime that only \
+         starts in the future, beyond the clock skew tolerance, so one of the clocks is likely \
+         wrong.\n\n\
+         Remedy: check both clocks; raise the tolerance with `use-state --clock-skew <seconds>` \
+         if they legitimately differ.",
+    ),
+    (
+        19,
+        "CredentialRejected",
+        "The credential validator plugged in by the application rejected a credential in a key \
+         package, a Welcome or a received commit, e.g. because the identity provider doesn't \
+         vouch for it. A rejected Welcome isn't joined and a rejected commit isn't merged.\n\n\
+         Remedy: have the member obtain a credential the validator accepts, or ask the sender to \
+         remove the member.",
+    ),
+    (
+        20,
+        "PskQueueFull",
+        "A received commit would queue another exporter PSK, but the queue is at its cap and the \
+         overflow policy is `error`, so the commit isn't merged.\n\n\
+         Remedy: inject the queued PSKs into the send group (`commit`), then process the commit \
+         again; or switch to another overflow policy with `psk-queue-policy --overflow`.",
+    ),
+    (
+        21,
+        "KeyPackageMissing",
+        "A Welcome is addressed to a key package whose private part the state no longer holds, \
+         so the group secrets can't be decrypted. The state may have been restored from a backup \
+         older than the key package, the key package may have been consumed by an earlier \
+         Welcome, or it was generated with another state. The message names the key package \
+         and, if this state generated it, when.\n\n\
+         Remedy: regenerate and republish key packages (`gen-kp`, `publish-kp` or `kp-pool`), \
+         withdraw the stale ones from the directory, and ask the group's owner to add the agent \
+         again with a fresh key package.",
+    ),
+    (
+        22,
+        "ResumptionPskNotRetained",
+        "A resumption PSK of the group is needed (to `reinit` or `branch` it, or to join a group \
+         re-initialized or branched from it), but the group retains fewer resumption PSKs than \
+         dmls keeps for its groups, possibly none. Groups joined or created before dmls retained \
+         resumption PSKs keep the OpenMLS default, which stores no resumption PSKs.\n\n\
+         Remedy: create a new send group (or have the group's owner add the agent again) so the \
+         group is set up with the current retention.",
+    ),
+    (
+        23,
+        "IntegrityCheckFailed",
+        "The integrity MAC (`<state>.mac`, see --integrity-key) doesn't match the state file, its \
+         journal or its storage backend, so one of them was corrupted or tampered with since it \
+         was last saved, or a different integrity key was passed.\n\n\
+         Remedy: check that the right --integrity-key and --storage are passed; otherwise restore \
+         the state from a backup (`restore`) rather than using it.",
+    ),
+    (
+        24,
+        "InsecurePermissions",
+        "A state file or storage backend, which holds private keys, can be read or written by its \
+         group or others, and --strict-perms refuses to load it.\n\n\
+         Remedy: restrict it with the `chmod` the message names, and check that nobody else read \
+         or changed it.",
+    ),
+];
+
+impl DmlsError {
+    /// Returns the stable code of the error.
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::GroupNotFound => 1,
+            Self::NoPendingCommit => 2,
+            Self::PendingCommit => 3,
+            Self::SendGroupExists => 4,
+            Self::NoMatchingMembers => 5,
+            Self::NotSentByOwner => 6,
+            Self::NoSendGroup => 7,
+            Self::NotAWelcome => 8,
+            Self::ResumptionPskNotFound => 9,
+            Self::KeyPackageMismatch => 10,
+            Self::SignatureSchemeMismatch { .. } => 11,
+            Self::LabelNotAllowed(_) => 12,
+            Self::NotAdmin => 13,
+            Self::CiphersuiteNotAllowed(_) => 14,
+            Self::MembershipLimitExceeded(_) => 15,
+            Self::BannedIdentity(_) => 16,
+            Self::LifetimeExpired(_) => 17,
+            Self::LifetimeNotYetValid(_) => 18,
+            Self::CredentialRejected(_) => 19,
+            Self::PskQueueFull(_) => 20,
+            Self::KeyPackageMissing(_) => 21,
+            Self::ResumptionPskNotRetained(_) => 22,
+            Self::IntegrityCheckFailed(_) => 23,
+            Self::InsecurePermissions(_) => 24,
+        }
+    }
+}
+
+impl Display for DmlsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "{}: ", format_code(self.code()))?;
+        match self {
+            Self::GroupNotFound => write!(f, "No local group found with the given Group ID"),
+            Self::NoPendingCommit => write!(f, "No pending commit"),
+            Self::PendingCommit => write!(
+                f,
+                "Group has a pending commit that may already have been sent; confirm or abort it \
+                 first (or force discarding it)"
+            ),
+            Self::SendGroupExists => write!(f, "Send group already exists"),
+            Self::NoMatchingMembers => write!(f, "No matching members in group"),
+            Self::NotSentByOwner => write!(f, "Message not sent by the send group owner"),
+            Self::NoSendGroup => write!(f, "No send group exists"),
+            Self::NotAWelcome => write!(f, "Message is not a Welcome"),
+            Self::ResumptionPskNotFound => write!(f, "No resumption PSK found for the given epoch"),
+            Self::KeyPackageMismatch => write!(f, "Key package does not match requested identity"),
+            Self::SignatureSchemeMismatch { key, ciphersuite } => {
+                write!(f, "{key} key cannot be used with {ciphersuite}")
+            }
+            Self::LabelNotAllowed(label) => {
+                write!(f, "Label {label} is not on the export allow-list")
+            }
+            Self::NotAdmin => write!(f, "Only admins can grant or revoke the admin role"),
+            Self::CiphersuiteNotAllowed(ciphersuite) => {
+                write!(
+                    f,
+                    "Ciphersuite {ciphersuite} is not allowed by the suite policy"
+                )
+            }
+            Self::MembershipLimitExceeded(limit) => {
+                write!(f, "Membership policy limit exceeded: {limit}")
+            }
+            Self::BannedIdentity(identity) => write!(f, "Identity {identity} is banned"),
+            Self::LifetimeExpired(seconds) => write!(f, "Lifetime expired {seconds}s ago"),
+            Self::LifetimeNotYetValid(seconds) => write!(f, "Lifetime only starts in {seconds}s"),
+            Self::CredentialRejected(reason) => write!(f, "Credential rejected: {reason}"),
+            Self::PskQueueFull(max) => write!(f, "Exporter PSK queue is full ({max} PSKs)"),
+            Self::KeyPackageMissing(kp) => write!(
+                f,
+                "Welcome is addressed to key package {kp}, which is no longer stored; regenerate \
+                 and republish key packages"
+            ),
+            Self::ResumptionPskNotRetained(retained) => write!(
+                f,
+                "Group retains resumption PSKs of only {retained} epochs; it was set up before \
+                 dmls retained them"
+            ),
+            Self::IntegrityCheckFailed(state_path) => {
+                write!(f, "Integrity check of state {state_path} failed")
+            }
+            Self::InsecurePermissions(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl core::error::Error for DmlsError {}
+
+/// Format an error code, e.g. `DMLS-0007`.
+pub fn format_code(code: u16) -> String {
+    format!("DMLS-{code:04}")
+}
+
+/// Returns the name and explanation of an error code (`DMLS-0007`, `dmls-7` or just `7`), if known.
+///
+/// Example:
+///
+/// ```ignore
+/// if let Some(explanation) = explain("DMLS-0007") { println!("{explanation}"); }
+/// ```
+pub fn explain(code: &str) -> Option<String> {
+    let code = code.trim();
+    let number = code
+        .get(..5)
+        .filter(|prefix| prefix.eq_ignore_ascii_case("DMLS-"))
+        .map_or(code, |_| &code[5..]);
+    let number: u16 = number.parse().ok()?;
+    EXPLANATIONS
+        .iter()
+        .find(|(code, _, _)| *code == number)
+        .map(|(code, name, text)| format!("{} {name}\n\n{text}", format_code(*code)))
+}
@@ -0,0 +1,108 @@
+//! Watch mode for `Process`.
+//!
+//! This module follows a file or a directory and hands newly arriving messages to a callback in
+//! batches, so the agent can run continuously without a surrounding shell loop:
+//!
+//! - a file is tailed like `tail -f`: lines appended after following started are delivered as
+//!   base64 messages (a truncated file is read again from the start)
+//! - a directory is treated as a maildir-style inbox (see `maildir`): message files already in
+//!   `new/` and files arriving there later are delivered, and claimed (archived) once the
+//!   callback returns, which is expected to save the state first
+//!
+//! Changes are detected with the `notify` crate; every burst of change notifications results in
+//! one batch. The callback can stop following by returning `ControlFlow::Break`.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! follow("./alice-in", |batch| {
+//!     for msg in batch { /* process */ }
+//!     // checkpoint state
+//!     ControlFlow::Continue(())
+//! })?;
+//! ```
+
+use super::maildir::Maildir;
+use core::{error::Error, ops::ControlFlow};
+use notify::{RecursiveMode, Watcher, recommended_watcher};
+use std::{
+    fs::{File, create_dir_all},
+    io::{BufRead, BufReader, Seek, SeekFrom},
+    path::Path,
+    sync::mpsc::channel,
+};
+
+/// Follow the file or directory at `path` and call `on_batch` with every batch of new messages.
+///
+/// Messages are the raw file content (directories) or one line each (files). Blocks until
+/// watching fails or `on_batch` breaks; message files of a batch that breaks are left unclaimed,
+/// to be read again next time.
+///
+/// Example:
+///
+/// ```ignore
+/// follow(&path, |batch| handle(&mut provider, batch))?;
+/// ```
+pub fn follow(
+    path: &str,
+    mut on_batch: impl FnMut(Vec<Vec<u8>>) -> ControlFlow<()>,
+) -> Result<(), Box<dyn Error>> {
+    let (tx, rx) = channel();
+    let mut watcher = recommended_watcher(tx)?;
+    if Path::new(path).is_dir() {
+        let inbox = Maildir::new(path);
+        let new = Path::new(path).join("new");
+        create_dir_all(&new)?;
+        watcher.watch(&new, RecursiveMode::NonRecursive)?;
+        tracing::info!("Following directory {path}");
+        // messages that arrived before we started watching
+        let mut batch = inbox.read_messages()?;
+        loop {
+            if !batch.is_empty() {
+                let names = batch
+                    .iter()
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>();
+                if on_batch(batch.into_iter().map(|(_, message)| message).collect()).is_break() {
+                    return Ok(());
+                }
+                inbox.claim(&names)?;
+            }
+            // wait for a change, then drain the burst of notifications
+            rx.recv()??;
+            for event in rx.try_iter() {
+                event?;
+            }
+            batch = inbox.read_messages()?;
+        }
+    } else {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut offset = reader.seek(SeekFrom::End(0))?;
+        watcher.watch(Path::new(path), RecursiveMode::NonRecursive)?;
+        tracing::info!("Following file {path}");
+        loop {
+            rx.recv()??;
+            for event in rx.try_iter() {
+                event?;
+            }
+            if reader.get_ref().metadata()?.len() < offset {
+                tracing::warn!("{path} was truncated; reading from start");
+                offset = reader.seek(SeekFrom::Start(0))?;
+            }
+            let mut batch = Vec::new();
+            let mut line = String::new();
+            // only consume complete lines; a partial line is read again on the next change
+            while reader.read_line(&mut line)? > 0 && line.ends_with('\n') {
+                offset += line.len() as u64;
+                if !line.trim().is_empty() {
+                    batch.push(line.trim().as_bytes().to_vec());
+                }
+                line.clear();
+            }
+            offset = reader.seek(SeekFrom::Start(offset))?;
+            if !batch.is_empty() && on_batch(batch).is_break() {
+                return Ok(());
+            }
+        }
+    }
+}
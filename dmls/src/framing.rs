@@ -0,0 +1,115 @@
+//! Framing of blobs on stdin/stdout.
+//!
+//! By default (`lines`), every MLS message on stdin/stdout is a line of encoded text (see
+//! `encoding`). For high-throughput pipelines, `raw-len32` frames every message as its raw
+//! TLS encoding preceded by its length as a big-endian `u32`, so pipelines never see (or pay for
+//! parsing) a text encoding. `armor` writes artifacts as armored blocks (see `armor`) for pasting
+//! into email or chat; armored blocks are accepted on input with `lines` and `armor` alike.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! write_frame(&mut stdout().lock(), &msg.tls_serialize_detached()?)?;
+//! for frame in read_frames(stdin().lock()) {
+//!     let body = bytes_extract(&frame?)?;
+//! }
+//! ```
+
+use core::str::FromStr;
+use std::io::{ErrorKind, Read, Result, Write};
+
+/// How blobs are framed on stdin/stdout.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Framing {
+    /// One encoded blob per line.
+    #[default]
+    Lines,
+    /// Raw blobs, each preceded by its length as a big-endian `u32`.
+    RawLen32,
+    /// Like `Lines`, but artifacts are written armored (see `armor`).
+    Armor,
+}
+
+impl FromStr for Framing {
+    type Err = String;
+    fn from_str(s: &str) -> core::result::Result<Self, Self::Err> {
+        match s {
+            "lines" => Ok(Self::Lines),
+            "raw-len32" => Ok(Self::RawLen32),
+            "armor" => Ok(Self::Armor),
+            _ => Err(format!("Unknown framing: {s}")),
+        }
+    }
+}
+
+/// Iterator over length-prefixed frames (see `read_frames`).
+#[derive(Debug)]
+pub struct Frames<R> {
+    /// The underlying reader.
+    reader: R,
+    /// Set after the end of the stream or an error.
+    done: bool,
+}
+
+impl<R: Read> Frames<R> {
+    /// Read the next frame, or `None` at a clean end of the stream.
+    fn read_frame(&mut self) -> Result<Option<Vec<u8>>> {
+        let mut len = [0u8; 4];
+        // end of stream is only clean before the first byte of a length prefix
+        loop {
+            match self.reader.read(&mut len[..1]) {
+                Ok(0) => return Ok(None),
+                Ok(_) => break,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        self.reader.read_exact(&mut len[1..])?;
+        let mut frame = vec![0; u32::from_be_bytes(len) as usize];
+        self.reader.read_exact(&mut frame)?;
+        Ok(Some(frame))
+    }
+}
+
+impl<R: Read> Iterator for Frames<R> {
+    type Item = Result<Vec<u8>>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let frame = self.read_frame().transpose();
+        self.done = !matches!(frame, Some(Ok(_)));
+        frame
+    }
+}
+
+/// Iterate over the length-prefixed frames read from `reader`.
+///
+/// A truncated frame yields an error and ends the iteration.
+///
+/// Example:
+///
+/// ```ignore
+/// for frame in read_frames(stdin().lock()) { /* ... */ }
+/// ```
+pub fn read_frames<R: Read>(reader: R) -> Frames<R> {
+    Frames {
+        reader,
+        done: false,
+    }
+}
+
+/// Write `data` as a length-prefixed frame and flush.
+///
+/// Example:
+///
+/// ```ignore
+/// write_frame(&mut stdout().lock(), &bytes)?;
+/// ```
+pub fn write_frame(writer: &mut impl Write, data: &[u8]) -> Result<()> {
+    let len = u32::try_from(data.len())
+        .map_err(|_| std::io::Error::new(ErrorKind::InvalidInput, "Frame too large"))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(data)?;
+    writer.flush()
+}
@@ -0,0 +1,291 @@
+//! gRPC API for agent operations (enabled with the `grpc` feature).
+//!
+//! This module exposes the agent as the `dmls.Dmls` gRPC service defined in `proto/dmls.proto`,
+//! so non-Rust backends can drive DMLS without shelling out to the CLI. Every RPC names a state
+//! profile; the profile `alice` is the state file `alice.json` in the configured state directory.
+//! Each call loads the profile like the CLI does (checking its permissions and integrity MAC and
+//! replaying its journal, see `helpers::load_state`), runs the operation and saves the profile
+//! again if it succeeded, in full or, with `journal`, by appending the changes to its journal.
+//! With the `mlock` feature, the profile's secrets are locked into memory while the call runs and
+//! zeroed and unlocked once it is done.
+//! Calls are serialized, so concurrent RPCs never race on a state file; they wait for their turn
+//! without blocking the runtime, and the file IO and MLS operations run on a blocking thread.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! run("127.0.0.1:50051", "./states", ciphersuite, 32, false)?;
+//! // from a client:
+//! client.generate_key_package(ProfileRequest { profile: "alice".into() }).await?;
+//! ```
+
+#[cfg(feature = "mlock")]
+use super::mlock::{lock_secrets, unlock_secrets};
+use super::{
+    encoding::Blob,
+    helpers::{
+        GroupConfigOptions, force_add_members_base64, gen_recorded_kp_base64, gen_send_group,
+        group_base64, load_state, persist_state, process_body_main, send_group_inject_psks_base64,
+        stdin_base64_extract, stdin_base64_to_kp, stdin_create_message_base64,
+    },
+    provider::DmlsProvider,
+    state::{DmlsState, ExporterParams, OutboxKind},
+};
+use core::error::Error;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::Ciphersuite;
+use proto::{
+    AddMembersRequest, ArtifactsReply, CreateGroupReply, EncryptRequest, ProcessMessageReply,
+    ProcessMessageRequest, ProfileRequest,
+    dmls_server::{Dmls, DmlsServer},
+};
+use std::path::PathBuf;
+use tokio::{sync::Mutex, task::block_in_place};
+use tonic::{Request, Response, Status, transport::Server};
+
+/// Types generated from `proto/dmls.proto`.
+#[allow(missing_docs)]
+pub mod proto {
+    tonic::include_proto!("dmls");
+}
+
+/// gRPC service operating on state profiles in a directory.
+#[derive(Debug)]
+pub struct DmlsService {
+    /// Directory holding `<profile>.json` state files.
+    state_dir: PathBuf,
+    /// Ciphersuite to use.
+    ciphersuite: Ciphersuite,
+    /// Exporter length to use for DMLS exporter PSK.
+    exporter_length: usize,
+    /// Whether profiles are saved by appending their changes to their journals.
+    journal: bool,
+    /// Serializes access to the state files.
+    lock: Mutex<()>,
+}
+
+impl DmlsService {
+    /// Creates a service for the profiles in `state_dir`.
+    pub fn new(
+        state_dir: &str,
+        ciphersuite: Ciphersuite,
+        exporter_length: usize,
+        journal: bool,
+    ) -> Self {
+        Self {
+            state_dir: PathBuf::from(state_dir),
+            ciphersuite,
+            exporter_length,
+            journal,
+            lock: Mutex::new(()),
+        }
+    }
+}
+
+impl DmlsService {
+    /// Load the named profile, run `f` on its provider and save the profile if `f` succeeded.
+    ///
+    /// Profile names are restricted to ASCII alphanumerics, `-` and `_`.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let kp = self.with_profile("alice", |provider| gen_recorded_kp_base64(provider, cs)).await?;
+    /// ```
+    async fn with_profile<T>(
+        &self,
+        profile: &str,
+        f: impl FnOnce(&mut DmlsProvider) -> Result<T, Box<dyn Error>>,
+    ) -> Result<T, Status> {
+        if profile.is_empty()
+            || !profile
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(Status::invalid_argument(format!(
+                "Invalid profile name: {profile}"
+            )));
+        }
+        let path = self.state_dir.join(format!("{profile}.json"));
+        let _guard = self.lock.lock().await;
+        block_in_place(|| {
+            if !path.exists() {
+                return Err(Status::not_found(format!("No profile {profile}")));
+            }
+            let state_path = path.to_string_lossy();
+            let (state, format) = load_state(&state_path)
+                .map_err(|e| Status::internal(format!("Error loading profile {profile}: {e}")))?;
+            #[cfg(feature = "mlock")]
+            lock_secrets(&state);
+            let mut provider = DmlsProvider::new(state, RustCrypto::default());
+            let result = f(&mut provider).map_err(|e| Status::failed_precondition(e.to_string()));
+            let state: DmlsState = provider.into();
+            let result = result.and_then(|result| {
+                persist_state(&state_path, &state, format, self.journal, None).map_err(|e| {
+                    Status::internal(format!("Error saving profile {profile}: {e}"))
+                })?;
+                Ok(result)
+            });
+            // the next request locks the profile's secrets again
+            #[cfg(feature = "mlock")]
+            unlock_secrets(state);
+            result
+        })
+    }
+}
+
+#[tonic::async_trait]
+impl Dmls for DmlsService {
+    async fn generate_key_package(
+        &self,
+        request: Request<ProfileRequest>,
+    ) -> Result<Response<ArtifactsReply>, Status> {
+        let kp = self
+            .with_profile(&request.get_ref().profile, |provider| {
+                gen_recorded_kp_base64(provider, self.ciphersuite)
+            })
+            .await?;
+        Ok(Response::new(ArtifactsReply {
+            artifacts: vec![kp],
+        }))
+    }
+
+    async fn create_group(
+        &self,
+        request: Request<ProfileRequest>,
+    ) -> Result<Response<CreateGroupReply>, Status> {
+        let group_id = self
+            .with_profile(&request.get_ref().profile, |provider| {
+                Ok(Blob.encode(
+                    gen_send_group(
+                        provider,
+                        self.ciphersuite,
+                        &GroupConfigOptions {
+                            exporter: ExporterParams::new(self.exporter_length),
+                            ..Default::default()
+                        },
+                    )?
+                    .group_id()
+                    .as_slice(),
+                ))
+            })
+            .await?;
+        Ok(Response::new(CreateGroupReply { group_id }))
+    }
+
+    async fn add_members(
+        &self,
+        request: Request<AddMembersRequest>,
+    ) -> Result<Response<ArtifactsReply>, Status> {
+        let request = request.into_inner();
+        let welcome = self
+            .with_profile(&request.profile, |provider| {
+                let kps = request
+                    .key_packages
+                    .into_iter()
+                    .map(|kp| stdin_base64_to_kp(provider, Ok(kp)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let mut sg = group_base64(provider, None)?;
+                let welcome =
+                    force_add_members_base64(provider, &mut sg, &kps, self.exporter_length)?;
+                provider
+                    .state_mut()
+                    .push_outbox(OutboxKind::Welcome, welcome.clone());
+                Ok(welcome)
+            })
+            .await?;
+        Ok(Response::new(ArtifactsReply {
+            artifacts: vec![welcome],
+        }))
+    }
+
+    async fn encrypt(
+        &self,
+        request: Request<EncryptRequest>,
+    ) -> Result<Response<ArtifactsReply>, Status> {
+        let request = request.into_inner();
+        let artifacts = self
+            .with_profile(&request.profile, |provider| {
+                let mut g = group_base64(provider, request.group_id.as_deref())?;
+                let mut msgs = Vec::new();
+                for pt in request.plaintexts {
+                    let msg = stdin_create_message_base64(provider, &mut g, Ok(pt))?;
+                    provider
+                        .state_mut()
+                        .push_outbox(OutboxKind::Message, msg.clone());
+                    msgs.push(msg);
+                }
+                Ok(msgs)
+            })
+            .await?;
+        Ok(Response::new(ArtifactsReply { artifacts }))
+    }
+
+    async fn process_message(
+        &self,
+        request: Request<ProcessMessageRequest>,
+    ) -> Result<Response<ProcessMessageReply>, Status> {
+        let request = request.into_inner();
+        let plaintexts = self
+            .with_profile(&request.profile, |provider| {
+                let mut pts = Vec::new();
+                for msg in request.messages {
+                    let body = stdin_base64_extract(Ok(msg))?;
+                    pts.extend(
+                        process_body_main(provider, body, self.ciphersuite, self.exporter_length)
+                            .map(|message| message.plaintext),
+                    );
+                }
+                Ok(pts)
+            })
+            .await?;
+        Ok(Response::new(ProcessMessageReply { plaintexts }))
+    }
+
+    async fn inject_psks(
+        &self,
+        request: Request<ProfileRequest>,
+    ) -> Result<Response<ArtifactsReply>, Status> {
+        let commit = self
+            .with_profile(&request.get_ref().profile, |provider| {
+                let commit = send_group_inject_psks_base64(
+                    provider,
+                    self.ciphersuite,
+                    self.exporter_length,
+                )?;
+                provider
+                    .state_mut()
+                    .push_outbox(OutboxKind::Commit, commit.clone());
+                Ok(commit)
+            })
+            .await?;
+        Ok(Response::new(ArtifactsReply {
+            artifacts: vec![commit],
+        }))
+    }
+}
+
+/// Serve the gRPC API on `listen` until the process is terminated.
+///
+/// Example:
+///
+/// ```ignore
+/// run("127.0.0.1:50051", "./states", ciphersuite, 32, false)?;
+/// ```
+pub fn run(
+    listen: &str,
+    state_dir: &str,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+    journal: bool,
+) -> Result<(), Box<dyn Error>> {
+    let addr = listen.parse()?;
+    let service = DmlsService::new(state_dir, ciphersuite, exporter_length, journal);
+    tracing::warn!("Serving gRPC on {listen}");
+    tokio::runtime::Runtime::new()?.block_on(
+        Server::builder()
+            .add_service(DmlsServer::new(service))
+            .serve(addr),
+    )?;
+    Ok(())
+}
@@ -19,32 +19,81 @@
 //! println!("{}", kp_b64);
 //!
 //! // create send group from validated key packages provided via stdin
-//! let sg = gen_send_group(&mut provider, ciphersuite)?;
-//! let welcome_b64 = force_add_members_base64(&provider, &mut sg, &kps)?;
+//! let sg = gen_send_group(&mut provider, ciphersuite, &GroupConfigOptions::default())?;
+//! let welcome_b64 = force_add_members_base64(&mut provider, &mut sg, &kps, 32)?;
 //! println!("{}", welcome_b64);
 //! ```
 
-use super::provider::DmlsProvider;
-use base64::{Engine, engine::general_purpose::STANDARD as Base64};
-use core::error::Error;
+use super::{
+    armor::{armor, armor_label, dearmor},
+    audit::{AuditEvent, append as append_audit_entries, commit_events},
+    byte_store::{ByteStore, attach, write_entries},
+    continuity::{CONTINUITY_EXTENSION_TYPE, check_commit as check_continuity, verify_proof},
+    encoding::Blob,
+    envelope::{Envelope, is_envelope, verify as verify_envelope},
+    error::DmlsError,
+    integrity::{check as check_integrity, seal as seal_integrity},
+    journal::{append as append_to_journal, journal_path, replay as replay_journal},
+    kp_pool::mark_consumed,
+    openmls_keys::{SignatureKeyPair, SignaturePublicKey, labeled_payload, signature_scheme_name},
+    openmls_kvstore::{
+        EPOCH_KEY_PAIRS_LABEL, GROUP_CONTEXT_LABEL, KEY_PACKAGE_LABEL, PSK_LABEL, decode_changes,
+    },
+    perms::{check as check_perms, write_private},
+    provider::DmlsProvider,
+    psk_id::ExporterPskId,
+    roles::{ADMINS_EXTENSION_TYPE, authorize_commit},
+    state::{CapabilitiesOptions, DmlsState, ExporterParams, OutboxKind, StateFormat},
+    tree::TreeStats,
+    validator::{CredentialSource, validate as validate_credential, validate_leaf_node},
+};
+use core::{error::Error, mem::take, str::FromStr};
 use openmls::{
-    credentials::{BasicCredential, CredentialWithKey},
+    ciphersuite::hash_ref::ProposalRef,
+    credentials::{BasicCredential, CredentialType, CredentialWithKey},
+    extensions::{Extension, ExtensionType, Extensions, RequiredCapabilitiesExtension},
     framing::{
-        ApplicationMessage, MlsMessageBodyIn, MlsMessageIn, MlsMessageOut, ProcessedMessage,
-        ProtocolMessage, Sender,
+        ApplicationMessage, ContentType, MlsMessageBodyIn, MlsMessageIn, MlsMessageOut,
+        ProcessedMessage, ProcessedMessageContent, ProtocolMessage, Sender,
+    },
+    group::{
+        GroupEpoch, GroupId, MIXED_CIPHERTEXT_WIRE_FORMAT_POLICY,
+        MIXED_PLAINTEXT_WIRE_FORMAT_POLICY, MlsGroup, MlsGroupCreateConfig, MlsGroupJoinConfig,
+        ProcessedWelcome, QueuedProposal, StagedCommit, WireFormatPolicy,
     },
-    group::{MlsGroup, MlsGroupCreateConfig, MlsGroupJoinConfig, StagedCommit, StagedWelcome},
     key_packages::{KeyPackage, key_package_in::KeyPackageIn},
     messages::{
         Welcome,
-        proposals::{PreSharedKeyProposal, Proposal},
+        group_info::{GroupInfo, VerifiableGroupInfo},
+        proposals::{PreSharedKeyProposal, Proposal, ReInitProposal},
     },
-    schedule::{ExternalPsk, PreSharedKeyId, Psk},
-    treesync::LeafNodeParameters,
+    prelude::{Capabilities, Lifetime, SenderRatchetConfiguration},
+    schedule::{ExternalPsk, PreSharedKeyId, Psk, ResumptionPsk, ResumptionPskUsage},
+    treesync::{LeafNodeParameters, RatchetTreeIn},
     versions::ProtocolVersion,
 };
-use openmls_traits::{OpenMlsProvider, types::Ciphersuite};
-use tls_codec::{Deserialize, Serialize};
+use openmls_traits::{
+    OpenMlsProvider,
+    crypto::OpenMlsCrypto,
+    storage::StorageProvider,
+    types::{Ciphersuite, HashType, SignatureScheme},
+};
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{metadata, read as read_file, remove_file},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tls_codec::{Deserialize, Serialize, TlsSerialize, TlsSize, VLBytes};
+
+/// Wire format of Welcome messages (RFC 9420, section 6).
+const WELCOME_WIRE_FORMAT: u16 = 3;
+/// Number of past epochs whose resumption PSKs are retained per group.
+pub const RESUMPTION_PSK_RETENTION: usize = 32;
+/// PSK id prefix for resumption PSKs bridged into a re-initialized group.
+pub const REINIT_PSK_PREFIX: &[u8] = b"reinit";
+/// PSK id prefix for resumption PSKs bridged into a branched group.
+pub const BRANCH_PSK_PREFIX: &[u8] = b"branch";
 
 /// Inject queued exporter PSK proposals into the current send-group and return the
 /// resulting commit as a base64-encoded MLS message blob.
@@ -56,15 +105,61 @@ use tls_codec::{Deserialize, Serialize};
 /// Example:
 ///
 /// ```ignore
-/// let commit_b64 = send_group_inject_psks_base64(&mut provider, ciphersuite)?;
+/// let commit_b64 = send_group_inject_psks_base64(&mut provider, ciphersuite, 32)?;
 /// println!("{}", commit_b64);
 /// ```
 pub fn send_group_inject_psks_base64(
     provider: &mut DmlsProvider,
     ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    inject_psks_base64(provider, &mut sg, ciphersuite, exporter_length)
+}
+
+/// Stage a commit injecting queued PSKs into the send-group and return it as base64.
+///
+/// The commit stays pending until `send_group_confirm_commit` or `send_group_abort_commit`.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = send_group_stage_inject_psks_base64(&mut provider, ciphersuite)?;
+/// ```
+pub fn send_group_stage_inject_psks_base64(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
 ) -> Result<String, Box<dyn Error>> {
     let mut sg = send_group(provider)?;
-    inject_psks_base64(provider, &mut sg, ciphersuite)
+    Ok(Blob.encode(stage_inject_psks(provider, &mut sg, ciphersuite)?.tls_serialize_detached()?))
+}
+
+/// Confirm (merge) the send-group's pending commit after delivery.
+///
+/// Example:
+///
+/// ```ignore
+/// send_group_confirm_commit(&mut provider, ciphersuite, 32)?;
+/// ```
+pub fn send_group_confirm_commit(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<(), Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    confirm_commit(provider, &mut sg, ciphersuite, exporter_length)
+}
+
+/// Abort (discard) the send-group's pending commit.
+///
+/// Example:
+///
+/// ```ignore
+/// send_group_abort_commit(&provider)?;
+/// ```
+pub fn send_group_abort_commit(provider: &DmlsProvider) -> Result<(), Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    abort_commit(provider, &mut sg)
 }
 
 /// Inject queued PSKs into the provided group and return the serialized commit (base64).
@@ -77,42 +172,93 @@ pub fn send_group_inject_psks_base64(
 ///
 /// ```ignore
 /// let mut group = send_group(&provider)?;
-/// let commit_b64 = inject_psks_base64(&mut provider, &mut group, ciphersuite)?;
+/// let commit_b64 = inject_psks_base64(&mut provider, &mut group, ciphersuite, 32)?;
 /// println!("{}", commit_b64);
 /// ```
 pub fn inject_psks_base64(
     provider: &mut DmlsProvider,
     group: &mut MlsGroup,
     ciphersuite: Ciphersuite,
+    exporter_length: usize,
 ) -> Result<String, Box<dyn Error>> {
-    Ok(Base64.encode(inject_psks(provider, group, ciphersuite)?.tls_serialize_detached()?))
+    Ok(Blob.encode(
+        inject_psks(provider, group, ciphersuite, exporter_length)?.tls_serialize_detached()?,
+    ))
 }
 
 /// Inject queued PSKs into the provided group and return the staged commit message.
 ///
 /// This returns an `MlsMessageOut` which can be serialized and sent on the wire. The
-/// commit will be merged into the `group` state before returning.
+/// commit will be merged into the `group` state before returning (see `confirm_commit`), so the
+/// injected PSK ids are removed from the queue and the new epoch's exporter PSK is stored.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let commit = inject_psks(&mut provider, &mut group, ciphersuite)?;
+/// let commit = inject_psks(&mut provider, &mut group, ciphersuite, 32)?;
 /// let commit_bytes = commit.tls_serialize_detached()?;
 /// ```
 pub fn inject_psks(
     provider: &mut DmlsProvider,
     group: &mut MlsGroup,
     ciphersuite: Ciphersuite,
+    exporter_length: usize,
 ) -> Result<MlsMessageOut, Box<dyn Error>> {
-    group.clear_pending_commit(provider.storage())?;
+    let commit = stage_inject_psks(provider, group, ciphersuite)?;
+    confirm_commit(provider, group, ciphersuite, exporter_length)?;
+    Ok(commit)
+}
+
+/// Stage a commit injecting all queued (exporter, external and resumption) PSKs into the group
+/// without merging it.
+///
+/// The commit is left pending in the group (and in storage) until `confirm_commit` or
+/// `abort_commit` is called; the PSK queue is left untouched until the commit is confirmed.
+/// Exporter PSKs the bridging rules (see `DmlsState::bridges`) keep out of the group stay queued.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = stage_inject_psks(&mut provider, &mut group, ciphersuite)?;
+/// // ... deliver commit ...
+/// confirm_commit(&mut provider, &mut group, ciphersuite, 32)?;
+/// ```
+pub fn stage_inject_psks(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    ciphersuite: Ciphersuite,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    discard_pending_commit(provider, group)?;
     group.clear_pending_proposals(provider.storage())?;
     let mut commit_builder = group.commit_builder();
-    for psk_id_vec in provider.state_mut().clear_exporter_psk_ids().into_iter() {
+    let state = provider.state();
+    for psk_id_vec in state
+        .exporter_psk_ids()
+        .iter()
+        .filter(|psk_id| {
+            ExporterPskId::parse(psk_id)
+                .is_none_or(|source| state.bridges(&source.group_id, group.group_id().as_slice()))
+        })
+        .chain(state.external_psk_ids())
+    {
         let proposal =
             Proposal::PreSharedKey(Box::new(PreSharedKeyProposal::new(PreSharedKeyId::new(
                 ciphersuite,
                 provider.rand(),
-                Psk::External(ExternalPsk::new(psk_id_vec)),
+                Psk::External(ExternalPsk::new(psk_id_vec.clone())),
+            )?)));
+        commit_builder = commit_builder.add_proposal(proposal);
+    }
+    for &epoch in state.resumption_psk_epochs() {
+        let proposal =
+            Proposal::PreSharedKey(Box::new(PreSharedKeyProposal::new(PreSharedKeyId::new(
+                ciphersuite,
+                provider.rand(),
+                Psk::Resumption(ResumptionPsk::new(
+                    ResumptionPskUsage::Application,
+                    group.group_id().clone(),
+                    GroupEpoch::from(epoch),
+                )),
             )?)));
         commit_builder = commit_builder.add_proposal(proposal);
     }
@@ -121,448 +267,3768 @@ pub fn inject_psks(
         .build(provider.rand(), provider.crypto(), provider, |_| true)?
         .stage_commit(provider)?
         .into_messages();
-    group.merge_pending_commit(provider)?;
     Ok(commit)
 }
 
-/// Derive an exporter PSK from the group's exporter and store it in the local PSK store.
-///
-/// Returns the PSK identifier (a byte vector) for later injection. The PSK id is constructed
-/// from the group's epoch and group id. The derived PSK secret is stored using the OpenMLS
-/// PSK storage API so it can be looked up by other operations.
+/// Return the external PSK ids injected by the group's pending commit.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let psk_id = store_exporter_psk(&mut provider, &group, ciphersuite, 32)?;
-/// // psk_id can be serialized and saved with state if desired
+/// let psk_ids = pending_commit_psk_ids(&group)?;
 /// ```
-pub fn store_exporter_psk(
-    provider: &mut DmlsProvider,
-    group: &MlsGroup,
-    ciphersuite: Ciphersuite,
-    exporter_length: usize,
-) -> Result<Vec<u8>, Box<dyn Error>> {
-    // psk id = epoch + group id
-    let mut psk_id_vec = Vec::from(group.epoch().as_u64().to_be_bytes());
-    psk_id_vec.extend(group.group_id().to_vec());
-    // psk secret
-    let psk_secret = group.export_secret(
-        provider.crypto(),
-        "exporter_psk",
-        &psk_id_vec,
-        exporter_length,
-    )?;
-    // store psk
-    PreSharedKeyId::new(
-        ciphersuite,
-        provider.rand(),
-        Psk::External(ExternalPsk::new(psk_id_vec.clone())),
-    )?
-    .store(provider, &psk_secret)?;
-    // done; return psk id
-    Ok(psk_id_vec)
+pub fn pending_commit_psk_ids(group: &MlsGroup) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    match group.pending_commit() {
+        None => Err(DmlsError::NoPendingCommit.into()),
+        Some(staged_commit) => Ok(staged_commit
+            .psk_proposals()
+            .filter_map(|p| {
+                let psk_id = p.psk_proposal().clone().into_psk_id();
+                match psk_id.psk() {
+                    Psk::External(external_psk) => Some(external_psk.psk_id().to_vec()),
+                    _ => None,
+                }
+            })
+            .collect()),
+    }
 }
 
-/// Convenience wrapper to deserialize a base64-encoded MLS message from an input line
-/// and extract the `MlsMessageBodyIn` variant (Welcome, PublicMessage, PrivateMessage).
+/// Describe the group's pending commit, one line per item: the epoch it leads to, whether it
+/// carries a path update, and every member it adds or removes and external or resumption PSK it
+/// injects.
 ///
 /// Example:
 ///
 /// ```ignore
-/// for line in stdin.lock().lines() {
-///     let body = stdin_base64_extract(line)?;
-///     match body { ... }
+/// for line in describe_pending_commit(&group)? {
+///     println!("{line}");
 /// }
 /// ```
-pub fn stdin_base64_extract(
-    s: std::io::Result<String>,
-) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
-    Ok(stdin_base64_to_mls_msg_in(s)?.extract())
+pub fn describe_pending_commit(group: &MlsGroup) -> Result<Vec<String>, Box<dyn Error>> {
+    let Some(staged_commit) = group.pending_commit() else {
+        return Err(DmlsError::NoPendingCommit.into());
+    };
+    let mut lines = vec![
+        format!(
+            "epoch {} -> {}",
+            group.epoch().as_u64(),
+            group.epoch().as_u64() + 1
+        ),
+        format!(
+            "path update: {}",
+            staged_commit.update_path_leaf_node().is_some()
+        ),
+    ];
+    for event in commit_events(group, staged_commit) {
+        let (kind, items) = match event {
+            AuditEvent::CommitApplied => continue,
+            AuditEvent::MembersAdded { identities } => ("add", identities),
+            AuditEvent::MembersRemoved { identities } => ("remove", identities),
+            AuditEvent::PsksInjected { psk_ids } => ("psk", psk_ids),
+        };
+        lines.extend(
+            items
+                .iter()
+                .map(|item| format!("{kind} {}", Blob.encode(item))),
+        );
+    }
+    for proposal in staged_commit.psk_proposals() {
+        if let Psk::Resumption(resumption_psk) = proposal.psk_proposal().clone().into_psk_id().psk()
+        {
+            lines.push(format!(
+                "resumption psk epoch {}",
+                resumption_psk.psk_epoch().as_u64()
+            ));
+        }
+    }
+    Ok(lines)
 }
 
-/// Process a Welcome message and return the joined `MlsGroup` instance.
-///
-/// A Welcome is produced by a group creator when adding members. This helper creates a
-/// `StagedWelcome` and then converts it into an `MlsGroup` (performing necessary validations).
+/// Stage a commit with `stage`, describe it (see `describe_pending_commit`) and roll the state
+/// back, so nothing is merged or stored. Returns the description and the commit (base64).
 ///
 /// Example:
 ///
 /// ```ignore
-/// let welcome = ...; // Welcome parsed from base64
-/// let group = process_welcome(&provider, welcome)?;
+/// let (summary, commit_b64) = preview_commit(&mut provider, |p| send_group_stage_update_base64(p, false))?;
 /// ```
-pub fn process_welcome(
-    provider: &DmlsProvider,
-    welcome: Welcome,
-) -> Result<MlsGroup, Box<dyn Error>> {
-    Ok(StagedWelcome::new_from_welcome(
-        provider,
-        &MlsGroupJoinConfig::builder().build(),
-        welcome,
-        None,
-    )?
-    .into_group(provider)?)
+pub fn preview_commit(
+    provider: &mut DmlsProvider,
+    stage: impl FnOnce(&mut DmlsProvider) -> Result<String, Box<dyn Error>>,
+) -> Result<(Vec<String>, String), Box<dyn Error>> {
+    let meta = provider.state().meta().clone();
+    let snapshot = provider.state().openmls_values().snapshot();
+    let preview = stage(provider)
+        .and_then(|commit| Ok((describe_pending_commit(&send_group(provider)?)?, commit)));
+    provider.state().openmls_values().restore(&snapshot);
+    provider.state_mut().set_meta(meta);
+    preview
 }
 
-/// Load the local group matching the proto message group id and process the protocol message.
-///
-/// Returns the group (loaded before processing) and the `ProcessedMessage` result which the
-/// caller can inspect to handle application messages or staged commits.
+/// Merge the group's pending commit, counting it and recording it in the audit log.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let (group, processed) = process_proto_msg(&provider, proto_msg)?;
+/// merge_pending_commit(&mut provider, &mut group)?;
 /// ```
-pub fn process_proto_msg(
-    provider: &DmlsProvider,
-    proto_msg: ProtocolMessage,
-) -> Result<(MlsGroup, ProcessedMessage), Box<dyn Error>> {
-    match MlsGroup::load(provider.storage(), proto_msg.group_id())? {
-        Some(mut g) => {
-            let m = g.process_message(provider, proto_msg)?;
-            match m.sender() {
-                Sender::Member(leaf_idx) if leaf_idx.usize() == 0 => Ok((g, m)),
-                _ => Err("Message not sent by the send group owner".into()),
-            }
-        }
-        None => Err("No local group found with the given Group ID".into()),
+pub fn merge_pending_commit(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+) -> Result<(), Box<dyn Error>> {
+    let (events, path_update) = match group.pending_commit() {
+        None => return Err(DmlsError::NoPendingCommit.into()),
+        Some(staged_commit) => (
+            commit_events(group, staged_commit),
+            staged_commit.update_path_leaf_node().is_some(),
+        ),
+    };
+    group.merge_pending_commit(provider)?;
+    provider.state_mut().record_commit(group.group_id());
+    enforce_epoch_retention(provider, group)?;
+    if path_update {
+        provider.state_mut().record_self_update(group.group_id());
     }
+    append_audit_entries(provider, group, events)
 }
 
-/// Convert an application message payload into a UTF-8 string.
-///
-/// Panics if the payload is not valid UTF-8; the function returns an `Err` in that case.
-///
-/// Example:
-///
-/// ```ignore
-/// let s = plaintext(app_msg)?;
-/// println!("plaintext: {}", s);
-/// ```
-pub fn plaintext(app_msg: ApplicationMessage) -> Result<String, Box<dyn Error>> {
-    Ok(String::from_utf8(app_msg.into_bytes())?)
-}
-
-/// Apply a staged commit to the group and, if the group remains active, store the derived
-/// exporter PSK and queue its id for later injection.
+/// Merge the group's pending commit after it has been delivered.
 ///
-/// If the commit results in the local leaf being evicted, the group is deleted from storage.
+/// Injected PSK ids are removed from the queue, and the exporter PSK of the new epoch is stored
+/// so peers injecting it later can be processed. Every commit of the agent's own is merged this
+/// way, whether staged (`--stage`) or merged right away (`inject_psks`, `force_add_members`).
 ///
 /// Example:
 ///
 /// ```ignore
-/// apply_commit(&mut provider, &mut group, staged_commit, ciphersuite, 32)?;
+/// confirm_commit(&mut provider, &mut group, ciphersuite, 32)?;
 /// ```
-pub fn apply_commit(
+pub fn confirm_commit(
     provider: &mut DmlsProvider,
     group: &mut MlsGroup,
-    commit: StagedCommit,
     ciphersuite: Ciphersuite,
     exporter_length: usize,
 ) -> Result<(), Box<dyn Error>> {
-    group.merge_staged_commit(provider, commit)?;
-    if group.is_active() {
-        // store exporter-psk
-        let psk_id_vec = store_exporter_psk(provider, group, ciphersuite, exporter_length)?;
-        // enqueue this psk id to be injected on next commit
-        provider.state_mut().push_exporter_psk_id(psk_id_vec);
-        Ok(())
-    } else {
-        // delete group if evicted
-        group.delete(provider.storage())?;
-        Ok(())
+    let psk_ids = pending_commit_psk_ids(group)?;
+    let resumption_psks = group.pending_commit().is_some_and(|staged_commit| {
+        staged_commit.psk_proposals().any(|p| {
+            matches!(
+                p.psk_proposal().clone().into_psk_id().psk(),
+                Psk::Resumption(_)
+            )
+        })
+    });
+    merge_pending_commit(provider, group)?;
+    provider
+        .state_mut()
+        .record_psks_injected(group.group_id(), group.epoch().as_u64(), &psk_ids);
+    provider.state_mut().remove_exporter_psk_ids(&psk_ids);
+    // the queued resumption PSKs only leave the queue with a commit injecting them
+    if resumption_psks {
+        provider.state_mut().clear_resumption_psk_epochs();
     }
+    drop(store_exporter_psk(
+        provider,
+        group,
+        ciphersuite,
+        exporter_length,
+    )?);
+    Ok(())
 }
 
-/// Deserialize a base64-encoded MLS message line into an `MlsMessageIn` instance.
+/// Discard the group's pending commit, e.g. because it could not be delivered.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let m = stdin_base64_to_mls_msg_in(line)?;
+/// abort_commit(&provider, &mut group)?;
 /// ```
-pub fn stdin_base64_to_mls_msg_in(
-    s: std::io::Result<String>,
-) -> Result<MlsMessageIn, Box<dyn Error>> {
-    Ok(MlsMessageIn::tls_deserialize_exact(&Base64.decode(s?)?)?)
+pub fn abort_commit(provider: &DmlsProvider, group: &mut MlsGroup) -> Result<(), Box<dyn Error>> {
+    if group.pending_commit().is_none() {
+        return Err(DmlsError::NoPendingCommit.into());
+    }
+    group.clear_pending_commit(provider.storage())?;
+    Ok(())
 }
 
-/// Validate and deserialize a base64-encoded KeyPackage provided via stdin.
+/// Discard the group's pending commit, if any, before staging a new one.
 ///
-/// The key package is validated using the provider's crypto and the MLS protocol version.
+/// A pending commit (see e.g. `stage_self_update`) may already have been sent to members, so
+/// this refuses to discard it unless the provider allows it (see `DmlsProvider::with_force`);
+/// confirm or abort it first (see `confirm_commit` and `abort_commit`).
 ///
 /// Example:
 ///
 /// ```ignore
-/// let kp = stdin_base64_to_kp(&provider, line)?;
+/// discard_pending_commit(&provider, &mut group)?;
 /// ```
-pub fn stdin_base64_to_kp(
+pub fn discard_pending_commit(
     provider: &DmlsProvider,
-    s: std::io::Result<String>,
-) -> Result<KeyPackage, Box<dyn Error>> {
-    Ok(KeyPackageIn::tls_deserialize_exact(&Base64.decode(s?)?)?
-        .validate(provider.crypto(), ProtocolVersion::Mls10)?)
+    group: &mut MlsGroup,
+) -> Result<(), Box<dyn Error>> {
+    if group.pending_commit().is_some() && !provider.force() {
+        return Err(DmlsError::PendingCommit.into());
+    }
+    group.clear_pending_commit(provider.storage())?;
+    Ok(())
 }
 
-/// Build a minimal `CredentialWithKey` from the provider's signature public key.
-///
-/// The credential identity used here is the first 8 bytes of the signature public key. This
-/// is sufficient for the examples in this crate but not suitable for production identity
-/// management.
+/// Return the group's pending commit (base64) as recorded in the outbox, e.g. to resend it.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let cred = cred_with_key(&provider);
+/// let commit_b64 = pending_commit_base64(&provider, &group)?;
 /// ```
-pub fn cred_with_key(provider: &DmlsProvider) -> CredentialWithKey {
-    // credential identity is just first 8 bytes of public key
-    let signature_public_key = provider.state().signature_key_pair().public_key_raw();
-    CredentialWithKey {
-        credential: BasicCredential::new(signature_public_key[..8].to_vec()).into(),
-        signature_key: signature_public_key.into(),
-    }
-}
-/*
-pub fn stdin_create_message_in_send_group_base64(
+pub fn pending_commit_base64(
     provider: &DmlsProvider,
-    s: std::io::Result<String>,
+    group: &MlsGroup,
 ) -> Result<String, Box<dyn Error>> {
-    stdin_create_message_base64(provider, &mut send_group(provider)?, s)
+    if group.pending_commit().is_none() {
+        return Err(DmlsError::NoPendingCommit.into());
+    }
+    // the pending commit is the group's only commit from the current epoch
+    let is_pending = |message: &str| -> Option<bool> {
+        let proto_msg = match bytes_extract(&Blob.decode(message).ok()?).ok()? {
+            MlsMessageBodyIn::PublicMessage(m) => ProtocolMessage::from(m),
+            MlsMessageBodyIn::PrivateMessage(m) => ProtocolMessage::from(m),
+            _ => return None,
+        };
+        Some(proto_msg.group_id() == group.group_id() && proto_msg.epoch() == group.epoch())
+    };
+    provider
+        .state()
+        .outbox()
+        .iter()
+        .rev()
+        .filter(|entry| entry.kind == OutboxKind::Commit)
+        .find(|entry| is_pending(&entry.message).unwrap_or(false))
+        .map(|entry| entry.message.clone())
+        .ok_or_else(|| "Pending commit not found in the outbox (already acknowledged?)".into())
 }
-*/
-/// Create an MLS application message from a plaintext line and return it as base64.
-///
-/// This helper uses the group's state to create an encrypted application message that can
-/// be delivered to other members. The returned string is the TLS-serialized `MlsMessageOut`
-/// encoded in base64.
+
+/// Discard the send-group's pending commit and proposals.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let msg_b64 = stdin_create_message_base64(&provider, &mut group, Ok("Hello".to_string()))?;
-/// println!("{}", msg_b64);
+/// send_group_clear_pending(&provider)?;
 /// ```
-pub fn stdin_create_message_base64(
-    provider: &DmlsProvider,
-    group: &mut MlsGroup,
-    s: std::io::Result<String>,
-) -> Result<String, Box<dyn Error>> {
-    Ok(Base64.encode(create_message(provider, group, s?.as_bytes())?.tls_serialize_detached()?))
+pub fn send_group_clear_pending(provider: &DmlsProvider) -> Result<(), Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    sg.clear_pending_commit(provider.storage())?;
+    sg.clear_pending_proposals(provider.storage())?;
+    Ok(())
 }
 
-/// Directly create an `MlsMessageOut` application message from raw plaintext bytes.
+/// Derive an exporter PSK from the group's exporter and store it in the local PSK store.
 ///
-/// This is the lower-level primitive behind `stdin_create_message_base64` and returns the
-/// `MlsMessageOut` ready for serialization.
+/// Returns the PSK identifier (a byte vector) for later injection. The PSK id is constructed
+/// from the group's ciphersuite, group id and epoch (see `psk_id`). The derived PSK secret is stored using the OpenMLS
+/// PSK storage API so it can be looked up by other operations.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let msg = create_message(&provider, &mut group, b"Hello")?;
+/// let psk_id = store_exporter_psk(&mut provider, &group, ciphersuite, 32)?;
+/// // psk_id can be serialized and saved with state if desired
 /// ```
-pub fn create_message(
-    provider: &DmlsProvider,
-    group: &mut MlsGroup,
-    plaintext: &[u8],
-) -> Result<MlsMessageOut, Box<dyn Error>> {
-    Ok(group.create_message(provider, provider, plaintext)?)
+pub fn store_exporter_psk(
+    provider: &mut DmlsProvider,
+    group: &MlsGroup,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let (psk_id_vec, psk_secret) = exporter_psk(provider, group, exporter_length)?;
+    // store psk
+    PreSharedKeyId::new(
+        ciphersuite,
+        provider.rand(),
+        Psk::External(ExternalPsk::new(psk_id_vec.clone())),
+    )?
+    .store(provider, &psk_secret)?;
+    // done; return psk id
+    Ok(psk_id_vec)
 }
 
-/// Force-add the provided key packages to the group (no update) and return the Welcome as base64.
+/// Store an out-of-band external PSK and, if `queue` is set, queue it for injection into the send
+/// group with the next commit.
 ///
-/// This helper uses `add_members_without_update` so the creator can add members and emit a
-/// Welcome for them to join. The Welcome blob is returned as a base64 string that can be
-/// distributed to new members.
+/// The injecting agent queues the PSK; every other member of the send group must store the same
+/// id and secret (without queueing it) before processing that commit.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let welcome_b64 = force_add_members_base64(&provider, &mut group, &kps)?;
-/// println!("{}", welcome_b64);
+/// store_external_psk(&mut provider, ciphersuite, psk_id, &secret, true)?;
 /// ```
-pub fn force_add_members_base64(
-    provider: &DmlsProvider,
-    group: &mut MlsGroup,
-    kps: &[KeyPackage],
-) -> Result<String, Box<dyn Error>> {
-    Ok(Base64.encode(force_add_members(provider, group, kps)?.tls_serialize_detached()?))
-}
-
-/// Force-add the provided key packages and return the `MlsMessageOut` Welcome message.
-///
-/// The caller should serialize this message and deliver it to the joiner(s) who will call
-/// `process_welcome` to convert it into a group instance.
+pub fn store_external_psk(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    psk_id: Vec<u8>,
+    secret: &[u8],
+    queue: bool,
+) -> Result<(), Box<dyn Error>> {
+    if secret.is_empty() {
+        return Err("External PSK secret is empty".into());
+    }
+    PreSharedKeyId::new(
+        ciphersuite,
+        provider.rand(),
+        Psk::External(ExternalPsk::new(psk_id.clone())),
+    )?
+    .store(provider, secret)?;
+    if queue {
+        provider.state_mut().push_external_psk_id(psk_id);
+    }
+    Ok(())
+}
+
+/// Derive the exporter PSK of the group's current epoch and return its id and secret.
+///
+/// The PSK id names the group's ciphersuite, group id and epoch (see `psk_id`); every member of
+/// the epoch derives the same secret. The exporter length and label are those recorded for the group when it was created or
+/// joined; `exporter_length` is only used for groups without recorded parameters.
+/// `store_exporter_psk` stores the result.
+///
+/// Example:
+///
+/// ```ignore
+/// let (psk_id, psk_secret) = exporter_psk(&provider, &group, 32)?;
+/// ```
+pub fn exporter_psk(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    exporter_length: usize,
+) -> Result<(Vec<u8>, Vec<u8>), Box<dyn Error>> {
+    let psk_id_vec =
+        ExporterPskId::new(group.ciphersuite(), group.group_id(), group.epoch()).to_bytes();
+    // psk secret
+    let params = provider
+        .state()
+        .exporter_params(group.group_id())
+        .cloned()
+        .unwrap_or_else(|| ExporterParams::new(exporter_length));
+    let psk_secret =
+        group.export_secret(provider.crypto(), &params.label, &psk_id_vec, params.length)?;
+    Ok((psk_id_vec, psk_secret))
+}
+
+/// Delete the exporter PSKs and epoch key pairs of the group's epochs before the last `keep`
+/// ones and return how many entries were deleted.
+///
+/// The current epoch and exporter PSKs still queued for injection are always kept. Message
+/// secrets of past epochs are bounded separately by the group's `max_past_epochs`, and resumption
+/// PSKs by `RESUMPTION_PSK_RETENTION`.
+///
+/// Example:
+///
+/// ```ignore
+/// let purged = purge_epochs(&provider, &group, 2)?;
+/// ```
+pub fn purge_epochs(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    keep: u64,
+) -> Result<usize, Box<dyn Error>> {
+    let cutoff = group.epoch().as_u64().saturating_sub(keep);
+    let group_id = group.group_id();
+    let storage = provider.storage();
+    let queued = provider.state().exporter_psk_ids();
+    let mut purged = 0;
+    for key in storage.keys_for_label(PSK_LABEL) {
+        let Ok(Psk::External(psk)) = serde_json::from_slice::<Psk>(&key) else {
+            continue;
+        };
+        let Some(source) = ExporterPskId::parse(psk.psk_id()) else {
+            continue;
+        };
+        if source.group_id == group_id.as_slice()
+            && source.epoch < cutoff
+            && !queued.iter().any(|queued| queued == psk.psk_id())
+        {
+            storage.delete_psk(&Psk::External(psk))?;
+            purged += 1;
+        }
+    }
+    for key in storage.keys_for_label(EPOCH_KEY_PAIRS_LABEL) {
+        // the key is the group id, the epoch and the leaf index
+        let mut parts = serde_json::Deserializer::from_slice(&key).into_iter::<serde_json::Value>();
+        let (Some(Ok(id)), Some(Ok(epoch)), Some(Ok(leaf_index))) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            continue;
+        };
+        let (Ok(id), Ok(epoch), Ok(leaf_index)) = (
+            serde_json::from_value::<GroupId>(id),
+            serde_json::from_value::<GroupEpoch>(epoch),
+            serde_json::from_value::<u32>(leaf_index),
+        ) else {
+            continue;
+        };
+        if id == *group_id && epoch.as_u64() < cutoff {
+            storage.delete_encryption_epoch_key_pairs(group_id, &epoch, leaf_index)?;
+            purged += 1;
+        }
+    }
+    if purged > 0 {
+        tracing::debug!("Purged {purged} entries of epochs before {cutoff}");
+    }
+    Ok(purged)
+}
+
+/// Purge the group's old epochs if the state limits their retention (see
+/// `DmlsState::epoch_retention`).
+fn enforce_epoch_retention(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(keep) = provider.state().epoch_retention() {
+        purge_epochs(provider, group, keep)?;
+    }
+    Ok(())
+}
+
+/// Export a secret from the group's current epoch for use by an application, as base64.
+///
+/// Only labels on the state's allow-list (see `DmlsState::allow_export_label`) are accepted, and
+/// never a label used for exporter PSKs, which would hand out the secrets DMLS injects.
+///
+/// Example:
+///
+/// ```ignore
+/// let key_b64 = export_secret_base64(&provider, &group, "my-app key", b"", 32)?;
+/// ```
+pub fn export_secret_base64(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<String, Box<dyn Error>> {
+    if provider.state().is_exporter_psk_label(label) {
+        return Err(format!("Label {label} is reserved for exporter PSKs").into());
+    }
+    if !provider.state().export_labels().iter().any(|l| l == label) {
+        return Err(DmlsError::LabelNotAllowed(label.to_string()).into());
+    }
+    Ok(Blob.encode(group.export_secret(provider.crypto(), label, context, length)?))
+}
+
+/// Convenience wrapper to deserialize a base64-encoded MLS message from an input line
+/// and extract the `MlsMessageBodyIn` variant (Welcome, PublicMessage, PrivateMessage).
+///
+/// Example:
+///
+/// ```ignore
+/// for line in stdin.lock().lines() {
+///     let body = stdin_base64_extract(line)?;
+///     match body { ... }
+/// }
+/// ```
+pub fn stdin_base64_extract(
+    s: std::io::Result<String>,
+) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    Ok(stdin_base64_to_mls_msg_in(s)?.extract())
+}
+
+/// Deserialize a raw (TLS-encoded, not base64) MLS message and extract its `MlsMessageBodyIn`.
+///
+/// Example:
+///
+/// ```ignore
+/// let body = bytes_extract(&frame_bytes)?;
+/// ```
+pub fn bytes_extract(bytes: &[u8]) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    Ok(bytes_to_mls_msg_in(bytes)?.extract())
+}
+
+/// Deserialize a raw (TLS-encoded, not base64) MLS message.
+///
+/// Example:
+///
+/// ```ignore
+/// let m = bytes_to_mls_msg_in(&frame_bytes)?;
+/// ```
+pub fn bytes_to_mls_msg_in(bytes: &[u8]) -> Result<MlsMessageIn, Box<dyn Error>> {
+    Ok(MlsMessageIn::tls_deserialize_exact(bytes)?)
+}
+
+/// Extract the `MlsMessageBodyIn` of a message read from a file, which holds either base64 text
+/// or a raw TLS-encoded MLS message.
+///
+/// Example:
+///
+/// ```ignore
+/// let body = file_extract(std::fs::read(path)?)?;
+/// ```
+pub fn file_extract(content: Vec<u8>) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    bytes_extract(&file_blob(content)?)
+}
+
+/// Decode the content of a message file: encoded text is decoded, binary content is returned as is.
+///
+/// Binary content is recognized by its first byte: raw MLS messages start with their protocol
+/// version (0x0001) and envelopes with a CBOR map (see `envelope::is_envelope`), neither of which
+/// text (base64, hex or armor) starts with.
+///
+/// Example:
+///
+/// ```ignore
+/// let bytes = file_blob(std::fs::read(path)?)?;
+/// ```
+pub fn file_blob(content: Vec<u8>) -> Result<Vec<u8>, Box<dyn Error>> {
+    if content.first() == Some(&0x00) || is_envelope(&content) {
+        return Ok(content);
+    }
+    let text = String::from_utf8(content)
+        .map_err(|_| "Message file is neither an MLS message nor text")?;
+    if text.lines().any(|line| armor_label(line).is_some()) {
+        Ok(dearmor(&text)?.1)
+    } else {
+        Blob.decode(text)
+    }
+}
+
+/// Returns the armor label (see `armor`) for a serialized artifact, based on its content.
+///
+/// Example:
+///
+/// ```ignore
+/// assert_eq!(artifact_label(&welcome_bytes), "WELCOME");
+/// ```
+pub fn artifact_label(bytes: &[u8]) -> &'static str {
+    let content_label = |content_type| match content_type {
+        ContentType::Application => "MESSAGE",
+        ContentType::Proposal => "PROPOSAL",
+        ContentType::Commit => "COMMIT",
+    };
+    if is_envelope(bytes) {
+        return "ENVELOPE";
+    }
+    match MlsMessageIn::tls_deserialize_exact(bytes).map(MlsMessageIn::extract) {
+        Ok(MlsMessageBodyIn::Welcome(_)) => "WELCOME",
+        Ok(MlsMessageBodyIn::GroupInfo(_)) => "GROUP INFO",
+        Ok(MlsMessageBodyIn::KeyPackage(_)) => "KEY PACKAGE",
+        Ok(MlsMessageBodyIn::PublicMessage(m)) => {
+            content_label(ProtocolMessage::from(m).content_type())
+        }
+        Ok(MlsMessageBodyIn::PrivateMessage(m)) => {
+            content_label(ProtocolMessage::from(m).content_type())
+        }
+        // `gen-kp` prints bare key packages
+        _ if KeyPackageIn::tls_deserialize_exact(bytes).is_ok() => "KEY PACKAGE",
+        _ => "ARTIFACT",
+    }
+}
+
+/// Convert an encoded artifact into its armored form (see `armor`).
+///
+/// Example:
+///
+/// ```ignore
+/// println!("{}", armor_base64(&welcome_b64)?);
+/// ```
+pub fn armor_base64(artifact: &str) -> Result<String, Box<dyn Error>> {
+    let bytes = Blob.decode(artifact)?;
+    Ok(armor(artifact_label(&bytes), &bytes))
+}
+
+/// An incoming message decoded from a blob, with the envelope it came in (if any).
+#[derive(Debug)]
+pub struct DecodedMessage {
+    /// The (not yet verified) envelope, if the message came in one.
+    pub envelope: Option<Envelope>,
+    /// The extracted message.
+    pub body: MlsMessageBodyIn,
+    /// The serialized MLS message, as hashed for deduplication (see `check_duplicate`).
+    pub message: Vec<u8>,
+}
+
+/// Decode a raw blob into a message, unwrapping an application envelope (see `envelope`) first
+/// if `envelope` is set.
+///
+/// Decoding doesn't touch agent state, so blobs can be decoded in parallel (see
+/// `decode_blobs_parallel`); envelopes are verified separately by `check_envelope`.
+///
+/// Example:
+///
+/// ```ignore
+/// let decoded = decode_blob(&bytes, true)?;
+/// let body = check_envelope(&provider, decoded)?;
+/// ```
+pub fn decode_blob(blob: &[u8], envelope: bool) -> Result<DecodedMessage, Box<dyn Error>> {
+    if envelope {
+        let envelope = Envelope::from_bytes(blob)?;
+        Ok(DecodedMessage {
+            body: bytes_extract(&envelope.message)?,
+            message: envelope.message.clone(),
+            envelope: Some(envelope),
+        })
+    } else {
+        Ok(DecodedMessage {
+            envelope: None,
+            body: bytes_extract(blob)?,
+            message: blob.to_vec(),
+        })
+    }
+}
+
+/// Decode raw blobs (see `decode_blob`) on the rayon thread pool, keeping their order.
+///
+/// Errors are returned as strings, since boxed errors can't cross threads.
+///
+/// Example:
+///
+/// ```ignore
+/// for decoded in decode_blobs_parallel(&blobs, false) { /* ... */ }
+/// ```
+pub fn decode_blobs_parallel(
+    blobs: &[Vec<u8>],
+    envelope: bool,
+) -> Vec<Result<DecodedMessage, String>> {
+    blobs
+        .par_iter()
+        .map(|blob| decode_blob(blob, envelope).map_err(|e| e.to_string()))
+        .collect()
+}
+
+/// Verify the envelope of a decoded message, if any, and return the message.
+///
+/// Example:
+///
+/// ```ignore
+/// let body = check_envelope(&provider, decode_blob(&bytes, true)?)?;
+/// ```
+pub fn check_envelope(
+    provider: &DmlsProvider,
+    decoded: DecodedMessage,
+) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    if let Some(envelope) = &decoded.envelope {
+        verify_envelope(provider, envelope)?;
+        tracing::info!(
+            "Envelope {} from {} ({}, sent at {})",
+            Blob.encode(&envelope.message_id),
+            Blob.encode(&envelope.sender),
+            envelope.content_type,
+            envelope.timestamp
+        );
+    }
+    Ok(decoded.body)
+}
+
+/// Record a serialized MLS message as processed and return whether it already was.
+///
+/// At-least-once transports deliver messages again, and processing a commit or application
+/// message twice only produces duplicate output or errors about past epochs. The state remembers
+/// the hashes of the most recently processed messages (see `DmlsState::record_seen_message`), so
+/// repeated deliveries can be skipped, across runs too. Messages are only recorded once
+/// processing succeeds (see `process_decoded`), so ones that failed, e.g. because they arrived
+/// early, are processed again when delivered again.
+///
+/// Example:
+///
+/// ```ignore
+/// if !is_duplicate(&provider, &decoded.message)? {
+///     process_body_main(&mut provider, check_envelope(&provider, decoded)?, cs, 32);
+///     check_duplicate(&mut provider, &decoded.message)?;
+/// }
+/// ```
+pub fn check_duplicate(
+    provider: &mut DmlsProvider,
+    message: &[u8],
+) -> Result<bool, Box<dyn Error>> {
+    if provider.state().dedupe_capacity() == 0 {
+        return Ok(false);
+    }
+    let digest = provider.crypto().hash(HashType::Sha2_256, message)?;
+    Ok(provider.state_mut().record_seen_message(digest))
+}
+
+/// Returns whether a serialized MLS message was processed before, without recording it (see
+/// `check_duplicate`).
+///
+/// Example:
+///
+/// ```ignore
+/// if is_duplicate(&provider, &decoded.message)? { /* skip */ }
+/// ```
+pub fn is_duplicate(provider: &DmlsProvider, message: &[u8]) -> Result<bool, Box<dyn Error>> {
+    if provider.state().dedupe_capacity() == 0 {
+        return Ok(false);
+    }
+    let digest = provider.crypto().hash(HashType::Sha2_256, message)?;
+    Ok(provider.state().has_seen_message(&digest))
+}
+
+/// Open and verify an application envelope (see `envelope`) and extract the wrapped message.
+///
+/// Example:
+///
+/// ```ignore
+/// let body = envelope_extract(&provider, &bytes)?;
+/// ```
+pub fn envelope_extract(
+    provider: &DmlsProvider,
+    bytes: &[u8],
+) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    check_envelope(provider, decode_blob(bytes, true)?)
+}
+
+/// Wrap an encoded MLS message of `group` into a signed application envelope (see `envelope`).
+///
+/// Example:
+///
+/// ```ignore
+/// let msg_b64 = seal_envelope_base64(&provider, &sg, "text/plain", &msg_b64)?;
+/// ```
+pub fn seal_envelope_base64(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    content_type: &str,
+    message: &str,
+) -> Result<String, Box<dyn Error>> {
+    let envelope = Envelope::seal(provider, group, content_type, Blob.decode(message)?)?;
+    Ok(Blob.encode(envelope.to_bytes()?))
+}
+
+/// Process a Welcome message and return the joined `MlsGroup` instance.
+///
+/// A Welcome is produced by a group creator when adding members. This helper creates a
+/// `StagedWelcome` and then converts it into an `MlsGroup` (performing necessary validations).
+/// Bridged resumption PSKs referenced by the Welcome (see `store_bridged_resumption_psk`) are
+/// derived from the local copy of their source group before the Welcome is staged.
+///
+/// Welcomes to groups whose ciphersuite the state's allow-lists reject (see `check_suite_policy`)
+/// are refused before anything is decrypted, and Welcomes to an expired key package (see
+/// `check_lifetime`) or to a group with a member the credential validator rejects (see
+/// `validator`) once it is.
+///
+/// Groups that don't use the ratchet_tree extension need the ratchet tree to be provided out of
+/// band (see `export_ratchet_tree_base64`). Handshake messages of the joined group are accepted
+/// as either `PublicMessage` or `PrivateMessage` (see `HandshakeWireFormat`).
+///
+/// The group's exporter parameters are recorded (keeping those of an earlier join) and, unless
+/// disabled (see `DmlsState::welcome_psk`), the exporter PSK of the join epoch is stored and
+/// queued like those of received commits, so a new member contributes entropy to its send group
+/// right away. Its peers stored the same PSK when they merged the commit adding it.
+///
+/// Example:
+///
+/// ```ignore
+/// let welcome = ...; // Welcome parsed from base64
+/// let group = process_welcome(&mut provider, welcome, None, 32)?;
+/// ```
+pub fn process_welcome(
+    provider: &mut DmlsProvider,
+    welcome: Welcome,
+    ratchet_tree: Option<RatchetTreeIn>,
+    exporter_length: usize,
+) -> Result<MlsGroup, Box<dyn Error>> {
+    check_suite_policy(provider, welcome.ciphersuite())?;
+    check_welcome_kp(provider, &welcome)?;
+    let processed_welcome = ProcessedWelcome::new_from_welcome(
+        provider,
+        &MlsGroupJoinConfig::builder()
+            .wire_format_policy(HandshakeWireFormat::default().policy())
+            .number_of_resumption_psks(RESUMPTION_PSK_RETENTION)
+            .build(),
+        welcome,
+    )?;
+    for psk_id in processed_welcome.psks() {
+        if let Psk::External(external_psk) = psk_id.psk() {
+            resolve_bridged_resumption_psk(provider, external_psk.psk_id())?;
+        }
+    }
+    let mut group = processed_welcome
+        .into_staged_welcome(provider, ratchet_tree)?
+        .into_group(provider)?;
+    // the Welcome is addressed to one of our key packages, whose lifetime the own leaf carries
+    let checked = group
+        .own_leaf_node()
+        .and_then(|leaf| leaf.life_time())
+        .map_or(Ok(()), |lifetime| check_lifetime(provider, lifetime))
+        .and_then(|()| {
+            group.members().try_for_each(|m| {
+                validate_credential(
+                    provider,
+                    &m.credential,
+                    &m.signature_key,
+                    CredentialSource::Welcome,
+                )
+            })
+        });
+    if let Err(e) = checked {
+        group.delete(provider.storage())?;
+        return Err(e);
+    }
+    // the exporter parameters are fixed from now on; a re-join keeps them
+    if provider.state().exporter_params(group.group_id()).is_none() {
+        provider
+            .state_mut()
+            .set_exporter_params(group.group_id(), ExporterParams::new(exporter_length));
+    }
+    if provider.state().welcome_psk() {
+        let psk_id = store_exporter_psk(provider, &group, group.ciphersuite(), exporter_length)?;
+        // a full queue refusing the PSK doesn't undo the join
+        if bridges_into_send_group(provider.state(), &group)
+            && let Err(e) = queue_exporter_psk(provider, psk_id)
+        {
+            tracing::warn!("Exporter PSK of the join epoch not queued: {e}");
+        }
+    }
+    Ok(group)
+}
+
+/// Check that a Welcome is addressed to a key package whose private part is still stored.
+///
+/// Otherwise OpenMLS fails to decrypt the group secrets without saying why. The error names the
+/// key package instead: the one this agent generated (with when, see
+/// `DmlsState::record_generated_kp`) if any, else all the Welcome is addressed to.
+fn check_welcome_kp(provider: &DmlsProvider, welcome: &Welcome) -> Result<(), Box<dyn Error>> {
+    let stored = provider
+        .state()
+        .openmls_values()
+        .keys_for_label(KEY_PACKAGE_LABEL);
+    let kp_refs = welcome
+        .secrets()
+        .iter()
+        .map(|secrets| secrets.new_member())
+        .collect::<Vec<_>>();
+    for kp_ref in &kp_refs {
+        // key packages are stored under their JSON-serialized reference
+        if stored.contains(&serde_json::to_vec(kp_ref)?) {
+            return Ok(());
+        }
+    }
+    let generated = kp_refs
+        .iter()
+        .find_map(|kp_ref| provider.state().generated_kp(kp_ref.as_slice()));
+    let described = match generated {
+        Some(record) => format!(
+            "{} (ciphersuite {:#06x}, generated at Unix time {})",
+            Blob.encode(&record.kp_ref),
+            record.ciphersuite,
+            record.generated_at
+        ),
+        None => format!(
+            "{} (not generated by this state, or before it was restored)",
+            kp_refs
+                .iter()
+                .map(|kp_ref| Blob.encode(kp_ref.as_slice()))
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ),
+    };
+    Err(DmlsError::KeyPackageMissing(described).into())
+}
+
+/// Rejoin a group with an external commit from a fresh GroupInfo, e.g. after the local copy of
+/// the group was compromised or lost, and return the commit for the group's members.
+///
+/// The local copy of the group (if any) is deleted first and the new one is joined with a fresh
+/// leaf, whose path update replaces all secrets the old copy knew. The exporter PSK of the new
+/// epoch is stored and queued like those of received commits (see `apply_commit`), so the fresh
+/// entropy also reaches the send group with its next PSK injection. The commit removes the old
+/// leaf: OpenMLS adds a Remove of the leaf holding the joiner's signature key to external commits
+/// (a resync), so the agent rejoins with the key of its old leaf, and the commit is refused if it
+/// doesn't remove that leaf (e.g. after `continuity::rotate_key`). Members accept the commit
+/// because the new leaf has the old leaf's signature key (see `check_sender`). The send group
+/// itself can't be healed this way, as only its owner at leaf 0 may commit to it; use a full-path
+/// `update` instead.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = heal(&mut provider, &group_id, group_info, None, 32)?;
+/// ```
+pub fn heal(
+    provider: &mut DmlsProvider,
+    group_id: &GroupId,
+    group_info: VerifiableGroupInfo,
+    ratchet_tree: Option<RatchetTreeIn>,
+    exporter_length: usize,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    if group_info.group_id() != group_id {
+        return Err("GroupInfo belongs to another group".into());
+    }
+    if provider.state().send_group_id().as_ref() == Some(group_id) {
+        return Err("The send group can't be healed by rejoining; use a full-path update".into());
+    }
+    let ciphersuite = group_info.ciphersuite();
+    check_suite_policy(provider, ciphersuite)?;
+    if let Some(mut old) = MlsGroup::load(provider.storage(), group_id)? {
+        old.delete(provider.storage())?;
+    }
+    let (mut group, commit, _) = MlsGroup::join_by_external_commit(
+        provider,
+        provider,
+        ratchet_tree,
+        group_info,
+        &MlsGroupJoinConfig::builder()
+            .wire_format_policy(HandshakeWireFormat::default().policy())
+            .number_of_resumption_psks(RESUMPTION_PSK_RETENTION)
+            .build(),
+        Some(capabilities(provider.state().capabilities(), ciphersuite)?),
+        None,
+        &[],
+        cred_with_key(provider),
+    )?;
+    let signature_key = provider.state().signature_key_pair().public_key_raw();
+    let Some(old_leaf) = group
+        .members()
+        .find(|member| member.signature_key == signature_key)
+        .map(|member| member.index)
+    else {
+        return Err("No leaf of the group has this agent's signature key".into());
+    };
+    let removes_old_leaf = group.pending_commit().is_some_and(|commit| {
+        commit
+            .remove_proposals()
+            .any(|p| p.remove_proposal().removed() == old_leaf)
+    });
+    if !removes_old_leaf {
+        return Err(format!(
+            "External commit doesn't remove the old leaf {}",
+            old_leaf.u32()
+        )
+        .into());
+    }
+    merge_pending_commit(provider, &mut group)?;
+    if provider.state().exporter_params(group_id).is_none() {
+        provider
+            .state_mut()
+            .set_exporter_params(group_id, ExporterParams::new(exporter_length));
+    }
+    let psk_id = store_exporter_psk(provider, &group, ciphersuite, exporter_length)?;
+    if bridges_into_send_group(provider.state(), &group) {
+        queue_exporter_psk(provider, psk_id)?;
+    }
+    Ok(commit)
+}
+
+/// Like `heal`, but takes the group id as base64 and the GroupInfo as a blob, and returns the
+/// commit as a base64 blob.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = heal_base64(&mut provider, &group_id_b64, &group_info_bytes, None, 32)?;
+/// ```
+pub fn heal_base64(
+    provider: &mut DmlsProvider,
+    group_id: &str,
+    group_info: &[u8],
+    ratchet_tree: Option<RatchetTreeIn>,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    let MlsMessageBodyIn::GroupInfo(group_info) = bytes_to_mls_msg_in(group_info)?.extract() else {
+        return Err("Not a GroupInfo".into());
+    };
+    let group_id = GroupId::from_slice(&Blob.decode(group_id)?);
+    let commit = heal(
+        provider,
+        &group_id,
+        group_info,
+        ratchet_tree,
+        exporter_length,
+    )?;
+    Ok(Blob.encode(commit.tls_serialize_detached()?))
+}
+
+/// Load the local group matching the proto message group id and process the protocol message.
+///
+/// Returns the group (loaded before processing) and the `ProcessedMessage` result which the
+/// caller can inspect to handle application messages or staged commits. Only messages of the
+/// group's owner and external commits of rejoining members (see `heal` and `check_sender`) are
+/// accepted.
+///
+/// Example:
+///
+/// ```ignore
+/// let (group, processed) = process_proto_msg(&provider, proto_msg)?;
+/// ```
+pub fn process_proto_msg(
+    provider: &DmlsProvider,
+    proto_msg: ProtocolMessage,
+) -> Result<(MlsGroup, ProcessedMessage), Box<dyn Error>> {
+    match MlsGroup::load(provider.storage(), proto_msg.group_id())? {
+        Some(mut g) => {
+            let m = g.process_message(provider, proto_msg)?;
+            check_sender(provider.crypto(), &g, &m)?;
+            Ok((g, m))
+        }
+        None => Err(DmlsError::GroupNotFound.into()),
+    }
+}
+
+/// Accept messages sent by the group's owner (leaf 0) and external commits by which a member
+/// rejoins the group (see `heal`).
+///
+/// Credential identities aren't authenticated (by default they are a prefix of the public key,
+/// see `credential_for`), so an external commit is only accepted if its new leaf has the
+/// signature key of the member with the same identity, or carries a valid continuity proof by
+/// that key (see `continuity`).
+fn check_sender(
+    crypto: &impl OpenMlsCrypto,
+    group: &MlsGroup,
+    processed: &ProcessedMessage,
+) -> Result<(), DmlsError> {
+    match processed.sender() {
+        Sender::Member(leaf_idx) if leaf_idx.usize() == 0 => Ok(()),
+        Sender::NewMemberCommit => {
+            let ProcessedMessageContent::StagedCommitMessage(commit) = processed.content() else {
+                return Err(DmlsError::NotSentByOwner);
+            };
+            let Some(leaf_node) = commit.update_path_leaf_node() else {
+                return Err(DmlsError::NotSentByOwner);
+            };
+            let rejoins = group
+                .members()
+                .filter(|m| {
+                    m.credential.serialized_content() == processed.credential().serialized_content()
+                })
+                .any(|m| {
+                    m.signature_key == leaf_node.signature_key().as_slice()
+                        || verify_proof(
+                            crypto,
+                            group.ciphersuite().signature_algorithm(),
+                            leaf_node,
+                            &m.signature_key,
+                        )
+                        .is_ok()
+                });
+            if rejoins {
+                Ok(())
+            } else {
+                Err(DmlsError::NotSentByOwner)
+            }
+        }
+        _ => Err(DmlsError::NotSentByOwner),
+    }
+}
+
+/// Groups loaded while processing a batch of messages, keyed by group id.
+///
+/// OpenMLS persists every change to a group through the storage provider as it is made, so
+/// cached groups never need to be written back: the cache only saves loading (and deserializing)
+/// the group from storage again for every message of a batch.
+pub type GroupCache = HashMap<GroupId, MlsGroup>;
+
+/// Like `process_proto_msg`, but takes the group from (or loads it into) the cache.
+///
+/// A group whose message fails to process is evicted from the cache, so it is reloaded from
+/// storage for the next message.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut groups = GroupCache::new();
+/// let (group, processed) = process_proto_msg_cached(&provider, &mut groups, proto_msg)?;
+/// ```
+pub fn process_proto_msg_cached<'a>(
+    provider: &DmlsProvider,
+    groups: &'a mut GroupCache,
+    proto_msg: ProtocolMessage,
+) -> Result<(&'a mut MlsGroup, ProcessedMessage), Box<dyn Error>> {
+    let group_id = proto_msg.group_id().clone();
+    if !groups.contains_key(&group_id) {
+        groups.insert(group_id.clone(), load_group(provider, &group_id)?);
+    }
+    let processed = groups
+        .get_mut(&group_id)
+        .ok_or(DmlsError::GroupNotFound)?
+        .process_message(provider, proto_msg);
+    match processed {
+        Err(e) => {
+            groups.remove(&group_id);
+            Err(e.into())
+        }
+        Ok(m) => {
+            let group = groups.get_mut(&group_id).ok_or(DmlsError::GroupNotFound)?;
+            check_sender(provider.crypto(), group, &m)?;
+            Ok((group, m))
+        }
+    }
+}
+
+/// High-level processing of a ProtocolMessage.
+///
+/// This helper loads the group referenced by the protocol message (through the `GroupCache`),
+/// processes the message, and handles application messages, proposals and staged commits.
+/// Application message plaintexts are returned; proposals are queued in the group for a later
+/// commit; staged commits are applied to the group and may queue exporter PSKs.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut groups = GroupCache::new();
+/// if let Some(pt) =
+///     try_process_proto_msg(&mut provider, &mut groups, proto_msg, ciphersuite, exporter_length)?
+/// {
+///     println!("{pt}");
+/// }
+/// ```
+pub fn try_process_proto_msg(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    proto_msg: ProtocolMessage,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<DecryptedMessage>, Box<dyn Error>> {
+    let _span = tracing::info_span!(
+        "message",
+        group_id = %Blob.encode(proto_msg.group_id().as_slice()),
+        epoch = proto_msg.epoch().as_u64(),
+        content_type = ?proto_msg.content_type(),
+    )
+    .entered();
+    let (g, m) = process_proto_msg_cached(provider, groups, proto_msg)?;
+    tracing::warn!("Processed message:\n{m:#?}");
+    if matches!(m.content(), ProcessedMessageContent::ApplicationMessage(_)) {
+        provider.state_mut().record_decrypted(g.group_id());
+        return Ok(Some(DecryptedMessage::from_processed(m)?));
+    }
+    let sender = m.sender().clone();
+    match m.into_content() {
+        ProcessedMessageContent::ProposalMessage(proposal) => {
+            g.store_pending_proposal(provider.storage(), *proposal)?;
+        }
+        ProcessedMessageContent::StagedCommitMessage(commit) => {
+            apply_commit(provider, g, *commit, &sender, ciphersuite, exporter_length)?;
+        }
+        _ => return Err("Unsupported processed message content".into()),
+    }
+    Ok(None)
+}
+
+/// Like `try_process_proto_msg`, but logs errors instead of returning them.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut groups = GroupCache::new();
+/// if let Some(pt) =
+///     process_proto_msg_main(&mut provider, &mut groups, proto_msg, ciphersuite, exporter_length)
+/// {
+///     println!("{pt}");
+/// }
+/// ```
+pub fn process_proto_msg_main(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    proto_msg: ProtocolMessage,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Option<DecryptedMessage> {
+    try_process_proto_msg(provider, groups, proto_msg, ciphersuite, exporter_length).unwrap_or_else(
+        |e| {
+            tracing::error!("Error processing message: {e}");
+            None
+        },
+    )
+}
+
+/// High-level processing of an extracted incoming MLS message.
+///
+/// Welcomes are joined, protocol messages are handed to `process_proto_msg_main`, and key
+/// packages are validated and stored in the address book. Returns the plaintext of an
+/// application message, attributed to its sender, if any. Groups are loaded afresh; use
+/// `process_body_with_ratchet_tree` with a `GroupCache` to process batches of messages.
+///
+/// Example:
+///
+/// ```ignore
+/// let pt = process_body_main(&mut provider, stdin_base64_extract(line)?, ciphersuite, exporter_length);
+/// ```
+pub fn process_body_main(
+    provider: &mut DmlsProvider,
+    body: MlsMessageBodyIn,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Option<DecryptedMessage> {
+    process_body_with_ratchet_tree(
+        provider,
+        &mut GroupCache::new(),
+        body,
+        None,
+        ciphersuite,
+        exporter_length,
+    )
+}
+
+/// Like `process_body_main`, but joins Welcomes using the given out-of-band ratchet tree and
+/// keeps loaded groups in the given cache across calls.
+///
+/// Example:
+///
+/// ```ignore
+/// let tree = ratchet_tree_from_base64(&tree_b64)?;
+/// let mut groups = GroupCache::new();
+/// let pt = process_body_with_ratchet_tree(&mut provider, &mut groups, body, Some(&tree), cs, 32);
+/// ```
+pub fn process_body_with_ratchet_tree(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    body: MlsMessageBodyIn,
+    ratchet_tree: Option<&RatchetTreeIn>,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Option<DecryptedMessage> {
+    let kind = match &body {
+        MlsMessageBodyIn::Welcome(_) => "welcome",
+        MlsMessageBodyIn::KeyPackage(_) => "key package",
+        _ => "message",
+    };
+    try_process_body(
+        provider,
+        groups,
+        body,
+        ratchet_tree,
+        ciphersuite,
+        exporter_length,
+    )
+    .unwrap_or_else(|e| {
+        tracing::error!("Error processing {kind}: {e}");
+        None
+    })
+}
+
+/// Like `process_body_with_ratchet_tree`, but returns errors instead of logging them, so callers
+/// can tell whether the message was processed.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut groups = GroupCache::new();
+/// let pt = try_process_body(&mut provider, &mut groups, body, None, cs, 32)?;
+/// ```
+pub fn try_process_body(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    body: MlsMessageBodyIn,
+    ratchet_tree: Option<&RatchetTreeIn>,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<DecryptedMessage>, Box<dyn Error>> {
+    match body {
+        MlsMessageBodyIn::Welcome(welcome) => {
+            let _span = tracing::info_span!("message", content_type = "welcome").entered();
+            let g = process_welcome(provider, welcome, ratchet_tree.cloned(), exporter_length)?;
+            tracing::warn!("Group joined:\n{g:#?}");
+            // (re-)joined groups replace stale cached copies
+            groups.insert(g.group_id().clone(), g);
+            // joining consumed one of our key packages; the join itself stands regardless
+            if let Err(e) = mark_consumed(provider) {
+                tracing::warn!("Error updating key package pool: {e}");
+            }
+            Ok(None)
+        }
+        MlsMessageBodyIn::PublicMessage(pub_msg_in) => try_process_proto_msg(
+            provider,
+            groups,
+            pub_msg_in.into(),
+            ciphersuite,
+            exporter_length,
+        ),
+        MlsMessageBodyIn::PrivateMessage(prv_msg_in) => try_process_proto_msg(
+            provider,
+            groups,
+            prv_msg_in.into(),
+            ciphersuite,
+            exporter_length,
+        ),
+        MlsMessageBodyIn::KeyPackage(kp_in) => {
+            let _span = tracing::info_span!("message", content_type = "key_package").entered();
+            let kp = process_kp(provider, kp_in)?;
+            tracing::warn!("Key package added to address book:\n{kp:#?}");
+            Ok(None)
+        }
+        _ => Err("Unsupported wire format".into()),
+    }
+}
+
+/// Process a decoded message unless it was processed before, verifying its envelope first, and
+/// record it as processed (see `check_duplicate`) once processing succeeds.
+///
+/// A message that fails, e.g. a commit arriving before the Welcome of its group, is thus
+/// processed again when it is delivered again. Returns the plaintext of an application message,
+/// if any; duplicates are skipped and return `None`.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut groups = GroupCache::new();
+/// for blob in transport.fetch(&own_identity(&provider), 0)? {
+///     let decoded = decode_blob(&blob?, false)?;
+///     process_decoded(&mut provider, &mut groups, decoded, None, cs, 32)?;
+/// }
+/// ```
+pub fn process_decoded(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    mut decoded: DecodedMessage,
+    ratchet_tree: Option<&RatchetTreeIn>,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<DecryptedMessage>, Box<dyn Error>> {
+    let message = take(&mut decoded.message);
+    let body = check_envelope(provider, decoded)?;
+    if is_duplicate(provider, &message)? {
+        tracing::info!("Skipping duplicate message");
+        return Ok(None);
+    }
+    let decrypted = try_process_body(
+        provider,
+        groups,
+        body,
+        ratchet_tree,
+        ciphersuite,
+        exporter_length,
+    )?;
+    check_duplicate(provider, &message)?;
+    Ok(decrypted)
+}
+
+/// Write a state to the state file at `state_path` in the given format.
+///
+/// Example:
+///
+/// ```ignore
+/// save_state(state_path, provider.state(), StateFormat::Json)?;
+/// ```
+pub fn save_state(
+    state_path: &str,
+    state: &DmlsState,
+    format: StateFormat,
+) -> Result<(), Box<dyn Error>> {
+    write_state(state_path, state, format)?;
+    seal_integrity(state_path, None)
+}
+
+/// Write a state to its state file and remove the journal it supersedes, without sealing it.
+fn write_state(
+    state_path: &str,
+    state: &DmlsState,
+    format: StateFormat,
+) -> Result<(), Box<dyn Error>> {
+    tracing::info!("Path to write state: {state_path}");
+    tracing::debug!("Updated state to write:\n{state:#?}");
+    write_private(state_path, &state.to_bytes(format)?)?;
+    // the full state supersedes any journal
+    state.openmls_values().take_changes();
+    if let Err(e) = remove_file(journal_path(state_path))
+        && e.kind() != std::io::ErrorKind::NotFound
+    {
+        return Err(format!("Error removing journal of {state_path}: {e}").into());
+    }
+    Ok(())
+}
+
+/// Persist a state whose OpenMLS values live in a storage backend (see `byte_store`).
+///
+/// The OpenMLS entries changed since the last save are written to the backend; the state file
+/// only keeps the agent's bookkeeping. The integrity MAC, if any, covers both (see `integrity`).
+///
+/// Example:
+///
+/// ```ignore
+/// save_state_with_storage(state_path, provider.state(), StateFormat::Json, &store)?;
+/// ```
+pub fn save_state_with_storage(
+    state_path: &str,
+    state: &DmlsState,
+    format: StateFormat,
+    storage: &dyn ByteStore,
+) -> Result<(), Box<dyn Error>> {
+    let changes = decode_changes(state.openmls_values().take_changes())?;
+    tracing::info!("Writing {} changed entries to storage", changes.len());
+    write_entries(storage, changes)?;
+    write_state(state_path, &state.without_openmls_values(), format)?;
+    seal_integrity(state_path, Some(storage))
+}
+
+/// Persist a state incrementally by appending its changes to the state file's journal.
+///
+/// Falls back to (and thereby compacts the journal with) `save_state` if the state file doesn't
+/// exist yet or the journal has grown as large as the state file.
+///
+/// Example:
+///
+/// ```ignore
+/// save_state_incremental(state_path, provider.state(), StateFormat::Json)?;
+/// ```
+pub fn save_state_incremental(
+    state_path: &str,
+    state: &DmlsState,
+    format: StateFormat,
+) -> Result<(), Box<dyn Error>> {
+    let size = |path: &str| metadata(path).map(|m| m.len()).ok();
+    match (size(state_path), size(&journal_path(state_path))) {
+        (Some(state_len), journal_len) if journal_len.unwrap_or(0) < state_len => {
+            tracing::info!("Path to append state changes: {}", journal_path(state_path));
+            append_to_journal(state_path, state)?;
+            seal_integrity(state_path, None)
+        }
+        _ => save_state(state_path, state, format),
+    }
+}
+
+/// Save a state the way the agent was told to: to its storage backend (see
+/// `save_state_with_storage`), by appending its changes to the journal (see
+/// `save_state_incremental`) or in full (see `save_state`).
+///
+/// Example:
+///
+/// ```ignore
+/// persist_state(state_path, provider.state(), StateFormat::Json, true, None)?;
+/// ```
+pub fn persist_state(
+    state_path: &str,
+    state: &DmlsState,
+    format: StateFormat,
+    journal: bool,
+    storage: Option<&dyn ByteStore>,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(storage) = storage {
+        // the state file is small without the OpenMLS values, so there is nothing to journal
+        save_state_with_storage(state_path, state, format, storage)
+    } else if journal {
+        save_state_incremental(state_path, state, format)
+    } else {
+        save_state(state_path, state, format)
+    }
+}
+
+/// Read a state from the state file at `state_path`, detecting its format, and replay its
+/// journal (see `journal`), if any.
+///
+/// Example:
+///
+/// ```ignore
+/// let (state, format) = load_state(state_path)?;
+/// ```
+pub fn load_state(state_path: &str) -> Result<(DmlsState, StateFormat), Box<dyn Error>> {
+    load_state_with_storage(state_path, None)
+}
+
+/// Read a state like `load_state` and load its OpenMLS values from a storage backend, if any
+/// (see `byte_store::attach`), after verifying the integrity MAC over both (see `integrity`).
+///
+/// Example:
+///
+/// ```ignore
+/// let (state, format) = load_state_with_storage(state_path, Some(&store))?;
+/// ```
+pub fn load_state_with_storage(
+    state_path: &str,
+    storage: Option<&dyn ByteStore>,
+) -> Result<(DmlsState, StateFormat), Box<dyn Error>> {
+    tracing::info!("Path to read state: {state_path}");
+    check_perms(state_path)?;
+    check_integrity(state_path, storage)?;
+    let (mut state, format) = DmlsState::from_bytes(&read_file(state_path)?)?;
+    let replayed = replay_journal(&mut state, state_path)?;
+    if replayed > 0 {
+        tracing::info!("Replayed {replayed} journal records");
+    }
+    if let Some(storage) = storage {
+        // migrates values still in the state file
+        let count = attach(storage, state.openmls_values())?;
+        tracing::info!("Loaded {count} entries from storage");
+    }
+    Ok((state, format))
+}
+
+/// Convert an application message payload into a UTF-8 string.
+///
+/// Panics if the payload is not valid UTF-8; the function returns an `Err` in that case.
+///
+/// Example:
+///
+/// ```ignore
+/// let s = plaintext(app_msg)?;
+/// println!("plaintext: {}", s);
+/// ```
+pub fn plaintext(app_msg: ApplicationMessage) -> Result<String, Box<dyn Error>> {
+    Ok(String::from_utf8(app_msg.into_bytes())?)
+}
+
+/// An application message decrypted by `decrypt_message`, with its authenticated sender.
+///
+/// Ids are encoded with the wire blob encoding (see `encoding`).
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct DecryptedMessage {
+    /// The plaintext.
+    pub plaintext: String,
+    /// Leaf index of the sender in the group.
+    pub sender_leaf_index: u32,
+    /// Credential identity of the sender.
+    pub sender_identity: String,
+    /// Group the message was sent in.
+    pub group_id: String,
+    /// Epoch the message was sent in.
+    pub epoch: u64,
+}
+
+impl DecryptedMessage {
+    /// Take the plaintext of a processed application message, attributing it to the sender.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let message = DecryptedMessage::from_processed(processed)?;
+    /// ```
+    pub fn from_processed(processed: ProcessedMessage) -> Result<Self, Box<dyn Error>> {
+        let Sender::Member(sender_leaf_index) = processed.sender() else {
+            return Err("Application message not sent by a member".into());
+        };
+        let sender_leaf_index = sender_leaf_index.u32();
+        let sender_identity = Blob.encode(processed.credential().serialized_content());
+        let group_id = Blob.encode(processed.group_id().as_slice());
+        let epoch = processed.epoch().as_u64();
+        let ProcessedMessageContent::ApplicationMessage(app_msg) = processed.into_content() else {
+            return Err("Not an application message".into());
+        };
+        Ok(Self {
+            plaintext: plaintext(app_msg)?,
+            sender_leaf_index,
+            sender_identity,
+            group_id,
+            epoch,
+        })
+    }
+}
+
+/// Human-readable form: `<sender identity> (epoch <epoch>): <plaintext>`.
+impl core::fmt::Display for DecryptedMessage {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "{} (epoch {}): {}",
+            self.sender_identity, self.epoch, self.plaintext
+        )
+    }
+}
+
+/// Decrypt an application message and attribute it to its (authenticated) sender.
+///
+/// Handshake messages are left alone and yield `None`, so consumers only interested in
+/// application data don't need to care about commits and proposals.
+///
+/// Example:
+///
+/// ```ignore
+/// if let Some(message) = decrypt_message(&mut provider, &mut groups, proto_msg)? {
+///     println!("{}", serde_json::to_string(&message)?);
+/// }
+/// ```
+pub fn decrypt_message(
+    provider: &mut DmlsProvider,
+    groups: &mut GroupCache,
+    proto_msg: ProtocolMessage,
+) -> Result<Option<DecryptedMessage>, Box<dyn Error>> {
+    if proto_msg.content_type() != ContentType::Application {
+        return Ok(None);
+    }
+    let (g, m) = process_proto_msg_cached(provider, groups, proto_msg)?;
+    provider.state_mut().record_decrypted(g.group_id());
+    DecryptedMessage::from_processed(m).map(Some)
+}
+
+/// Apply a staged commit to the group and, if the group remains active, store the derived
+/// exporter PSK and queue its id for later injection, unless the bridging rules (see
+/// `DmlsState::bridges`) keep it out of the send group.
+///
+/// Commits changing membership from senders without the admin role (see `roles`), commits
+/// changing the sender's signature key without a continuity proof (see `continuity`), commits
+/// violating the membership policy (see `check_membership_policy`) and commits with credentials
+/// the credential validator rejects (see `validator`) are rejected, as are all commits while the
+/// exporter PSK queue is full and refuses more (see `DmlsState::push_exporter_psk_id`).
+/// If the commit results in the local leaf being evicted, the group is deleted from storage.
+///
+/// Example:
+///
+/// ```ignore
+/// apply_commit(&mut provider, &mut group, staged_commit, &sender, ciphersuite, 32)?;
+/// ```
+pub fn apply_commit(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    commit: StagedCommit,
+    sender: &Sender,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<(), Box<dyn Error>> {
+    authorize_commit(group, sender, &commit)?;
+    check_continuity(provider.crypto(), group, sender, &commit)?;
+    let added = commit
+        .add_proposals()
+        .map(|add| kp_identity(add.add_proposal().key_package()))
+        .collect::<Vec<_>>();
+    check_membership_policy(provider, group, &added, commit.remove_proposals().count())?;
+    for leaf_node in commit
+        .add_proposals()
+        .map(|add| add.add_proposal().key_package().leaf_node())
+        .chain(
+            commit
+                .update_proposals()
+                .map(|update| update.update_proposal().leaf_node()),
+        )
+        .chain(commit.update_path_leaf_node())
+    {
+        validate_leaf_node(provider, leaf_node, CredentialSource::Commit)?;
+    }
+    // the exporter PSK of the new epoch is queued unless the bridging rules keep it out of the
+    // send group; a full queue refusing it rejects the commit before it is merged
+    let queue_psk = bridges_into_send_group(provider.state(), group);
+    if queue_psk {
+        provider.state().check_psk_queue()?;
+    }
+    let events = commit_events(group, &commit);
+    group.merge_staged_commit(provider, commit)?;
+    provider.state_mut().record_commit(group.group_id());
+    enforce_epoch_retention(provider, group)?;
+    append_audit_entries(provider, group, events)?;
+    if group.is_active() {
+        // store exporter-psk
+        let psk_id_vec = store_exporter_psk(provider, group, ciphersuite, exporter_length)?;
+        // enqueue this psk id to be injected on next commit
+        if queue_psk {
+            queue_exporter_psk(provider, psk_id_vec)?;
+        }
+        Ok(())
+    } else {
+        // delete group if evicted
+        group.delete(provider.storage())?;
+        provider
+            .state_mut()
+            .remove_exporter_params(group.group_id());
+        Ok(())
+    }
+}
+
+/// Returns whether the bridging rules (see `DmlsState::bridges`) let exporter PSKs of the group
+/// into the send group; without a send group, they are queued for the one created later.
+fn bridges_into_send_group(state: &DmlsState, group: &MlsGroup) -> bool {
+    state
+        .send_group_id()
+        .is_none_or(|sg| state.bridges(group.group_id().as_slice(), sg.as_slice()))
+}
+
+/// Queue an exporter PSK id for injection into the send group, warning about queued ids the
+/// queue policy dropped to make room for it.
+fn queue_exporter_psk(provider: &mut DmlsProvider, psk_id: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    let dropped = provider.state_mut().push_exporter_psk_id(psk_id)?;
+    if dropped > 0 {
+        tracing::warn!("Exporter PSK queue is full; dropped {dropped} queued PSKs");
+    }
+    Ok(())
+}
+
+/// Deserialize a base64-encoded MLS message line into an `MlsMessageIn` instance.
+///
+/// Example:
+///
+/// ```ignore
+/// let m = stdin_base64_to_mls_msg_in(line)?;
+/// ```
+pub fn stdin_base64_to_mls_msg_in(
+    s: std::io::Result<String>,
+) -> Result<MlsMessageIn, Box<dyn Error>> {
+    Ok(MlsMessageIn::tls_deserialize_exact(&Blob.decode(s?)?)?)
+}
+
+/// Validate and deserialize a base64-encoded KeyPackage provided via stdin.
+///
+/// The key package is validated using the provider's crypto and the MLS protocol version.
+///
+/// Example:
+///
+/// ```ignore
+/// let kp = stdin_base64_to_kp(&provider, line)?;
+/// ```
+pub fn stdin_base64_to_kp(
+    provider: &DmlsProvider,
+    s: std::io::Result<String>,
+) -> Result<KeyPackage, Box<dyn Error>> {
+    bytes_to_kp(provider, &Blob.decode(s?)?)
+}
+
+/// Deserialize and validate a raw (TLS-encoded, not base64) key package.
+///
+/// Example:
+///
+/// ```ignore
+/// let kp = bytes_to_kp(&provider, &frame_bytes)?;
+/// ```
+pub fn bytes_to_kp(provider: &DmlsProvider, bytes: &[u8]) -> Result<KeyPackage, Box<dyn Error>> {
+    validate_kp(provider, KeyPackageIn::tls_deserialize_exact(bytes)?)
+}
+
+/// Validate a key package, check its ciphersuite against the state's allow-lists (see
+/// `check_suite_policy`), check that it hasn't expired (see `check_lifetime`) and pass its
+/// credential to the credential validator (see `validator`).
+///
+/// Example:
+///
+/// ```ignore
+/// let kp = validate_kp(&provider, kp_in)?;
+/// ```
+pub fn validate_kp(
+    provider: &DmlsProvider,
+    kp_in: KeyPackageIn,
+) -> Result<KeyPackage, Box<dyn Error>> {
+    let kp = kp_in.validate(provider.crypto(), ProtocolVersion::Mls10)?;
+    check_suite_policy(provider, kp.ciphersuite())?;
+    check_lifetime(provider, kp.life_time())?;
+    validate_leaf_node(provider, kp.leaf_node(), CredentialSource::KeyPackage)?;
+    Ok(kp)
+}
+
+/// Validate an incoming KeyPackage and record it in the local address book.
+///
+/// The key package is validated the same way as `stdin_base64_to_kp` and then stored in
+/// `DmlsState` keyed by its credential identity, so a group creator can collect key packages
+/// through the `Process` pipe and consume them later.
+///
+/// Example:
+///
+/// ```ignore
+/// if let MlsMessageBodyIn::KeyPackage(kp_in) = body {
+///     let kp = process_kp(&mut provider, kp_in)?;
+/// }
+/// ```
+pub fn process_kp(
+    provider: &mut DmlsProvider,
+    kp_in: KeyPackageIn,
+) -> Result<KeyPackage, Box<dyn Error>> {
+    let kp = validate_kp(provider, kp_in)?;
+    provider
+        .state_mut()
+        .push_address_book_kp(kp_identity(&kp), kp.tls_serialize_detached()?);
+    Ok(kp)
+}
+
+/// Validate a base64 KeyPackage fetched for `identity` and record it in the local address book.
+///
+/// Fails if the key package does not carry the expected credential identity, so a directory
+/// cannot hand out someone else's key package under the requested identity.
+///
+/// Example:
+///
+/// ```ignore
+/// let kp = process_fetched_kp_base64(&mut provider, &bob_identity, kp_b64)?;
+/// ```
+pub fn process_fetched_kp_base64(
+    provider: &mut DmlsProvider,
+    identity: &[u8],
+    s: String,
+) -> Result<KeyPackage, Box<dyn Error>> {
+    let kp = stdin_base64_to_kp(provider, Ok(s))?;
+    if kp_identity(&kp) != identity {
+        return Err(DmlsError::KeyPackageMismatch.into());
+    }
+    provider
+        .state_mut()
+        .push_address_book_kp(identity.to_vec(), kp.tls_serialize_detached()?);
+    Ok(kp)
+}
+
+/// Remove the entries for `identity` from a copy of the address book and return the first one
+/// that validates and matches the ciphersuite.
+///
+/// Callers work on a copy so the address book in state is only updated (via
+/// `DmlsState::set_address_book`) once the key packages have actually been used.
+///
+/// Example:
+///
+/// ```ignore
+/// let mut address_book = provider.state().address_book().clone();
+/// let kp = address_book_kp(&provider, &mut address_book, identity, ciphersuite);
+/// ```
+pub fn address_book_kp(
+    provider: &DmlsProvider,
+    address_book: &mut BTreeMap<Vec<u8>, Vec<Vec<u8>>>,
+    identity: &[u8],
+    ciphersuite: Ciphersuite,
+) -> Option<KeyPackage> {
+    address_book
+        .remove(identity)
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|kp_bytes| {
+            validate_kp(
+                provider,
+                KeyPackageIn::tls_deserialize_exact(&kp_bytes).ok()?,
+            )
+            .ok()
+        })
+        .find(|kp| kp.ciphersuite() == ciphersuite)
+}
+
+/// Drain the address book and return its key packages, re-validated.
+///
+/// Entries that no longer validate are skipped with an error log rather than failing the
+/// whole batch.
+///
+/// Example:
+///
+/// ```ignore
+/// let kps = take_address_book_kps(&mut provider);
+/// let welcome_b64 = force_add_members_base64(&mut provider, &mut group, &kps)?;
+/// ```
+pub fn take_address_book_kps(provider: &mut DmlsProvider) -> Vec<KeyPackage> {
+    let mut kps = Vec::new();
+    for kp_bytes in provider
+        .state_mut()
+        .clear_address_book()
+        .into_values()
+        .flatten()
+    {
+        match KeyPackageIn::tls_deserialize_exact(&kp_bytes)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|kp_in| validate_kp(provider, kp_in))
+        {
+            Err(e) => {
+                tracing::error!("Error validating address book key package: {e}");
+            }
+            Ok(kp) => {
+                kps.push(kp);
+            }
+        }
+    }
+    kps
+}
+
+/// Return the credential identity of a key package's owner.
+///
+/// Example:
+///
+/// ```ignore
+/// let identity = kp_identity(&kp);
+/// ```
+pub fn kp_identity(kp: &KeyPackage) -> Vec<u8> {
+    kp.leaf_node().credential().serialized_content().to_vec()
+}
+
+/// Return the credential identities of the key packages' owners.
+fn kps_identities(kps: &[KeyPackage]) -> Vec<Vec<u8>> {
+    kps.iter().map(kp_identity).collect()
+}
+
+/// Check a membership change adding members with the given identities and removing `removed`
+/// members against the state's membership policy (see `DmlsState::membership_policy`), before it
+/// is committed or merged.
+///
+/// Example:
+///
+/// ```ignore
+/// check_membership_policy(&provider, &group, &[kp_identity(&kp)], 0)?;
+/// ```
+pub fn check_membership_policy(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    added: &[Vec<u8>],
+    removed: usize,
+) -> Result<(), Box<dyn Error>> {
+    let policy = provider.state().membership_policy();
+    if let Some(banned) = added
+        .iter()
+        .find(|identity| policy.banned.contains(identity))
+    {
+        return Err(DmlsError::BannedIdentity(Blob.encode(banned)).into());
+    }
+    let adds = added.len() as u64;
+    if let Some(max) = policy.max_adds_per_commit
+        && adds > max
+    {
+        return Err(DmlsError::MembershipLimitExceeded(format!(
+            "{adds} members added by one commit, at most {max} allowed"
+        ))
+        .into());
+    }
+    let members = (group.members().count() + added.len()).saturating_sub(removed) as u64;
+    if let Some(max) = policy.max_members
+        && members > max
+    {
+        return Err(DmlsError::MembershipLimitExceeded(format!(
+            "{members} members in the group, at most {max} allowed"
+        ))
+        .into());
+    }
+    Ok(())
+}
+
+/// Return the credential identities of all other members of the group.
+///
+/// Example:
+///
+/// ```ignore
+/// let recipients = member_identities(&group);
+/// ```
+pub fn member_identities(group: &MlsGroup) -> Vec<Vec<u8>> {
+    group
+        .members()
+        .filter(|m| m.index != group.own_leaf_index())
+        .map(|m| m.credential.serialized_content().to_vec())
+        .collect()
+}
+
+/// Return this agent's own credential identity (see `cred_with_key`).
+///
+/// Example:
+///
+/// ```ignore
+/// let identity = own_identity(&provider);
+/// ```
+pub fn own_identity(provider: &DmlsProvider) -> Vec<u8> {
+    cred_with_key(provider)
+        .credential
+        .serialized_content()
+        .to_vec()
+}
+
+/// Build a minimal `CredentialWithKey` from the provider's signature public key.
+///
+/// The credential identity used here is the one chosen with `update --new-identity` or, by
+/// default, the first 8 bytes of the signature public key. This is sufficient for the examples
+/// in this crate but not suitable for production identity management.
+///
+/// Example:
+///
+/// ```ignore
+/// let cred = cred_with_key(&provider);
+/// ```
+pub fn cred_with_key(provider: &DmlsProvider) -> CredentialWithKey {
+    credential_for(
+        provider.state().signature_key_pair(),
+        provider.state().identity(),
+    )
+}
+
+/// Build the `CredentialWithKey` of a signature key pair (see `cred_with_key`), with the given
+/// identity or the one derived from the public key.
+///
+/// Example:
+///
+/// ```ignore
+/// let cred = credential_for(&new_key_pair, provider.state().identity());
+/// ```
+pub fn credential_for(
+    signature_key_pair: &SignatureKeyPair,
+    identity: Option<&[u8]>,
+) -> CredentialWithKey {
+    // default credential identity is just first 8 bytes of public key
+    let signature_public_key = signature_key_pair.public_key_raw();
+    let identity = identity.unwrap_or(&signature_public_key[..8]);
+    CredentialWithKey {
+        credential: BasicCredential::new(identity.to_vec()).into(),
+        signature_key: signature_public_key.into(),
+    }
+}
+/*
+pub fn stdin_create_message_in_send_group_base64(
+    provider: &DmlsProvider,
+    s: std::io::Result<String>,
+) -> Result<String, Box<dyn Error>> {
+    stdin_create_message_base64(provider, &mut send_group(provider)?, s)
+}
+*/
+/// Create an MLS application message from a plaintext line and return it as base64.
+///
+/// This helper uses the group's state to create an encrypted application message that can
+/// be delivered to other members. The returned string is the TLS-serialized `MlsMessageOut`
+/// encoded in base64. The message is counted in the group's activity counters (see
+/// `DmlsState::record_encrypted`).
+///
+/// Example:
+///
+/// ```ignore
+/// let msg_b64 = stdin_create_message_base64(&mut provider, &mut group, Ok("Hello".to_string()))?;
+/// println!("{}", msg_b64);
+/// ```
+pub fn stdin_create_message_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    s: std::io::Result<String>,
+) -> Result<String, Box<dyn Error>> {
+    let msg = create_message(provider, group, s?.as_bytes())?;
+    provider.state_mut().record_encrypted(group.group_id());
+    Ok(Blob.encode(msg.tls_serialize_detached()?))
+}
+
+/// Directly create an `MlsMessageOut` application message from raw plaintext bytes.
+///
+/// This is the lower-level primitive behind `stdin_create_message_base64` and returns the
+/// `MlsMessageOut` ready for serialization.
+///
+/// Example:
+///
+/// ```ignore
+/// let msg = create_message(&provider, &mut group, b"Hello")?;
+/// ```
+pub fn create_message(
+    provider: &DmlsProvider,
+    group: &mut MlsGroup,
+    plaintext: &[u8],
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    Ok(group.create_message(provider, provider, plaintext)?)
+}
+
+/// Force-add the provided key packages to the group (no update) and return the Welcome as base64.
+///
+/// This helper uses `add_members_without_update` so the creator can add members and emit a
+/// Welcome for them to join. The Welcome blob is returned as a base64 string that can be
+/// distributed to new members.
+///
+/// Example:
+///
+/// ```ignore
+/// let welcome_b64 = force_add_members_base64(&mut provider, &mut group, &kps, 32)?;
+/// println!("{}", welcome_b64);
+/// ```
+pub fn force_add_members_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(
+        force_add_members(provider, group, kps, exporter_length)?.tls_serialize_detached()?,
+    ))
+}
+
+/// Stage adding the provided key packages and return the Welcome as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let welcome_b64 = stage_add_members_base64(&provider, &mut group, &kps)?;
+/// ```
+pub fn stage_add_members_base64(
+    provider: &DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(stage_add_members(provider, group, kps)?.tls_serialize_detached()?))
+}
+
+/// Force-add the provided key packages and return the `MlsMessageOut` Welcome message.
+///
+/// The caller should serialize this message and deliver it to the joiner(s) who will call
+/// `process_welcome` to convert it into a group instance. The commit is merged right away (see
+/// `confirm_commit`).
+///
+/// Example:
+///
+/// ```ignore
+/// let welcome = force_add_members(&mut provider, &mut group, &kps, 32)?;
+/// ```
+pub fn force_add_members(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+    exporter_length: usize,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    let welcome = stage_add_members(provider, group, kps)?;
+    let ciphersuite = group.ciphersuite();
+    confirm_commit(provider, group, ciphersuite, exporter_length)?;
+    Ok(welcome)
+}
+
+/// Stage adding the provided key packages without merging the commit and return the Welcome.
+///
+/// The Welcome must only be acted upon once the commit is confirmed with `confirm_commit`.
+///
+/// Example:
+///
+/// ```ignore
+/// let welcome = stage_add_members(&provider, &mut group, &kps)?;
+/// ```
+pub fn stage_add_members(
+    provider: &DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    check_membership_policy(provider, group, &kps_identities(kps), 0)?;
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (_, welcome, _) = group.add_members_without_update(provider, provider, kps)?;
+    Ok(welcome)
+}
+
+/// The artifacts of a membership change, as base64: the commit for existing members, the
+/// Welcome for new members (if any were added) and the GroupInfo of the new epoch (if any).
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct MembershipChangeBundle {
+    /// The commit, for existing members.
+    pub commit: String,
+    /// The Welcome, for new members.
+    pub welcome: Option<String>,
+    /// The GroupInfo of the new epoch, e.g. for observers.
+    pub group_info: Option<String>,
+    /// The Welcome split per new member (see `split_welcome`).
+    pub recipient_welcomes: Vec<RecipientWelcome>,
+}
+
+impl MembershipChangeBundle {
+    /// Encode the messages of a membership change.
+    fn new(
+        commit: &MlsMessageOut,
+        welcome: Option<&MlsMessageOut>,
+        group_info: Option<GroupInfo>,
+        recipient_welcomes: Vec<RecipientWelcome>,
+    ) -> Result<Self, Box<dyn Error>> {
+        Ok(Self {
+            commit: Blob.encode(commit.tls_serialize_detached()?),
+            welcome: match welcome {
+                Some(welcome) => Some(Blob.encode(welcome.tls_serialize_detached()?)),
+                None => None,
+            },
+            group_info: match group_info {
+                Some(group_info) => {
+                    Some(Blob.encode(MlsMessageOut::from(group_info).tls_serialize_detached()?))
+                }
+                None => None,
+            },
+            recipient_welcomes,
+        })
+    }
+
+    /// Return the artifacts as `commit: <b64>`, `welcome: <b64>`, `group-info: <b64>` and
+    /// `welcome <identity>: <b64>` lines, leaving out absent ones.
+    ///
+    /// Per-recipient Welcomes are labeled with the key package reference when the identity is
+    /// unknown.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// println!("{}", bundle.labeled());
+    /// ```
+    pub fn labeled(&self) -> String {
+        let mut lines = vec![format!("commit: {}", self.commit)];
+        if let Some(welcome) = &self.welcome {
+            lines.push(format!("welcome: {welcome}"));
+        }
+        if let Some(group_info) = &self.group_info {
+            lines.push(format!("group-info: {group_info}"));
+        }
+        for recipient in &self.recipient_welcomes {
+            let label = recipient
+                .identity
+                .as_ref()
+                .unwrap_or(&recipient.key_package_ref);
+            lines.push(format!("welcome {label}: {}", recipient.welcome));
+        }
+        lines.join("\n")
+    }
+}
+
+/// Add the provided key packages like `force_add_members` (or, with `stage`, like
+/// `stage_add_members`) and return all artifacts of the change.
+///
+/// Example:
+///
+/// ```ignore
+/// let bundle = add_members_bundle(&mut provider, &mut group, &kps, false)?;
+/// println!("{}", bundle.labeled());
+/// ```
+pub fn add_members_bundle(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+    stage: bool,
+) -> Result<MembershipChangeBundle, Box<dyn Error>> {
+    check_membership_policy(provider, group, &kps_identities(kps), 0)?;
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (commit, welcome, group_info) =
+        group.add_members_without_update(provider, provider, kps)?;
+    if !stage {
+        merge_pending_commit(provider, group)?;
+    }
+    let recipient_welcomes = split_welcome(provider, &welcome, kps)?;
+    MembershipChangeBundle::new(&commit, Some(&welcome), group_info, recipient_welcomes)
+}
+
+/// A Welcome carrying only the group secrets of one new member, as base64.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct RecipientWelcome {
+    /// Hash reference of the key package the group secrets are encrypted to.
+    pub key_package_ref: String,
+    /// Credential identity of the new member, if its key package was given.
+    pub identity: Option<String>,
+    /// The Welcome.
+    pub welcome: String,
+}
+
+/// Split a Welcome for several new members into one Welcome per member, so each is only sent
+/// its own group secrets; members are identified through the key packages they were added with.
+///
+/// Example:
+///
+/// ```ignore
+/// for recipient in split_welcome(&provider, &welcome, &kps)? {
+///     ds.post_messages(&Blob.decode(recipient.identity.unwrap())?, &[recipient.welcome])?;
+/// }
+/// ```
+pub fn split_welcome(
+    provider: &DmlsProvider,
+    welcome: &MlsMessageOut,
+    kps: &[KeyPackage],
+) -> Result<Vec<RecipientWelcome>, Box<dyn Error>> {
+    let MlsMessageBodyIn::Welcome(welcome) = bytes_extract(&welcome.tls_serialize_detached()?)?
+    else {
+        return Err(DmlsError::NotAWelcome.into());
+    };
+    let mut identities = Vec::with_capacity(kps.len());
+    for kp in kps {
+        identities.push((kp.hash_ref(provider.crypto())?, kp_identity(kp)));
+    }
+    let mut recipients = Vec::with_capacity(welcome.secrets().len());
+    for secrets in welcome.secrets() {
+        // a Welcome message with the single secrets entry
+        let mut welcome_bytes = ProtocolVersion::Mls10.tls_serialize_detached()?;
+        welcome_bytes.extend(WELCOME_WIRE_FORMAT.tls_serialize_detached()?);
+        welcome_bytes.extend(welcome.ciphersuite().tls_serialize_detached()?);
+        welcome_bytes.extend(vec![secrets.clone()].tls_serialize_detached()?);
+        welcome_bytes.extend(
+            VLBytes::new(welcome.encrypted_group_info().to_vec()).tls_serialize_detached()?,
+        );
+        // make sure it still parses
+        drop(bytes_extract(&welcome_bytes)?);
+        let kp_ref = secrets.new_member();
+        recipients.push(RecipientWelcome {
+            key_package_ref: Blob.encode(kp_ref.as_slice()),
+            identity: identities
+                .iter()
+                .find(|(hash_ref, _)| *hash_ref == kp_ref)
+                .map(|(_, identity)| Blob.encode(identity)),
+            welcome: Blob.encode(welcome_bytes),
+        });
+    }
+    Ok(recipients)
+}
+
+/// Add the provided key packages to the group and return the commit and Welcome as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let (commit_b64, welcome_b64) = add_members_base64(&mut provider, &mut group, &kps, cs, 32)?;
+/// ```
+pub fn add_members_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<(String, String), Box<dyn Error>> {
+    let (commit, welcome) = add_members(provider, group, kps, ciphersuite, exporter_length)?;
+    Ok((
+        Blob.encode(commit.tls_serialize_detached()?),
+        Blob.encode(welcome.tls_serialize_detached()?),
+    ))
+}
+
+/// Add the provided key packages to the group, merge the commit and return the commit (for
+/// existing members) and the Welcome (for the new members).
+///
+/// Unlike `force_add_members`, the commit is returned, so this also works for groups that
+/// already have members. The exporter PSK of the new epoch is stored.
+///
+/// Example:
+///
+/// ```ignore
+/// let (commit, welcome) = add_members(&mut provider, &mut group, &kps, ciphersuite, 32)?;
+/// ```
+pub fn add_members(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    kps: &[KeyPackage],
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<(MlsMessageOut, MlsMessageOut), Box<dyn Error>> {
+    check_membership_policy(provider, group, &kps_identities(kps), 0)?;
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (commit, welcome, _) = group.add_members_without_update(provider, provider, kps)?;
+    merge_pending_commit(provider, group)?;
+    drop(store_exporter_psk(
+        provider,
+        group,
+        ciphersuite,
+        exporter_length,
+    )?);
+    Ok((commit, welcome))
+}
+
+/// Remove the members with the given credential identities from the group and return the
+/// commit as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = remove_members_base64(&mut provider, &mut group, &identities, cs, 32)?;
+/// ```
+pub fn remove_members_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    identities: &[Vec<u8>],
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(
+        remove_members(provider, group, identities, ciphersuite, exporter_length)?
+            .tls_serialize_detached()?,
+    ))
+}
+
+/// Remove the members with the given credential identities from the group, merge the commit
+/// and return it.
+///
+/// The commit must be delivered to all previous members, including the removed ones, so they
+/// learn about their removal. The exporter PSK of the new epoch is stored.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = remove_members(&mut provider, &mut group, &identities, ciphersuite, 32)?;
+/// ```
+pub fn remove_members(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    identities: &[Vec<u8>],
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    let leaves = group
+        .members()
+        .filter(|m| m.index != group.own_leaf_index())
+        .filter(|m| identities.contains(&m.credential.serialized_content().to_vec()))
+        .map(|m| m.index)
+        .collect::<Vec<_>>();
+    if leaves.is_empty() {
+        return Err(DmlsError::NoMatchingMembers.into());
+    }
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (commit, _, _) = group.remove_members(provider, provider, &leaves)?;
+    merge_pending_commit(provider, group)?;
+    drop(store_exporter_psk(
+        provider,
+        group,
+        ciphersuite,
+        exporter_length,
+    )?);
+    Ok(commit)
+}
+
+/// Return the current send-group (the group's id stored in `DmlsState`) loaded from storage.
+///
+/// Returns an error if no send-group id is set or if the group cannot be loaded.
+///
+/// Example:
+///
+/// ```ignore
+/// let group = send_group(&provider)?;
+/// ```
+pub fn send_group(provider: &DmlsProvider) -> Result<MlsGroup, Box<dyn Error>> {
+    match provider.state().send_group_id() {
+        None => Err(DmlsError::NoSendGroup.into()),
+        Some(send_group_id) => Ok(MlsGroup::load(provider.storage(), &send_group_id)?.unwrap()),
+    }
+}
+
+/// Load a local group by id.
+///
+/// Returns an error if the group cannot be found in storage.
+///
+/// Example:
+///
+/// ```ignore
+/// let group = load_group(&provider, &group_id)?;
+/// ```
+pub fn load_group(provider: &DmlsProvider, group_id: &GroupId) -> Result<MlsGroup, Box<dyn Error>> {
+    match MlsGroup::load(provider.storage(), group_id)? {
+        Some(group) => Ok(group),
+        None => Err(DmlsError::GroupNotFound.into()),
+    }
+}
+
+/// Return the ids of all locally stored groups, sorted by their storage key.
+///
+/// Example:
+///
+/// ```ignore
+/// for group_id in stored_group_ids(&provider)? {
+///     let group = load_group(&provider, &group_id)?;
+/// }
+/// ```
+pub fn stored_group_ids(provider: &DmlsProvider) -> Result<Vec<GroupId>, Box<dyn Error>> {
+    Ok(provider
+        .state()
+        .openmls_values()
+        .keys_for_label(GROUP_CONTEXT_LABEL)
+        .iter()
+        .map(|key| serde_json::from_slice(key))
+        .collect::<Result<_, _>>()?)
+}
+
+/// Statistics and health of a group, e.g. to decide when a full-path update is due.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct GroupStats {
+    /// Group id, encoded like blobs.
+    pub group_id: String,
+    /// Current epoch.
+    pub epoch: u64,
+    /// Number of members.
+    pub members: usize,
+    /// Shape and health of the ratchet tree.
+    #[serde(flatten)]
+    pub tree: TreeStats,
+    /// Number of pending proposals.
+    pub pending_proposals: usize,
+    /// Whether a commit is pending (staged but neither confirmed nor aborted).
+    pub pending_commit: bool,
+    /// Number of retained resumption PSKs.
+    pub resumption_psks: usize,
+    /// Approximate storage footprint of the group in bytes, as persisted.
+    pub storage_bytes: usize,
+}
+
+/// Compute the statistics of a group (see `GroupStats`).
+///
+/// The storage footprint counts every stored entry whose key mentions the group id: group
+/// state, tree, secrets, epoch key pairs and queued proposals.
+///
+/// Example:
+///
+/// ```ignore
+/// let stats = group_stats(&provider, &group)?;
+/// println!("{} blank nodes", stats.tree.blank_nodes);
+/// ```
+pub fn group_stats(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+) -> Result<GroupStats, Box<dyn Error>> {
+    let group_key = serde_json::to_vec(group.group_id())?;
+    let storage_bytes = provider
+        .state()
+        .openmls_values()
+        .entry_sizes()
+        .into_iter()
+        .filter(|(_, key, _)| {
+            key.windows(group_key.len())
+                .any(|window| window == group_key.as_slice())
+        })
+        .map(|(_, _, size)| size)
+        .sum();
+    Ok(GroupStats {
+        group_id: Blob.encode(group.group_id().as_slice()),
+        epoch: group.epoch().as_u64(),
+        members: group.members().count(),
+        tree: TreeStats::new(group)?,
+        pending_proposals: group.pending_proposals().count(),
+        pending_commit: group.pending_commit().is_some(),
+        resumption_psks: resumption_psk_epochs(group).len(),
+        storage_bytes,
+    })
+}
+
+/// Export the group's ratchet tree, TLS-serialized as in RFC 9420, as base64.
+///
+/// Joiners need this to process a Welcome of a group that doesn't use the ratchet_tree
+/// extension.
+///
+/// Example:
+///
+/// ```ignore
+/// let tree_b64 = export_ratchet_tree_base64(&group)?;
+/// ```
+pub fn export_ratchet_tree_base64(group: &MlsGroup) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(group.export_ratchet_tree().tls_serialize_detached()?))
+}
+
+/// Export a signed GroupInfo of the group as base64, optionally carrying the ratchet tree.
+///
+/// Observers start following a group from it (see `observe::start`).
+///
+/// Example:
+///
+/// ```ignore
+/// let group_info_b64 = export_group_info_base64(&provider, &group, true)?;
+/// ```
+pub fn export_group_info_base64(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    with_ratchet_tree: bool,
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(
+        group
+            .export_group_info(provider.crypto(), provider, with_ratchet_tree)?
+            .tls_serialize_detached()?,
+    ))
+}
+
+/// Deserialize a base64-encoded, TLS-serialized ratchet tree.
+///
+/// Example:
+///
+/// ```ignore
+/// let tree = ratchet_tree_from_base64(&tree_b64)?;
+/// ```
+pub fn ratchet_tree_from_base64(s: &str) -> Result<RatchetTreeIn, Box<dyn Error>> {
+    Ok(RatchetTreeIn::tls_deserialize_exact(Blob.decode(s)?)?)
+}
+
+/// Load the group with the given base64-encoded id, or the send-group if no id is given.
+///
+/// Example:
+///
+/// ```ignore
+/// let group = group_base64(&provider, Some("AAEC..."))?;
+/// ```
+pub fn group_base64(
+    provider: &DmlsProvider,
+    group_id: Option<&str>,
+) -> Result<MlsGroup, Box<dyn Error>> {
+    match group_id {
+        None => send_group(provider),
+        Some(group_id) => load_group(provider, &GroupId::from_slice(&Blob.decode(group_id)?)),
+    }
+}
+
+/// Wire format of outgoing handshake (proposal and commit) messages.
+///
+/// Application messages are always encrypted. Incoming handshake messages are accepted in either
+/// wire format, so members process a group's commits the same way whichever format its creator
+/// chose.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum HandshakeWireFormat {
+    /// `PublicMessage`, readable (but not forgeable) by the delivery service.
+    Public,
+    /// `PrivateMessage`, encrypted to the group.
+    #[default]
+    Private,
+}
+
+impl FromStr for HandshakeWireFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "public" => Ok(Self::Public),
+            "private" => Ok(Self::Private),
+            _ => Err(format!("Unknown handshake wire format: {s}")),
+        }
+    }
+}
+
+impl HandshakeWireFormat {
+    /// The OpenMLS wire format policy: this outgoing format, either incoming format.
+    pub fn policy(self) -> WireFormatPolicy {
+        match self {
+            Self::Public => MIXED_PLAINTEXT_WIRE_FORMAT_POLICY,
+            Self::Private => MIXED_CIPHERTEXT_WIRE_FORMAT_POLICY,
+        }
+    }
+}
+
+/// Group configuration chosen at creation time, besides the ciphersuite.
+///
+/// The defaults match what groups were always created with: the ratchet tree is sent in Welcomes,
+/// no past epoch secrets are kept, no capabilities are required, the creator's leaf node gets the
+/// OpenMLS default lifetime, handshake messages are encrypted, and exporter PSKs are 32 bytes.
+#[derive(Clone, Debug)]
+pub struct GroupConfigOptions {
+    /// Whether Welcomes carry the ratchet tree (ratchet_tree extension).
+    pub ratchet_tree_extension: bool,
+    /// Number of past epochs whose message secrets are kept to decrypt late messages.
+    pub max_past_epochs: usize,
+    /// Extension types every member must support (required_capabilities extension).
+    pub required_extensions: Vec<ExtensionType>,
+    /// Lifetime of the creator's leaf node in seconds, if not the OpenMLS default.
+    pub lifetime: Option<u64>,
+    /// Wire format of outgoing handshake messages.
+    pub handshake_wire_format: HandshakeWireFormat,
+    /// Exporter PSK parameters, recorded for the group in the state.
+    pub exporter: ExporterParams,
+    /// Whether message secrets are deleted as soon as they are used (see `set_per_message_fs`).
+    pub per_message_fs: bool,
+}
+
+impl Default for GroupConfigOptions {
+    fn default() -> Self {
+        Self {
+            ratchet_tree_extension: true,
+            max_past_epochs: 0,
+            required_extensions: Vec::new(),
+            lifetime: None,
+            handshake_wire_format: HandshakeWireFormat::default(),
+            exporter: ExporterParams::new(32),
+            per_message_fs: false,
+        }
+    }
+}
+
+impl GroupConfigOptions {
+    /// Build the OpenMLS create config for a group with the given ciphersuite and the creator's
+    /// leaf node capabilities.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let capabilities = capabilities(provider.state().capabilities(), ciphersuite)?;
+    /// let config = GroupConfigOptions::default().create_config(ciphersuite, capabilities)?;
+    /// ```
+    pub fn create_config(
+        &self,
+        ciphersuite: Ciphersuite,
+        capabilities: Capabilities,
+    ) -> Result<MlsGroupCreateConfig, Box<dyn Error>> {
+        let mut builder = MlsGroupCreateConfig::builder()
+            .ciphersuite(ciphersuite)
+            .use_ratchet_tree_extension(self.ratchet_tree_extension)
+            .max_past_epochs(self.max_past_epochs)
+            .wire_format_policy(self.handshake_wire_format.policy())
+            .number_of_resumption_psks(RESUMPTION_PSK_RETENTION)
+            .sender_ratchet_configuration(sender_ratchet_configuration(self.per_message_fs))
+            .capabilities(capabilities);
+        if !self.required_extensions.is_empty() {
+            builder = builder.with_group_context_extensions(Extensions::single(
+                Extension::RequiredCapabilities(RequiredCapabilitiesExtension::new(
+                    &self.required_extensions,
+                    &[],
+                    &[],
+                )),
+            ))?;
+        }
+        if let Some(lifetime) = self.lifetime {
+            builder = builder.lifetime(Lifetime::new(lifetime));
+        }
+        Ok(builder.build())
+    }
+}
+
+/// Returns the sender ratchet configuration for per-message forward secrecy (no out-of-order
+/// tolerance) or the OpenMLS default.
+fn sender_ratchet_configuration(per_message_fs: bool) -> SenderRatchetConfiguration {
+    let default = SenderRatchetConfiguration::default();
+    if per_message_fs {
+        SenderRatchetConfiguration::new(0, default.maximum_forward_distance())
+    } else {
+        default
+    }
+}
+
+/// Returns whether the group deletes message secrets as soon as they are used (see
+/// `set_per_message_fs`).
+///
+/// Example:
+///
+/// ```ignore
+/// if per_message_fs(&group) { tracing::info!("Late messages will be dropped"); }
+/// ```
+pub fn per_message_fs(group: &MlsGroup) -> bool {
+    group
+        .configuration()
+        .sender_ratchet_configuration()
+        .out_of_order_tolerance()
+        == 0
+}
+
+/// Select per-message forward secrecy for a group, or go back to the OpenMLS default.
+///
+/// With per-message forward secrecy, the ratchet secret of an application message is deleted as
+/// soon as the message is decrypted, and no secrets of skipped generations are kept for messages
+/// arriving out of order, which then fail to decrypt. Secrets of past epochs are kept as set with
+/// `max_past_epochs` either way.
+///
+/// Example:
+///
+/// ```ignore
+/// set_per_message_fs(&provider, &mut group, true)?;
+/// ```
+pub fn set_per_message_fs(
+    provider: &DmlsProvider,
+    group: &mut MlsGroup,
+    enabled: bool,
+) -> Result<(), Box<dyn Error>> {
+    // the join config has no getters for all its fields; change it through its serialized form
+    let mut config = serde_json::to_value(group.configuration())?;
+    config["sender_ratchet_configuration"] =
+        serde_json::to_value(sender_ratchet_configuration(enabled))?;
+    let config: MlsGroupJoinConfig = serde_json::from_value(config)?;
+    group.set_configuration(provider.storage(), &config)?;
+    Ok(())
+}
+
+/// Record the exporter PSK parameters of `from` (if any) for the group `to` derived from it.
+fn inherit_exporter_params(provider: &mut DmlsProvider, from: &GroupId, to: &GroupId) {
+    if let Some(params) = provider.state().exporter_params(from).cloned() {
+        provider.state_mut().set_exporter_params(to, params);
+    }
+}
+
+/// Create a new send-group and persist its id to state. Returns an error if a send-group already exists.
+///
+/// This function sets `send_group_id` in the provider state so subsequent calls to `send_group`
+/// will return the correct group instance, and records the group's exporter PSK parameters.
+///
+/// Example:
+///
+/// ```ignore
+/// let sg = gen_send_group(&mut provider, ciphersuite, &GroupConfigOptions::default())?;
+/// ```
+pub fn gen_send_group(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    options: &GroupConfigOptions,
+) -> Result<MlsGroup, Box<dyn Error>> {
+    match provider.state().send_group_id() {
+        None => {
+            check_suite_policy(provider, ciphersuite)?;
+            check_signature_scheme(
+                ciphersuite,
+                provider.state().signature_key_pair().signature_scheme(),
+            )?;
+            let group = MlsGroup::new(
+                provider,
+                provider,
+                &options.create_config(
+                    ciphersuite,
+                    capabilities(provider.state().capabilities(), ciphersuite)?,
+                )?,
+                cred_with_key(provider),
+            )?;
+            provider
+                .state_mut()
+                .set_exporter_params(group.group_id(), options.exporter.clone());
+            provider
+                .state_mut()
+                .set_send_group_id(group.group_id().clone());
+            Ok(group)
+        }
+        Some(_) => Err(DmlsError::SendGroupExists.into()),
+    }
+}
+
+/// Force a self-update (rekey) in the send-group and return the staged commit as base64.
+///
+/// The function also stores the derived exporter PSK to the PSK store. See `stage_self_update`
+/// for `full_path`.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = send_group_update_base64(&mut provider, false, ciphersuite, 32)?;
+/// ```
+pub fn send_group_update_base64(
+    provider: &mut DmlsProvider,
+    full_path: bool,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    let commit =
+        force_self_update_base64(provider, &mut sg, full_path, ciphersuite, exporter_length)?;
+    // store exporter psk
+    drop(store_exporter_psk(
+        provider,
+        &sg,
+        ciphersuite,
+        exporter_length,
+    )?);
+    // done
+    Ok(commit)
+}
+
+/// Stage a self-update in the send-group and return the commit as base64.
+///
+/// The commit stays pending until `send_group_confirm_commit` or `send_group_abort_commit`.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = send_group_stage_update_base64(&provider, false)?;
+/// ```
+pub fn send_group_stage_update_base64(
+    provider: &DmlsProvider,
+    full_path: bool,
+) -> Result<String, Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    Ok(Blob.encode(stage_self_update(provider, &mut sg, full_path)?.tls_serialize_detached()?))
+}
+
+/// Force a self-update and return the serialized commit (base64).
+///
+/// This performs a local self-update and stages & merges the commit into the group.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = force_self_update_base64(&mut provider, &mut group, false, ciphersuite, 32)?;
+/// ```
+pub fn force_self_update_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    full_path: bool,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(
+        force_self_update(provider, group, full_path, ciphersuite, exporter_length)?
+            .tls_serialize_detached()?,
+    ))
+}
+
+/// Force a self-update of the send group if the re-key policy (see `RekeyPolicy`) calls for it,
+/// returning the commit as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// if let Some(commit_b64) = rekey_if_due(&mut provider, &mut sg, ciphersuite, 32)? {
+///     println!("{commit_b64}");
+/// }
+/// ```
+pub fn rekey_if_due(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<String>, Box<dyn Error>> {
+    // only the send group's owner commits to it
+    if provider.state().send_group_id().as_ref() != Some(group.group_id())
+        || !provider.state().rekey_due(group.group_id())
+    {
+        return Ok(None);
+    }
+    tracing::info!("Re-key policy reached; updating own leaf in send group");
+    force_self_update_base64(provider, group, false, ciphersuite, exporter_length).map(Some)
+}
+
+/// Force a self-update and return the staged commit message.
+///
+/// The commit is produced by calling `self_update` on the group, staged, merged, and its
+/// corresponding exporter PSK will be stored. The resulting `MlsMessageOut` should be sent
+/// to other group members to finalize the update. With `full_path`, the whole direct path is
+/// refreshed (see `stage_self_update`).
+///
+/// Example:
+///
+/// ```ignore
+/// let staged_commit = force_self_update(&mut provider, &mut group, true, ciphersuite, 32)?;
+/// ```
+pub fn force_self_update(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    full_path: bool,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    let commit = stage_self_update(provider, group, full_path)?;
+    merge_pending_commit(provider, group)?;
+    drop(store_exporter_psk(
+        provider,
+        group,
+        ciphersuite,
+        exporter_length,
+    )?);
+    Ok(commit)
+}
+
+/// Stage a self-update without merging the commit and return the commit message.
+///
+/// With `full_path`, the commit is built to always carry an update path with a freshly
+/// generated leaf (re-advertising the configured capabilities, see `capabilities`), so every
+/// node on the filtered direct path gets new keys and any blank nodes on it are filled. This
+/// costs one HPKE encryption per copath subtree but gives the strongest post-compromise
+/// security an update can; without it, OpenMLS' default self-update is used.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = stage_self_update(&provider, &mut group, true)?;
+/// ```
+pub fn stage_self_update(
+    provider: &DmlsProvider,
+    group: &mut MlsGroup,
+    full_path: bool,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (commit, _, _) = if full_path {
+        let leaf_node_parameters = LeafNodeParameters::builder()
+            .with_capabilities(capabilities(
+                provider.state().capabilities(),
+                group.ciphersuite(),
+            )?)
+            .build();
+        group
+            .commit_builder()
+            .force_self_update(true)
+            .leaf_node_parameters(leaf_node_parameters)
+            .load_psks(provider.storage())?
+            .build(provider.rand(), provider.crypto(), provider, |_| true)?
+            .stage_commit(provider)?
+            .into_messages()
+    } else {
+        group
+            .self_update(provider, provider, LeafNodeParameters::builder().build())?
+            .into_messages()
+    };
+    Ok(commit)
+}
+
+/// Replace the agent's credential identity, updating its leaf in the send group (if any) in
+/// place, and return the commit as base64.
+///
+/// The signature key stays the same, so members accept the new credential without a
+/// continuity proof (see `continuity`); new key packages and groups use the new identity too.
+/// Members address the agent by its identity, and an explicit admin list (see `roles`) naming
+/// the old identity has to be updated by another admin.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = update_identity_base64(&mut provider, b"alice".to_vec(), ciphersuite, 32)?;
+/// ```
+pub fn update_identity_base64(
+    provider: &mut DmlsProvider,
+    identity: Vec<u8>,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<Option<String>, Box<dyn Error>> {
+    if identity.is_empty() {
+        return Err("Identity must not be empty".into());
+    }
+    let commit = match provider.state().send_group_id() {
+        None => None,
+        Some(_) => {
+            let mut sg = send_group(provider)?;
+            if member_identities(&sg).contains(&identity) {
+                return Err("Identity already used by another member of the send group".into());
+            }
+            let leaf_node_parameters = LeafNodeParameters::builder()
+                .with_credential_with_key(credential_for(
+                    provider.state().signature_key_pair(),
+                    Some(&identity),
+                ))
+                .with_capabilities(capabilities(
+                    provider.state().capabilities(),
+                    sg.ciphersuite(),
+                )?)
+                .build();
+            discard_pending_commit(provider, &mut sg)?;
+            sg.clear_pending_proposals(provider.storage())?;
+            let (commit, _, _) = sg
+                .self_update(provider, provider, leaf_node_parameters)?
+                .into_messages();
+            merge_pending_commit(provider, &mut sg)?;
+            drop(store_exporter_psk(
+                provider,
+                &sg,
+                ciphersuite,
+                exporter_length,
+            )?);
+            Some(Blob.encode(commit.tls_serialize_detached()?))
+        }
+    };
+    provider.state_mut().set_identity(identity);
+    Ok(commit)
+}
+
+/// Leaf node capabilities for key packages, created groups and updated leaves.
+///
+/// They are the configured ones (see `CapabilitiesOptions`), always advertising the agent's
+/// custom extensions (the admin list, see `roles`, and continuity proofs, see `continuity`) and,
+/// if ciphersuites are configured, the ciphersuite in use.
+///
+/// Example:
+///
+/// ```ignore
+/// let bundle = KeyPackage::builder()
+///     .leaf_node_capabilities(capabilities(provider.state().capabilities(), ciphersuite)?)
+///     .build(ciphersuite, &provider, &provider, cred_with_key(&provider))?;
+/// ```
+pub fn capabilities(
+    options: &CapabilitiesOptions,
+    ciphersuite: Ciphersuite,
+) -> Result<Capabilities, Box<dyn Error>> {
+    let versions = options
+        .versions
+        .iter()
+        .map(|&version| match version {
+            1 => Ok(ProtocolVersion::Mls10),
+            _ => Err(format!("Unsupported protocol version {version}")),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut ciphersuites = options
+        .ciphersuites
+        .iter()
+        .map(|&ciphersuite| {
+            Ciphersuite::try_from(ciphersuite)
+                .map_err(|_| format!("Unknown ciphersuite {ciphersuite}"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if !ciphersuites.is_empty() && !ciphersuites.contains(&ciphersuite) {
+        ciphersuites.push(ciphersuite);
+    }
+    let mut extensions = vec![
+        ExtensionType::Unknown(ADMINS_EXTENSION_TYPE),
+        ExtensionType::Unknown(CONTINUITY_EXTENSION_TYPE),
+    ];
+    for &extension_type in &options.extensions {
+        let extension_type = ExtensionType::from(extension_type);
+        if !extensions.contains(&extension_type) {
+            extensions.push(extension_type);
+        }
+    }
+    let credentials = options
+        .credentials
+        .iter()
+        .map(|&credential_type| CredentialType::from(credential_type))
+        .collect::<Vec<_>>();
+    // empty lists fall back to the OpenMLS defaults
+    Ok(Capabilities::new(
+        (!versions.is_empty()).then_some(versions.as_slice()),
+        (!ciphersuites.is_empty()).then_some(ciphersuites.as_slice()),
+        Some(&extensions),
+        None,
+        (!credentials.is_empty()).then_some(credentials.as_slice()),
+    ))
+}
+
+/// Check that the crypto backend supports the ciphersuite and that it signs with the scheme of
+/// the agent's signing key.
+///
+/// Example:
+///
+/// ```ignore
+/// check_ciphersuite(&crypto, ciphersuite, state.signature_key_pair().signature_scheme())?;
+/// ```
+pub fn check_ciphersuite(
+    crypto: &impl OpenMlsCrypto,
+    ciphersuite: Ciphersuite,
+    signature_scheme: SignatureScheme,
+) -> Result<(), Box<dyn Error>> {
+    if crypto.supports(ciphersuite).is_err() {
+        return Err(format!("{ciphersuite:?} is not supported by the crypto backend").into());
+    }
+    check_signature_scheme(ciphersuite, signature_scheme)
+}
+
+/// Check a ciphersuite, and the signature scheme it signs with, against the state's allow-lists
+/// (see `DmlsState::suite_policy`), so the agent doesn't create, join or invite others into a
+/// group using a suite its policy rejects; empty allow-lists accept everything.
+///
+/// Example:
+///
+/// ```ignore
+/// check_suite_policy(&provider, kp.ciphersuite())?;
+/// ```
+pub fn check_suite_policy(
+    provider: &DmlsProvider,
+    ciphersuite: Ciphersuite,
+) -> Result<(), Box<dyn Error>> {
+    let policy = provider.state().suite_policy();
+    let accepts = |allowed: &[u16], value: u16| allowed.is_empty() || allowed.contains(&value);
+    if !accepts(&policy.ciphersuites, ciphersuite.into())
+        || !accepts(
+            &policy.signature_schemes,
+            ciphersuite.signature_algorithm() as u16,
+        )
+    {
+        return Err(DmlsError::CiphersuiteNotAllowed(format!("{ciphersuite:?}")).into());
+    }
+    Ok(())
+}
+
+/// Check that a key package or leaf node lifetime covers the current time, tolerating clocks that
+/// differ by up to the provider's clock skew (see `DmlsProvider::with_clock_skew`).
+///
+/// Lifetimes only missed by less than the tolerance pass with a warning; the check is skipped
+/// entirely if the provider ignores lifetimes (see `DmlsProvider::with_ignore_lifetime`). This
+/// comes in addition to any checks OpenMLS performs itself.
+///
+/// Example:
+///
+/// ```ignore
+/// check_lifetime(&provider, kp.life_time())?;
+/// ```
+pub fn check_lifetime(provider: &DmlsProvider, lifetime: &Lifetime) -> Result<(), Box<dyn Error>> {
+    if provider.ignore_lifetime() {
+        return Ok(());
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let skew = provider.clock_skew();
+    let (not_before, not_after) = (lifetime.not_before(), lifetime.not_after());
+    if now.saturating_add(skew) < not_before {
+        return Err(DmlsError::LifetimeNotYetValid(not_before - now).into());
+    }
+    if now.saturating_sub(skew) > not_after {
+        return Err(DmlsError::LifetimeExpired(now - not_after).into());
+    }
+    if now < not_before {
+        tracing::warn!(
+            "Lifetime starts in {}s; accepted within the clock skew tolerance",
+            not_before - now
+        );
+    } else if now > not_after {
+        tracing::warn!(
+            "Lifetime expired {}s ago; accepted within the clock skew tolerance",
+            now - not_after
+        );
+    }
+    Ok(())
+}
+
+/// Check that a signing key of the scheme can be used with the ciphersuite, before OpenMLS fails
+/// on the mismatch deep inside an operation.
+///
+/// Example:
+///
+/// ```ignore
+/// check_signature_scheme(ciphersuite, provider.state().signature_key_pair().signature_scheme())?;
+/// ```
+pub fn check_signature_scheme(
+    ciphersuite: Ciphersuite,
+    signature_scheme: SignatureScheme,
+) -> Result<(), Box<dyn Error>> {
+    if ciphersuite.signature_algorithm() != signature_scheme {
+        return Err(DmlsError::SignatureSchemeMismatch {
+            key: signature_scheme_name(signature_scheme),
+            ciphersuite: format!(
+                "{} ciphersuite {ciphersuite:?}",
+                signature_scheme_name(ciphersuite.signature_algorithm())
+            ),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+/// Verify a signature (encoded like blobs) by a public key (encoded like blobs) over a payload,
+/// or over the MLS `SignWithLabel` payload for the content if a label is given.
+///
+/// Example:
+///
+/// ```ignore
+/// verify_signature_base64(&crypto, scheme, &pk_b64, &sig_b64, &tbs, Some("LeafNodeTBS"))?;
+/// ```
+pub fn verify_signature_base64(
+    crypto: &impl OpenMlsCrypto,
+    signature_scheme: SignatureScheme,
+    public_key: &str,
+    signature: &str,
+    payload: &[u8],
+    label: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let public_key = SignaturePublicKey::from(Blob.decode(public_key)?);
+    let payload = match label {
+        Some(label) => labeled_payload(label, payload)?,
+        None => payload.to_vec(),
+    };
+    public_key
+        .verify(crypto, signature_scheme, &payload, &Blob.decode(signature)?)
+        .map_err(|e| format!("{e:?}").into())
+}
+
+/// Generate a KeyPackage for the provider's credential and return it as a base64 blob.
+///
+/// KeyPackages are used when adding members to MLS groups; the producer of a KeyPackage
+/// should distribute the base64 string to the group creator who will validate and include it.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let welcome = force_add_members(&provider, &mut group, &kps)?;
+/// let kp_b64 = gen_kp_base64(&provider, ciphersuite)?;
+/// println!("{}", kp_b64);
 /// ```
-pub fn force_add_members(
+pub fn gen_kp_base64(
     provider: &DmlsProvider,
-    group: &mut MlsGroup,
-    kps: &[KeyPackage],
-) -> Result<MlsMessageOut, Box<dyn Error>> {
-    group.clear_pending_commit(provider.storage())?;
-    group.clear_pending_proposals(provider.storage())?;
-    let (_, welcome, _) = group.add_members_without_update(provider, provider, kps)?;
-    group.merge_pending_commit(provider)?;
-    Ok(welcome)
+    ciphersuite: Ciphersuite,
+) -> Result<String, Box<dyn Error>> {
+    Ok(Blob.encode(gen_kp(provider, ciphersuite)?.tls_serialize_detached()?))
 }
 
-/// Return the current send-group (the group's id stored in `DmlsState`) loaded from storage.
-///
-/// Returns an error if no send-group id is set or if the group cannot be loaded.
+/// Generate a KeyPackage like `gen_kp_base64` and record it in the state, to explain Welcomes
+/// addressed to it once its private part is gone (see `DmlsState::record_generated_kp`).
 ///
 /// Example:
 ///
 /// ```ignore
-/// let group = send_group(&provider)?;
+/// let kp_b64 = gen_recorded_kp_base64(&mut provider, ciphersuite)?;
 /// ```
-pub fn send_group(provider: &DmlsProvider) -> Result<MlsGroup, Box<dyn Error>> {
-    match provider.state().send_group_id() {
-        None => Err("No send group exists".into()),
-        Some(send_group_id) => Ok(MlsGroup::load(provider.storage(), &send_group_id)?.unwrap()),
-    }
+pub fn gen_recorded_kp_base64(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+) -> Result<String, Box<dyn Error>> {
+    let kp = gen_kp(provider, ciphersuite)?;
+    let kp_ref = kp.hash_ref(provider.crypto())?;
+    provider
+        .state_mut()
+        .record_generated_kp(kp_ref.as_slice().to_vec(), ciphersuite.into());
+    Ok(Blob.encode(kp.tls_serialize_detached()?))
 }
 
-/// Create a new send-group and persist its id to state. Returns an error if a send-group already exists.
+/// Generate a KeyPackage for the provider's credential, storing its private part.
+fn gen_kp(provider: &DmlsProvider, ciphersuite: Ciphersuite) -> Result<KeyPackage, Box<dyn Error>> {
+    check_suite_policy(provider, ciphersuite)?;
+    check_signature_scheme(
+        ciphersuite,
+        provider.state().signature_key_pair().signature_scheme(),
+    )?;
+    Ok(KeyPackage::builder()
+        .leaf_node_capabilities(capabilities(provider.state().capabilities(), ciphersuite)?)
+        .build(ciphersuite, provider, provider, cred_with_key(provider))?
+        .key_package()
+        .clone())
+}
+
+/// Return the epochs of the group whose resumption PSKs are still retained, oldest first.
 ///
-/// This function sets `send_group_id` in the provider state so subsequent calls to `send_group`
-/// will return the correct group instance.
+/// Example:
+///
+/// ```ignore
+/// for epoch in resumption_psk_epochs(&group) {
+///     println!("{epoch}");
+/// }
+/// ```
+pub fn resumption_psk_epochs(group: &MlsGroup) -> Vec<u64> {
+    let current = group.epoch().as_u64();
+    (current.saturating_sub(RESUMPTION_PSK_RETENTION as u64)..=current)
+        .filter(|&epoch| {
+            group
+                .get_past_resumption_psk(GroupEpoch::from(epoch))
+                .is_some()
+        })
+        .collect()
+}
+
+/// Queue the resumption PSKs of the given epochs of the send group for injection with its next
+/// commit.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let sg = gen_send_group(&mut provider, ciphersuite)?;
+/// queue_resumption_psks(&mut provider, &[3, 4])?;
 /// ```
-pub fn gen_send_group(
+pub fn queue_resumption_psks(
     provider: &mut DmlsProvider,
-    ciphersuite: Ciphersuite,
-) -> Result<MlsGroup, Box<dyn Error>> {
-    match provider.state().send_group_id() {
-        None => {
-            let group = MlsGroup::new(
-                provider,
-                provider,
-                &MlsGroupCreateConfig::builder()
-                    .ciphersuite(ciphersuite)
-                    .use_ratchet_tree_extension(true)
-                    .build(),
-                cred_with_key(provider),
-            )?;
-            provider
-                .state_mut()
-                .set_send_group_id(group.group_id().clone());
-            Ok(group)
+    epochs: &[u64],
+) -> Result<(), Box<dyn Error>> {
+    let retained = resumption_psk_epochs(&send_group(provider)?);
+    for &epoch in epochs {
+        if !retained.contains(&epoch) {
+            return Err(format!("No resumption PSK retained for epoch {epoch}").into());
         }
-        Some(_) => Err("Send group already exists".into()),
+        provider.state_mut().push_resumption_psk_epoch(epoch);
     }
+    Ok(())
 }
 
-/// Force a self-update (rekey) in the send-group and return the staged commit as base64.
+/// Return the resumption PSK secret of the given epoch of the group.
 ///
-/// The function also stores the derived exporter PSK to the PSK store.
+/// The secret is looked up in the group's resumption PSK store, so only epochs still retained by
+/// the store are available. Groups set up to retain fewer than `RESUMPTION_PSK_RETENTION` epochs
+/// (joined or created before dmls set the retention) fail with
+/// `DmlsError::ResumptionPskNotRetained` instead of `DmlsError::ResumptionPskNotFound`.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let commit_b64 = send_group_update_base64(&mut provider, ciphersuite, 32)?;
+/// let secret = resumption_psk_secret(&group, group.epoch())?;
 /// ```
-pub fn send_group_update_base64(
+pub fn resumption_psk_secret(
+    group: &MlsGroup,
+    epoch: GroupEpoch,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    match group.get_past_resumption_psk(epoch) {
+        Some(secret) => Ok(secret.as_slice().to_vec()),
+        None => match resumption_psk_retention(group)? {
+            retained if retained < RESUMPTION_PSK_RETENTION => {
+                Err(DmlsError::ResumptionPskNotRetained(retained).into())
+            }
+            _ => Err(DmlsError::ResumptionPskNotFound.into()),
+        },
+    }
+}
+
+/// Returns the number of past epochs whose resumption PSKs the group retains.
+///
+/// Example:
+///
+/// ```ignore
+/// if resumption_psk_retention(&group)? < RESUMPTION_PSK_RETENTION { /* set up before */ }
+/// ```
+pub fn resumption_psk_retention(group: &MlsGroup) -> Result<usize, Box<dyn Error>> {
+    // the join config has no getter for it; read it from its serialized form
+    match serde_json::to_value(group.configuration())?["number_of_resumption_psks"].as_u64() {
+        Some(retained) => Ok(usize::try_from(retained)?),
+        None => Err("Group configuration has no resumption PSK retention".into()),
+    }
+}
+
+/// Store a resumption PSK of the group as an external PSK so it can be used in another group.
+///
+/// OpenMLS only resolves resumption PSKs within the group they belong to, so for
+/// re-initialization and branching the secret is bridged into the new group as an external PSK
+/// with id `prefix + epoch + group id`. Members joining the new group derive the same secret from
+/// their own copy of the source group (see `resolve_bridged_resumption_psk`).
+///
+/// Example:
+///
+/// ```ignore
+/// let psk_id = store_bridged_resumption_psk(&provider, &group, group.epoch(), BRANCH_PSK_PREFIX)?;
+/// ```
+pub fn store_bridged_resumption_psk(
+    provider: &DmlsProvider,
+    group: &MlsGroup,
+    epoch: GroupEpoch,
+    prefix: &[u8],
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    // psk id = prefix + epoch + group id
+    let mut psk_id_vec = prefix.to_vec();
+    psk_id_vec.extend(epoch.as_u64().to_be_bytes());
+    psk_id_vec.extend(group.group_id().to_vec());
+    // psk secret
+    let psk_secret = resumption_psk_secret(group, epoch)?;
+    // store psk
+    PreSharedKeyId::new(
+        group.ciphersuite(),
+        provider.rand(),
+        Psk::External(ExternalPsk::new(psk_id_vec.clone())),
+    )?
+    .store(provider, &psk_secret)?;
+    // done; return psk id
+    Ok(psk_id_vec)
+}
+
+/// Derive and store the bridged resumption PSK with the given id, if it is one.
+///
+/// External PSK ids that do not start with a known bridge prefix are left alone (they are
+/// expected to be in storage already, e.g. exporter PSKs). Bridged ids whose source group is not
+/// held locally produce an error.
+///
+/// Example:
+///
+/// ```ignore
+/// resolve_bridged_resumption_psk(&provider, external_psk.psk_id())?;
+/// ```
+pub fn resolve_bridged_resumption_psk(
+    provider: &DmlsProvider,
+    psk_id: &[u8],
+) -> Result<(), Box<dyn Error>> {
+    let prefix = match [REINIT_PSK_PREFIX, BRANCH_PSK_PREFIX]
+        .into_iter()
+        .find(|prefix| psk_id.starts_with(prefix))
+    {
+        Some(prefix) => prefix,
+        None => return Ok(()),
+    };
+    let rest = &psk_id[prefix.len()..];
+    if rest.len() < 8 {
+        return Err("Malformed bridged resumption PSK id".into());
+    }
+    let (epoch, group_id) = rest.split_at(8);
+    let epoch = GroupEpoch::from(u64::from_be_bytes(epoch.try_into()?));
+    match MlsGroup::load(provider.storage(), &GroupId::from_slice(group_id))? {
+        Some(group) => {
+            drop(store_bridged_resumption_psk(
+                provider, &group, epoch, prefix,
+            )?);
+            Ok(())
+        }
+        None => Err("No local group found for bridged resumption PSK".into()),
+    }
+}
+
+/// Re-initialize the send-group under a new ciphersuite and return the ReInit commit and the
+/// Welcome for the new group, both as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let (commit_b64, welcome_b64) = send_group_reinit_base64(&mut provider, new_ciphersuite)?;
+/// println!("{}\n{}", commit_b64, welcome_b64);
+/// ```
+pub fn send_group_reinit_base64(
     provider: &mut DmlsProvider,
     ciphersuite: Ciphersuite,
-    exporter_length: usize,
-) -> Result<String, Box<dyn Error>> {
+) -> Result<(String, String), Box<dyn Error>> {
     let mut sg = send_group(provider)?;
-    let commit = force_self_update_base64(provider, &mut sg, ciphersuite, exporter_length)?;
-    // store exporter psk
-    drop(store_exporter_psk(
-        provider,
-        &sg,
-        ciphersuite,
-        exporter_length,
-    )?);
-    // done
-    Ok(commit)
+    let (commit, welcome) = reinit(provider, &mut sg, ciphersuite)?;
+    Ok((
+        Blob.encode(commit.tls_serialize_detached()?),
+        Blob.encode(welcome.tls_serialize_detached()?),
+    ))
 }
 
-/// Force a self-update and return the serialized commit (base64).
+/// The content of a ReInit proposal (RFC 9420, section 12.1.5).
+#[derive(TlsSerialize, TlsSize)]
+struct ReInit {
+    /// Id of the new group.
+    group_id: GroupId,
+    /// Protocol version of the new group.
+    version: ProtocolVersion,
+    /// Ciphersuite of the new group.
+    cipher_suite: Ciphersuite,
+    /// Group context extensions of the new group.
+    extensions: Extensions,
+}
+
+/// Build a ReInit proposal for a new group.
 ///
-/// This performs a local self-update and stages & merges the commit into the group.
+/// OpenMLS has no public constructor for `ReInitProposal`, so the proposal is decoded from its
+/// encoding.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let commit = force_self_update_base64(&mut provider, &mut group, ciphersuite, 32)?;
+/// let proposal = reinit_proposal(new_group_id, ciphersuite, group.extensions().clone())?;
 /// ```
-pub fn force_self_update_base64(
-    provider: &mut DmlsProvider,
-    group: &mut MlsGroup,
+fn reinit_proposal(
+    group_id: GroupId,
     ciphersuite: Ciphersuite,
-    exporter_length: usize,
-) -> Result<String, Box<dyn Error>> {
-    Ok(Base64.encode(
-        force_self_update(provider, group, ciphersuite, exporter_length)?
-            .tls_serialize_detached()?,
-    ))
+    extensions: Extensions,
+) -> Result<ReInitProposal, Box<dyn Error>> {
+    let reinit = ReInit {
+        group_id,
+        version: ProtocolVersion::Mls10,
+        cipher_suite: ciphersuite,
+        extensions,
+    };
+    Ok(ReInitProposal::tls_deserialize_exact(
+        &reinit.tls_serialize_detached()?,
+    )?)
 }
 
-/// Force a self-update and return the staged commit message.
+/// Re-initialize the group under a new ciphersuite.
 ///
-/// The commit is produced by calling `self_update` on the group, staged, merged, and its
-/// corresponding exporter PSK will be stored. The resulting `MlsMessageOut` should be sent
-/// to other group members to finalize the update.
+/// This commits a ReInit proposal to the old group, creates a new send-group under the new
+/// ciphersuite, and adds all current members to it using key packages from the address book
+/// (which must have been generated for the new ciphersuite); it keeps the old group's
+/// configuration and group context extensions. The new group's first commit injects the old
+/// group's resumption PSK (see `store_bridged_resumption_psk`), binding the new group to the old
+/// one. The new group becomes the send-group; the old group is kept so peers can still be
+/// processed until they have joined.
+///
+/// Returns the ReInit commit for the old group and the Welcome for the new group.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let staged_commit = force_self_update(&mut provider, &mut group, ciphersuite, 32)?;
+/// let (commit, welcome) = reinit(&mut provider, &mut group, new_ciphersuite)?;
 /// ```
-pub fn force_self_update(
+pub fn reinit(
     provider: &mut DmlsProvider,
     group: &mut MlsGroup,
     ciphersuite: Ciphersuite,
-    exporter_length: usize,
-) -> Result<MlsMessageOut, Box<dyn Error>> {
-    group.clear_pending_commit(provider.storage())?;
+) -> Result<(MlsMessageOut, MlsMessageOut), Box<dyn Error>> {
+    if group.ciphersuite() == ciphersuite {
+        return Err("Send group already uses the requested ciphersuite".into());
+    }
+    check_suite_policy(provider, ciphersuite)?;
+    // collect key packages for all other members from the address book
+    let mut address_book = provider.state().address_book().clone();
+    let mut kps = Vec::new();
+    for member in group.members() {
+        if member.index == group.own_leaf_index() {
+            continue;
+        }
+        let identity = member.credential.serialized_content();
+        match address_book_kp(provider, &mut address_book, identity, ciphersuite) {
+            Some(kp) => kps.push(kp),
+            None => {
+                return Err(format!(
+                    "No key package for member {} with the requested ciphersuite",
+                    Blob.encode(identity)
+                )
+                .into());
+            }
+        }
+    }
+    if kps.is_empty() {
+        return Err("Send group has no other members to re-initialize with".into());
+    }
+    // reinit proposal; the new group id is random, the extensions are the old group's
+    let new_group_id = GroupId::random(provider.rand());
+    let proposal = Proposal::ReInit(reinit_proposal(
+        new_group_id.clone(),
+        ciphersuite,
+        group.extensions().clone(),
+    )?);
+    // commit reinit to the old group
+    discard_pending_commit(provider, group)?;
     group.clear_pending_proposals(provider.storage())?;
     let (commit, _, _) = group
-        .self_update(provider, provider, LeafNodeParameters::builder().build())?
+        .commit_builder()
+        .add_proposal(proposal)
+        .load_psks(provider.storage())?
+        .build(provider.rand(), provider.crypto(), provider, |_| true)?
+        .stage_commit(provider)?
         .into_messages();
-    group.merge_pending_commit(provider)?;
-    drop(store_exporter_psk(
+    merge_pending_commit(provider, group)?;
+    let psk_id_vec =
+        store_bridged_resumption_psk(provider, group, group.epoch(), REINIT_PSK_PREFIX)?;
+    // new group with the old group's configuration, bound to the old one via the reinit psk
+    let mut new_group = MlsGroup::new_with_group_id(
         provider,
-        group,
-        ciphersuite,
-        exporter_length,
-    )?);
-    Ok(commit)
+        provider,
+        &MlsGroupCreateConfig::builder()
+            .ciphersuite(ciphersuite)
+            .capabilities(capabilities(provider.state().capabilities(), ciphersuite)?)
+            .with_group_context_extensions(group.extensions().clone())?
+            .build(),
+        new_group_id,
+        cred_with_key(provider),
+    )?;
+    new_group.set_configuration(provider.storage(), group.configuration())?;
+    inherit_exporter_params(provider, group.group_id(), new_group.group_id());
+    let proposal =
+        Proposal::PreSharedKey(Box::new(PreSharedKeyProposal::new(PreSharedKeyId::new(
+            ciphersuite,
+            provider.rand(),
+            Psk::External(ExternalPsk::new(psk_id_vec)),
+        )?)));
+    check_membership_policy(provider, &new_group, &kps_identities(&kps), 0)?;
+    let (_, welcome, _) = new_group
+        .commit_builder()
+        .propose_adds(kps)
+        .add_proposal(proposal)
+        .load_psks(provider.storage())?
+        .build(provider.rand(), provider.crypto(), provider, |_| true)?
+        .stage_commit(provider)?
+        .into_messages();
+    merge_pending_commit(provider, &mut new_group)?;
+    // consumed key packages leave the address book
+    provider.state_mut().set_address_book(address_book);
+    provider
+        .state_mut()
+        .set_send_group_id(new_group.group_id().clone());
+    match welcome {
+        Some(welcome) => Ok((commit, welcome)),
+        None => Err("No welcome produced for the re-initialized group".into()),
+    }
 }
 
-/// Generate a KeyPackage for the provider's credential and return it as a base64 blob.
+/// Branch the send-group into a new group with a subset of its members and return the new
+/// group id and the Welcome (base64).
 ///
-/// KeyPackages are used when adding members to MLS groups; the producer of a KeyPackage
-/// should distribute the base64 string to the group creator who will validate and include it.
+/// Example:
+///
+/// ```ignore
+/// let (group_id, welcome_b64) = send_group_branch_base64(&provider, &identities)?;
+/// ```
+pub fn send_group_branch_base64(
+    provider: &mut DmlsProvider,
+    identities: &[Vec<u8>],
+) -> Result<(GroupId, String), Box<dyn Error>> {
+    let sg = send_group(provider)?;
+    let (group, welcome) = branch(provider, &sg, identities)?;
+    Ok((
+        group.group_id().clone(),
+        Blob.encode(welcome.tls_serialize_detached()?),
+    ))
+}
+
+/// Create a branch of the group containing the members with the given credential identities.
+///
+/// The branch is a new group (same ciphersuite) created by this agent; its first commit adds the
+/// selected members using key packages from the address book and injects the parent group's
+/// current resumption PSK (see `store_bridged_resumption_psk`), so the branch is bound to the
+/// parent's history. The parent group is unchanged, and the branch does not replace the send-group.
+///
+/// Returns the branch group and the Welcome for the selected members.
 ///
 /// Example:
 ///
 /// ```ignore
-/// let kp_b64 = gen_kp_base64(&provider, ciphersuite)?;
-/// println!("{}", kp_b64);
+/// let (branch_group, welcome) = branch(&mut provider, &parent, &identities)?;
 /// ```
-pub fn gen_kp_base64(
+pub fn branch(
+    provider: &mut DmlsProvider,
+    parent: &MlsGroup,
+    identities: &[Vec<u8>],
+) -> Result<(MlsGroup, MlsMessageOut), Box<dyn Error>> {
+    let ciphersuite = parent.ciphersuite();
+    // collect key packages for the selected members from the address book
+    let mut address_book = provider.state().address_book().clone();
+    let mut kps = Vec::new();
+    for identity in identities {
+        if !parent
+            .members()
+            .any(|m| m.credential.serialized_content() == identity.as_slice())
+        {
+            return Err(format!("{} is not a member of the group", Blob.encode(identity)).into());
+        }
+        match address_book_kp(provider, &mut address_book, identity, ciphersuite) {
+            Some(kp) => kps.push(kp),
+            None => {
+                return Err(format!(
+                    "No key package for member {} in the address book",
+                    Blob.encode(identity)
+                )
+                .into());
+            }
+        }
+    }
+    if kps.is_empty() {
+        return Err("No members selected for the branch".into());
+    }
+    // new group, bound to the parent via the branch psk
+    let psk_id_vec =
+        store_bridged_resumption_psk(provider, parent, parent.epoch(), BRANCH_PSK_PREFIX)?;
+    let mut group = MlsGroup::new(
+        provider,
+        provider,
+        &GroupConfigOptions::default().create_config(
+            ciphersuite,
+            capabilities(provider.state().capabilities(), ciphersuite)?,
+        )?,
+        cred_with_key(provider),
+    )?;
+    inherit_exporter_params(provider, parent.group_id(), group.group_id());
+    let proposal =
+        Proposal::PreSharedKey(Box::new(PreSharedKeyProposal::new(PreSharedKeyId::new(
+            ciphersuite,
+            provider.rand(),
+            Psk::External(ExternalPsk::new(psk_id_vec)),
+        )?)));
+    check_membership_policy(provider, &group, &kps_identities(&kps), 0)?;
+    let (_, welcome, _) = group
+        .commit_builder()
+        .propose_adds(kps)
+        .add_proposal(proposal)
+        .load_psks(provider.storage())?
+        .build(provider.rand(), provider.crypto(), provider, |_| true)?
+        .stage_commit(provider)?
+        .into_messages();
+    merge_pending_commit(provider, &mut group)?;
+    // consumed key packages leave the address book
+    provider.state_mut().set_address_book(address_book);
+    match welcome {
+        Some(welcome) => Ok((group, welcome)),
+        None => Err("No welcome produced for the branch".into()),
+    }
+}
+
+/// Return the pending (queued) proposals of the group, read from storage.
+///
+/// Example:
+///
+/// ```ignore
+/// for qp in queued_proposals(&provider, group.group_id())? {
+///     println!("{:?}", qp.proposal_reference());
+/// }
+/// ```
+pub fn queued_proposals(
     provider: &DmlsProvider,
-    ciphersuite: Ciphersuite,
+    group_id: &GroupId,
+) -> Result<Vec<QueuedProposal>, Box<dyn Error>> {
+    Ok(provider
+        .storage()
+        .queued_proposals::<GroupId, ProposalRef, QueuedProposal>(group_id)?
+        .into_iter()
+        .map(|(_, qp)| qp)
+        .collect())
+}
+
+/// Return one line per pending proposal of the group: the base64 (TLS-serialized) proposal
+/// reference followed by the proposal type.
+///
+/// Example:
+///
+/// ```ignore
+/// for line in queued_proposals_base64(&provider, group.group_id())? {
+///     println!("{}", line);
+/// }
+/// ```
+pub fn queued_proposals_base64(
+    provider: &DmlsProvider,
+    group_id: &GroupId,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    queued_proposals(provider, group_id)?
+        .iter()
+        .map(|qp| {
+            Ok(format!(
+                "{} {:?}",
+                Blob.encode(qp.proposal_reference().tls_serialize_detached()?),
+                qp.proposal().proposal_type()
+            ))
+        })
+        .collect()
+}
+
+/// Decode a base64 (TLS-serialized) proposal reference as printed by `queued_proposals_base64`.
+///
+/// Example:
+///
+/// ```ignore
+/// let proposal_ref = proposal_ref_from_base64("IOq...")?;
+/// ```
+pub fn proposal_ref_from_base64(s: &str) -> Result<ProposalRef, Box<dyn Error>> {
+    Ok(ProposalRef::tls_deserialize_exact(Blob.decode(s)?)?)
+}
+
+/// Commit only the pending proposals with the given references and return the commit as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = commit_proposals_base64(&mut provider, &mut group, &refs)?;
+/// ```
+pub fn commit_proposals_base64(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    refs: &[ProposalRef],
 ) -> Result<String, Box<dyn Error>> {
-    Ok(Base64.encode(
-        KeyPackage::builder()
-            .build(ciphersuite, provider, provider, cred_with_key(provider))?
-            .key_package()
-            .clone()
-            .tls_serialize_detached()?,
-    ))
+    Ok(Blob.encode(commit_proposals(provider, group, refs)?.tls_serialize_detached()?))
+}
+
+/// Commit only the pending proposals with the given references.
+///
+/// The selected proposals are included by reference; every reference must match a proposal
+/// queued in storage. As with any commit, proposals that are not selected are discarded once the
+/// commit is merged, since proposals do not outlive their epoch.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = commit_proposals(&mut provider, &mut group, &refs)?;
+/// ```
+pub fn commit_proposals(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    refs: &[ProposalRef],
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    let queued = queued_proposals(provider, group.group_id())?;
+    for proposal_ref in refs {
+        if !queued
+            .iter()
+            .any(|qp| &qp.proposal_reference() == proposal_ref)
+        {
+            return Err(format!(
+                "No pending proposal with reference {}",
+                Blob.encode(proposal_ref.tls_serialize_detached()?)
+            )
+            .into());
+        }
+    }
+    discard_pending_commit(provider, group)?;
+    let (commit, _, _) = group
+        .commit_builder()
+        .consume_proposal_store(true)
+        .load_psks(provider.storage())?
+        .build(provider.rand(), provider.crypto(), provider, |qp| {
+            refs.contains(&qp.proposal_reference())
+        })?
+        .stage_commit(provider)?
+        .into_messages();
+    merge_pending_commit(provider, group)?;
+    Ok(commit)
 }
@@ -0,0 +1,191 @@
+//! State integrity MACs.
+//!
+//! A state file that was silently corrupted (a bad disk, a botched copy) or tampered with usually
+//! still parses, and only later makes OpenMLS fail with errors that say nothing about the cause.
+//! With an integrity key set (`set_integrity_key`, the CLI's global `--integrity-key <file>`
+//! option), every save writes an HMAC-SHA256 over the state file, its journal (see `journal`) and
+//! the entries of its storage backend (see `byte_store`), if any, to `<state file>.mac`, and every
+//! load verifies it before the state is parsed. Each part is length-prefixed, so bytes can't move
+//! from one to another unnoticed, and the MAC file is replaced atomically, so a crash mid-save
+//! never leaves a torn MAC. With a storage backend, every save reads all its entries to MAC them.
+//!
+//! States saved before the key was set have no MAC yet; loading them only warns, and the next
+//! save adds the MAC.
+//!
+//! The key also MACs the head of the audit log (see `audit`), whose hash chain alone only detects
+//! accidental changes.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! set_integrity_key(std::fs::read("./integrity.key")?)?;
+//! save_state(state_path, &state, format)?; // also writes <state_path>.mac
+//! let (state, format) = load_state(state_path)?; // fails if the MAC doesn't match
+//! let (state, format) = load_state_with_storage(state_path, Some(&store))?; // covers the store
+//! ```
+
+use super::{
+    byte_store::ByteStore, error::DmlsError, journal::journal_path, openmls_kvstore::LABELS,
+    perms::private_options,
+};
+use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use core::error::Error;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{crypto::OpenMlsCrypto, types::HashType};
+use std::{
+    fs::{read as read_file, read_to_string as read_file_to_string, rename},
+    io::{ErrorKind, Write},
+    sync::OnceLock,
+};
+
+/// The agent-wide integrity key.
+static INTEGRITY_KEY: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Set the agent-wide integrity key; it can only be set once.
+pub fn set_integrity_key(key: Vec<u8>) -> Result<(), Box<dyn Error>> {
+    if key.is_empty() {
+        return Err("Empty integrity key".into());
+    }
+    INTEGRITY_KEY
+        .set(key)
+        .map_err(|_| "Integrity key already set".into())
+}
+
+/// Returns the path of the MAC file belonging to a state file.
+pub fn mac_path(state_path: &str) -> String {
+    format!("{state_path}.mac")
+}
+
+/// Append a part of the MAC input, prefixed with its length.
+fn push_part(data: &mut Vec<u8>, part: &[u8]) {
+    data.extend_from_slice(&(part.len() as u64).to_be_bytes());
+    data.extend_from_slice(part);
+}
+
+/// Compute the MAC over the state file, its journal (if any) and the entries of its storage
+/// backend (if any), sorted by label and key.
+fn compute(
+    key: &[u8],
+    state_path: &str,
+    storage: Option<&dyn ByteStore>,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut data = Vec::new();
+    push_part(&mut data, &read_file(state_path)?);
+    match read_file(journal_path(state_path)) {
+        Err(e) if e.kind() == ErrorKind::NotFound => push_part(&mut data, &[]),
+        journal => push_part(&mut data, &journal?),
+    }
+    if let Some(storage) = storage {
+        for label in LABELS {
+            let mut entries = storage.entries(label)?;
+            entries.sort();
+            for (key, value) in entries {
+                push_part(&mut data, label);
+                push_part(&mut data, &key);
+                push_part(&mut data, &value);
+            }
+        }
+    }
+    hmac(key, &data)
+}
+
+/// Compute HMAC-SHA256 over data.
+fn hmac(key: &[u8], data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    // HKDF-Extract is HMAC with the salt as key
+    let mac = RustCrypto::default().hkdf_extract(HashType::Sha2_256, key, data)?;
+    Ok(mac.as_slice().to_vec())
+}
+
+/// Compare two MACs in constant time.
+fn mac_eq(expected: &[u8], actual: &[u8]) -> bool {
+    let diff = expected.len() ^ actual.len()
+        | expected
+            .iter()
+            .zip(actual)
+            .fold(0, |acc, (a, b)| acc | usize::from(a ^ b));
+    diff == 0
+}
+
+/// Returns the MAC of the audit log's head hash (see `audit`), if an integrity key is set.
+///
+/// Example:
+///
+/// ```ignore
+/// let head_mac = audit_head_mac(&entry.hash)?;
+/// ```
+pub fn audit_head_mac(head_hash: &[u8]) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+    let Some(key) = INTEGRITY_KEY.get() else {
+        return Ok(None);
+    };
+    let mut data = Vec::new();
+    push_part(&mut data, b"audit-head");
+    push_part(&mut data, head_hash);
+    Ok(Some(hmac(key, &data)?))
+}
+
+/// Verify the MAC of the audit log's head hash, if an integrity key is set; a missing MAC only
+/// warns, as logs written before the key was set have none.
+///
+/// Example:
+///
+/// ```ignore
+/// check_audit_head(&last.hash, state.audit_head_mac())?;
+/// ```
+pub fn check_audit_head(head_hash: &[u8], mac: Option<&[u8]>) -> Result<(), Box<dyn Error>> {
+    let Some(actual) = audit_head_mac(head_hash)? else {
+        return Ok(());
+    };
+    match mac {
+        None => tracing::warn!("Audit log has no head MAC yet; it is added with the next entry"),
+        Some(expected) if !mac_eq(expected, &actual) => {
+            return Err("Audit log head MAC doesn't match".into());
+        }
+        Some(_) => {}
+    }
+    Ok(())
+}
+
+/// Write the MAC of the state file and its storage backend (if any), if an integrity key is set;
+/// the MAC file is written to a temporary file first and renamed over the old one.
+///
+/// Example:
+///
+/// ```ignore
+/// seal(state_path, Some(&store))?;
+/// ```
+pub fn seal(state_path: &str, storage: Option<&dyn ByteStore>) -> Result<(), Box<dyn Error>> {
+    if let Some(key) = INTEGRITY_KEY.get() {
+        let path = mac_path(state_path);
+        let tmp = format!("{path}.tmp");
+        let mut file = private_options().truncate(true).open(&tmp)?;
+        file.write_all(Base64.encode(compute(key, state_path, storage)?).as_bytes())?;
+        file.sync_all()?;
+        rename(&tmp, &path)?;
+    }
+    Ok(())
+}
+
+/// Verify the MAC of the state file and its storage backend (if any), if an integrity key is set.
+///
+/// Example:
+///
+/// ```ignore
+/// check(state_path, Some(&store))?;
+/// ```
+pub fn check(state_path: &str, storage: Option<&dyn ByteStore>) -> Result<(), Box<dyn Error>> {
+    let Some(key) = INTEGRITY_KEY.get() else {
+        return Ok(());
+    };
+    let expected = match read_file_to_string(mac_path(state_path)) {
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            tracing::warn!("State {state_path} has no integrity MAC yet; it is added on save");
+            return Ok(());
+        }
+        expected => Base64.decode(expected?.trim())?,
+    };
+    let actual = compute(key, state_path, storage)?;
+    if !mac_eq(&expected, &actual) {
+        return Err(DmlsError::IntegrityCheckFailed(state_path.to_string()).into());
+    }
+    Ok(())
+}
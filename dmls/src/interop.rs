@@ -0,0 +1,389 @@
+//! RFC 9420 interop test vectors.
+//!
+//! This module checks dmls against the official MLS test vectors
+//! (<https://github.com/mlswg/mls-implementations>), so behavior can be compared with other
+//! implementations such as mls-rs and MLSpp. Supported vector files:
+//!
+//! - `tree-math.json`: array-based tree navigation (root, left, right, parent, sibling)
+//! - `key-schedule.json`: the epoch key schedule (joiner, welcome, epoch-derived secrets and the
+//!   exporter); `external_pub` is not checked
+//! - `messages.json`: framing of the `MLSMessage`-wrapped messages (Welcome, GroupInfo,
+//!   KeyPackage, public and private messages) and of the ratchet tree, which must parse exactly;
+//!   the bare proposal/commit/group-secrets structures are not checked
+//!
+//! Only the `messages.json` vectors go through OpenMLS itself (its deserializers). OpenMLS keeps
+//! its tree math and key schedule private, so this module recomputes both from RFC 9420, the key
+//! schedule with the crypto provider's HKDF: these vectors check the crypto provider and the
+//! RFC's derivations as dmls relies on them, not OpenMLS's own implementation of them.
+//!
+//! The kind of a file is detected from its content.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let checked = verify_file("key-schedule.json", &RustCrypto::default())?;
+//! println!("{checked} test vectors passed");
+//! ```
+
+use super::encoding::hex_decode;
+use core::error::Error;
+use openmls::{framing::MlsMessageIn, treesync::RatchetTreeIn};
+use openmls_traits::{crypto::OpenMlsCrypto, types::Ciphersuite};
+use serde::Deserialize;
+use serde_json::Value;
+use std::fs::read_to_string as read_file_to_string;
+use tls_codec::Deserialize as TlsDeserialize;
+
+/// A `tree-math.json` test vector.
+#[derive(Clone, Debug, Deserialize)]
+pub struct TreeMathVector {
+    /// Number of leaves.
+    pub n_leaves: u32,
+    /// Number of nodes.
+    pub n_nodes: u32,
+    /// Root node index.
+    pub root: u32,
+    /// Left child of every node (`None` for leaves).
+    pub left: Vec<Option<u32>>,
+    /// Right child of every node (`None` for leaves).
+    pub right: Vec<Option<u32>>,
+    /// Parent of every node (`None` for the root).
+    pub parent: Vec<Option<u32>>,
+    /// Sibling of every node (`None` for the root).
+    pub sibling: Vec<Option<u32>>,
+}
+
+/// A `key-schedule.json` test vector.
+#[derive(Clone, Debug, Deserialize)]
+pub struct KeyScheduleVector {
+    /// Ciphersuite code point.
+    pub cipher_suite: u16,
+    /// Group id (hex).
+    pub group_id: String,
+    /// Init secret of the first epoch (hex).
+    pub initial_init_secret: String,
+    /// Consecutive epochs.
+    pub epochs: Vec<KeyScheduleEpoch>,
+}
+
+/// One epoch of a `key-schedule.json` test vector; all values are hex.
+#[derive(Clone, Debug, Deserialize)]
+#[allow(missing_docs)]
+pub struct KeyScheduleEpoch {
+    pub group_context: String,
+    pub commit_secret: String,
+    pub psk_secret: String,
+    pub joiner_secret: String,
+    pub welcome_secret: String,
+    pub init_secret: String,
+    pub sender_data_secret: String,
+    pub encryption_secret: String,
+    pub exporter_secret: String,
+    pub epoch_authenticator: String,
+    pub external_secret: String,
+    pub confirmation_key: String,
+    pub membership_key: String,
+    pub resumption_psk: String,
+    pub exporter: ExporterVector,
+}
+
+/// Exporter check of a key-schedule epoch; all values but `length` are hex.
+#[derive(Clone, Debug, Deserialize)]
+#[allow(missing_docs)]
+pub struct ExporterVector {
+    pub label: String,
+    pub context: String,
+    pub length: usize,
+    pub secret: String,
+}
+
+/// Returns the level of a node (0 for leaves).
+fn level(x: u32) -> u32 {
+    x.trailing_ones()
+}
+
+/// Returns the root of a tree with `n` leaves.
+pub fn root(n: u32) -> u32 {
+    let width = 2 * (n - 1) + 1;
+    (1 << width.ilog2()) - 1
+}
+
+/// Returns the left child of a node.
+pub fn left(x: u32) -> Option<u32> {
+    match level(x) {
+        0 => None,
+        k => Some(x ^ (1 << (k - 1))),
+    }
+}
+
+/// Returns the right child of a node.
+pub fn right(x: u32) -> Option<u32> {
+    match level(x) {
+        0 => None,
+        k => Some(x ^ (3 << (k - 1))),
+    }
+}
+
+/// Returns the parent of a node in a tree with `n` leaves.
+pub fn parent(x: u32, n: u32) -> Option<u32> {
+    if x == root(n) {
+        return None;
+    }
+    let k = level(x);
+    let b = (x >> (k + 1)) & 1;
+    Some((x | (1 << k)) ^ (b << (k + 1)))
+}
+
+/// Returns the sibling of a node in a tree with `n` leaves.
+pub fn sibling(x: u32, n: u32) -> Option<u32> {
+    let p = parent(x, n)?;
+    if x < p { right(p) } else { left(p) }
+}
+
+/// Verify a tree-math test vector.
+///
+/// Example:
+///
+/// ```ignore
+/// verify_tree_math(&vector)?;
+/// ```
+pub fn verify_tree_math(v: &TreeMathVector) -> Result<(), Box<dyn Error>> {
+    let n = v.n_leaves;
+    let nodes = v.n_nodes as usize;
+    if n == 0
+        || [&v.left, &v.right, &v.parent, &v.sibling]
+            .iter()
+            .any(|a| a.len() != nodes)
+    {
+        return Err(format!("Malformed tree math test vector for {n} leaves").into());
+    }
+    if 2 * (n - 1) + 1 != v.n_nodes || root(n) != v.root {
+        return Err(format!("Tree math mismatch for {n} leaves: size or root").into());
+    }
+    for x in 0..v.n_nodes {
+        let i = x as usize;
+        let expected = (v.left[i], v.right[i], v.parent[i], v.sibling[i]);
+        let actual = (left(x), right(x), parent(x, n), sibling(x, n));
+        if expected != actual {
+            return Err(format!("Tree math mismatch for {n} leaves at node {x}").into());
+        }
+    }
+    Ok(())
+}
+
+/// Append an MLS variable-length vector (RFC 9420, section 2.1.2) to `out`.
+fn push_vl(out: &mut Vec<u8>, bytes: &[u8]) {
+    let len = bytes.len();
+    if len < 1 << 6 {
+        out.push(len as u8);
+    } else if len < 1 << 14 {
+        out.extend((len as u16 | 0x4000).to_be_bytes());
+    } else {
+        out.extend((len as u32 | 0x8000_0000).to_be_bytes());
+    }
+    out.extend(bytes);
+}
+
+/// RFC 9420 `ExpandWithLabel`.
+fn expand_with_label(
+    crypto: &impl OpenMlsCrypto,
+    ciphersuite: Ciphersuite,
+    secret: &[u8],
+    label: &str,
+    context: &[u8],
+    length: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut info = (length as u16).to_be_bytes().to_vec();
+    push_vl(&mut info, format!("MLS 1.0 {label}").as_bytes());
+    push_vl(&mut info, context);
+    Ok(crypto
+        .hkdf_expand(ciphersuite.hash_algorithm(), secret, &info, length)?
+        .as_slice()
+        .to_vec())
+}
+
+/// RFC 9420 `DeriveSecret`.
+fn derive_secret(
+    crypto: &impl OpenMlsCrypto,
+    ciphersuite: Ciphersuite,
+    secret: &[u8],
+    label: &str,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    expand_with_label(
+        crypto,
+        ciphersuite,
+        secret,
+        label,
+        &[],
+        ciphersuite.hash_length(),
+    )
+}
+
+/// Verify a key-schedule test vector, chaining the init secret across epochs.
+///
+/// Example:
+///
+/// ```ignore
+/// verify_key_schedule(&vector, &RustCrypto::default())?;
+/// ```
+pub fn verify_key_schedule(
+    v: &KeyScheduleVector,
+    crypto: &impl OpenMlsCrypto,
+) -> Result<(), Box<dyn Error>> {
+    let ciphersuite = Ciphersuite::try_from(v.cipher_suite)
+        .map_err(|_| format!("Unsupported ciphersuite: {}", v.cipher_suite))?;
+    let hash = ciphersuite.hash_algorithm();
+    let nh = ciphersuite.hash_length();
+    let mut init_secret = hex_decode(&v.initial_init_secret)?;
+    for (i, epoch) in v.epochs.iter().enumerate() {
+        let group_context = hex_decode(&epoch.group_context)?;
+        let extracted =
+            crypto.hkdf_extract(hash, &init_secret, &hex_decode(&epoch.commit_secret)?)?;
+        let joiner_secret = expand_with_label(
+            crypto,
+            ciphersuite,
+            extracted.as_slice(),
+            "joiner",
+            &group_context,
+            nh,
+        )?;
+        let member_secret =
+            crypto.hkdf_extract(hash, &joiner_secret, &hex_decode(&epoch.psk_secret)?)?;
+        let welcome_secret =
+            derive_secret(crypto, ciphersuite, member_secret.as_slice(), "welcome")?;
+        let epoch_secret = expand_with_label(
+            crypto,
+            ciphersuite,
+            member_secret.as_slice(),
+            "epoch",
+            &group_context,
+            nh,
+        )?;
+        let derived = |label: &str| derive_secret(crypto, ciphersuite, &epoch_secret, label);
+        init_secret = derived("init")?;
+        let exporter_secret = derived("exporter")?;
+        let exporter = expand_with_label(
+            crypto,
+            ciphersuite,
+            &derive_secret(
+                crypto,
+                ciphersuite,
+                &exporter_secret,
+                &String::from_utf8(hex_decode(&epoch.exporter.label)?)?,
+            )?,
+            "exported",
+            &crypto.hash(hash, &hex_decode(&epoch.exporter.context)?)?,
+            epoch.exporter.length,
+        )?;
+        let checks = [
+            ("joiner_secret", &epoch.joiner_secret, joiner_secret),
+            ("welcome_secret", &epoch.welcome_secret, welcome_secret),
+            ("init_secret", &epoch.init_secret, init_secret.clone()),
+            (
+                "sender_data_secret",
+                &epoch.sender_data_secret,
+                derived("sender data")?,
+            ),
+            (
+                "encryption_secret",
+                &epoch.encryption_secret,
+                derived("encryption")?,
+            ),
+            ("exporter_secret", &epoch.exporter_secret, exporter_secret),
+            (
+                "epoch_authenticator",
+                &epoch.epoch_authenticator,
+                derived("authentication")?,
+            ),
+            (
+                "external_secret",
+                &epoch.external_secret,
+                derived("external")?,
+            ),
+            (
+                "confirmation_key",
+                &epoch.confirmation_key,
+                derived("confirm")?,
+            ),
+            (
+                "membership_key",
+                &epoch.membership_key,
+                derived("membership")?,
+            ),
+            (
+                "resumption_psk",
+                &epoch.resumption_psk,
+                derived("resumption")?,
+            ),
+            ("exporter.secret", &epoch.exporter.secret, exporter),
+        ];
+        for (name, expected, actual) in checks {
+            if hex_decode(expected)? != actual {
+                return Err(format!(
+                    "Key schedule mismatch for ciphersuite {} in epoch {i}: {name}",
+                    v.cipher_suite
+                )
+                .into());
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Verify the framing of a `messages.json` test vector.
+///
+/// Every `MLSMessage` field and the ratchet tree must deserialize, consuming all bytes.
+///
+/// Example:
+///
+/// ```ignore
+/// verify_messages(&vector)?;
+/// ```
+pub fn verify_messages(v: &Value) -> Result<(), Box<dyn Error>> {
+    let field = |name: &str| -> Result<Vec<u8>, Box<dyn Error>> {
+        match v.get(name).and_then(Value::as_str) {
+            Some(hex) => hex_decode(hex),
+            None => Err(format!("Messages test vector lacks {name}").into()),
+        }
+    };
+    for name in [
+        "mls_welcome",
+        "mls_group_info",
+        "mls_key_package",
+        "public_message_application",
+        "public_message_proposal",
+        "public_message_commit",
+        "private_message",
+    ] {
+        MlsMessageIn::tls_deserialize_exact(field(name)?)
+            .map_err(|e| format!("Error deserializing {name}: {e}"))?;
+    }
+    RatchetTreeIn::tls_deserialize_exact(field("ratchet_tree")?)
+        .map_err(|e| format!("Error deserializing ratchet_tree: {e}"))?;
+    Ok(())
+}
+
+/// Verify all test vectors in a file, detecting the kind of vectors from the content.
+///
+/// Returns the number of test vectors checked.
+///
+/// Example:
+///
+/// ```ignore
+/// let checked = verify_file("tree-math.json", &RustCrypto::default())?;
+/// ```
+pub fn verify_file(path: &str, crypto: &impl OpenMlsCrypto) -> Result<usize, Box<dyn Error>> {
+    let vectors: Vec<Value> = serde_json::from_str(&read_file_to_string(path)?)?;
+    for vector in &vectors {
+        if vector.get("n_leaves").is_some() {
+            verify_tree_math(&TreeMathVector::deserialize(vector)?)?;
+        } else if vector.get("initial_init_secret").is_some() {
+            verify_key_schedule(&KeyScheduleVector::deserialize(vector)?, crypto)?;
+        } else if vector.get("mls_welcome").is_some() {
+            verify_messages(vector)?;
+        } else {
+            return Err("Unsupported kind of test vector".into());
+        }
+    }
+    Ok(vectors.len())
+}
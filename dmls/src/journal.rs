@@ -0,0 +1,94 @@
+//! Incremental state persistence.
+//!
+//! Rewriting the whole state file after every command costs O(state size), even if a message only
+//! touched a handful of OpenMLS storage keys. With a journal, each save appends one JSON line to
+//! `<state file>.journal` holding the agent's bookkeeping (`DmlsStateMeta`, which is small) and
+//! only the OpenMLS entries changed since the last save (see
+//! `OpenMlsKeyValueStore::take_changes`). Loading replays the journal on top of the state file.
+//!
+//! Once the journal has grown as large as the state file itself, the next save compacts it: the
+//! full state is written and the journal removed. A full save (`save_state`) always removes the
+//! journal, so the two never disagree.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let (mut state, format) = DmlsState::from_bytes(&std::fs::read(state_path)?)?;
+//! replay(&mut state, state_path)?;
+//! // ... run a command
+//! append(state_path, &state)?;
+//! ```
+
+use super::{
+    perms::append_private,
+    state::{DmlsState, DmlsStateMeta},
+};
+use core::error::Error;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, fs::read_to_string as read_file_to_string, io::ErrorKind};
+
+/// One journal line: the bookkeeping after a save and the OpenMLS entries changed since the
+/// previous one.
+#[derive(Serialize, Deserialize)]
+struct JournalRecord {
+    /// The agent's bookkeeping.
+    meta: DmlsStateMeta,
+    /// Changed OpenMLS entries; `None` for deleted ones.
+    changes: BTreeMap<String, Option<String>>,
+}
+
+/// Returns the path of the journal belonging to a state file.
+pub fn journal_path(state_path: &str) -> String {
+    format!("{state_path}.journal")
+}
+
+/// Append the changes since the last save to the journal of the state file.
+///
+/// Example:
+///
+/// ```ignore
+/// append(state_path, provider.state())?;
+/// ```
+pub fn append(state_path: &str, state: &DmlsState) -> Result<(), Box<dyn Error>> {
+    let record = JournalRecord {
+        meta: state.meta().clone(),
+        changes: state.openmls_values().take_changes(),
+    };
+    let mut line = serde_json::to_vec(&record)?;
+    line.push(b'\n');
+    append_private(&journal_path(state_path), &line)?;
+    Ok(())
+}
+
+/// Replay the journal of the state file (if any) on top of the loaded state.
+///
+/// Returns the number of replayed records.
+///
+/// Example:
+///
+/// ```ignore
+/// let replayed = replay(&mut state, state_path)?;
+/// ```
+pub fn replay(state: &mut DmlsState, state_path: &str) -> Result<usize, Box<dyn Error>> {
+    let journal = match read_file_to_string(journal_path(state_path)) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(0),
+        journal => journal?,
+    };
+    let lines = journal
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .collect::<Vec<_>>();
+    for (i, line) in lines.iter().enumerate() {
+        let record: JournalRecord = match serde_json::from_str(line) {
+            // a torn write can only affect the last record
+            Err(e) if i + 1 == lines.len() => {
+                tracing::warn!("Ignoring truncated journal record: {e}");
+                return Ok(i);
+            }
+            record => record?,
+        };
+        state.set_meta(record.meta);
+        state.openmls_values().apply_changes(record.changes);
+    }
+    Ok(lines.len())
+}
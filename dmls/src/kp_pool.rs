@@ -0,0 +1,116 @@
+//! Key package pool replenishment.
+//!
+//! Peers add an agent to groups using key packages it published beforehand, and every Welcome
+//! consumes one of them. `maintain` keeps a pool of published key packages topped up: the state
+//! records the references of the key packages in the pool, `mark_consumed` drops those whose
+//! private part OpenMLS deleted when joining a group through them, and once fewer than `min` are
+//! left, new key packages are generated to bring the pool back up to `max`. The caller prints or
+//! publishes them (see `transport::KeyPackageDirectory::publish_key_packages`).
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let kps = maintain(&mut provider, ciphersuite, 10, 50)?;
+//! ds.publish_key_packages(&own_identity(&provider), &kps)?;
+//! ```
+
+use super::{
+    encoding::Blob,
+    helpers::{capabilities, check_signature_scheme, check_suite_policy, cred_with_key},
+    openmls_kvstore::KEY_PACKAGE_LABEL,
+    provider::DmlsProvider,
+};
+use core::error::Error;
+use openmls::{ciphersuite::hash_ref::KeyPackageRef, key_packages::KeyPackage};
+use openmls_traits::{OpenMlsProvider, types::Ciphersuite};
+use tls_codec::Serialize;
+
+/// Drop the pool key packages that were consumed, i.e. whose private part is no longer stored.
+///
+/// Returns the number of key packages dropped.
+///
+/// Example:
+///
+/// ```ignore
+/// let consumed = mark_consumed(&mut provider)?;
+/// ```
+pub fn mark_consumed(provider: &mut DmlsProvider) -> Result<usize, Box<dyn Error>> {
+    let stored = provider
+        .state()
+        .openmls_values()
+        .keys_for_label(KEY_PACKAGE_LABEL);
+    let mut consumed = Vec::new();
+    for kp_ref in provider.state().kp_pool() {
+        // key packages are stored under their JSON-serialized reference
+        let key = serde_json::to_vec(&KeyPackageRef::from_slice(kp_ref))?;
+        if !stored.contains(&key) {
+            consumed.push(kp_ref.clone());
+        }
+    }
+    Ok(provider
+        .state_mut()
+        .retain_kp_pool(|kp_ref| !consumed.contains(kp_ref)))
+}
+
+/// Generate `count` key packages for the pool and return them as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let kps = replenish(&mut provider, ciphersuite, 5)?;
+/// ```
+pub fn replenish(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    count: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    check_suite_policy(provider, ciphersuite)?;
+    check_signature_scheme(
+        ciphersuite,
+        provider.state().signature_key_pair().signature_scheme(),
+    )?;
+    let mut kps = Vec::with_capacity(count);
+    for _ in 0..count {
+        let bundle = KeyPackage::builder()
+            .leaf_node_capabilities(capabilities(provider.state().capabilities(), ciphersuite)?)
+            .build(ciphersuite, &*provider, &*provider, cred_with_key(provider))?;
+        let kp = bundle.key_package();
+        let kp_ref = kp.hash_ref(provider.crypto())?;
+        kps.push(Blob.encode(kp.tls_serialize_detached()?));
+        provider
+            .state_mut()
+            .record_generated_kp(kp_ref.as_slice().to_vec(), ciphersuite.into());
+        provider
+            .state_mut()
+            .push_kp_pool_ref(kp_ref.as_slice().to_vec());
+    }
+    Ok(kps)
+}
+
+/// Drop consumed key packages from the pool and, if fewer than `min` are left, generate new ones
+/// to bring it back up to `max`; returns the new key packages as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// for kp in maintain(&mut provider, ciphersuite, 10, 50)? {
+///     println!("{kp}");
+/// }
+/// ```
+pub fn maintain(
+    provider: &mut DmlsProvider,
+    ciphersuite: Ciphersuite,
+    min: usize,
+    max: usize,
+) -> Result<Vec<String>, Box<dyn Error>> {
+    if min > max {
+        return Err(format!("Pool minimum {min} exceeds maximum {max}").into());
+    }
+    let consumed = mark_consumed(provider)?;
+    let size = provider.state().kp_pool().len();
+    tracing::info!("Key package pool: {size} unconsumed, {consumed} consumed since last check");
+    if size >= min {
+        return Ok(Vec::new());
+    }
+    replenish(provider, ciphersuite, max - size)
+}
@@ -0,0 +1,87 @@
+//! Distributed Messaging Layer Security (DMLS) agent library.
+//!
+//! This library contains everything behind the `dmls` command-line agent: its configuration files
+//! (`config`), the persistent agent state (`state`, `audit`, `journal`, `integrity`, `perms`,
+//! `mlock`, `backup`, `redact`, `doctor`), the OpenMLS provider and storage wiring (`provider`,
+//! `crypto`, `openmls_kvstore`, `openmls_keys`, the `byte_store`, `dir_storage` and `redb_storage`
+//! backends, and `archive`), the high-level protocol helpers (`helpers`, `error`, `kp_pool`,
+//! `psk_id`, `encoding`, `armor`, `framing`, `envelope`, `tree`), admin roles, credential
+//! continuity across key rotations and application-defined credential validation (`roles`,
+//! `continuity`, `validator`), passive observation of public groups (`observe`), the transports
+//! used to exchange artifacts (`transport`, `stdio`, `ds`, `nats`, `matrix`, `maildir`, `follow`,
+//! `ws`, `serve`, `grpc`), and in-process tooling for simulations, benchmarks, property tests and
+//! RFC 9420 test vectors (`simulate`, `bench`, `testing`, `interop`).
+//!
+//! The binaries and their dependencies need the `cli` feature (on by default); library users can
+//! turn it off with `default-features = false`. `simulate` (part of `cli`) and `bench` are behind
+//! features of the same names.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! use dmls::{helpers::gen_kp_base64, provider::DmlsProvider, state::DmlsState};
+//!
+//! let mut provider = DmlsProvider::new(state, RustCrypto::default());
+//! let kp_b64 = gen_kp_base64(&provider, ciphersuite)?;
+//! ```
+
+#![allow(clippy::multiple_crate_versions)]
+// the binaries' dependencies (feature `cli`) are unused by the library itself
+#![cfg_attr(feature = "cli", allow(unused_crate_dependencies))]
+
+#[cfg(feature = "archive")]
+pub mod archive;
+pub mod armor;
+pub mod audit;
+pub mod backup;
+#[cfg(feature = "bench")]
+pub mod bench;
+pub mod byte_store;
+pub mod config;
+pub mod continuity;
+pub mod crypto;
+pub mod dir_storage;
+pub mod doctor;
+pub mod ds;
+pub mod encoding;
+pub mod envelope;
+pub mod error;
+pub mod follow;
+pub mod framing;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+pub mod helpers;
+pub mod integrity;
+pub mod interop;
+pub mod journal;
+pub mod kp_pool;
+pub mod maildir;
+#[cfg(feature = "matrix")]
+pub mod matrix;
+#[cfg(feature = "mlock")]
+pub mod mlock;
+#[cfg(feature = "nats")]
+pub mod nats;
+pub mod observe;
+pub mod openmls_keys;
+pub mod openmls_kvstore;
+pub mod perms;
+pub mod provider;
+pub mod psk_id;
+pub mod rand;
+pub mod redact;
+#[cfg(feature = "redb")]
+pub mod redb_storage;
+pub mod roles;
+#[cfg(unix)]
+pub mod serve;
+#[cfg(feature = "simulate")]
+pub mod simulate;
+pub mod state;
+pub mod stdio;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transport;
+pub mod tree;
+pub mod validator;
+pub mod ws;
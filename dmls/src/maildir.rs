@@ -0,0 +1,139 @@
+//! Maildir-style file mailboxes.
+//!
+//! For air-gapped or filesystem-based setups, artifacts can be exchanged as files instead of over
+//! stdin/stdout or the network. A mailbox directory follows the maildir layout:
+//!
+//! - `tmp/` holds files while they are being written
+//! - `new/` holds complete messages, one message per file, named by sequence number
+//! - `cur/` holds consumed messages (the archive)
+//!
+//! Writers create the file in `tmp/` and atomically rename it into `new/`, so readers never see
+//! partial files. Readers claim a file by atomically renaming it into `cur/` only once its message
+//! is processed and the state saved, so a reader that fails in between finds the message in `new/`
+//! again instead of losing it. A message may thus be read twice (also by concurrent readers);
+//! processing skips messages it has seen before.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let outbox = Maildir::new("./alice-out");
+//! outbox.deliver(seq, commit_b64.as_bytes())?;
+//! // ... copy alice-out/new/* into bob-in/new/ ...
+//! let inbox = Maildir::new("./bob-in");
+//! let messages = inbox.read_messages()?;
+//! for (_, msg) in &messages { /* process */ }
+//! // ... save state ...
+//! inbox.claim(messages.iter().map(|(name, _)| name))?;
+//! ```
+
+use core::error::Error;
+use std::{
+    ffi::OsString,
+    fs::{create_dir_all, read, read_dir, rename, write},
+    io::ErrorKind,
+    path::PathBuf,
+};
+
+/// A maildir-style mailbox directory.
+#[derive(Clone, Debug)]
+pub struct Maildir {
+    /// Root directory of the mailbox.
+    dir: PathBuf,
+}
+
+impl Maildir {
+    /// Creates a handle for the mailbox at the given directory.
+    pub fn new(dir: &str) -> Self {
+        Self {
+            dir: PathBuf::from(dir),
+        }
+    }
+    /// Returns the root directory of the mailbox.
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+    /// Returns the given subdirectory of the mailbox, creating it if necessary.
+    fn subdir(&self, name: &str) -> std::io::Result<PathBuf> {
+        let dir = self.dir.join(name);
+        create_dir_all(&dir)?;
+        Ok(dir)
+    }
+}
+
+impl Maildir {
+    /// Write a message into the mailbox under the given sequence number.
+    ///
+    /// The file is written to `tmp/` and then renamed into `new/`; the path of the delivered file
+    /// is returned.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let path = outbox.deliver(provider.state_mut().next_maildir_seq(), msg.as_bytes())?;
+    /// ```
+    pub fn deliver(&self, seq: u64, message: &[u8]) -> Result<PathBuf, Box<dyn Error>> {
+        let name = format!("{seq:020}.msg");
+        let tmp = self.subdir("tmp")?.join(&name);
+        let new = self.subdir("new")?.join(&name);
+        write(&tmp, message)?;
+        rename(&tmp, &new)?;
+        Ok(new)
+    }
+
+    /// Return all messages in `new/` with their file names, in file name order, without claiming
+    /// them.
+    ///
+    /// Files claimed concurrently by another reader are skipped.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// for (name, msg) in inbox.read_messages()? {
+    ///     // msg is the file content (base64 text or a raw MLS message)
+    /// }
+    /// ```
+    pub fn read_messages(&self) -> Result<Vec<(OsString, Vec<u8>)>, Box<dyn Error>> {
+        let new = self.subdir("new")?;
+        let mut names = read_dir(&new)?
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .map(|entry| entry.file_name())
+            .collect::<Vec<_>>();
+        names.sort();
+        let mut messages = Vec::new();
+        for name in names {
+            match read(new.join(&name)) {
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+                Ok(message) => messages.push((name, message)),
+            }
+        }
+        Ok(messages)
+    }
+
+    /// Claim and archive read messages by renaming them from `new/` into `cur/`.
+    ///
+    /// Call this only once the messages are processed and the state is saved. Files claimed
+    /// concurrently by another reader are skipped.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// inbox.claim(messages.iter().map(|(name, _)| name))?;
+    /// ```
+    pub fn claim<'a>(
+        &self,
+        names: impl IntoIterator<Item = &'a OsString>,
+    ) -> Result<(), Box<dyn Error>> {
+        let new = self.subdir("new")?;
+        let cur = self.subdir("cur")?;
+        for name in names {
+            match rename(new.join(name), cur.join(name)) {
+                Err(e) if e.kind() == ErrorKind::NotFound => continue,
+                Err(e) => return Err(e.into()),
+                Ok(()) => {}
+            }
+        }
+        Ok(())
+    }
+}
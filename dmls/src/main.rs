@@ -1,32 +1,136 @@
 #![doc = include_str!("../README.md")]
 #![allow(clippy::multiple_crate_versions)]
+// the library's dependencies are shared by this binary but partly unused here
+#![allow(unused_crate_dependencies)]
 
-mod helpers;
-mod openmls_keys;
-mod openmls_kvstore;
-mod provider;
-mod state;
-
-use crate::{
+use clap::{
+    ArgAction, Command, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum,
+    error::ErrorKind,
+};
+use clap_complete::{Shell, generate as generate_completions, generate_to as write_completions};
+use clap_mangen::{Man, generate_to as write_manpages};
+use core::{error::Error, num::NonZeroUsize, ops::ControlFlow};
+#[cfg(feature = "archive")]
+use dmls::archive::{export as export_storage, import as import_storage};
+#[cfg(feature = "grpc")]
+use dmls::grpc;
+#[cfg(feature = "mlock")]
+use dmls::mlock::lock_secrets;
+#[cfg(feature = "redb")]
+use dmls::redb_storage::RedbStore;
+#[cfg(unix)]
+use dmls::serve::{ServeRequest, serve};
+use dmls::{
+    audit::verify as verify_audit_log,
+    backup::{newer_groups, open as open_backup, seal as seal_backup},
+    byte_store::ByteStore,
+    config::Config,
+    continuity::rotate_key_base64,
+    crypto::CryptoBackend,
+    dir_storage::DirStore,
+    doctor::{diagnose, repair},
+    ds::HttpDeliveryService,
+    encoding::{Blob, Encoding, set_encoding},
+    envelope::{Envelope, is_envelope},
+    error::explain,
+    follow::follow,
+    framing::Framing,
     helpers::{
-        apply_commit, force_add_members_base64, gen_kp_base64, gen_send_group, plaintext,
-        process_proto_msg, process_welcome, send_group, send_group_inject_psks_base64,
-        send_group_update_base64, stdin_base64_extract, stdin_base64_to_kp,
-        stdin_base64_to_mls_msg_in, stdin_create_message_base64,
-    },
-    openmls_keys::SignatureKeyPair,
-    provider::DmlsProvider,
-    state::DmlsState,
+        DecodedMessage, DecryptedMessage, GroupCache, GroupConfigOptions, HandshakeWireFormat,
+        add_members_base64, add_members_bundle, bytes_to_kp, bytes_to_mls_msg_in, capabilities,
+        check_ciphersuite, check_duplicate, check_envelope, commit_proposals_base64, decode_blob,
+        decode_blobs_parallel, decrypt_message, describe_pending_commit, export_group_info_base64,
+        export_ratchet_tree_base64, export_secret_base64, file_blob, force_add_members_base64,
+        gen_recorded_kp_base64, gen_send_group, group_base64, group_stats, heal_base64,
+        kp_identity, load_group, load_state, load_state_with_storage, member_identities,
+        own_identity, pending_commit_base64, persist_state, preview_commit, process_body_main,
+        process_decoded, process_fetched_kp_base64, proposal_ref_from_base64, purge_epochs,
+        queue_resumption_psks, queued_proposals_base64, ratchet_tree_from_base64, rekey_if_due,
+        resumption_psk_epochs, save_state, seal_envelope_base64, send_group,
+        send_group_abort_commit, send_group_branch_base64, send_group_clear_pending,
+        send_group_confirm_commit, send_group_inject_psks_base64, send_group_reinit_base64,
+        send_group_stage_inject_psks_base64, send_group_stage_update_base64,
+        send_group_update_base64, set_per_message_fs, stage_add_members_base64,
+        stdin_base64_extract, stdin_create_message_base64, store_external_psk, stored_group_ids,
+        take_address_book_kps, update_identity_base64, verify_signature_base64,
+    },
+    integrity::set_integrity_key,
+    interop::verify_file as verify_test_vectors,
+    kp_pool::{maintain as maintain_kp_pool, mark_consumed},
+    maildir::Maildir,
+    observe::{process as process_observed, start as start_observing, status as observe_status},
+    openmls_keys::{SignatureKeyPair, parse_signature_scheme},
+    perms::{check as check_perms, set_strict_perms},
+    provider::{DEFAULT_CLOCK_SKEW, DmlsProvider},
+    redact::{redact as redact_state, set_log_secrets},
+    roles::{admins, send_group_set_admin_base64},
+    simulate::{Scenario, Simulation},
+    state::{
+        CapabilitiesOptions, DmlsState, ExporterParams, MembershipPolicy, OutboxKind, PskOverflow,
+        PskQueuePolicy, RekeyPolicy, StateFormat, SuitePolicy,
+    },
+    stdio::{input, output, set_input, set_output},
+    transport::{
+        Blobs, KeyPackageDirectory, StdioTransport, Transport, emit as emit_artifact,
+        parse_transport, read_blobs,
+    },
+    tree::TreeView,
+    ws::{WsFrame, listen},
+};
+use openmls::{
+    extensions::ExtensionType,
+    framing::{MlsMessageBodyIn, ProtocolMessage},
+    treesync::RatchetTreeIn,
 };
-use clap::{Parser, Subcommand};
-use openmls::framing::{MlsMessageBodyIn, ProcessedMessageContent, ProtocolMessage};
 use openmls_rust_crypto::RustCrypto;
-use openmls_traits::types::{Ciphersuite, SignatureScheme};
-use serde_json::{from_str as json_decode, to_string as json_encode};
+use openmls_traits::{
+    OpenMlsProvider,
+    types::{Ciphersuite, SignatureScheme},
+};
+use rayon::prelude::*;
+use serde_json::{to_string as json_encode, to_string_pretty as json_encode_pretty};
 use std::{
-    fs::{read_to_string as read_file_to_string, write as write_string_to_file},
-    io::{BufRead, stdin},
+    fs::{
+        create_dir_all, read as read_file, read_to_string as read_file_to_string,
+        write as write_file,
+    },
+    io::{BufRead, Read, Write, stderr},
+    path::Path,
+    time::{Duration, Instant},
 };
+use tracing_subscriber::{
+    EnvFilter,
+    layer::{Layer, SubscriberExt},
+    util::SubscriberInitExt,
+};
+
+/// Like `print!`, but to the output (stdout or `--out`); write errors are logged.
+macro_rules! out {
+    ($($arg:tt)*) => {
+        if let Err(e) = write!(output(), $($arg)*) {
+            tracing::error!("Error writing output: {e}");
+        }
+    };
+}
+
+/// Like `println!`, but to the output (stdout or `--out`); write errors are logged.
+macro_rules! outln {
+    ($($arg:tt)*) => {
+        if let Err(e) = writeln!(output(), $($arg)*) {
+            tracing::error!("Error writing output: {e}");
+        }
+    };
+}
+
+/// Log target of human commentary (see `note!`), shown by default but not with `--quiet`.
+const NOTE_TARGET: &str = "dmls::note";
+
+/// Log human commentary on stderr, e.g. what a command did; stdout only carries artifacts.
+macro_rules! note {
+    ($($arg:tt)*) => {
+        tracing::info!(target: NOTE_TARGET, $($arg)*)
+    };
+}
 
 /// Command-line arguments for the DMLS example agent.
 ///
@@ -45,14 +149,103 @@ use std::{
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct CliArgs {
+    /// Log output format (`text` or `json`)
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+    /// Only log errors (optional)
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Log more: `-v` for informational messages, `-vv` for debugging output including state
+    /// dumps, `-vvv` for everything; without `-q`/`-v`, `RUST_LOG` is honored if set (optional)
+    #[arg(short, long, global = true, action = ArgAction::Count)]
+    verbose: u8,
+    /// Show private keys and secrets in full in logged state dumps instead of hashed; only for
+    /// debugging throwaway states (optional)
+    #[arg(long, global = true)]
+    unsafe_log_secrets: bool,
+    /// Text encoding of blobs on stdin/stdout: `base64`, `base64url` or `hex`
+    #[arg(long, global = true, default_value = "base64")]
+    encoding: Encoding,
+    /// Framing of MLS messages on stdin/stdout: `lines` of encoded text, `armor`ed text blocks, or
+    /// `raw-len32` binary frames each preceded by a big-endian `u32` length
+    #[arg(long, global = true, default_value = "lines")]
+    framing: Framing,
+    /// Format for writing state files: `json`, `cbor` or `msgpack`; defaults to JSON for new
+    /// states and to the detected format of existing ones
+    #[arg(long, global = true)]
+    state_format: Option<StateFormat>,
+    /// File holding a key for MACs over state files, written on save and verified on load
+    /// (optional)
+    #[arg(long, global = true)]
+    integrity_key: Option<String>,
+    /// Fail instead of warning when a state file is readable or writable by its group or others
+    /// (Unix only; optional)
+    #[arg(long, global = true)]
+    strict_perms: bool,
+    /// Crypto backend: `rust-crypto`, or `libcrux` if compiled in (`--features libcrux`)
+    #[arg(long, global = true, default_value = "rust-crypto")]
+    crypto_backend: String,
+    /// File to read input from instead of stdin (optional)
+    #[arg(long = "in", global = true)]
+    input: Option<String>,
+    /// File to write output to instead of stdout, creating missing directories (optional)
+    #[arg(long = "out", global = true)]
+    output: Option<String>,
+    /// Append to the `--out` file instead of replacing its content (optional)
+    #[arg(long, global = true, requires = "output")]
+    append: bool,
+    /// Profile from `dmls.toml` supplying the state path and defaults (optional)
+    #[arg(long, global = true)]
+    profile: Option<String>,
     /// Command to use for loading state
     #[command(subcommand)]
     state_command: StateCommands,
 }
 
+/// Log output formats.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum LogFormat {
+    /// Human-readable lines.
+    Text,
+    /// One JSON object per event, for log pipelines.
+    Json,
+}
+
+/// Output formats of a membership change bundle.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum BundleFormat {
+    /// One `<artifact>: <base64>` line per artifact.
+    Labeled,
+    /// One JSON object with `commit`, `welcome` and `group_info` fields.
+    Json,
+}
+
+/// Output formats of `export-pubkey`.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum PubkeyFormat {
+    /// The raw public key (base64).
+    Raw,
+    /// A PEM `PUBLIC KEY` block (SubjectPublicKeyInfo).
+    Pem,
+    /// An OpenSSH `authorized_keys` line.
+    Openssh,
+    /// A JSON Web Key.
+    Jwk,
+}
+
+/// Subcommands of `test-vectors`.
+#[derive(Clone, Debug, Subcommand)]
+enum TestVectorsCommands {
+    /// Verify a test-vector file (tree-math, key-schedule or messages JSON).
+    Verify {
+        /// Path to the JSON test-vector file
+        file: String,
+    },
+}
+
 /// Top-level state commands supported by the CLI.
 ///
-/// - `GenState` creates a new JSON state file containing the generated signature key pair.
+/// - `GenState` creates a new state file containing the generated signature key pair.
 /// - `UseState` loads an existing state file and runs `MainCommands` against it.
 /// - `InspectMessages` attempts to deserialize base64-encoded MLS messages from stdin and
 ///   pretty-prints them for debugging.
@@ -60,28 +253,156 @@ struct CliArgs {
 enum StateCommands {
     /// Create a new per-participant state and write it to `state_path`.
     GenState {
-        /// Path to a JSON file to write (required)
+        /// Path to a state file to write (required)
         state_path: String,
-        /// Digital signature algorithm to use to generate signing key (optional)
+        /// Digital signature algorithm to use to generate signing key: Ed25519, Ed448, P256, P384
+        /// or P521, as supported by the crypto backend (optional)
         #[arg(long, default_value = "Ed25519")]
         signature_scheme: String,
+        /// Print the Ed25519 signing seed as a 24-word BIP39 mnemonic for backup (optional)
+        #[arg(long, conflicts_with = "from_mnemonic")]
+        print_mnemonic: bool,
+        /// Restore the Ed25519 signing key from a BIP39 mnemonic read from stdin (optional)
+        #[arg(long)]
+        from_mnemonic: bool,
     },
     /// Load an existing state and run a main command using that state.
     UseState {
-        /// Path to a JSON file to update (required)
+        /// Path to a state file to update (required)
         state_path: String,
         /// Ciphersuite to use (optional)
         #[arg(long, default_value = "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519")]
         ciphersuite: String,
-        /// Exporter length to use for DMLS exporter PSK (optional)
+        /// Exporter PSK length for groups created or joined now; existing groups keep theirs (optional)
         #[arg(long, default_value_t = 32)]
         exporter_length: usize,
+        /// Transport for artifacts: `stdio`, `http:<url>` of a delivery service, `nats:<url>` of
+        /// a NATS JetStream server (`--features nats`) or `matrix:<homeserver url>?room=<room id>`
+        /// of a Matrix room, with the access token in `DMLS_MATRIX_TOKEN` (`--features matrix`)
+        /// (optional)
+        #[arg(long, default_value = "stdio")]
+        transport: String,
+        /// Maildir-style directory to read incoming message files from in `process` (optional)
+        #[arg(long)]
+        inbox: Option<String>,
+        /// Maildir-style directory to also drop produced artifacts into (optional)
+        #[arg(long)]
+        outbox: Option<String>,
+        /// Append only the changes to `<state_path>.journal` instead of rewriting the state file
+        /// (optional)
+        #[arg(long)]
+        journal: bool,
+        /// Keep the OpenMLS values in a storage backend instead of the state file: `dir:<path>` or
+        /// `redb:<path>` (optional; redb needs the `redb` feature)
+        #[arg(long)]
+        storage: Option<String>,
+        /// Let commands discard a pending commit that may already have been sent (optional)
+        #[arg(long)]
+        force: bool,
+        /// Seconds by which clocks may differ when checking key package lifetimes (optional)
+        #[arg(long, default_value_t = DEFAULT_CLOCK_SKEW)]
+        clock_skew: u64,
+        /// Don't check key package lifetimes at all; for testing only (optional)
+        #[arg(long)]
+        ignore_lifetime: bool,
         /// Main command to run using the loaded state
         #[command(subcommand)]
         main_command: MainCommands,
     },
-    /// Inspect base64-encoded MLS messages read from stdin and pretty-print them.
-    InspectMessages {},
+    /// Inspect base64-encoded MLS messages (or envelopes) read from stdin and pretty-print them.
+    InspectMessages {
+        /// Decode and format messages on all cores, printing them in input order (optional)
+        #[arg(long)]
+        parallel: bool,
+    },
+    /// Verify a signature by a peer's public key over a payload read from stdin (raw bytes).
+    VerifySignature {
+        /// Signature public key, encoded like blobs (required)
+        #[arg(long)]
+        public_key: String,
+        /// Signature, encoded like blobs (required)
+        #[arg(long)]
+        signature: String,
+        /// Signature scheme of the key: Ed25519, Ed448, P256, P384 or P521 (optional)
+        #[arg(long, default_value = "Ed25519")]
+        signature_scheme: String,
+        /// Verify an MLS `SignWithLabel` signature with this label (e.g. `LeafNodeTBS`) over the
+        /// payload (optional)
+        #[arg(long)]
+        label: Option<String>,
+    },
+    /// Check the behavior against the official RFC 9420 test vectors.
+    TestVectors {
+        /// Test-vector command to run
+        #[command(subcommand)]
+        test_vectors_command: TestVectorsCommands,
+    },
+    /// Print a shell completion script, or write it to a directory for packaging.
+    Completions {
+        /// Shell to generate the script for (required)
+        shell: Shell,
+        /// Directory to write the script to instead of printing it (optional)
+        #[arg(long)]
+        dir: Option<String>,
+    },
+    /// Print the manual page (roff), or write pages for all commands to a directory for packaging.
+    Manpage {
+        /// Directory to write the pages to instead of printing the main page (optional)
+        #[arg(long)]
+        dir: Option<String>,
+    },
+    /// Explain an error code printed with a failure, and how to remedy it.
+    Explain {
+        /// Error code, e.g. `DMLS-0007` (required)
+        code: String,
+    },
+    /// Restore a state file from an encrypted backup (see `use-state ... backup`).
+    Restore {
+        /// Path to the state file to restore (required)
+        state_path: String,
+        /// Backup file to restore from (required)
+        backup: String,
+        /// File holding the backup key (required)
+        #[arg(long)]
+        key: String,
+        /// Restore even if groups in the existing state are at a later epoch than in the backup
+        /// (optional)
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run a YAML/JSON scenario with in-process agents, print a transcript and check convergence.
+    Simulate {
+        /// Path to the scenario file (required)
+        scenario_path: String,
+        /// Ciphersuite to use (optional)
+        #[arg(long, default_value = "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519")]
+        ciphersuite: String,
+        /// Exporter length to use for DMLS exporter PSK (optional)
+        #[arg(long, default_value_t = 32)]
+        exporter_length: usize,
+        /// Seed for the participants' signing keys and randomness (optional; needs deterministic-rng)
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+    /// Serve the gRPC API for the state profiles (`<profile>.json`) in a directory.
+    #[cfg(feature = "grpc")]
+    ServeGrpc {
+        /// Directory holding the state profiles (required)
+        state_dir: String,
+        /// Address to listen on (optional)
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        listen: String,
+        /// Ciphersuite to use (optional)
+        #[arg(long, default_value = "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519")]
+        ciphersuite: String,
+        /// Exporter PSK length for groups created or joined; existing groups keep theirs (optional)
+        #[arg(long, default_value_t = 32)]
+        exporter_length: usize,
+        /// Append only the changes of each call to `<profile>.json.journal` instead of rewriting
+        /// the profile (optional)
+        #[arg(long)]
+        journal: bool,
+    },
 }
 
 /// Main commands that operate on a loaded `DmlsState`.
@@ -93,62 +414,998 @@ enum StateCommands {
 enum MainCommands {
     /// Generate a KeyPackage (prints base64 to stdout).
     GenKp {},
+    /// Print the credential identity, signature scheme and public key fingerprint.
+    Whoami {},
+    /// Print the signature public key, e.g. for PKI or SSH-based trust tooling.
+    ExportPubkey {
+        /// Output format (optional)
+        #[arg(long, value_enum, default_value_t = PubkeyFormat::Raw)]
+        format: PubkeyFormat,
+    },
+    /// Generate key packages and publish them to a key-package directory.
+    PublishKp {
+        /// Base URL of the key-package directory (required)
+        #[arg(long)]
+        url: String,
+        /// Number of key packages to publish (optional)
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+    },
+    /// Export a secret from a group's current epoch for an application (prints base64).
+    ///
+    /// The label must be on the allow-list managed with `export-labels`.
+    ExportSecret {
+        /// Exporter label (required)
+        #[arg(long)]
+        label: String,
+        /// Exporter context, as base64 (optional)
+        #[arg(long, default_value = "")]
+        context: String,
+        /// Length of the secret in bytes (optional)
+        #[arg(long, default_value_t = 32)]
+        length: usize,
+        /// Group to export from, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Manage the allow-list of labels `export-secret` accepts.
+    ExportLabels {
+        /// Export label command to run
+        #[command(subcommand)]
+        export_labels_command: ExportLabelsCommands,
+    },
+    /// Manage which send groups the exporter PSKs of each receive group are injected into.
+    ///
+    /// Without rules, exporter PSKs of all groups are injected into the send group; once a rule
+    /// exists, only those of groups whose rule lists the send group are.
+    Bridge {
+        /// Bridging rule command to run
+        #[command(subcommand)]
+        bridge_command: BridgeCommands,
+    },
+    /// Manage the admin role, which is required to add or remove members.
+    Admin {
+        /// Admin command to run
+        #[command(subcommand)]
+        admin_command: AdminCommands,
+    },
+    /// Keep a pool of published key packages topped up, replacing those consumed by Welcomes.
+    KpPool {
+        /// Key package pool command to run
+        #[command(subcommand)]
+        kp_pool_command: KpPoolCommands,
+    },
+    /// Fetch a member's key package from a key-package directory (prints base64 to stdout).
+    ///
+    /// The key package is validated and also stored in the local address book.
+    FetchKp {
+        /// Credential identity of the member, as base64 (required)
+        #[arg(long)]
+        identity: String,
+        /// Base URL of the key-package directory (required)
+        #[arg(long)]
+        url: String,
+    },
     /// Process incoming messages (reads base64 messages from stdin).
-    Process {},
+    ///
+    /// Key packages found on stdin are validated and stored in the local address book. With
+    /// `--listen`, messages are instead received from a WebSocket until the server closes the
+    /// connection, and state is checkpointed to disk periodically along the way. With `--follow`,
+    /// new messages in a file or directory are processed as they appear, and state is
+    /// checkpointed after each batch. Messages delivered again are skipped (see `dedupe`).
+    Process {
+        /// Seconds to wait for messages to arrive (optional; http, nats and matrix transports)
+        #[arg(long, default_value_t = 0)]
+        wait: u64,
+        /// WebSocket endpoint (`ws://...`) to receive messages from continuously (optional)
+        #[arg(long)]
+        listen: Option<String>,
+        /// File to tail, or maildir-style directory to watch, for new messages (optional)
+        #[arg(long)]
+        follow: Option<String>,
+        /// Seconds between state checkpoints while listening (optional; 0 saves after every message)
+        #[arg(long, default_value_t = 10)]
+        checkpoint_interval: u64,
+        /// Encoded ratchet tree for joining groups without the ratchet_tree extension (optional)
+        #[arg(long)]
+        ratchet_tree: Option<String>,
+        /// Expect messages wrapped in signed application envelopes and verify them (optional)
+        #[arg(long)]
+        envelope: bool,
+        /// Decode all messages on all cores up front, then apply them in input order (optional;
+        /// stdin, inbox and http only)
+        #[arg(long)]
+        parallel: bool,
+        /// Print decrypted messages as JSON records, like `decrypt` (optional)
+        #[arg(long)]
+        json: bool,
+        /// Stop at the first message that fails instead of logging the error and going on
+        /// (optional)
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Decrypt application messages (reads base64 messages from stdin) and print one JSON record
+    /// per message with the plaintext, sender leaf index, sender identity, group id and epoch.
+    ///
+    /// Handshake messages, Welcomes and key packages are skipped without being processed.
+    Decrypt {
+        /// Expect messages wrapped in signed application envelopes and verify them (optional)
+        #[arg(long)]
+        envelope: bool,
+    },
+    /// Print a group's ratchet tree, TLS-serialized (RFC 9420) and base64-encoded.
+    ExportRatchetTree {
+        /// Group to export, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Print a signed GroupInfo of a group, from which observers can start following it.
+    ExportGroupInfo {
+        /// Group to export, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+        /// Leave the ratchet tree out; observers then need `export-ratchet-tree` (optional)
+        #[arg(long)]
+        no_ratchet_tree: bool,
+    },
+    /// Rejoin a group with an external commit from a fresh GroupInfo (stdin; see
+    /// `export-group-info`), e.g. after its local state was compromised, queue the new epoch's
+    /// exporter PSK and deliver the commit.
+    Heal {
+        /// Group to rejoin, as base64 (required)
+        #[arg(long)]
+        group_id: String,
+        /// Ratchet tree of the group, as base64, if the GroupInfo doesn't carry it (optional)
+        #[arg(long)]
+        ratchet_tree: Option<String>,
+    },
+    /// Follow groups as a passive observer, from their public handshake messages.
+    Observe {
+        /// Observe command to run
+        #[command(subcommand)]
+        observe_command: ObserveCommands,
+    },
     /// Encrypt plaintext lines into base64 application messages (reads plaintext from stdin).
-    Encrypt {},
+    Encrypt {
+        /// Group to encrypt in, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+        /// Wrap messages in signed application envelopes with routing metadata (optional)
+        #[arg(long)]
+        envelope: bool,
+        /// Content type recorded in envelopes (optional)
+        #[arg(long, default_value = "text/plain")]
+        content_type: String,
+    },
     /// Create a self-update commit (prints base64 commit to stdout).
-    Update {},
+    Update {
+        /// Leave the commit pending until `confirm-commit`/`abort-commit` (optional)
+        #[arg(long)]
+        stage: bool,
+        /// Print what the commit would contain without merging it or changing the state (optional)
+        #[arg(long, conflicts_with = "stage")]
+        dry_run: bool,
+        /// With `--dry-run`, also print the commit that would be sent (optional)
+        #[arg(long, requires = "dry_run")]
+        show_commit: bool,
+        /// Refresh the full direct path with a fresh leaf, not just OpenMLS' default update (optional)
+        #[arg(long)]
+        full_path: bool,
+        /// Replace the credential identity with this one, keeping the signature key (optional)
+        #[arg(long, conflicts_with_all = ["stage", "dry_run", "full_path"])]
+        new_identity: Option<String>,
+    },
+    /// Replace the signing key with a fresh one (prints base64 commit to stdout, if any).
+    ///
+    /// The send group's leaf is updated to the new credential together with a proof signed by
+    /// the old key, which members check before accepting the new key.
+    RotateKey {},
     /// Inject queued PSKs into send-group and return commit (base64).
-    Commit {},
+    Commit {
+        /// Also inject the resumption PSK of this epoch of the send group (repeatable; optional)
+        #[arg(long)]
+        resumption_epoch: Vec<u64>,
+        /// Leave the commit pending until `confirm-commit`/`abort-commit` (optional)
+        #[arg(long)]
+        stage: bool,
+        /// Print what the commit would contain without merging it or changing the state (optional)
+        #[arg(long, conflicts_with = "stage")]
+        dry_run: bool,
+        /// With `--dry-run`, also print the commit that would be sent (optional)
+        #[arg(long, requires = "dry_run")]
+        show_commit: bool,
+    },
+    /// List the epochs of a group whose resumption PSKs are still retained, oldest first.
+    ResumptionPsks {
+        /// Group to list, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Store an out-of-band external PSK and queue it for injection with the next `commit`.
+    InjectExternalPsk {
+        /// PSK id, as base64 (required)
+        #[arg(long)]
+        id: String,
+        /// File holding the raw PSK secret (required)
+        #[arg(long)]
+        secret_file: String,
+        /// Only store the PSK, e.g. as a member of the group it will be injected into (optional)
+        #[arg(long)]
+        store_only: bool,
+    },
+    /// Merge the send-group's pending commit once it has been delivered.
+    ConfirmCommit {},
+    /// Discard the send-group's pending commit.
+    AbortCommit {},
+    /// Inspect, discard or resend the send-group's pending commit and proposals.
+    ///
+    /// Commands staging a new commit refuse to discard a pending one, which may already have
+    /// been sent, unless `--force` is given.
+    Pending {
+        /// Pending command to run
+        #[command(subcommand)]
+        pending_command: PendingCommands,
+    },
+    /// List pending proposals (reference and type), or commit only the selected ones (base64).
+    CommitProposals {
+        /// Group to commit in, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+        /// Reference of a pending proposal to commit, as base64 (repeatable; lists if omitted)
+        #[arg(long = "include-ref")]
+        include_refs: Vec<String>,
+    },
+    /// Re-initialize the send-group under a new ciphersuite (prints ReInit commit and welcome).
+    ///
+    /// Members' key packages for the new ciphersuite must have been collected in the address
+    /// book beforehand (see `process`).
+    Reinit {
+        /// Ciphersuite to migrate the send group to (required)
+        #[arg(long)]
+        ciphersuite: String,
+    },
+    /// Branch the send-group into a new group with a subset of its members (prints welcome).
+    ///
+    /// Key packages for the selected members must have been collected in the address book.
+    Branch {
+        /// Credential identity of a member to include, as base64 (repeatable)
+        #[arg(long = "member", required = true)]
+        members: Vec<String>,
+    },
+    /// Manage artifacts awaiting delivery acknowledgement.
+    Outbox {
+        /// Outbox command to run
+        #[command(subcommand)]
+        outbox_command: OutboxCommands,
+    },
+    /// Render a group's ratchet tree, labeling leaves with credential identities.
+    Tree {
+        /// Group to render, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+        /// Output format: `ascii` or `dot` (Graphviz)
+        #[arg(long, default_value = "ascii")]
+        format: String,
+    },
+    /// Print the activity counters (messages, commits, PSKs, last activity) per group as JSON.
+    Stats {},
+    /// Print statistics of groups as JSON: epoch, members, tree depth, blank nodes, unmerged
+    /// leaves, pending proposals, resumption PSKs and storage footprint.
+    GroupStats {
+        /// Group to inspect, as base64 (optional; defaults to all stored groups)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Delete the exporter PSKs and epoch key pairs of past epochs outside the retention window,
+    /// then print how many entries were deleted.
+    PurgeEpochs {
+        /// Past epochs to keep (optional; defaults to the window set with `epoch-retention`)
+        #[arg(long)]
+        keep: Option<u64>,
+        /// Group to purge, as base64 (optional; defaults to all stored groups)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Select whether a group deletes message secrets as soon as they are used, then print its
+    /// sender ratchet configuration as JSON.
+    ///
+    /// Per-message forward secrecy keeps no secrets for messages arriving out of order, which then
+    /// can't be decrypted. Without options, the configuration is printed unchanged.
+    ForwardSecrecy {
+        /// Delete message secrets as soon as they are used (optional)
+        #[arg(long)]
+        per_message: bool,
+        /// Keep secrets for messages arriving out of order again (optional)
+        #[arg(long, conflicts_with = "per_message")]
+        tolerate_reordering: bool,
+        /// Group to configure, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Set when the send group is re-keyed automatically while encrypting, then print the policy.
+    ///
+    /// Without options, the current policy is printed unchanged.
+    RekeyPolicy {
+        /// Re-key after this many application messages since the last self-update (optional)
+        #[arg(long)]
+        max_messages: Option<u64>,
+        /// Re-key once this many seconds passed since the last self-update (optional)
+        #[arg(long)]
+        max_age: Option<u64>,
+        /// Turn automatic re-keying off (optional)
+        #[arg(long, conflicts_with_all = ["max_messages", "max_age"])]
+        off: bool,
+    },
+    /// Set the leaf node capabilities advertised in key packages, created groups and updated
+    /// leaves, then print them.
+    ///
+    /// Values are protocol code points; options that aren't given keep their current value, and an
+    /// empty list means the OpenMLS default. Without options, the capabilities are printed
+    /// unchanged.
+    Capabilities {
+        /// Supported protocol versions, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        versions: Option<Vec<u16>>,
+        /// Supported ciphersuites, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        ciphersuites: Option<Vec<u16>>,
+        /// Supported extension types beyond the DMLS ones, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        extensions: Option<Vec<u16>>,
+        /// Supported credential types, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        credentials: Option<Vec<u16>>,
+        /// Go back to the defaults (optional)
+        #[arg(long, conflicts_with_all = ["versions", "ciphersuites", "extensions", "credentials"])]
+        reset: bool,
+    },
+    /// Set the ciphersuites and signature schemes accepted in key packages, Welcomes and new
+    /// groups, then print them.
+    ///
+    /// Values are protocol code points; options that aren't given keep their current value, and an
+    /// empty list accepts all. Without options, the policy is printed unchanged.
+    SuitePolicy {
+        /// Accepted ciphersuites, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        ciphersuites: Option<Vec<u16>>,
+        /// Accepted signature schemes, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        signature_schemes: Option<Vec<u16>>,
+        /// Accept all ciphersuites and signature schemes again (optional)
+        #[arg(long, conflicts_with_all = ["ciphersuites", "signature_schemes"])]
+        reset: bool,
+    },
+    /// Set limits on group membership, enforced when adding members and on received commits, then
+    /// print them.
+    ///
+    /// Options that aren't given keep their current value. Without options, the policy is printed
+    /// unchanged.
+    MembershipPolicy {
+        /// Members a group may have (optional)
+        #[arg(long)]
+        max_members: Option<u64>,
+        /// Members a single commit may add (optional)
+        #[arg(long)]
+        max_adds_per_commit: Option<u64>,
+        /// Credential identity to ban from being added, as base64 (optional; repeatable)
+        #[arg(long)]
+        ban: Vec<String>,
+        /// Credential identity to lift the ban of, as base64 (optional; repeatable)
+        #[arg(long)]
+        unban: Vec<String>,
+        /// Remove all limits and bans (optional)
+        #[arg(long, conflicts_with_all = ["max_members", "max_adds_per_commit", "ban", "unban"])]
+        reset: bool,
+    },
+    /// Cap the queue of exporter PSKs awaiting injection into the send group, then print the
+    /// policy.
+    ///
+    /// Options that aren't given keep their current value. Without options, the policy is printed
+    /// unchanged.
+    PskQueuePolicy {
+        /// Exporter PSKs the queue may hold (optional)
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        max_len: Option<u64>,
+        /// What happens when the queue is full: drop-oldest, coalesce (keep the latest PSK of each
+        /// group) or error (reject commits until the queue is injected) (optional)
+        #[arg(long)]
+        overflow: Option<PskOverflow>,
+        /// Remove the cap (optional)
+        #[arg(long, conflicts_with_all = ["max_len", "overflow"])]
+        reset: bool,
+    },
+    /// Set whether joining a group from a Welcome stores and queues the exporter PSK of the join
+    /// epoch, then print whether it does.
+    ///
+    /// Without options, the setting is printed unchanged.
+    WelcomePsk {
+        /// Queue the exporter PSK of join epochs (the default; optional)
+        #[arg(long)]
+        enable: bool,
+        /// Only queue exporter PSKs of received commits (optional)
+        #[arg(long, conflicts_with = "enable")]
+        disable: bool,
+    },
+    /// Set how many past epochs of exporter PSKs and epoch key pairs are kept per group, purged
+    /// after every merged commit, then print it.
+    ///
+    /// Without options, the window is printed unchanged (`null` keeps all epochs).
+    EpochRetention {
+        /// Past epochs to keep (optional)
+        #[arg(long)]
+        epochs: Option<u64>,
+        /// Keep all epochs again (optional)
+        #[arg(long, conflicts_with = "epochs")]
+        reset: bool,
+    },
+    /// Set how many processed messages `process` remembers to skip repeated deliveries of, then
+    /// print it and how many are remembered.
+    ///
+    /// Without options, the setting is printed unchanged.
+    Dedupe {
+        /// Messages to remember; 0 disables deduplication (optional)
+        #[arg(long)]
+        capacity: Option<u64>,
+        /// Remember the default number of messages again (optional)
+        #[arg(long, conflicts_with = "capacity")]
+        reset: bool,
+        /// Forget the processed messages, so they are processed again if delivered (optional)
+        #[arg(long)]
+        clear: bool,
+    },
+    /// Print the state as JSON.
+    ExportState {
+        /// Hash all private keys and secrets, keeping group structure, epochs and public keys, so
+        /// the output can be attached to bug reports (optional)
+        #[arg(long)]
+        redact: bool,
+    },
+    /// Check the state for inconsistencies (dangling send group, queued PSKs without secrets,
+    /// orphaned epoch key pairs, unresolvable key packages).
+    Doctor {
+        /// Repair the problems found by clearing or pruning the broken entries (optional)
+        #[arg(long)]
+        fix: bool,
+    },
+    /// Write an encrypted, compressed snapshot of the state for disaster recovery.
+    Backup {
+        /// File to write the snapshot to (required)
+        #[arg(long)]
+        out: String,
+        /// File holding the backup key, at least 16 bytes (required)
+        #[arg(long)]
+        key: String,
+        /// Leave out private keys, PSKs, epoch and message secrets, key packages and pending
+        /// commits (optional)
+        #[arg(long)]
+        exclude_secrets: bool,
+    },
+    /// Export the OpenMLS values into a zstd-compressed tar archive.
+    #[cfg(feature = "archive")]
+    ExportStorage {
+        /// Archive to write, e.g. `backup.tar.zst` (required)
+        file: String,
+        /// Only export the entries of this group, as base64 (optional)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Import OpenMLS values from an archive written by `export-storage`.
+    #[cfg(feature = "archive")]
+    ImportStorage {
+        /// Archive to read (required)
+        file: String,
+    },
+    /// Inspect the hash-chained audit log of commits, membership changes and PSK injections.
+    Audit {
+        /// Audit command to run
+        #[command(subcommand)]
+        audit_command: AuditCommands,
+    },
+    /// Print which exporter PSKs (source group and epoch) were injected into which group epoch, one
+    /// JSON record per line, oldest first.
+    PskHistory {
+        /// Only show injections into this group, as base64 (optional)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Keep the state loaded and answer newline-delimited JSON requests on a Unix socket.
+    ///
+    /// Supported requests are `gen-kp`, `encrypt`, `process`, `commit` and `shutdown`; the state
+    /// is checkpointed periodically and saved on shutdown.
+    #[cfg(unix)]
+    Serve {
+        /// Path of the Unix socket to listen on (required)
+        #[arg(long)]
+        socket: String,
+        /// Seconds between state checkpoints (optional; 0 saves after every request)
+        #[arg(long, default_value_t = 10)]
+        checkpoint_interval: u64,
+    },
     /// Create a send-group (creator) and add members via key packages (stdin).
-    GenSendGroup {},
+    GenSendGroup {
+        /// Use key packages collected in the address book instead of reading stdin (optional)
+        #[arg(long)]
+        address_book: bool,
+        /// Leave the add commit pending until `confirm-commit`/`abort-commit` (optional)
+        #[arg(long)]
+        stage: bool,
+        /// Leave the ratchet tree out of Welcomes; joiners then need `export-ratchet-tree` (optional)
+        #[arg(long)]
+        no_ratchet_tree_extension: bool,
+        /// Number of past epochs whose secrets are kept to decrypt late messages (optional)
+        #[arg(long, default_value_t = 0)]
+        max_past_epochs: usize,
+        /// Delete message secrets as soon as they are used; messages arriving out of order can't
+        /// be decrypted (optional)
+        #[arg(long)]
+        per_message_fs: bool,
+        /// Extension types all members must support, as comma-separated numbers (optional)
+        #[arg(long, value_delimiter = ',')]
+        required_capabilities: Vec<u16>,
+        /// Lifetime of the creator's leaf node in seconds (optional)
+        #[arg(long)]
+        lifetime: Option<u64>,
+        /// Wire format of handshake messages: public (visible to the delivery service) or private
+        /// (optional)
+        #[arg(long, default_value = "private")]
+        handshake_wire_format: HandshakeWireFormat,
+        /// Print the add commit, Welcome and GroupInfo as a bundle instead of only the Welcome
+        /// (optional)
+        #[arg(long)]
+        bundle: Option<BundleFormat>,
+        /// Add the key packages in chunks of this many members, one commit and Welcome per chunk,
+        /// to keep commits and Welcomes small in very large groups; if a chunk fails, the chunks
+        /// before it stay added (optional)
+        #[arg(long, conflicts_with_all = ["stage", "bundle"])]
+        chunk_size: Option<NonZeroUsize>,
+    },
+}
+
+/// Outbox commands.
+///
+/// Commits, welcomes and application messages produced by other commands are recorded in the
+/// outbox until acknowledged, so they can be re-printed for delivery instead of regenerated.
+#[derive(Clone, Debug, Subcommand)]
+enum OutboxCommands {
+    /// List outbox entries (one `<id> <kind>` line per entry).
+    List {},
+    /// Re-print outbox entries (all entries if no ids are given).
+    Resend {
+        /// Ids of entries to re-print (optional)
+        ids: Vec<u64>,
+    },
+    /// Acknowledge delivery of outbox entries, removing them.
+    Ack {
+        /// Ids of entries to acknowledge
+        ids: Vec<u64>,
+        /// Acknowledge all entries (optional)
+        #[arg(long)]
+        all: bool,
+    },
+}
+
+/// Subcommands of `pending`.
+#[derive(Clone, Debug, Subcommand)]
+enum PendingCommands {
+    /// Print the pending commit (see `--dry-run`) and the pending proposals (reference and type).
+    Show {},
+    /// Discard the pending commit and proposals.
+    Clear {},
+    /// Re-send the pending commit, as recorded in the outbox.
+    Resend {},
+}
+
+/// Subcommands of `export-labels`.
+#[derive(Clone, Debug, Subcommand)]
+enum ExportLabelsCommands {
+    /// Print the allowed labels, one per line.
+    List {},
+    /// Allow exporting secrets with a label.
+    Allow {
+        /// Exporter label (required)
+        label: String,
+    },
+    /// Stop allowing exports with a label.
+    Revoke {
+        /// Exporter label (required)
+        label: String,
+    },
+}
+
+/// Subcommands of `bridge`.
+#[derive(Clone, Debug, Subcommand)]
+enum BridgeCommands {
+    /// Print the rules, one `<source> <target>` line (base64) per allowed path; sources whose
+    /// rule blocks all send groups are printed alone.
+    List {},
+    /// Allow exporter PSKs of a group to be injected into a send group.
+    Allow {
+        /// Source group, as base64 (required)
+        source: String,
+        /// Target send group, as base64 (required)
+        target: String,
+    },
+    /// Stop injecting exporter PSKs of a group into a send group, or remove the group's rule.
+    Revoke {
+        /// Source group, as base64 (required)
+        source: String,
+        /// Target send group, as base64 (optional; removes the whole rule if omitted)
+        target: Option<String>,
+    },
 }
 
-/// High-level processing of a ProtocolMessage.
+/// Subcommands of `admin`.
+#[derive(Clone, Debug, Subcommand)]
+enum AdminCommands {
+    /// Print the admins of a group, one credential identity (base64) per line.
+    List {
+        /// Group to list, as base64 (optional; defaults to the send group)
+        #[arg(long)]
+        group_id: Option<String>,
+    },
+    /// Grant a member the admin role in the send group (commits the change).
+    Grant {
+        /// Credential identity of the member, as base64 (required)
+        identity: String,
+    },
+    /// Revoke a member's admin role in the send group (commits the change).
+    Revoke {
+        /// Credential identity of the member, as base64 (required)
+        identity: String,
+    },
+}
+
+/// Subcommands of `observe`.
+#[derive(Clone, Debug, Subcommand)]
+enum ObserveCommands {
+    /// Start observing the group of a GroupInfo (stdin; see `export-group-info`).
+    Start {
+        /// Ratchet tree of the group, as base64, if the GroupInfo doesn't carry it (optional)
+        #[arg(long)]
+        ratchet_tree: Option<String>,
+    },
+    /// Apply public proposals and commits (stdin) to the observed groups, printing each group's
+    /// new state as JSON.
+    Process {},
+    /// Print the state of every observed group as JSON, one per line.
+    Status {},
+}
+
+/// Subcommands of `kp-pool`.
+#[derive(Clone, Debug, Subcommand)]
+enum KpPoolCommands {
+    /// Print the number of unconsumed key packages in the pool.
+    Status {},
+    /// Drop consumed key packages and, if fewer than `--min` are left, generate new ones up to
+    /// `--max` (printed, or published to the key-package directory of `--url` or the http
+    /// transport).
+    Maintain {
+        /// Pool size below which the pool is replenished (optional)
+        #[arg(long, default_value_t = 10)]
+        min: usize,
+        /// Pool size to replenish up to (optional)
+        #[arg(long, default_value_t = 50)]
+        max: usize,
+        /// Base URL of the key-package directory to publish to (optional)
+        #[arg(long)]
+        url: Option<String>,
+    },
+}
+
+/// Subcommands of `audit`.
+#[derive(Clone, Debug, Subcommand)]
+enum AuditCommands {
+    /// Print the audit log (one JSON entry per line).
+    Show {},
+    /// Verify the hash chain of the audit log and, with `--integrity-key`, the MAC of its head.
+    Verify {},
+}
+
+/// Deliver a produced artifact to the given recipients using the transport.
+///
+/// With the stdio transport the artifact is simply emitted. If the transport hands the artifact
+/// back to its sender, it is marked as processed (see `check_duplicate`), so `process` skips it.
+fn deliver(
+    provider: &mut DmlsProvider,
+    transport: &dyn Transport,
+    recipients: &[Vec<u8>],
+    message: &str,
+) -> Result<(), Box<dyn Error>> {
+    transport
+        .send(message, recipients)
+        .map_err(|e| format!("Error delivering artifact: {e}"))?;
+    if transport.echoes() {
+        Blob.decode(message)
+            .and_then(|bytes| check_duplicate(provider, &bytes))
+            .map_err(|e| format!("Error marking artifact as processed: {e}"))?;
+    }
+    Ok(())
+}
+
+/// Write an encoded artifact to the output (stdout or `--out`) using the framing.
+fn emit(framing: Framing, message: &str) -> Result<(), Box<dyn Error>> {
+    emit_artifact(framing, message).map_err(|e| format!("Error writing artifact: {e}").into())
+}
+
+/// Extract a message body from a blob, opening and verifying an envelope first if requested.
+fn extract(
+    provider: &DmlsProvider,
+    blob: &[u8],
+    envelope: bool,
+) -> Result<MlsMessageBodyIn, Box<dyn Error>> {
+    check_envelope(provider, decode_blob(blob, envelope)?)
+}
+
+/// Print a previewed commit's summary, followed by the commit itself if requested.
+fn print_preview(summary: &[String], commit: &str, show_commit: bool) {
+    for line in summary {
+        outln!("{line}");
+    }
+    if show_commit {
+        outln!("{commit}");
+    }
+}
+
+/// Log a failed message, or print a decrypted one; returns whether the message failed.
+fn report_processed(
+    processed: Result<Option<DecryptedMessage>, Box<dyn Error>>,
+    json: bool,
+) -> bool {
+    match processed.and_then(|message| message.map_or(Ok(()), |m| print_decrypted(&m, json))) {
+        Err(e) => {
+            tracing::error!("Error processing message: {e}");
+            true
+        }
+        Ok(()) => false,
+    }
+}
+
+/// Print a decrypted message, either human-readable or as a JSON record.
+fn print_decrypted(message: &DecryptedMessage, json: bool) -> Result<(), Box<dyn Error>> {
+    if json {
+        let json = json_encode(message).map_err(|e| format!("Error encoding message: {e}"))?;
+        outln!("{json}");
+    } else {
+        outln!("{message}");
+    }
+    Ok(())
+}
+
+/// Pretty-print a raw MLS message, or an envelope and the message it wraps, for debugging.
 ///
-/// This helper loads the group referenced by the protocol message, processes the message,
-/// and handles application messages and staged commits. Application message plaintexts are
-/// printed to stdout; staged commits are applied to the group and may queue exporter PSKs.
+/// Envelopes can't be verified without state, so their metadata is shown as is.
+fn inspect(bytes: &[u8]) -> Result<String, Box<dyn Error>> {
+    if is_envelope(bytes) {
+        let envelope = Envelope::from_bytes(bytes)?;
+        let message = bytes_to_mls_msg_in(&envelope.message)?;
+        Ok(format!(
+            "Envelope (unverified):\n{envelope:#?}\nMessage:\n{message:#?}"
+        ))
+    } else {
+        Ok(format!("Message:\n{:#?}", bytes_to_mls_msg_in(bytes)?))
+    }
+}
+
+/// Returns the `--profile` given on the command line, if any; it is needed before parsing, as
+/// the profile supplies defaults.
+fn profile_arg(mut args: impl Iterator<Item = String>) -> Option<String> {
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            return Some(profile.to_string());
+        }
+    }
+    None
+}
+
+/// Set the default value of an argument, if any, which makes it optional.
+fn with_default(command: Command, arg: &str, value: Option<String>) -> Command {
+    match value {
+        None => command,
+        Some(value) => command.mut_arg(arg, |a| a.default_value(value).required(false)),
+    }
+}
+
+/// Parse the command line, taking defaults from the configuration files and the selected
+/// profile (see `config`); exits on errors like `CliArgs::parse`.
+fn parse_args() -> CliArgs {
+    let profile = profile_arg(std::env::args().skip(1));
+    let (defaults, state_path) =
+        match Config::load().and_then(|config| config.resolve(profile.as_deref())) {
+            Ok(resolved) => resolved,
+            Err(e) => CliArgs::command().error(ErrorKind::InvalidValue, e).exit(),
+        };
+    let exporter_length = defaults.exporter_length.map(|length| length.to_string());
+    let command = with_default(CliArgs::command(), "encoding", defaults.encoding)
+        .mut_subcommand("gen-state", |sc| {
+            with_default(sc, "state_path", state_path.clone())
+        })
+        .mut_subcommand("use-state", |sc| {
+            let sc = with_default(sc, "state_path", state_path);
+            let sc = with_default(sc, "ciphersuite", defaults.ciphersuite);
+            let sc = with_default(sc, "exporter_length", exporter_length);
+            with_default(sc, "storage", defaults.storage)
+        });
+    CliArgs::from_arg_matches(&command.get_matches()).unwrap_or_else(|e| e.exit())
+}
+
+/// Returns the log filter for the `--quiet`/`--verbose` flags: warnings and commentary (see
+/// `note!`) by default, or `RUST_LOG` if set and neither flag is given.
 ///
 /// Example:
 ///
 /// ```ignore
-/// process_proto_msg_main(&mut provider, proto_msg, ciphersuite, exporter_length);
+/// let filter = log_filter(false, 2); // debug
 /// ```
-fn process_proto_msg_main(
+fn log_filter(quiet: bool, verbose: u8) -> EnvFilter {
+    match (quiet, verbose) {
+        (true, _) => EnvFilter::new("error"),
+        (false, 0) => EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| EnvFilter::new(format!("warn,{NOTE_TARGET}=info"))),
+        (false, 1) => EnvFilter::new("info"),
+        (false, 2) => EnvFilter::new("debug"),
+        (false, _) => EnvFilter::new("trace"),
+    }
+}
+
+/// Exit code of a failed command, or of `process` when all messages failed or couldn't be read.
+const EXIT_FAILURE: i32 = 1;
+
+/// Exit code of `process` when some, but not all, messages failed.
+const EXIT_PARTIAL_FAILURE: i32 = 2;
+
+/// Messages handled by `process` and how many of them failed.
+struct ProcessTally {
+    processed: usize,
+    failed: usize,
+    /// Whether reading the messages (fetching, listening, following) failed.
+    unreadable: bool,
+}
+
+impl ProcessTally {
+    fn new() -> Self {
+        Self {
+            processed: 0,
+            failed: 0,
+            unreadable: false,
+        }
+    }
+
+    /// Count a message, given whether it failed, and return whether it failed.
+    fn record(&mut self, failed: bool) -> bool {
+        self.processed += 1;
+        self.failed += usize::from(failed);
+        failed
+    }
+
+    /// Returns 0 if nothing failed, `EXIT_PARTIAL_FAILURE` if some messages were processed
+    /// successfully and `EXIT_FAILURE` otherwise.
+    fn exit_code(&self) -> i32 {
+        if self.failed == 0 && !self.unreadable {
+            0
+        } else if self.processed > self.failed {
+            EXIT_PARTIAL_FAILURE
+        } else {
+            EXIT_FAILURE
+        }
+    }
+}
+
+impl core::fmt::Display for ProcessTally {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(
+            f,
+            "processed {} messages, {} failed",
+            self.processed, self.failed
+        )
+    }
+}
+
+/// Returns whether a command runs as a transaction, i.e. its changes to the state are discarded
+/// if it fails (returns an error), so a half-done command (e.g. a commit merged locally whose output
+/// never reached the peers) doesn't leave the state diverged from theirs.
+///
+/// Commands working through a stream of messages are not transactions: every message is a unit
+/// of its own, and earlier output has already reached peers when a later message fails. The same
+/// goes for a chunked add (`gen-send-group --chunk-size`), which delivers each chunk's commit and
+/// Welcome before adding the next chunk.
+fn transactional(command: &MainCommands) -> bool {
+    match command {
+        MainCommands::Process { .. }
+        | MainCommands::Decrypt { .. }
+        | MainCommands::Encrypt { .. }
+        | MainCommands::Observe { .. }
+        | MainCommands::GenSendGroup {
+            chunk_size: Some(_),
+            ..
+        } => false,
+        #[cfg(unix)]
+        MainCommands::Serve { .. } => false,
+        _ => true,
+    }
+}
+
+/// Open the storage backend for OpenMLS values given as `dir:<path>` or `redb:<path>`, checking
+/// its permissions first (see `perms`).
+fn open_storage(spec: &str) -> Result<Box<dyn ByteStore>, Box<dyn Error>> {
+    if let Some((_, path)) = spec.split_once(':') {
+        check_perms(path)?;
+    }
+    match spec.split_once(':') {
+        Some(("dir", path)) => Ok(Box::new(DirStore::open(path)?)),
+        #[cfg(feature = "redb")]
+        Some(("redb", path)) => Ok(Box::new(RedbStore::open(path)?)),
+        _ => Err(format!("Unknown storage backend {spec}").into()),
+    }
+}
+
+/// Drop a produced artifact into the outbox directory, if one is configured.
+///
+/// Files are named by a sequence number kept in state.
+fn drop_to_outbox(
     provider: &mut DmlsProvider,
-    proto_msg: ProtocolMessage,
-    ciphersuite: Ciphersuite,
-    exporter_length: usize,
-) {
-    match process_proto_msg(provider, proto_msg) {
-        Err(e) => {
-            log::error!("Error processing message: {e}");
+    outbox: Option<&Maildir>,
+    message: &str,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(outbox) = outbox {
+        let seq = provider.state_mut().next_maildir_seq();
+        let path = outbox
+            .deliver(seq, message.as_bytes())
+            .map_err(|e| format!("Error writing to outbox {}: {e}", outbox.dir().display()))?;
+        tracing::info!("Wrote {}", path.display());
+    }
+    Ok(())
+}
+
+/// Parse a base64 ratchet tree, ignoring (with a warning) an invalid one.
+fn parse_ratchet_tree(ratchet_tree: Option<&str>) -> Option<RatchetTreeIn> {
+    match ratchet_tree.map(ratchet_tree_from_base64) {
+        None => None,
+        Some(Ok(ratchet_tree)) => Some(ratchet_tree),
+        Some(Err(e)) => {
+            tracing::warn!("Invalid ratchet tree; ignoring: {e}");
+            None
         }
-        Ok((mut g, m)) => {
-            log::warn!("Processed message:\n{m:#?}");
-            match m.into_content() {
-                ProcessedMessageContent::ApplicationMessage(app_msg) => match plaintext(app_msg) {
-                    Err(e) => {
-                        log::error!("Error getting plaintext: {e}");
-                    }
-                    Ok(pt) => {
-                        println!("{pt}");
-                    }
-                },
-                ProcessedMessageContent::StagedCommitMessage(commit) => {
-                    if let Err(e) =
-                        apply_commit(provider, &mut g, *commit, ciphersuite, exporter_length)
-                    {
-                        log::error!("Error applying commit: {e}");
-                    }
-                }
-                _ => {
-                    log::error!("Unsupported processed message content");
-                }
-            }
+    }
+}
+
+/// Parse a ciphersuite name as accepted on the command line.
+///
+/// Returns `None` for unknown or unsupported ciphersuite names.
+fn parse_ciphersuite(name: &str) -> Option<Ciphersuite> {
+    match name {
+        "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519" => {
+            Some(Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519)
+        }
+        "MLS_128_DHKEMP256_AES128GCM_SHA256_P256" => {
+            Some(Ciphersuite::MLS_128_DHKEMP256_AES128GCM_SHA256_P256)
+        }
+        "MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519" => {
+            Some(Ciphersuite::MLS_128_DHKEMX25519_CHACHA20POLY1305_SHA256_Ed25519)
+        }
+        "MLS_256_DHKEMX448_AES256GCM_SHA512_Ed448" => {
+            Some(Ciphersuite::MLS_256_DHKEMX448_AES256GCM_SHA512_Ed448)
+        }
+        "MLS_256_DHKEMP521_AES256GCM_SHA512_P521" => {
+            Some(Ciphersuite::MLS_256_DHKEMP521_AES256GCM_SHA512_P521)
+        }
+        "MLS_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448" => {
+            Some(Ciphersuite::MLS_256_DHKEMX448_CHACHA20POLY1305_SHA512_Ed448)
         }
+        "MLS_256_DHKEMP384_AES256GCM_SHA384_P384" => {
+            Some(Ciphersuite::MLS_256_DHKEMP384_AES256GCM_SHA384_P384)
+        }
+        // hybrid X25519 + ML-KEM-768 KEM; needs `--crypto-backend libcrux`
+        #[cfg(feature = "pq")]
+        "MLS_256_XWING_CHACHA20POLY1305_SHA256_Ed25519" => {
+            Some(Ciphersuite::MLS_256_XWING_CHACHA20POLY1305_SHA256_Ed25519)
+        }
+        _ => None,
     }
 }
 
@@ -162,7 +1419,8 @@ fn process_proto_msg_main(
 /// - `gen-state`: creates a JSON state file with a newly-generated signing key pair.
 /// - `use-state`: loads the JSON state, creates a `DmlsProvider` and executes `MainCommands`.
 /// - All operations that produce or consume protocol artifacts use base64 blobs on stdin/stdout
-///   to make them easy to pipe into the example scripts.
+///   to make them easy to pipe into the example scripts (or length-prefixed binary frames with
+///   `--framing raw-len32` for high-throughput pipelines).
 ///
 /// Example:
 ///
@@ -171,212 +1429,2414 @@ fn process_proto_msg_main(
 /// cargo run -- use-state ./alice_state.json gen-kp
 /// ```
 fn main() {
-    // logging
-    pretty_env_logger::init();
     // command-line args
-    let args = CliArgs::parse();
-    log::info!("Command-line arguments: {args:?}");
+    let args = parse_args();
+    // logging; stdout is reserved for artifacts
+    let fmt_layer = tracing_subscriber::fmt::layer().with_writer(stderr);
+    let fmt_layer = match args.log_format {
+        LogFormat::Text => fmt_layer.boxed(),
+        LogFormat::Json => fmt_layer.json().boxed(),
+    };
+    tracing_subscriber::registry()
+        .with(fmt_layer.with_filter(log_filter(args.quiet, args.verbose)))
+        .init();
+    if args.unsafe_log_secrets {
+        set_log_secrets(true);
+        tracing::warn!("Logging secrets in full (--unsafe-log-secrets)");
+    }
+    // wire blob encoding
+    set_encoding(args.encoding);
+    // redirected input and output
+    if let Err(e) = args
+        .input
+        .as_deref()
+        .map_or(Ok(()), |path| set_input(Path::new(path)))
+        .and_then(|()| {
+            args.output
+                .as_deref()
+                .map_or(Ok(()), |path| set_output(Path::new(path), args.append))
+        })
+    {
+        tracing::error!("Error redirecting input or output: {e}");
+        return;
+    }
+    // state file permissions and integrity
+    set_strict_perms(args.strict_perms);
+    if let Some(path) = &args.integrity_key {
+        read_file(path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(set_integrity_key)
+            .unwrap();
+    }
+    tracing::debug!("Command-line arguments: {args:?}");
     // crypto
-    let crypto = RustCrypto::default();
+    let crypto: CryptoBackend = match args.crypto_backend.parse() {
+        Ok(crypto) => crypto,
+        Err(e) => {
+            tracing::error!("{e}");
+            return;
+        }
+    };
     // process state command
     match &args.state_command {
-        StateCommands::InspectMessages {} => {
-            log::debug!("Trying to inspect message(s) from stdin");
-            // read lines from stdin; for each: try to deserialize and then pretty-print
-            for line in stdin().lock().lines() {
-                match stdin_base64_to_mls_msg_in(line) {
-                    Err(e) => {
-                        log::error!("Error inspecting message: {e}");
-                    }
-                    Ok(m) => {
-                        log::warn!("Message:\n{:#?}", m);
-                    }
+        StateCommands::VerifySignature {
+            public_key,
+            signature,
+            signature_scheme,
+            label,
+        } => {
+            tracing::debug!("Trying to verify signature");
+            let mut payload = Vec::new();
+            let verified = match parse_signature_scheme(signature_scheme) {
+                None => Err(format!("Invalid signature scheme: {signature_scheme}").into()),
+                Some(signature_scheme) => input()
+                    .read_to_end(&mut payload)
+                    .map_err(Box::<dyn Error>::from)
+                    .and_then(|_| {
+                        verify_signature_base64(
+                            &crypto,
+                            signature_scheme,
+                            public_key,
+                            signature,
+                            &payload,
+                            label.as_deref(),
+                        )
+                    }),
+            };
+            match verified {
+                Err(e) => {
+                    tracing::error!("Signature is not valid: {e}");
+                }
+                Ok(()) => {
+                    note!("Signature is valid");
                 }
             }
         }
-        StateCommands::GenState {
-            state_path,
-            signature_scheme,
+        StateCommands::TestVectors {
+            test_vectors_command: TestVectorsCommands::Verify { file },
         } => {
-            log::debug!("Creating new state");
-            // signature scheme
-            let signature_scheme = match signature_scheme.as_str() {
-                "Ed25519" => SignatureScheme::ED25519,
-                _ => {
-                    log::warn!("Invalid signature algorithm; using EdDSA with Curve25519");
-                    SignatureScheme::ED25519
+            tracing::debug!("Trying to verify test vectors in {file}");
+            match verify_test_vectors(file, &crypto) {
+                Err(e) => {
+                    tracing::error!("Error verifying test vectors: {e}");
                 }
-            };
-            // new state object
-            let state =
-                DmlsState::new(SignatureKeyPair::from_crypto(&crypto, signature_scheme).unwrap());
-            // save new state
-            log::info!("Path to write state: {state_path}");
-            log::info!("Updated state to write:\n{state:#?}");
-            write_string_to_file(state_path, json_encode(&state).unwrap()).unwrap();
+                Ok(checked) => {
+                    note!("{checked} test vectors passed");
+                }
+            }
         }
-        StateCommands::UseState {
+        StateCommands::Completions { shell, dir } => {
+            tracing::debug!("Trying to generate {shell} completions");
+            let mut command = CliArgs::command();
+            let bin_name = command.get_name().to_string();
+            match dir {
+                None => generate_completions(*shell, &mut command, bin_name, &mut output()),
+                Some(dir) => match create_dir_all(dir)
+                    .and_then(|()| write_completions(*shell, &mut command, bin_name, dir))
+                {
+                    Err(e) => {
+                        tracing::error!("Error writing completions to {dir}: {e}");
+                    }
+                    Ok(path) => {
+                        note!("Wrote {}", path.display());
+                    }
+                },
+            }
+        }
+        StateCommands::Manpage { dir } => {
+            tracing::debug!("Trying to generate manual pages");
+            let command = CliArgs::command();
+            let result = match dir {
+                None => Man::new(command).render(&mut output()),
+                Some(dir) => create_dir_all(dir).and_then(|()| write_manpages(command, dir)),
+            };
+            if let Err(e) = result {
+                tracing::error!("Error generating manual pages: {e}");
+            }
+        }
+        StateCommands::Explain { code } => {
+            tracing::debug!("Trying to explain error code {code}");
+            match explain(code) {
+                None => {
+                    tracing::error!("Unknown error code: {code}");
+                }
+                Some(explanation) => {
+                    outln!("{explanation}");
+                }
+            }
+        }
+        StateCommands::InspectMessages { parallel } => {
+            tracing::debug!("Trying to inspect message(s) from stdin");
+            // read blobs from stdin; for each: try to deserialize and then pretty-print
+            let blobs = read_blobs(args.framing);
+            let inspected: Box<dyn Iterator<Item = Result<String, String>>> = if *parallel {
+                let blobs = blobs
+                    .map(|blob| blob.map_err(|e| e.to_string()))
+                    .collect::<Vec<_>>();
+                Box::new(
+                    blobs
+                        .into_par_iter()
+                        .map(|blob| {
+                            blob.and_then(|bytes| inspect(&bytes).map_err(|e| e.to_string()))
+                        })
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                )
+            } else {
+                Box::new(blobs.map(|blob| {
+                    blob.and_then(|bytes| inspect(&bytes))
+                        .map_err(|e| e.to_string())
+                }))
+            };
+            for text in inspected {
+                match text {
+                    Err(e) => {
+                        tracing::error!("Error inspecting message: {e}");
+                    }
+                    Ok(text) => {
+                        note!("{text}");
+                    }
+                }
+            }
+        }
+        #[cfg(feature = "grpc")]
+        StateCommands::ServeGrpc {
+            state_dir,
+            listen,
+            ciphersuite,
+            exporter_length,
+            journal,
+        } => {
+            tracing::debug!("Trying to serve gRPC API");
+            match parse_ciphersuite(ciphersuite) {
+                None => {
+                    tracing::error!("Invalid ciphersuite: {ciphersuite}");
+                }
+                Some(ciphersuite) => {
+                    if let Err(e) =
+                        grpc::run(listen, state_dir, ciphersuite, *exporter_length, *journal)
+                    {
+                        tracing::error!("Error serving gRPC API: {e}");
+                    }
+                }
+            }
+        }
+        StateCommands::Simulate {
+            scenario_path,
+            ciphersuite,
+            exporter_length,
+            seed,
+        } => {
+            tracing::debug!("Trying to run scenario {scenario_path}");
+            let scenario: Result<Scenario, Box<dyn Error>> = read_file_to_string(scenario_path)
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|s| serde_yaml::from_str(&s).map_err(Box::<dyn Error>::from));
+            match (scenario, parse_ciphersuite(ciphersuite)) {
+                (Err(e), _) => {
+                    tracing::error!("Error reading scenario: {e}");
+                }
+                (_, None) => {
+                    tracing::error!("Invalid ciphersuite: {ciphersuite}");
+                }
+                (Ok(scenario), Some(ciphersuite)) => {
+                    match Simulation::new(
+                        &scenario.participants,
+                        ciphersuite,
+                        *exporter_length,
+                        *seed,
+                    ) {
+                        Err(e) => {
+                            tracing::error!("Error creating participants: {e}");
+                        }
+                        Ok(mut sim) => {
+                            let result = scenario
+                                .steps
+                                .iter()
+                                .try_for_each(|step| sim.step(step))
+                                .and_then(|()| sim.check_convergence());
+                            for line in sim.transcript() {
+                                outln!("{line}");
+                            }
+                            if let Err(e) = result {
+                                tracing::error!("Error running scenario: {e}");
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        StateCommands::Restore {
+            state_path,
+            backup,
+            key,
+            force,
+        } => {
+            tracing::debug!("Trying to restore {state_path} from {backup}");
+            let restored = read_file(key)
+                .and_then(|key| Ok((key, read_file(backup)?)))
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|(key, snapshot)| open_backup(&RustCrypto::default(), &snapshot, &key));
+            match restored {
+                Err(e) => {
+                    tracing::error!("Error reading backup: {e}");
+                }
+                Ok(restored) => {
+                    // rolling groups back to older epochs breaks them; an unreadable state is
+                    // what backups are for, so it doesn't block the restore, but groups whose
+                    // epochs can't be compared do
+                    let newer = match load_state(state_path) {
+                        Err(e) => {
+                            tracing::warn!("Not checking existing state: {e}");
+                            Some(Vec::new())
+                        }
+                        Ok((current, _)) => match newer_groups(&current, &restored) {
+                            Err(e) => {
+                                tracing::error!("Error comparing group epochs with backup: {e}");
+                                None
+                            }
+                            Ok(newer) => Some(newer),
+                        },
+                    };
+                    for (group_id, current, restored) in newer.iter().flatten() {
+                        tracing::warn!(
+                            "Group {} is at epoch {current}, the backup at epoch {restored}",
+                            Blob.encode(group_id.as_slice())
+                        );
+                    }
+                    if newer.as_ref().is_some_and(Vec::is_empty) || *force {
+                        match save_state(
+                            state_path,
+                            &restored,
+                            args.state_format.unwrap_or_default(),
+                        ) {
+                            Err(e) => tracing::error!("Error saving state {state_path}: {e}"),
+                            Ok(()) => note!("Restored {state_path} from {backup}"),
+                        }
+                    } else {
+                        tracing::error!("Refusing to restore; use --force to override");
+                    }
+                }
+            }
+        }
+        StateCommands::GenState {
+            from_mnemonic: true,
+            state_path,
+            ..
+        } => {
+            tracing::debug!("Restoring signing key from mnemonic");
+            let mut phrase = String::new();
+            match input()
+                .read_line(&mut phrase)
+                .map_err(Box::<dyn Error>::from)
+                .and_then(|_| SignatureKeyPair::from_mnemonic(&phrase))
+            {
+                Err(e) => {
+                    tracing::error!("Error restoring signing key from mnemonic: {e}");
+                }
+                Ok(signature_key_pair) => {
+                    let state = DmlsState::new(signature_key_pair);
+                    if let Err(e) =
+                        save_state(state_path, &state, args.state_format.unwrap_or_default())
+                    {
+                        tracing::error!("Error saving state {state_path}: {e}");
+                    }
+                }
+            }
+        }
+        StateCommands::GenState {
+            state_path,
+            signature_scheme,
+            print_mnemonic,
+            ..
+        } => {
+            tracing::debug!("Creating new state");
+            // signature scheme
+            let signature_scheme = match parse_signature_scheme(signature_scheme) {
+                Some(signature_scheme) => signature_scheme,
+                None => {
+                    tracing::warn!("Invalid signature algorithm; using EdDSA with Curve25519");
+                    SignatureScheme::ED25519
+                }
+            };
+            // new state object
+            match SignatureKeyPair::from_crypto(&crypto, signature_scheme) {
+                Err(e) => {
+                    tracing::error!(
+                        "Error generating {signature_scheme:?} signing key; the crypto backend may not support it: {e:?}"
+                    );
+                }
+                Ok(signature_key_pair) => {
+                    if *print_mnemonic {
+                        match signature_key_pair.to_mnemonic() {
+                            Err(e) => {
+                                tracing::error!("Error encoding signing key as mnemonic: {e}");
+                                return;
+                            }
+                            Ok(phrase) => {
+                                outln!("{phrase}");
+                            }
+                        }
+                    }
+                    let state = DmlsState::new(signature_key_pair);
+                    // save new state
+                    if let Err(e) =
+                        save_state(state_path, &state, args.state_format.unwrap_or_default())
+                    {
+                        tracing::error!("Error saving state {state_path}: {e}");
+                    }
+                }
+            }
+        }
+        StateCommands::UseState {
             state_path,
             ciphersuite,
             exporter_length,
+            transport,
+            inbox,
+            outbox,
+            journal,
+            storage,
+            force,
+            clock_skew,
+            ignore_lifetime,
             main_command,
         } => {
-            log::debug!("Trying to use existing state");
-            // ciphersuite
-            let ciphersuite = match ciphersuite.as_str() {
-                "MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519" => {
-                    Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519
+            tracing::debug!("Trying to use existing state");
+            // transport
+            let mut transport = match parse_transport(transport, args.framing) {
+                Ok(transport) => transport,
+                Err(e) => {
+                    tracing::warn!("Invalid transport: {e}; using stdio");
+                    Box::new(StdioTransport::new(args.framing))
                 }
-                "MLS_128_DHKEMP256_AES128GCM_SHA256_P256" => {
-                    Ciphersuite::MLS_128_DHKEMP256_AES128GCM_SHA256_P256
-                }
-                _ => {
-                    log::warn!(
+            };
+            // mailbox directories
+            let inbox = inbox.as_deref().map(Maildir::new);
+            let outbox = outbox.as_deref().map(Maildir::new);
+            // ciphersuite
+            let ciphersuite = match parse_ciphersuite(ciphersuite) {
+                Some(ciphersuite) => ciphersuite,
+                None => {
+                    tracing::warn!(
                         "Invalid ciphersuite; using MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519"
                     );
                     Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519
                 }
             };
-            // provider
-            let mut provider = DmlsProvider::new(
-                json_decode(&read_file_to_string(state_path).unwrap()).unwrap(),
-                crypto,
-            );
-            log::info!("Provider based on existing state:\n{provider:#?}");
-            // process main command
-            match main_command {
-                MainCommands::GenKp {} => {
-                    log::debug!("Trying to generate new key package");
-                    match gen_kp_base64(&provider, ciphersuite) {
-                        Err(e) => {
-                            log::error!("Error generating key package: {e}");
+            // storage backend, if any; the integrity MAC covers its entries too
+            let storage = match storage.as_deref().map(open_storage).transpose() {
+                Err(e) => {
+                    tracing::error!("Error opening storage backend: {e}");
+                    std::process::exit(EXIT_FAILURE);
+                }
+                Ok(storage) => storage,
+            };
+            // provider; keep the state file's format unless told otherwise
+            let (state, detected_format) =
+                match load_state_with_storage(state_path, storage.as_deref()) {
+                    Err(e) => {
+                        tracing::error!("Error loading state {state_path}: {e}");
+                        std::process::exit(EXIT_FAILURE);
+                    }
+                    Ok(loaded) => loaded,
+                };
+            if let Err(e) = check_ciphersuite(
+                &crypto,
+                ciphersuite,
+                state.signature_key_pair().signature_scheme(),
+            ) {
+                tracing::error!("Unusable ciphersuite: {e}");
+                std::process::exit(EXIT_FAILURE);
+            }
+            let state_format = args.state_format.unwrap_or(detected_format);
+            let mut provider = DmlsProvider::new(state, crypto)
+                .with_force(*force)
+                .with_clock_skew(*clock_skew)
+                .with_ignore_lifetime(*ignore_lifetime);
+            tracing::debug!("Provider based on existing state:\n{provider:#?}");
+            // transaction; the snapshot is cheap, entries are only copied once written
+            let rollback = transactional(main_command).then(|| {
+                let state = provider.state();
+                (state.meta().clone(), state.openmls_values().snapshot())
+            });
+            // entries copied from the snapshot on write are locked too, and its own are zeroed and
+            // unlocked once it is dropped
+            #[cfg(feature = "mlock")]
+            lock_secrets(provider.state());
+            let mut process_tally = None;
+            // inbox message files to claim once the state is saved
+            let mut inbox_claims = Vec::new();
+            // process main command; a failure is returned, so the transaction can be rolled back
+            let _span =
+                tracing::info_span!("command", state = %state_path, command = ?main_command)
+                    .entered();
+            let outcome = (|| -> Result<(), Box<dyn Error>> {
+                match main_command {
+                    MainCommands::GenKp {} => {
+                        tracing::debug!("Trying to generate new key package");
+                        match gen_recorded_kp_base64(&mut provider, ciphersuite) {
+                            Err(e) => {
+                                return Err(format!("Error generating key package: {e}").into());
+                            }
+                            Ok(kp) => {
+                                emit(args.framing, &kp)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &kp)?;
+                            }
                         }
-                        Ok(kp) => {
-                            println!("{kp}");
+                    }
+                    MainCommands::Whoami {} => {
+                        let signature_key_pair = provider.state().signature_key_pair();
+                        match signature_key_pair.fingerprint(provider.crypto()) {
+                            Err(e) => {
+                                return Err(format!("Error computing fingerprint: {e:?}").into());
+                            }
+                            Ok(fingerprint) => {
+                                outln!("identity: {}", Blob.encode(own_identity(&provider)));
+                                outln!(
+                                    "signature-scheme: {:?}",
+                                    signature_key_pair.signature_scheme()
+                                );
+                                outln!("fingerprint: {fingerprint}");
+                            }
                         }
                     }
-                }
-                MainCommands::GenSendGroup {} => {
-                    log::debug!("Trying to generate new send group");
-                    match gen_send_group(&mut provider, ciphersuite) {
-                        Err(e) => {
-                            log::error!("Error generating send group: {e}");
+                    MainCommands::ExportPubkey { format } => {
+                        let signature_key_pair = provider.state().signature_key_pair();
+                        let exported = match format {
+                            PubkeyFormat::Raw => {
+                                Ok(Blob.encode(signature_key_pair.public_key_raw()))
+                            }
+                            PubkeyFormat::Pem => signature_key_pair.public_key_pem(),
+                            PubkeyFormat::Openssh => signature_key_pair.public_key_openssh(),
+                            PubkeyFormat::Jwk => signature_key_pair.public_key_jwk(),
+                        };
+                        match exported {
+                            Err(e) => return Err(format!("Error exporting public key: {e}").into()),
+                            Ok(exported) => {
+                                outln!("{exported}");
+                            }
                         }
-                        Ok(mut sg) => {
-                            log::debug!("Trying to validate key packages provided via stdin");
-                            let mut kps = Vec::new();
-                            for line in stdin().lock().lines() {
-                                match stdin_base64_to_kp(&provider, line) {
+                    }
+                    MainCommands::PublishKp { url, count } => {
+                        tracing::debug!("Trying to publish key packages to {url}");
+                        match (0..*count)
+                            .map(|_| gen_recorded_kp_base64(&mut provider, ciphersuite))
+                            .collect::<Result<Vec<_>, _>>()
+                        {
+                            Err(e) => {
+                                return Err(format!("Error generating key package: {e}").into());
+                            }
+                            Ok(kps) => {
+                                let identity = own_identity(&provider);
+                                match HttpDeliveryService::new(url)
+                                    .publish_key_packages(&identity, &kps)
+                                {
                                     Err(e) => {
-                                        log::error!("Error validating key package: {e}");
+                                        return Err(format!(
+                                            "Error publishing key packages to {url}: {e}"
+                                        )
+                                        .into());
                                     }
-                                    Ok(kp) => {
-                                        log::info!("Validated key package:\n{kp:#?}");
-                                        kps.push(kp);
+                                    Ok(()) => {
+                                        note!(
+                                            "Published {} key packages for {}",
+                                            kps.len(),
+                                            Blob.encode(&identity)
+                                        );
                                     }
                                 }
                             }
-                            log::debug!("Adding validated key packages to send group");
-                            match force_add_members_base64(&provider, &mut sg, &kps) {
+                        }
+                    }
+                    MainCommands::ExportSecret {
+                        label,
+                        context,
+                        length,
+                        group_id,
+                    } => {
+                        tracing::debug!("Trying to export secret with label {label}");
+                        let secret = Blob.decode(context).and_then(|context| {
+                            let g = group_base64(&provider, group_id.as_deref())?;
+                            export_secret_base64(&provider, &g, label, &context, *length)
+                        });
+                        match secret {
+                            Err(e) => return Err(format!("Error exporting secret: {e}").into()),
+                            Ok(secret) => {
+                                outln!("{secret}");
+                            }
+                        }
+                    }
+                    MainCommands::ExportLabels {
+                        export_labels_command,
+                    } => match export_labels_command {
+                        ExportLabelsCommands::List {} => {
+                            for label in provider.state().export_labels() {
+                                outln!("{label}");
+                            }
+                        }
+                        ExportLabelsCommands::Allow { label } => {
+                            if provider.state().is_exporter_psk_label(label) {
+                                return Err(
+                                    format!("Label {label} is reserved for exporter PSKs").into()
+                                );
+                            }
+                            provider.state_mut().allow_export_label(label.clone());
+                        }
+                        ExportLabelsCommands::Revoke { label } => {
+                            provider.state_mut().revoke_export_label(label);
+                        }
+                    },
+                    MainCommands::Bridge { bridge_command } => match bridge_command {
+                        BridgeCommands::List {} => {
+                            for (source, targets) in provider.state().bridging_rules() {
+                                if targets.is_empty() {
+                                    outln!("{}", Blob.encode(source));
+                                }
+                                for target in targets {
+                                    outln!("{} {}", Blob.encode(source), Blob.encode(target));
+                                }
+                            }
+                        }
+                        BridgeCommands::Allow { source, target } => {
+                            match Blob.decode(source).and_then(|source| {
+                                Blob.decode(target).map(|target| (source, target))
+                            }) {
+                                Err(e) => return Err(format!("Invalid group id: {e}").into()),
+                                Ok((source, target)) => {
+                                    provider.state_mut().allow_bridge(source, target);
+                                }
+                            }
+                        }
+                        BridgeCommands::Revoke { source, target } => {
+                            match Blob.decode(source).and_then(|source| {
+                                target
+                                    .as_deref()
+                                    .map(|target| Blob.decode(target))
+                                    .transpose()
+                                    .map(|target| (source, target))
+                            }) {
+                                Err(e) => return Err(format!("Invalid group id: {e}").into()),
+                                Ok((source, target)) => {
+                                    provider
+                                        .state_mut()
+                                        .revoke_bridge(&source, target.as_deref());
+                                }
+                            }
+                        }
+                    },
+                    MainCommands::Admin { admin_command } => match admin_command {
+                        AdminCommands::List { group_id } => {
+                            match group_base64(&provider, group_id.as_deref())
+                                .and_then(|g| admins(&g))
+                            {
+                                Err(e) => return Err(format!("Error listing admins: {e}").into()),
+                                Ok(admins) => {
+                                    for admin in admins {
+                                        outln!("{}", Blob.encode(admin));
+                                    }
+                                }
+                            }
+                        }
+                        AdminCommands::Grant { identity } | AdminCommands::Revoke { identity } => {
+                            let grant = matches!(admin_command, AdminCommands::Grant { .. });
+                            tracing::debug!(
+                                "Trying to change admin role of {identity} in send group"
+                            );
+                            let recipients = send_group(&provider)
+                                .map(|sg| member_identities(&sg))
+                                .unwrap_or_default();
+                            let commit = Blob.decode(identity).and_then(|identity| {
+                                send_group_set_admin_base64(
+                                    &mut provider,
+                                    &identity,
+                                    grant,
+                                    ciphersuite,
+                                    *exporter_length,
+                                )
+                            });
+                            match commit {
                                 Err(e) => {
-                                    log::error!("Error adding members to send group: {e}");
+                                    return Err(format!(
+                                        "Error changing admin role in send group: {e}"
+                                    )
+                                    .into());
                                 }
-                                Ok(welcome) => {
-                                    log::warn!("Send group:\n{sg:#?}");
-                                    println!("{welcome}");
+                                Ok(commit) => {
+                                    deliver(&mut provider, &transport, &recipients, &commit)?;
+                                    drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                    provider.state_mut().push_outbox(OutboxKind::Commit, commit);
+                                }
+                            }
+                        }
+                    },
+                    MainCommands::KpPool { kp_pool_command } => match kp_pool_command {
+                        KpPoolCommands::Status {} => match mark_consumed(&mut provider) {
+                            Err(e) => {
+                                return Err(format!("Error checking key package pool: {e}").into());
+                            }
+                            Ok(_) => {
+                                outln!("{}", provider.state().kp_pool().len());
+                            }
+                        },
+                        KpPoolCommands::Maintain { min, max, url } => {
+                            tracing::debug!("Trying to maintain key package pool");
+                            let directory = url.as_deref().map(HttpDeliveryService::new);
+                            let directory = directory
+                                .as_ref()
+                                .map(|ds| ds as &dyn KeyPackageDirectory)
+                                .or(transport.directory());
+                            match maintain_kp_pool(&mut provider, ciphersuite, *min, *max) {
+                                Err(e) => {
+                                    return Err(
+                                        format!("Error maintaining key package pool: {e}").into()
+                                    );
+                                }
+                                Ok(kps) => match directory {
+                                    None => {
+                                        for kp in &kps {
+                                            emit(args.framing, kp)?;
+                                        }
+                                    }
+                                    Some(ds) => {
+                                        let identity = own_identity(&provider);
+                                        match ds.publish_key_packages(&identity, &kps) {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                    "Error publishing key packages: {e}"
+                                                )
+                                                .into());
+                                            }
+                                            Ok(()) => {
+                                                note!(
+                                                    "Published {} key packages to the pool",
+                                                    kps.len()
+                                                );
+                                            }
+                                        }
+                                    }
+                                },
+                            }
+                        }
+                    },
+                    MainCommands::FetchKp { identity, url } => {
+                        tracing::debug!("Trying to fetch key package from {url}");
+                        match Blob.decode(identity) {
+                            Err(e) => return Err(format!("Error decoding identity: {e}").into()),
+                            Ok(identity) => {
+                                match HttpDeliveryService::new(url).fetch_key_package(&identity) {
+                                    Err(e) => {
+                                        return Err(format!(
+                                            "Error fetching key package from {url}: {e}"
+                                        )
+                                        .into());
+                                    }
+                                    Ok(None) => {
+                                        return Err("No key package published for identity".into());
+                                    }
+                                    Ok(Some(kp_b64)) => {
+                                        match process_fetched_kp_base64(
+                                            &mut provider,
+                                            &identity,
+                                            kp_b64.clone(),
+                                        ) {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                    "Error validating key package: {e}"
+                                                )
+                                                .into());
+                                            }
+                                            Ok(kp) => {
+                                                tracing::debug!("Validated key package:\n{kp:#?}");
+                                                emit(args.framing, &kp_b64)?;
+                                            }
+                                        }
+                                    }
                                 }
                             }
                         }
                     }
-                }
-                MainCommands::Update {} => {
-                    log::debug!("Trying to update in send group");
-                    match send_group_update_base64(&mut provider, ciphersuite, *exporter_length) {
-                        Err(e) => {
-                            log::error!("Error updating in send group: {e}");
+                    MainCommands::GenSendGroup {
+                        address_book,
+                        stage,
+                        no_ratchet_tree_extension,
+                        max_past_epochs,
+                        per_message_fs,
+                        required_capabilities,
+                        lifetime,
+                        handshake_wire_format,
+                        bundle,
+                        chunk_size,
+                    } => {
+                        tracing::debug!("Trying to generate new send group");
+                        let options = GroupConfigOptions {
+                            ratchet_tree_extension: !*no_ratchet_tree_extension,
+                            max_past_epochs: *max_past_epochs,
+                            required_extensions: required_capabilities
+                                .iter()
+                                .map(|&extension_type| ExtensionType::from(extension_type))
+                                .collect(),
+                            lifetime: *lifetime,
+                            handshake_wire_format: *handshake_wire_format,
+                            exporter: ExporterParams::new(*exporter_length),
+                            per_message_fs: *per_message_fs,
+                        };
+                        match gen_send_group(&mut provider, ciphersuite, &options) {
+                            Err(e) => {
+                                return Err(format!("Error generating send group: {e}").into());
+                            }
+                            Ok(mut sg) => {
+                                let mut kps = Vec::new();
+                                if *address_book {
+                                    tracing::debug!("Trying to use key packages from address book");
+                                    kps = take_address_book_kps(&mut provider);
+                                } else {
+                                    tracing::debug!(
+                                        "Trying to validate key packages provided via stdin"
+                                    );
+                                    for blob in read_blobs(args.framing) {
+                                        match blob.and_then(|bytes| bytes_to_kp(&provider, &bytes))
+                                        {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                    "Error validating key package: {e}"
+                                                )
+                                                .into());
+                                            }
+                                            Ok(kp) => {
+                                                tracing::debug!("Validated key package:\n{kp:#?}");
+                                                kps.push(kp);
+                                            }
+                                        }
+                                    }
+                                }
+                                tracing::debug!("Adding validated key packages to send group");
+                                if let Some(format) = bundle {
+                                    match add_members_bundle(&mut provider, &mut sg, &kps, *stage) {
+                                        Err(e) => {
+                                            return Err(format!(
+                                                "Error adding members to send group: {e}"
+                                            )
+                                            .into());
+                                        }
+                                        Ok(bundle) => {
+                                            tracing::debug!("Send group:\n{sg:#?}");
+                                            match format {
+                                                BundleFormat::Labeled => {
+                                                    outln!("{}", bundle.labeled());
+                                                }
+                                                BundleFormat::Json => match json_encode(&bundle) {
+                                                    Err(e) => {
+                                                        return Err(format!(
+                                                            "Error encoding bundle as JSON: {e}"
+                                                        )
+                                                        .into());
+                                                    }
+                                                    Ok(json) => {
+                                                        outln!("{json}");
+                                                    }
+                                                },
+                                            }
+                                            provider
+                                                .state_mut()
+                                                .push_outbox(OutboxKind::Commit, bundle.commit);
+                                            if let Some(welcome) = bundle.welcome {
+                                                drop_to_outbox(
+                                                    &mut provider,
+                                                    outbox.as_ref(),
+                                                    &welcome,
+                                                )?;
+                                                provider
+                                                    .state_mut()
+                                                    .push_outbox(OutboxKind::Welcome, welcome);
+                                            }
+                                        }
+                                    }
+                                } else if let Some(chunk_size) = chunk_size {
+                                    for chunk in kps.chunks(chunk_size.get()) {
+                                        // members added by earlier chunks need this chunk's commit
+                                        let recipients = member_identities(&sg);
+                                        match add_members_base64(
+                                            &mut provider,
+                                            &mut sg,
+                                            chunk,
+                                            ciphersuite,
+                                            *exporter_length,
+                                        ) {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                "Error adding chunk of members to send group: {e}"
+                                            )
+                                            .into());
+                                            }
+                                            Ok((commit, welcome)) => {
+                                                if !recipients.is_empty() {
+                                                    deliver(
+                                                        &mut provider,
+                                                        &transport,
+                                                        &recipients,
+                                                        &commit,
+                                                    )?;
+                                                    drop_to_outbox(
+                                                        &mut provider,
+                                                        outbox.as_ref(),
+                                                        &commit,
+                                                    )?;
+                                                    provider
+                                                        .state_mut()
+                                                        .push_outbox(OutboxKind::Commit, commit);
+                                                }
+                                                let joiners = chunk
+                                                    .iter()
+                                                    .map(kp_identity)
+                                                    .collect::<Vec<_>>();
+                                                deliver(
+                                                    &mut provider,
+                                                    &transport,
+                                                    &joiners,
+                                                    &welcome,
+                                                )?;
+                                                drop_to_outbox(
+                                                    &mut provider,
+                                                    outbox.as_ref(),
+                                                    &welcome,
+                                                )?;
+                                                provider
+                                                    .state_mut()
+                                                    .push_outbox(OutboxKind::Welcome, welcome);
+                                            }
+                                        }
+                                    }
+                                    tracing::debug!("Send group:\n{sg:#?}");
+                                } else {
+                                    let welcome = if *stage {
+                                        stage_add_members_base64(&provider, &mut sg, &kps)
+                                    } else {
+                                        force_add_members_base64(
+                                            &mut provider,
+                                            &mut sg,
+                                            &kps,
+                                            *exporter_length,
+                                        )
+                                    };
+                                    match welcome {
+                                        Err(e) => {
+                                            return Err(format!(
+                                                "Error adding members to send group: {e}"
+                                            )
+                                            .into());
+                                        }
+                                        Ok(welcome) => {
+                                            tracing::debug!("Send group:\n{sg:#?}");
+                                            let recipients =
+                                                kps.iter().map(kp_identity).collect::<Vec<_>>();
+                                            deliver(
+                                                &mut provider,
+                                                &transport,
+                                                &recipients,
+                                                &welcome,
+                                            )?;
+                                            drop_to_outbox(
+                                                &mut provider,
+                                                outbox.as_ref(),
+                                                &welcome,
+                                            )?;
+                                            provider
+                                                .state_mut()
+                                                .push_outbox(OutboxKind::Welcome, welcome);
+                                        }
+                                    }
+                                }
+                            }
                         }
-                        Ok(commit) => {
-                            println!("{commit}");
+                    }
+                    MainCommands::Update {
+                        new_identity: Some(identity),
+                        ..
+                    } => {
+                        tracing::debug!("Trying to change identity to {identity}");
+                        let recipients = send_group(&provider)
+                            .map(|sg| member_identities(&sg))
+                            .unwrap_or_default();
+                        match update_identity_base64(
+                            &mut provider,
+                            identity.as_bytes().to_vec(),
+                            ciphersuite,
+                            *exporter_length,
+                        ) {
+                            Err(e) => return Err(format!("Error changing identity: {e}").into()),
+                            Ok(None) => {}
+                            Ok(Some(commit)) => {
+                                deliver(&mut provider, &transport, &recipients, &commit)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                provider.state_mut().push_outbox(OutboxKind::Commit, commit);
+                            }
                         }
                     }
-                }
-                MainCommands::Process {} => {
-                    log::debug!("Trying to process incoming messages");
-                    for line in stdin().lock().lines() {
-                        match stdin_base64_extract(line) {
+                    MainCommands::Update {
+                        dry_run: true,
+                        show_commit,
+                        full_path,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to preview update in send group");
+                        match preview_commit(&mut provider, |provider| {
+                            send_group_stage_update_base64(provider, *full_path)
+                        }) {
                             Err(e) => {
-                                log::error!("Error extracting message: {e}");
+                                return Err(
+                                    format!("Error previewing update in send group: {e}").into()
+                                );
+                            }
+                            Ok((summary, commit)) => print_preview(&summary, &commit, *show_commit),
+                        }
+                    }
+                    MainCommands::Update {
+                        stage, full_path, ..
+                    } => {
+                        tracing::debug!("Trying to update in send group");
+                        let recipients = send_group(&provider)
+                            .map(|sg| member_identities(&sg))
+                            .unwrap_or_default();
+                        let commit = if *stage {
+                            send_group_stage_update_base64(&provider, *full_path)
+                        } else {
+                            send_group_update_base64(
+                                &mut provider,
+                                *full_path,
+                                ciphersuite,
+                                *exporter_length,
+                            )
+                        };
+                        match commit {
+                            Err(e) => {
+                                return Err(format!("Error updating in send group: {e}").into());
+                            }
+                            Ok(commit) => {
+                                deliver(&mut provider, &transport, &recipients, &commit)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                provider.state_mut().push_outbox(OutboxKind::Commit, commit);
+                            }
+                        }
+                    }
+                    MainCommands::RotateKey {} => {
+                        tracing::debug!("Trying to rotate signature key");
+                        let recipients = send_group(&provider)
+                            .map(|sg| member_identities(&sg))
+                            .unwrap_or_default();
+                        match rotate_key_base64(&mut provider, ciphersuite, *exporter_length) {
+                            Err(e) => {
+                                return Err(format!("Error rotating signature key: {e}").into());
+                            }
+                            Ok(None) => {}
+                            Ok(Some(commit)) => {
+                                deliver(&mut provider, &transport, &recipients, &commit)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                provider.state_mut().push_outbox(OutboxKind::Commit, commit);
+                            }
+                        }
+                    }
+                    MainCommands::Process {
+                        listen: Some(url),
+                        checkpoint_interval,
+                        ratchet_tree,
+                        envelope,
+                        json,
+                        strict,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to process incoming messages from {url}");
+                        let ratchet_tree = parse_ratchet_tree(ratchet_tree.as_deref());
+                        let mut groups = GroupCache::new();
+                        let interval = Duration::from_secs(*checkpoint_interval);
+                        let mut last_checkpoint = Instant::now();
+                        let mut tally = ProcessTally::new();
+                        let result = listen(url, |frame| {
+                            let blob = match frame {
+                                WsFrame::Text(line) => Blob.decode(line),
+                                WsFrame::Binary(bytes) => Ok(bytes),
+                            };
+                            let processed = blob
+                                .and_then(|blob| decode_blob(&blob, *envelope))
+                                .and_then(|decoded| {
+                                    process_decoded(
+                                        &mut provider,
+                                        &mut groups,
+                                        decoded,
+                                        ratchet_tree.as_ref(),
+                                        ciphersuite,
+                                        *exporter_length,
+                                    )
+                                });
+                            let failed = tally.record(report_processed(processed, *json));
+                            if last_checkpoint.elapsed() >= interval {
+                                tracing::debug!("Checkpointing state");
+                                if let Err(e) = persist_state(
+                                    state_path,
+                                    provider.state(),
+                                    state_format,
+                                    *journal,
+                                    storage.as_deref(),
+                                ) {
+                                    tracing::error!("Error checkpointing state: {e}");
+                                }
+                                last_checkpoint = Instant::now();
+                            }
+                            if failed && *strict {
+                                tracing::warn!("Stopping at the first failed message");
+                                return ControlFlow::Break(());
+                            }
+                            ControlFlow::Continue(())
+                        });
+                        if let Err(e) = result {
+                            tracing::error!("Error listening on {url}: {e}");
+                            tally.unreadable = true;
+                        }
+                        process_tally = Some(tally);
+                    }
+                    MainCommands::Process {
+                        follow: Some(path),
+                        ratchet_tree,
+                        envelope,
+                        json,
+                        strict,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to process incoming messages from {path}");
+                        let ratchet_tree = parse_ratchet_tree(ratchet_tree.as_deref());
+                        let mut groups = GroupCache::new();
+                        let mut tally = ProcessTally::new();
+                        let result = follow(path, |batch| {
+                            let mut flow = ControlFlow::Continue(());
+                            for blob in batch.into_iter().map(file_blob) {
+                                let processed = blob
+                                    .and_then(|blob| decode_blob(&blob, *envelope))
+                                    .and_then(|decoded| {
+                                        process_decoded(
+                                            &mut provider,
+                                            &mut groups,
+                                            decoded,
+                                            ratchet_tree.as_ref(),
+                                            ciphersuite,
+                                            *exporter_length,
+                                        )
+                                    });
+                                if tally.record(report_processed(processed, *json)) && *strict {
+                                    tracing::warn!("Stopping at the first failed message");
+                                    flow = ControlFlow::Break(());
+                                    break;
+                                }
+                            }
+                            tracing::debug!("Checkpointing state");
+                            // the batch stays unclaimed if the state can't be saved
+                            if let Err(e) = persist_state(
+                                state_path,
+                                provider.state(),
+                                state_format,
+                                *journal,
+                                storage.as_deref(),
+                            ) {
+                                tracing::error!("Error checkpointing state: {e}");
+                                tally.unreadable = true;
+                                return ControlFlow::Break(());
                             }
-                            Ok(MlsMessageBodyIn::Welcome(welcome)) => {
-                                match process_welcome(&provider, welcome) {
+                            flow
+                        });
+                        if let Err(e) = result {
+                            tracing::error!("Error following {path}: {e}");
+                            tally.unreadable = true;
+                        }
+                        process_tally = Some(tally);
+                    }
+                    MainCommands::Process {
+                        wait,
+                        ratchet_tree,
+                        envelope,
+                        json,
+                        parallel,
+                        strict,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to process incoming messages");
+                        let ratchet_tree = parse_ratchet_tree(ratchet_tree.as_deref());
+                        let mut groups = GroupCache::new();
+                        let mut tally = ProcessTally::new();
+                        // transports with per-group streams (see `nats`) only fetch the streams
+                        // of the groups known when fetching, so rounds repeat for groups joined
+                        // meanwhile
+                        let mut fetched_groups: Option<Vec<Vec<u8>>> = None;
+                        let mut stopped = false;
+                        while !stopped {
+                            let blobs: Blobs = match &inbox {
+                                Some(_) if fetched_groups.is_some() => break,
+                                Some(inbox) => {
+                                    fetched_groups = Some(Vec::new());
+                                    match inbox.read_messages() {
+                                        Err(e) => {
+                                            tracing::error!(
+                                                "Error reading inbox {}: {e}",
+                                                inbox.dir().display()
+                                            );
+                                            tally.unreadable = true;
+                                            Box::new(std::iter::empty())
+                                        }
+                                        Ok(files) => {
+                                            inbox_claims
+                                                .extend(files.iter().map(|(name, _)| name.clone()));
+                                            Box::new(
+                                                files
+                                                    .into_iter()
+                                                    .map(|(_, content)| file_blob(content)),
+                                            )
+                                        }
+                                    }
+                                }
+                                None => {
+                                    let group_ids = stored_group_ids(&provider)
+                                        .unwrap_or_default()
+                                        .iter()
+                                        .map(|group_id| group_id.to_vec())
+                                        .collect::<Vec<_>>();
+                                    let wait = match &fetched_groups {
+                                        None => *wait,
+                                        Some(fetched)
+                                            if group_ids.iter().all(|id| fetched.contains(id)) =>
+                                        {
+                                            break;
+                                        }
+                                        Some(_) => {
+                                            tracing::debug!("Fetching messages of joined groups");
+                                            0
+                                        }
+                                    };
+                                    transport
+                                        .resume(provider.state().transport_cursors(), &group_ids);
+                                    fetched_groups = Some(group_ids);
+                                    match transport.fetch(&own_identity(&provider), wait) {
+                                        Err(e) => {
+                                            tracing::error!("Error fetching messages: {e}");
+                                            tally.unreadable = true;
+                                            Box::new(std::iter::empty())
+                                        }
+                                        Ok(blobs) => blobs,
+                                    }
+                                }
+                            };
+                            type Decoded =
+                                Box<dyn Iterator<Item = Result<DecodedMessage, Box<dyn Error>>>>;
+                            let envelope = *envelope;
+                            let decoded: Decoded = if *parallel {
+                                // decoding doesn't touch state, so it can run ahead on all cores
+                                let mut raw = Vec::new();
+                                for blob in blobs {
+                                    match blob {
+                                        Err(e) => {
+                                            tracing::error!("Error extracting message: {e}");
+                                            tally.record(true);
+                                        }
+                                        Ok(blob) => {
+                                            raw.push(blob);
+                                        }
+                                    }
+                                }
+                                if *strict && tally.failed > 0 {
+                                    tracing::warn!(
+                                        "Not processing any messages as some failed to read"
+                                    );
+                                    raw.clear();
+                                    stopped = true;
+                                }
+                                Box::new(
+                                    decode_blobs_parallel(&raw, envelope)
+                                        .into_iter()
+                                        .map(|decoded| decoded.map_err(Box::<dyn Error>::from)),
+                                )
+                            } else {
+                                Box::new(blobs.map(move |blob| decode_blob(&blob?, envelope)))
+                            };
+                            for decoded in decoded {
+                                let processed = decoded.and_then(|decoded| {
+                                    process_decoded(
+                                        &mut provider,
+                                        &mut groups,
+                                        decoded,
+                                        ratchet_tree.as_ref(),
+                                        ciphersuite,
+                                        *exporter_length,
+                                    )
+                                });
+                                if tally.record(report_processed(processed, *json)) && *strict {
+                                    tracing::warn!("Stopping at the first failed message");
+                                    stopped = true;
+                                    break;
+                                }
+                            }
+                            // fetched messages count as acknowledged once handed to processing
+                            provider
+                                .state_mut()
+                                .update_transport_cursors(transport.cursors());
+                        }
+                        // messages left unprocessed stay in the inbox; processed ones before
+                        // them are skipped as duplicates next time
+                        if stopped {
+                            inbox_claims.clear();
+                        }
+                        process_tally = Some(tally);
+                    }
+                    MainCommands::Decrypt { envelope } => {
+                        tracing::debug!("Trying to decrypt application messages");
+                        let mut groups = GroupCache::new();
+                        for blob in read_blobs(args.framing) {
+                            let proto_msg: ProtocolMessage =
+                                match blob.and_then(|blob| extract(&provider, &blob, *envelope)) {
                                     Err(e) => {
-                                        log::error!("Error processing welcome: {e}");
+                                        tracing::error!("Error extracting message: {e}");
+                                        continue;
                                     }
-                                    Ok(g) => {
-                                        log::warn!("Group joined:\n{g:#?}");
+                                    Ok(MlsMessageBodyIn::PrivateMessage(msg)) => msg.into(),
+                                    Ok(MlsMessageBodyIn::PublicMessage(msg)) => msg.into(),
+                                    Ok(_) => {
+                                        tracing::info!("Skipping Welcome or key package");
+                                        continue;
+                                    }
+                                };
+                            match decrypt_message(&mut provider, &mut groups, proto_msg) {
+                                Err(e) => {
+                                    tracing::error!("Error decrypting message: {e}");
+                                }
+                                Ok(None) => {
+                                    tracing::info!("Skipping handshake message");
+                                }
+                                Ok(Some(message)) => {
+                                    if let Err(e) = print_decrypted(&message, true) {
+                                        tracing::error!("{e}");
                                     }
                                 }
                             }
-                            Ok(MlsMessageBodyIn::PublicMessage(pub_msg_in)) => {
-                                process_proto_msg_main(
-                                    &mut provider,
-                                    pub_msg_in.into(),
-                                    ciphersuite,
-                                    *exporter_length,
+                        }
+                    }
+                    MainCommands::Reinit {
+                        ciphersuite: new_ciphersuite,
+                    } => {
+                        tracing::debug!("Trying to re-initialize send group");
+                        match parse_ciphersuite(new_ciphersuite) {
+                            None => {
+                                return Err(
+                                    format!("Invalid ciphersuite: {new_ciphersuite}").into()
                                 );
                             }
-                            Ok(MlsMessageBodyIn::PrivateMessage(prv_msg_in)) => {
-                                process_proto_msg_main(
+                            Some(new_ciphersuite) => {
+                                let recipients = send_group(&provider)
+                                    .map(|sg| member_identities(&sg))
+                                    .unwrap_or_default();
+                                match send_group_reinit_base64(&mut provider, new_ciphersuite) {
+                                    Err(e) => {
+                                        return Err(format!(
+                                            "Error re-initializing send group: {e}"
+                                        )
+                                        .into());
+                                    }
+                                    Ok((commit, welcome)) => {
+                                        deliver(&mut provider, &transport, &recipients, &commit)?;
+                                        drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                        provider
+                                            .state_mut()
+                                            .push_outbox(OutboxKind::Commit, commit);
+                                        deliver(&mut provider, &transport, &recipients, &welcome)?;
+                                        drop_to_outbox(&mut provider, outbox.as_ref(), &welcome)?;
+                                        provider
+                                            .state_mut()
+                                            .push_outbox(OutboxKind::Welcome, welcome);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    MainCommands::Branch { members } => {
+                        tracing::debug!("Trying to branch send group");
+                        match members
+                            .iter()
+                            .map(|m| Blob.decode(m))
+                            .collect::<Result<Vec<_>, _>>()
+                        {
+                            Err(e) => {
+                                return Err(format!("Error decoding member identity: {e}").into());
+                            }
+                            Ok(identities) => {
+                                match send_group_branch_base64(&mut provider, &identities) {
+                                    Err(e) => {
+                                        return Err(
+                                            format!("Error branching send group: {e}").into()
+                                        );
+                                    }
+                                    Ok((group_id, welcome)) => {
+                                        note!(
+                                            "Branch group created: {}",
+                                            Blob.encode(group_id.as_slice())
+                                        );
+                                        deliver(&mut provider, &transport, &identities, &welcome)?;
+                                        drop_to_outbox(&mut provider, outbox.as_ref(), &welcome)?;
+                                        provider
+                                            .state_mut()
+                                            .push_outbox(OutboxKind::Welcome, welcome);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    MainCommands::Commit {
+                        dry_run: true,
+                        show_commit,
+                        resumption_epoch,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to preview PSK injection into send group");
+                        match preview_commit(&mut provider, |provider| {
+                            queue_resumption_psks(provider, resumption_epoch)?;
+                            send_group_stage_inject_psks_base64(provider, ciphersuite)
+                        }) {
+                            Err(e) => {
+                                return Err(format!(
+                                    "Error previewing PSK injection into send group: {e}"
+                                )
+                                .into());
+                            }
+                            Ok((summary, commit)) => print_preview(&summary, &commit, *show_commit),
+                        }
+                    }
+                    MainCommands::Commit {
+                        stage,
+                        resumption_epoch,
+                        ..
+                    } => {
+                        tracing::debug!("Trying to inject queued PSKs into send group");
+                        let recipients = send_group(&provider)
+                            .map(|sg| member_identities(&sg))
+                            .unwrap_or_default();
+                        let commit = queue_resumption_psks(&mut provider, resumption_epoch)
+                            .and_then(|()| {
+                                if *stage {
+                                    send_group_stage_inject_psks_base64(&mut provider, ciphersuite)
+                                } else {
+                                    send_group_inject_psks_base64(
+                                        &mut provider,
+                                        ciphersuite,
+                                        *exporter_length,
+                                    )
+                                }
+                            });
+                        match commit {
+                            Err(e) => {
+                                return Err(
+                                    format!("Error injecting PSKs into send group: {e}").into()
+                                );
+                            }
+                            Ok(commit) => {
+                                deliver(&mut provider, &transport, &recipients, &commit)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                provider.state_mut().push_outbox(OutboxKind::Commit, commit);
+                            }
+                        }
+                    }
+                    MainCommands::ResumptionPsks { group_id } => {
+                        tracing::debug!("Trying to list resumption PSK epochs");
+                        match group_base64(&provider, group_id.as_deref()) {
+                            Err(e) => return Err(format!("Error getting group: {e}").into()),
+                            Ok(g) => {
+                                for epoch in resumption_psk_epochs(&g) {
+                                    outln!("{epoch}");
+                                }
+                            }
+                        }
+                    }
+                    MainCommands::InjectExternalPsk {
+                        id,
+                        secret_file,
+                        store_only,
+                    } => {
+                        tracing::debug!("Trying to store external PSK");
+                        let stored = Blob.decode(id).and_then(|psk_id| {
+                            let secret = read_file(secret_file)?;
+                            store_external_psk(
+                                &mut provider,
+                                ciphersuite,
+                                psk_id,
+                                &secret,
+                                !*store_only,
+                            )
+                        });
+                        match stored {
+                            Err(e) => return Err(format!("Error storing external PSK: {e}").into()),
+                            Ok(()) if *store_only => {
+                                note!("External PSK stored");
+                            }
+                            Ok(()) => {
+                                note!("External PSK stored and queued for the next commit");
+                            }
+                        }
+                    }
+                    MainCommands::ConfirmCommit {} => {
+                        tracing::debug!("Trying to confirm pending commit in send group");
+                        send_group_confirm_commit(&mut provider, ciphersuite, *exporter_length)
+                            .map_err(|e| format!("Error confirming pending commit: {e}"))?;
+                    }
+                    MainCommands::AbortCommit {} => {
+                        tracing::debug!("Trying to abort pending commit in send group");
+                        send_group_abort_commit(&provider)
+                            .map_err(|e| format!("Error aborting pending commit: {e}"))?;
+                    }
+                    MainCommands::Pending { pending_command } => match pending_command {
+                        PendingCommands::Show {} => {
+                            tracing::debug!("Trying to show pending commit and proposals");
+                            match send_group(&provider) {
+                                Err(e) => {
+                                    return Err(format!("Error getting send group: {e}").into());
+                                }
+                                Ok(sg) => {
+                                    if sg.pending_commit().is_some() {
+                                        match describe_pending_commit(&sg) {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                    "Error describing pending commit: {e}"
+                                                )
+                                                .into());
+                                            }
+                                            Ok(lines) => lines.iter().for_each(|l| outln!("{l}")),
+                                        }
+                                    }
+                                    match queued_proposals_base64(&provider, sg.group_id()) {
+                                        Err(e) => {
+                                            return Err(format!(
+                                                "Error listing pending proposals: {e}"
+                                            )
+                                            .into());
+                                        }
+                                        Ok(lines) => {
+                                            lines.iter().for_each(|l| outln!("proposal {l}"));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                        PendingCommands::Clear {} => {
+                            tracing::debug!("Trying to clear pending commit and proposals");
+                            send_group_clear_pending(&provider).map_err(|e| {
+                                format!("Error clearing pending commit and proposals: {e}")
+                            })?;
+                        }
+                        PendingCommands::Resend {} => {
+                            tracing::debug!("Trying to resend pending commit");
+                            match send_group(&provider).and_then(|sg| {
+                                Ok((
+                                    member_identities(&sg),
+                                    pending_commit_base64(&provider, &sg)?,
+                                ))
+                            }) {
+                                Err(e) => {
+                                    return Err(
+                                        format!("Error resending pending commit: {e}").into()
+                                    );
+                                }
+                                Ok((recipients, commit)) => {
+                                    deliver(&mut provider, &transport, &recipients, &commit)?;
+                                    drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                }
+                            }
+                        }
+                    },
+                    MainCommands::CommitProposals {
+                        group_id,
+                        include_refs,
+                    } => {
+                        tracing::debug!("Trying to commit selected pending proposals");
+                        match group_base64(&provider, group_id.as_deref()) {
+                            Err(e) => return Err(format!("Error getting group: {e}").into()),
+                            Ok(g) if include_refs.is_empty() => {
+                                match queued_proposals_base64(&provider, g.group_id()) {
+                                    Err(e) => {
+                                        return Err(format!(
+                                            "Error listing pending proposals: {e}"
+                                        )
+                                        .into());
+                                    }
+                                    Ok(lines) => {
+                                        for line in lines {
+                                            outln!("{line}");
+                                        }
+                                    }
+                                }
+                            }
+                            Ok(mut g) => {
+                                match include_refs
+                                    .iter()
+                                    .map(|r| proposal_ref_from_base64(r))
+                                    .collect::<Result<Vec<_>, _>>()
+                                {
+                                    Err(e) => {
+                                        return Err(format!(
+                                            "Error decoding proposal reference: {e}"
+                                        )
+                                        .into());
+                                    }
+                                    Ok(refs) => {
+                                        // members removed by the proposals need the commit too
+                                        let recipients = member_identities(&g);
+                                        match commit_proposals_base64(&mut provider, &mut g, &refs)
+                                        {
+                                            Err(e) => {
+                                                return Err(format!(
+                                                    "Error committing proposals: {e}"
+                                                )
+                                                .into());
+                                            }
+                                            Ok(commit) => {
+                                                deliver(
+                                                    &mut provider,
+                                                    &transport,
+                                                    &recipients,
+                                                    &commit,
+                                                )?;
+                                                drop_to_outbox(
+                                                    &mut provider,
+                                                    outbox.as_ref(),
+                                                    &commit,
+                                                )?;
+                                                provider
+                                                    .state_mut()
+                                                    .push_outbox(OutboxKind::Commit, commit);
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    #[cfg(unix)]
+                    MainCommands::Serve {
+                        socket,
+                        checkpoint_interval,
+                    } => {
+                        tracing::debug!("Trying to serve requests on {socket}");
+                        let interval = Duration::from_secs(*checkpoint_interval);
+                        let mut last_checkpoint = Instant::now();
+                        let result = serve(socket, |request| {
+                            tracing::info!("Request: {request:?}");
+                            let output = match request {
+                                ServeRequest::GenKp {} => {
+                                    vec![gen_recorded_kp_base64(&mut provider, ciphersuite)?]
+                                }
+                                ServeRequest::Encrypt {
+                                    plaintexts,
+                                    group_id,
+                                } => {
+                                    let mut g = group_base64(&provider, group_id.as_deref())?;
+                                    let mut msgs = Vec::new();
+                                    for pt in plaintexts {
+                                        let msg = stdin_create_message_base64(
+                                            &mut provider,
+                                            &mut g,
+                                            Ok(pt),
+                                        )?;
+                                        provider
+                                            .state_mut()
+                                            .push_outbox(OutboxKind::Message, msg.clone());
+                                        msgs.push(msg);
+                                    }
+                                    msgs
+                                }
+                                ServeRequest::Process { messages } => {
+                                    let mut pts = Vec::new();
+                                    for msg in messages {
+                                        let body = stdin_base64_extract(Ok(msg))?;
+                                        pts.extend(
+                                            process_body_main(
+                                                &mut provider,
+                                                body,
+                                                ciphersuite,
+                                                *exporter_length,
+                                            )
+                                            .map(|message| message.plaintext),
+                                        );
+                                    }
+                                    pts
+                                }
+                                ServeRequest::Commit {} => {
+                                    let commit = send_group_inject_psks_base64(
+                                        &mut provider,
+                                        ciphersuite,
+                                        *exporter_length,
+                                    )?;
+                                    provider
+                                        .state_mut()
+                                        .push_outbox(OutboxKind::Commit, commit.clone());
+                                    vec![commit]
+                                }
+                                ServeRequest::Shutdown {} => Vec::new(),
+                            };
+                            if last_checkpoint.elapsed() >= interval {
+                                tracing::debug!("Checkpointing state");
+                                if let Err(e) = persist_state(
+                                    state_path,
+                                    provider.state(),
+                                    state_format,
+                                    *journal,
+                                    storage.as_deref(),
+                                ) {
+                                    tracing::error!("Error checkpointing state: {e}");
+                                }
+                                last_checkpoint = Instant::now();
+                            }
+                            Ok(output)
+                        });
+                        result.map_err(|e| format!("Error serving on {socket}: {e}"))?;
+                    }
+                    MainCommands::ExportRatchetTree { group_id } => {
+                        tracing::debug!("Trying to export ratchet tree");
+                        match group_base64(&provider, group_id.as_deref())
+                            .and_then(|g| export_ratchet_tree_base64(&g))
+                        {
+                            Err(e) => {
+                                return Err(format!("Error exporting ratchet tree: {e}").into());
+                            }
+                            Ok(tree) => {
+                                outln!("{tree}");
+                            }
+                        }
+                    }
+                    MainCommands::ExportGroupInfo {
+                        group_id,
+                        no_ratchet_tree,
+                    } => {
+                        tracing::debug!("Trying to export group info");
+                        match group_base64(&provider, group_id.as_deref()).and_then(|g| {
+                            export_group_info_base64(&provider, &g, !*no_ratchet_tree)
+                        }) {
+                            Err(e) => return Err(format!("Error exporting group info: {e}").into()),
+                            Ok(group_info) => {
+                                emit(args.framing, &group_info)?;
+                            }
+                        }
+                    }
+                    MainCommands::Heal {
+                        group_id,
+                        ratchet_tree,
+                    } => {
+                        tracing::debug!("Trying to heal group {group_id}");
+                        let ratchet_tree = parse_ratchet_tree(ratchet_tree.as_deref());
+                        let healed = match read_blobs(args.framing).next() {
+                            None => Err("No GroupInfo on stdin".into()),
+                            Some(blob) => blob.and_then(|blob| {
+                                heal_base64(
                                     &mut provider,
-                                    prv_msg_in.into(),
-                                    ciphersuite,
+                                    group_id,
+                                    &blob,
+                                    ratchet_tree,
                                     *exporter_length,
-                                );
+                                )
+                            }),
+                        }
+                        .map(|commit| {
+                            let recipients = group_base64(&provider, Some(group_id))
+                                .map(|g| member_identities(&g))
+                                .unwrap_or_default();
+                            (commit, recipients)
+                        });
+                        match healed {
+                            Err(e) => return Err(format!("Error healing group: {e}").into()),
+                            Ok((commit, recipients)) => {
+                                deliver(&mut provider, &transport, &recipients, &commit)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &commit)?;
+                                provider.state_mut().push_outbox(OutboxKind::Commit, commit);
                             }
+                        }
+                    }
+                    MainCommands::Observe { observe_command } => match observe_command {
+                        ObserveCommands::Start { ratchet_tree } => {
+                            tracing::debug!("Trying to start observing a group");
+                            let ratchet_tree = parse_ratchet_tree(ratchet_tree.as_deref());
+                            let started = match read_blobs(args.framing).next() {
+                                None => Err("No GroupInfo on stdin".into()),
+                                Some(blob) => blob
+                                    .and_then(|blob| bytes_to_mls_msg_in(&blob))
+                                    .and_then(|message| match message.extract() {
+                                        MlsMessageBodyIn::GroupInfo(group_info) => {
+                                            start_observing(&mut provider, group_info, ratchet_tree)
+                                        }
+                                        _ => Err("Not a GroupInfo".into()),
+                                    }),
+                            };
+                            match started {
+                                Err(e) => {
+                                    return Err(
+                                        format!("Error starting to observe group: {e}").into()
+                                    );
+                                }
+                                Ok(group_id) => {
+                                    note!("Observing group {}", Blob.encode(group_id.as_slice()));
+                                }
+                            }
+                        }
+                        ObserveCommands::Process {} => {
+                            tracing::debug!("Trying to observe public handshake messages");
+                            for blob in read_blobs(args.framing) {
+                                let observed = blob
+                                    .and_then(|blob| bytes_to_mls_msg_in(&blob))
+                                    .and_then(|message| match message.extract() {
+                                        MlsMessageBodyIn::PublicMessage(message) => {
+                                            process_observed(&mut provider, message.into())
+                                        }
+                                        _ => Err("Only public messages can be observed".into()),
+                                    });
+                                match observed.and_then(|o| Ok(json_encode(&o)?)) {
+                                    Err(e) => {
+                                        tracing::error!("Error observing message: {e}");
+                                    }
+                                    Ok(observation) => {
+                                        outln!("{observation}");
+                                    }
+                                }
+                            }
+                        }
+                        ObserveCommands::Status {} => match observe_status(&provider) {
+                            Err(e) => {
+                                return Err(format!("Error reading observed groups: {e}").into());
+                            }
+                            Ok(observations) => {
+                                for observation in observations {
+                                    match json_encode(&observation) {
+                                        Err(e) => {
+                                            return Err(
+                                                format!("Error encoding observation: {e}").into()
+                                            );
+                                        }
+                                        Ok(observation) => {
+                                            outln!("{observation}");
+                                        }
+                                    }
+                                }
+                            }
+                        },
+                    },
+                    MainCommands::Tree { group_id, format } => {
+                        tracing::debug!("Trying to render ratchet tree");
+                        match group_base64(&provider, group_id.as_deref()) {
+                            Err(e) => return Err(format!("Error loading group: {e}").into()),
+                            Ok(g) => match format.as_str() {
+                                "ascii" => out!("{}", TreeView::new(&g).to_ascii()),
+                                "dot" => out!("{}", TreeView::new(&g).to_dot()),
+                                _ => return Err(format!("Unknown tree format: {format}").into()),
+                            },
+                        }
+                    }
+                    MainCommands::ExportState { redact } => {
+                        tracing::debug!("Trying to export state");
+                        let json = if *redact {
+                            redact_state(provider.state()).and_then(|json| {
+                                json_encode_pretty(&json).map_err(Box::<dyn Error>::from)
+                            })
+                        } else {
+                            json_encode_pretty(provider.state()).map_err(Box::<dyn Error>::from)
+                        };
+                        match json {
+                            Err(e) => return Err(format!("Error exporting state: {e}").into()),
+                            Ok(json) => {
+                                outln!("{json}");
+                            }
+                        }
+                    }
+                    MainCommands::Doctor { fix } => {
+                        tracing::debug!("Trying to check state consistency");
+                        match diagnose(&provider) {
+                            Err(e) => return Err(format!("Error checking state: {e}").into()),
+                            Ok(problems) if problems.is_empty() => {
+                                note!("No problems found");
+                            }
+                            Ok(problems) => {
+                                for problem in &problems {
+                                    outln!("{problem}");
+                                }
+                                if *fix {
+                                    repair(&mut provider, &problems);
+                                    note!("Repaired {} problems", problems.len());
+                                } else {
+                                    tracing::warn!(
+                                        "Found {} problems; run with --fix to repair them",
+                                        problems.len()
+                                    );
+                                }
+                            }
+                        }
+                    }
+                    MainCommands::Backup {
+                        out,
+                        key,
+                        exclude_secrets,
+                    } => {
+                        tracing::debug!("Trying to back up state to {out}");
+                        // backups are sealed with RustCrypto whatever the crypto backend, so they
+                        // can be restored by any build
+                        match read_file(key)
+                            .map_err(Box::<dyn Error>::from)
+                            .and_then(|key| {
+                                seal_backup(
+                                    &RustCrypto::default(),
+                                    provider.state(),
+                                    &key,
+                                    *exclude_secrets,
+                                )
+                            })
+                            .and_then(|snapshot| Ok(write_file(out, snapshot)?))
+                        {
+                            Err(e) => return Err(format!("Error writing backup: {e}").into()),
+                            Ok(()) => {
+                                note!("Wrote backup to {out}");
+                            }
+                        }
+                    }
+                    MainCommands::RekeyPolicy {
+                        max_messages,
+                        max_age,
+                        off,
+                    } => {
+                        if *off {
+                            tracing::debug!("Turning automatic re-keying off");
+                            provider
+                                .state_mut()
+                                .set_rekey_policy(RekeyPolicy::default());
+                        } else if max_messages.is_some() || max_age.is_some() {
+                            tracing::debug!("Setting automatic re-key policy");
+                            provider.state_mut().set_rekey_policy(RekeyPolicy {
+                                max_messages: *max_messages,
+                                max_age: *max_age,
+                            });
+                        }
+                        match json_encode_pretty(provider.state().rekey_policy()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding re-key policy: {e}").into());
+                            }
+                            Ok(policy) => {
+                                outln!("{policy}");
+                            }
+                        }
+                    }
+                    MainCommands::Capabilities {
+                        versions,
+                        ciphersuites,
+                        extensions,
+                        credentials,
+                        reset,
+                    } => {
+                        let mut options = if *reset {
+                            CapabilitiesOptions::default()
+                        } else {
+                            provider.state().capabilities().clone()
+                        };
+                        for (value, field) in [
+                            (versions, &mut options.versions),
+                            (ciphersuites, &mut options.ciphersuites),
+                            (extensions, &mut options.extensions),
+                            (credentials, &mut options.credentials),
+                        ] {
+                            if let Some(value) = value {
+                                value.clone_into(field);
+                            }
+                        }
+                        match capabilities(&options, ciphersuite) {
+                            Err(e) => return Err(format!("Invalid capabilities: {e}").into()),
                             Ok(_) => {
-                                log::error!("Unsupported wire format");
+                                tracing::debug!("Setting leaf node capabilities");
+                                provider.state_mut().set_capabilities(options);
+                            }
+                        }
+                        match json_encode_pretty(provider.state().capabilities()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding capabilities: {e}").into());
+                            }
+                            Ok(capabilities) => {
+                                outln!("{capabilities}");
                             }
                         }
                     }
-                }
-                MainCommands::Commit {} => {
-                    log::debug!("Trying to inject queued PSKs into send group");
-                    match send_group_inject_psks_base64(&mut provider, ciphersuite) {
-                        Err(e) => {
-                            log::error!("Error injecting PSKs into send group: {e}");
+                    MainCommands::SuitePolicy {
+                        ciphersuites,
+                        signature_schemes,
+                        reset,
+                    } => {
+                        let mut policy = if *reset {
+                            SuitePolicy::default()
+                        } else {
+                            provider.state().suite_policy().clone()
+                        };
+                        for (value, field) in [
+                            (ciphersuites, &mut policy.ciphersuites),
+                            (signature_schemes, &mut policy.signature_schemes),
+                        ] {
+                            if let Some(value) = value {
+                                value.clone_into(field);
+                            }
                         }
-                        Ok(commit) => {
-                            println!("{commit}");
+                        tracing::debug!("Setting suite policy");
+                        provider.state_mut().set_suite_policy(policy);
+                        match json_encode_pretty(provider.state().suite_policy()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding suite policy: {e}").into());
+                            }
+                            Ok(policy) => {
+                                outln!("{policy}");
+                            }
                         }
                     }
-                }
-                MainCommands::Encrypt {} => {
-                    log::debug!("Trying to encrypt messages in send-group");
-                    match send_group(&provider) {
-                        Err(e) => {
-                            log::error!("Error getting send group: {e}");
+                    MainCommands::MembershipPolicy {
+                        max_members,
+                        max_adds_per_commit,
+                        ban,
+                        unban,
+                        reset,
+                    } => {
+                        let mut policy = if *reset {
+                            MembershipPolicy::default()
+                        } else {
+                            provider.state().membership_policy().clone()
+                        };
+                        if max_members.is_some() {
+                            policy.max_members = *max_members;
+                        }
+                        if max_adds_per_commit.is_some() {
+                            policy.max_adds_per_commit = *max_adds_per_commit;
+                        }
+                        let changes = ban
+                            .iter()
+                            .map(|identity| Blob.decode(identity).map(|identity| (identity, true)))
+                            .chain(unban.iter().map(|identity| {
+                                Blob.decode(identity).map(|identity| (identity, false))
+                            }))
+                            .collect::<Result<Vec<_>, _>>();
+                        match changes {
+                            Err(e) => return Err(format!("Invalid identity: {e}").into()),
+                            Ok(changes) => {
+                                for (identity, banned) in changes {
+                                    policy.banned.retain(|b| *b != identity);
+                                    if banned {
+                                        policy.banned.push(identity);
+                                    }
+                                }
+                                tracing::debug!("Setting membership policy");
+                                provider.state_mut().set_membership_policy(policy);
+                            }
+                        }
+                        match json_encode_pretty(provider.state().membership_policy()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding membership policy: {e}").into());
+                            }
+                            Ok(policy) => {
+                                outln!("{policy}");
+                            }
+                        }
+                    }
+                    MainCommands::PskQueuePolicy {
+                        max_len,
+                        overflow,
+                        reset,
+                    } => {
+                        let mut policy = if *reset {
+                            PskQueuePolicy::default()
+                        } else {
+                            provider.state().psk_queue_policy().clone()
+                        };
+                        if max_len.is_some() {
+                            policy.max_len = *max_len;
+                        }
+                        if let Some(overflow) = overflow {
+                            policy.overflow = *overflow;
+                        }
+                        tracing::debug!("Setting PSK queue policy");
+                        provider.state_mut().set_psk_queue_policy(policy);
+                        match json_encode_pretty(provider.state().psk_queue_policy()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding PSK queue policy: {e}").into());
+                            }
+                            Ok(policy) => {
+                                outln!("{policy}");
+                            }
+                        }
+                    }
+                    MainCommands::WelcomePsk { enable, disable } => {
+                        if *enable || *disable {
+                            tracing::debug!("Setting welcome PSK");
+                            provider.state_mut().set_welcome_psk(*enable);
+                        }
+                        outln!("{}", provider.state().welcome_psk());
+                    }
+                    MainCommands::EpochRetention { epochs, reset } => {
+                        if *reset {
+                            provider.state_mut().set_epoch_retention(None);
+                        } else if epochs.is_some() {
+                            tracing::debug!("Setting epoch retention");
+                            provider.state_mut().set_epoch_retention(*epochs);
+                        }
+                        match json_encode_pretty(&provider.state().epoch_retention()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding epoch retention: {e}").into());
+                            }
+                            Ok(epochs) => {
+                                outln!("{epochs}");
+                            }
+                        }
+                    }
+                    MainCommands::Dedupe {
+                        capacity,
+                        reset,
+                        clear,
+                    } => {
+                        if *reset {
+                            provider.state_mut().set_dedupe_capacity(None);
+                        } else if capacity.is_some() {
+                            tracing::debug!("Setting dedupe capacity");
+                            provider.state_mut().set_dedupe_capacity(*capacity);
+                        }
+                        if *clear {
+                            let forgotten = provider.state_mut().clear_seen_messages();
+                            tracing::info!("Forgot {forgotten} processed messages");
+                        }
+                        outln!(
+                            "{} ({} remembered)",
+                            provider.state().dedupe_capacity(),
+                            provider.state().seen_messages().len()
+                        );
+                    }
+                    MainCommands::PurgeEpochs { keep, group_id } => {
+                        tracing::debug!("Trying to purge past epochs");
+                        let groups = match group_id {
+                            Some(_) => {
+                                group_base64(&provider, group_id.as_deref()).map(|g| vec![g])
+                            }
+                            None => stored_group_ids(&provider).and_then(|group_ids| {
+                                group_ids
+                                    .iter()
+                                    .map(|group_id| load_group(&provider, group_id))
+                                    .collect()
+                            }),
+                        };
+                        match keep
+                            .or(provider.state().epoch_retention())
+                            .ok_or_else(|| {
+                                Box::<dyn Error>::from("No retention window set; pass --keep")
+                            })
+                            .and_then(|keep| {
+                                groups?
+                                    .iter()
+                                    .map(|g| purge_epochs(&provider, g, keep))
+                                    .sum::<Result<usize, _>>()
+                            }) {
+                            Err(e) => return Err(format!("Error purging past epochs: {e}").into()),
+                            Ok(purged) => {
+                                outln!("{purged}");
+                            }
                         }
-                        Ok(mut sg) => {
-                            // assumes line is a utf-8 string
-                            for line in stdin().lock().lines() {
-                                match stdin_create_message_base64(&provider, &mut sg, line) {
+                    }
+                    MainCommands::ForwardSecrecy {
+                        per_message,
+                        tolerate_reordering,
+                        group_id,
+                    } => {
+                        tracing::debug!("Trying to configure forward secrecy");
+                        match group_base64(&provider, group_id.as_deref()).and_then(|mut g| {
+                            if *per_message || *tolerate_reordering {
+                                set_per_message_fs(&provider, &mut g, *per_message)?;
+                            }
+                            json_encode_pretty(g.configuration().sender_ratchet_configuration())
+                                .map_err(Box::<dyn Error>::from)
+                        }) {
+                            Err(e) => {
+                                return Err(
+                                    format!("Error configuring forward secrecy: {e}").into()
+                                );
+                            }
+                            Ok(config) => {
+                                outln!("{config}");
+                            }
+                        }
+                    }
+                    MainCommands::GroupStats { group_id } => {
+                        tracing::debug!("Trying to compute group statistics");
+                        let groups = match group_id {
+                            Some(_) => {
+                                group_base64(&provider, group_id.as_deref()).map(|g| vec![g])
+                            }
+                            None => stored_group_ids(&provider).and_then(|group_ids| {
+                                group_ids
+                                    .iter()
+                                    .map(|group_id| load_group(&provider, group_id))
+                                    .collect()
+                            }),
+                        };
+                        match groups.and_then(|groups| {
+                            groups
+                                .iter()
+                                .map(|g| group_stats(&provider, g))
+                                .collect::<Result<Vec<_>, _>>()
+                        }) {
+                            Err(e) => {
+                                return Err(format!("Error computing group statistics: {e}").into());
+                            }
+                            Ok(stats) => match json_encode_pretty(&stats) {
+                                Err(e) => {
+                                    return Err(
+                                        format!("Error encoding group statistics: {e}").into()
+                                    );
+                                }
+                                Ok(stats) => {
+                                    outln!("{stats}");
+                                }
+                            },
+                        }
+                    }
+                    MainCommands::Stats {} => {
+                        tracing::debug!("Trying to print activity counters");
+                        match json_encode_pretty(provider.state().stats()) {
+                            Err(e) => {
+                                return Err(format!("Error encoding activity counters: {e}").into());
+                            }
+                            Ok(stats) => {
+                                outln!("{stats}");
+                            }
+                        }
+                    }
+                    #[cfg(feature = "archive")]
+                    MainCommands::ExportStorage { file, group_id } => {
+                        tracing::debug!("Trying to export storage to {file}");
+                        match group_id
+                            .as_deref()
+                            .map(|group_id| group_base64(&provider, Some(group_id)))
+                            .transpose()
+                            .and_then(|group| {
+                                export_storage(
+                                    provider.state().openmls_values(),
+                                    file,
+                                    group.as_ref().map(|g| g.group_id()),
+                                )
+                            }) {
+                            Err(e) => return Err(format!("Error exporting storage: {e}").into()),
+                            Ok(count) => {
+                                note!("Exported {count} entries to {file}");
+                            }
+                        }
+                    }
+                    #[cfg(feature = "archive")]
+                    MainCommands::ImportStorage { file } => {
+                        tracing::debug!("Trying to import storage from {file}");
+                        match import_storage(provider.state().openmls_values(), file) {
+                            Err(e) => return Err(format!("Error importing storage: {e}").into()),
+                            Ok(count) => {
+                                note!("Imported {count} entries from {file}");
+                            }
+                        }
+                    }
+                    MainCommands::PskHistory { group_id } => {
+                        tracing::debug!("Trying to print PSK injection history");
+                        match group_id.as_deref().map(|g| Blob.decode(g)).transpose() {
+                            Err(e) => return Err(format!("Error decoding group id: {e}").into()),
+                            Ok(group_id) => {
+                                for injection in provider.state().psk_history() {
+                                    if group_id.as_ref().is_some_and(|g| *g != injection.group_id) {
+                                        continue;
+                                    }
+                                    match json_encode(injection) {
+                                        Err(e) => {
+                                            return Err(format!(
+                                                "Error encoding PSK injection: {e}"
+                                            )
+                                            .into());
+                                        }
+                                        Ok(injection) => {
+                                            outln!("{injection}");
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    MainCommands::Audit { audit_command } => match audit_command {
+                        AuditCommands::Show {} => {
+                            for entry in provider.state().audit_log() {
+                                match json_encode(entry) {
                                     Err(e) => {
-                                        log::error!("Error creating message: {e}");
+                                        return Err(
+                                            format!("Error encoding audit entry: {e}").into()
+                                        );
                                     }
-                                    Ok(msg) => {
-                                        println!("{msg}");
+                                    Ok(entry) => {
+                                        outln!("{entry}");
+                                    }
+                                }
+                            }
+                        }
+                        AuditCommands::Verify {} => {
+                            tracing::debug!("Trying to verify audit log");
+                            let entries = provider.state().audit_log();
+                            let head_mac = provider.state().audit_head_mac();
+                            match verify_audit_log(entries, head_mac, provider.crypto()) {
+                                Err(e) => {
+                                    return Err(format!("Error verifying audit log: {e}").into());
+                                }
+                                Ok(()) => {
+                                    note!("Audit log verified ({} entries)", entries.len());
+                                }
+                            }
+                        }
+                    },
+                    MainCommands::Outbox { outbox_command } => match outbox_command {
+                        OutboxCommands::List {} => {
+                            for entry in provider.state().outbox() {
+                                outln!("{} {:?}", entry.id, entry.kind);
+                            }
+                        }
+                        OutboxCommands::Resend { ids } => {
+                            let messages = provider
+                                .state()
+                                .outbox()
+                                .iter()
+                                .filter(|entry| ids.is_empty() || ids.contains(&entry.id))
+                                .map(|entry| entry.message.clone())
+                                .collect::<Vec<_>>();
+                            for message in messages {
+                                emit(args.framing, &message)?;
+                                drop_to_outbox(&mut provider, outbox.as_ref(), &message)?;
+                            }
+                        }
+                        OutboxCommands::Ack { ids, all } => {
+                            let acked = if *all {
+                                provider.state_mut().clear_outbox().len()
+                            } else {
+                                provider.state_mut().ack_outbox(ids)
+                            };
+                            note!("Acknowledged {acked} outbox entries");
+                        }
+                    },
+                    MainCommands::Encrypt {
+                        group_id,
+                        envelope,
+                        content_type,
+                    } => {
+                        tracing::debug!("Trying to encrypt messages in send-group");
+                        match group_base64(&provider, group_id.as_deref()) {
+                            Err(e) => return Err(format!("Error getting send group: {e}").into()),
+                            Ok(mut sg) => {
+                                let recipients = member_identities(&sg);
+                                // assumes line is a utf-8 string
+                                for line in input().lines() {
+                                    match rekey_if_due(
+                                        &mut provider,
+                                        &mut sg,
+                                        ciphersuite,
+                                        *exporter_length,
+                                    ) {
+                                        Err(e) => {
+                                            tracing::error!("Error re-keying send group: {e}");
+                                        }
+                                        Ok(Some(commit)) => {
+                                            if let Err(e) = deliver(
+                                                &mut provider,
+                                                &transport,
+                                                &recipients,
+                                                &commit,
+                                            )
+                                            .and_then(|()| {
+                                                drop_to_outbox(
+                                                    &mut provider,
+                                                    outbox.as_ref(),
+                                                    &commit,
+                                                )
+                                            }) {
+                                                tracing::error!("{e}");
+                                            }
+                                            provider
+                                                .state_mut()
+                                                .push_outbox(OutboxKind::Commit, commit);
+                                        }
+                                        Ok(None) => {}
+                                    }
+                                    let msg =
+                                        stdin_create_message_base64(&mut provider, &mut sg, line)
+                                            .and_then(|msg| {
+                                                if *envelope {
+                                                    seal_envelope_base64(
+                                                        &provider,
+                                                        &sg,
+                                                        content_type,
+                                                        &msg,
+                                                    )
+                                                } else {
+                                                    Ok(msg)
+                                                }
+                                            });
+                                    match msg {
+                                        Err(e) => {
+                                            tracing::error!("Error creating message: {e}");
+                                        }
+                                        Ok(msg) => {
+                                            if let Err(e) = deliver(
+                                                &mut provider,
+                                                &transport,
+                                                &recipients,
+                                                &msg,
+                                            )
+                                            .and_then(|()| {
+                                                drop_to_outbox(&mut provider, outbox.as_ref(), &msg)
+                                            }) {
+                                                tracing::error!("{e}");
+                                            }
+                                            provider
+                                                .state_mut()
+                                                .push_outbox(OutboxKind::Message, msg);
+                                        }
                                     }
                                 }
                             }
                         }
                     }
                 }
+                Ok(())
+            })();
+            // roll back a failed transaction, then recover updated state from agent & save
+            if let Err(e) = &outcome {
+                tracing::error!("{e}");
+            }
+            if let Some((meta, snapshot)) = rollback
+                && outcome.is_err()
+            {
+                tracing::warn!("Command failed; restoring the state from before the command");
+                provider.state().openmls_values().restore(&snapshot);
+                provider.state_mut().set_meta(meta);
             }
-            // recover updated state from agent & save
             let state: DmlsState = provider.into();
-            log::info!("Path to write state: {state_path}");
-            log::info!("Updated state to write:\n{state:#?}");
-            write_string_to_file(state_path, json_encode(&state).unwrap()).unwrap();
+            if let Err(e) = persist_state(
+                state_path,
+                &state,
+                state_format,
+                *journal,
+                storage.as_deref(),
+            ) {
+                tracing::error!("Error saving state {state_path}: {e}");
+                std::process::exit(EXIT_FAILURE);
+            }
+            // processed inbox messages are archived only now that the state is saved
+            if let Some(inbox) = &inbox
+                && let Err(e) = inbox.claim(&inbox_claims)
+            {
+                tracing::error!("Error archiving inbox {}: {e}", inbox.dir().display());
+            }
+            // summary and exit code for scripts
+            if let Some(tally) = process_tally {
+                eoutln!("{tally}");
+                let code = tally.exit_code();
+                if code != 0 {
+                    std::process::exit(code);
+                }
+            }
+            // the failure was logged and the state rolled back and saved above
+            if outcome.is_err() {
+                std::process::exit(EXIT_FAILURE);
+            }
         }
     }
     // done!
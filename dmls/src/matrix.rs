@@ -0,0 +1,357 @@
+//! Matrix bridge transport (`--features matrix`).
+//!
+//! `MatrixTransport` carries DMLS artifacts as custom `org.dmls.artifact` events in a Matrix
+//! room, so DMLS can be piloted over existing homeserver infrastructure: the homeserver stores
+//! and federates the events, and the agents never need a delivery service of their own. Every
+//! event holds one artifact, as standard base64, and the credential identities of its
+//! recipients, as unpadded base64url:
+//!
+//! ```text
+//! { "artifact": "AAEAAg...", "recipients": ["q83v...", "Yk9x..."] }
+//! ```
+//!
+//! The agent's Matrix account must have joined the room; its access token is read from the
+//! `DMLS_MATRIX_TOKEN` environment variable rather than the command line. Fetching pages
+//! forwards through the room's timeline (`/messages`) from the pagination token stored in the
+//! state, and returns the events addressed to the agent in timeline order. Tokens denote
+//! positions in the homeserver's timeline, so events that arrive late over federation are still
+//! fetched, unlike with timestamps. The stored token advances as events are handed to
+//! processing, a page at a time: events of a page that was only partly processed are fetched
+//! again and skipped by deduplication, as are the agent's own events. Without a token, the room
+//! history is read from its start, once.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let mut transport = MatrixTransport::connect("https://matrix.example.org", "!abc:example.org")?;
+//! transport.send(&commit_b64, &member_identities(&sg))?;
+//! transport.resume(provider.state().transport_cursors(), &[]);
+//! for blob in transport.fetch(&own_identity(&provider), 0)? { /* ... */ }
+//! provider.state_mut().update_transport_cursors(transport.cursors());
+//! ```
+
+use super::{
+    encoding::Blob,
+    transport::{Blobs, Cursor, Transport},
+};
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD as Base64, URL_SAFE_NO_PAD as Base64Url},
+};
+use core::{
+    error::Error,
+    fmt::{Debug, Formatter, Result as FmtResult, Write},
+    iter::from_fn,
+    sync::atomic::{AtomicU64, Ordering},
+    time::Duration,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    env::var,
+    sync::{Arc, Mutex},
+    thread::sleep,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+/// Type of the custom events carrying artifacts.
+pub const EVENT_TYPE: &str = "org.dmls.artifact";
+
+/// Environment variable holding the access token of the agent's Matrix account.
+pub const TOKEN_VAR: &str = "DMLS_MATRIX_TOKEN";
+
+/// Number of events requested per page of the room timeline.
+const PAGE_LIMIT: u32 = 100;
+
+/// How long to wait between polls of the room while waiting for events.
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Content of an `org.dmls.artifact` event.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArtifactEvent {
+    /// The artifact, as standard base64.
+    pub artifact: String,
+    /// Credential identities of the recipients, as unpadded base64url.
+    pub recipients: Vec<String>,
+}
+
+/// A room event, as returned by `/messages`.
+#[derive(Debug, Deserialize)]
+struct RoomEvent {
+    /// Event type.
+    #[serde(rename = "type")]
+    event_type: String,
+    /// Event content; only parsed for `org.dmls.artifact` events.
+    content: serde_json::Value,
+}
+
+/// A page of the room timeline, as returned by `/messages`.
+#[derive(Debug, Deserialize)]
+struct MessagesPage {
+    /// Events of the page, in the requested direction.
+    chunk: Vec<RoomEvent>,
+    /// Token to request the next page with; absent at the end of the timeline.
+    end: Option<String>,
+}
+
+/// Artifacts addressed to the agent on one page of the room timeline.
+#[derive(Debug)]
+struct Page {
+    /// Token the page was requested from, if any.
+    from: Option<String>,
+    /// Decoded artifacts, in timeline order.
+    artifacts: Vec<Vec<u8>>,
+}
+
+/// Percent-encode a path segment, keeping only unreserved characters (RFC 3986, section 2.3).
+///
+/// Room ids (`!abc:example.org`) and aliases contain reserved characters.
+///
+/// Example:
+///
+/// ```ignore
+/// assert_eq!(encode_path_segment("!abc:example.org"), "%21abc%3Aexample.org");
+/// ```
+pub fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        if byte.is_ascii_alphanumeric() || b"-._~".contains(&byte) {
+            encoded.push(char::from(byte));
+        } else {
+            // writing to a string can't fail
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+/// Split a `<homeserver url>?room=<room id>` specification into the homeserver URL and room id.
+///
+/// Example:
+///
+/// ```ignore
+/// let (homeserver, room_id) = parse_spec("https://matrix.example.org?room=!abc:example.org")?;
+/// ```
+pub fn parse_spec(spec: &str) -> Result<(&str, &str), Box<dyn Error>> {
+    match spec.split_once("?room=") {
+        Some((homeserver, room_id)) if !homeserver.is_empty() && !room_id.is_empty() => {
+            Ok((homeserver, room_id))
+        }
+        _ => Err("Matrix transport needs `<homeserver url>?room=<room id>`".into()),
+    }
+}
+
+/// Transport over custom events in a Matrix room.
+pub struct MatrixTransport {
+    /// Base URL of the homeserver, without trailing slash.
+    homeserver: String,
+    /// Id of the room carrying the events.
+    room_id: String,
+    /// Access token of the agent's Matrix account.
+    token: String,
+    /// Counter making transaction ids unique within the process.
+    txn_counter: AtomicU64,
+    /// Pagination token to continue fetching from; shared with fetched `Blobs`.
+    cursor: Arc<Mutex<Option<String>>>,
+}
+
+impl Debug for MatrixTransport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("MatrixTransport")
+            .field("homeserver", &self.homeserver)
+            .field("room_id", &self.room_id)
+            .field("cursor", &self.cursor)
+            .finish_non_exhaustive()
+    }
+}
+
+impl MatrixTransport {
+    /// Creates a transport for a room on the homeserver at `homeserver`, using an access token.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let transport = MatrixTransport::new("https://matrix.example.org", "!abc:example.org", token);
+    /// ```
+    pub fn new(homeserver: &str, room_id: &str, token: String) -> Self {
+        Self {
+            homeserver: homeserver.trim_end_matches('/').to_string(),
+            room_id: room_id.to_string(),
+            token,
+            txn_counter: AtomicU64::new(0),
+            cursor: Arc::default(),
+        }
+    }
+
+    /// Creates a transport for a room on the homeserver at `homeserver`, with the access token
+    /// from `DMLS_MATRIX_TOKEN`.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let transport = MatrixTransport::connect("https://matrix.example.org", "!abc:example.org")?;
+    /// ```
+    pub fn connect(homeserver: &str, room_id: &str) -> Result<Self, Box<dyn Error>> {
+        let token = var(TOKEN_VAR).map_err(|e| format!("{TOKEN_VAR}: {e}"))?;
+        Ok(Self::new(homeserver, room_id, token))
+    }
+
+    /// Parse a `<homeserver url>?room=<room id>` specification (see `parse_spec`) and create a
+    /// transport for it.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let transport = MatrixTransport::from_spec("https://matrix.example.org?room=!abc:example.org")?;
+    /// ```
+    pub fn from_spec(spec: &str) -> Result<Self, Box<dyn Error>> {
+        let (homeserver, room_id) = parse_spec(spec)?;
+        Self::connect(homeserver, room_id)
+    }
+
+    /// Returns the key of the room's cursor in the state.
+    pub fn cursor_key(&self) -> String {
+        format!("matrix:{}", self.room_id)
+    }
+
+    /// Returns the URL of a client-server API endpoint of the room.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let url = transport.room_url("messages");
+    /// ```
+    pub fn room_url(&self, endpoint: &str) -> String {
+        format!(
+            "{}/_matrix/client/v3/rooms/{}/{endpoint}",
+            self.homeserver,
+            encode_path_segment(&self.room_id)
+        )
+    }
+
+    /// Returns a transaction id for sending an event that is unique for the access token.
+    fn txn_id(&self) -> Result<String, Box<dyn Error>> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let counter = self.txn_counter.fetch_add(1, Ordering::Relaxed);
+        Ok(format!("dmls-{now}-{counter}"))
+    }
+
+    /// Read the pages of the room timeline after `from`, keeping the artifacts addressed to
+    /// `recipient`; returns the pages with artifacts and the token after the last page.
+    fn read_pages(
+        &self,
+        from: Option<String>,
+        recipient: &str,
+    ) -> Result<(Vec<Page>, Option<String>), Box<dyn Error>> {
+        let filter = serde_json::json!({ "types": [EVENT_TYPE] }).to_string();
+        let mut pages = Vec::new();
+        let mut from = from;
+        loop {
+            let mut request = ureq::get(&self.room_url("messages"))
+                .header("Authorization", &format!("Bearer {}", self.token))
+                .query("dir", "f")
+                .query("limit", PAGE_LIMIT.to_string())
+                .query("filter", &filter);
+            if let Some(from) = &from {
+                request = request.query("from", from);
+            }
+            let page: MessagesPage = request.call()?.body_mut().read_json()?;
+            let mut artifacts = Vec::new();
+            for event in &page.chunk {
+                if event.event_type != EVENT_TYPE {
+                    continue;
+                }
+                // events of other applications may reuse the type; skip what doesn't parse
+                let Ok(content) = ArtifactEvent::deserialize(&event.content) else {
+                    continue;
+                };
+                if content.recipients.iter().any(|r| r == recipient) {
+                    artifacts.push(Base64.decode(content.artifact)?);
+                }
+            }
+            if !artifacts.is_empty() {
+                pages.push(Page {
+                    from: from.clone(),
+                    artifacts,
+                });
+            }
+            // the end of the timeline has no further token; continue from the last one then
+            match page.end {
+                Some(end) if !page.chunk.is_empty() => from = Some(end),
+                _ => return Ok((pages, from)),
+            }
+        }
+    }
+}
+
+impl Transport for MatrixTransport {
+    /// Send the artifact as an `org.dmls.artifact` event into the room.
+    fn send(&self, message: &str, recipients: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+        let content = ArtifactEvent {
+            artifact: Base64.encode(Blob.decode(message)?),
+            recipients: recipients
+                .iter()
+                .map(|recipient| Base64Url.encode(recipient))
+                .collect(),
+        };
+        let url = self.room_url(&format!("send/{EVENT_TYPE}/{}", self.txn_id()?));
+        ureq::put(&url)
+            .header("Authorization", &format!("Bearer {}", self.token))
+            .send_json(&content)?;
+        Ok(())
+    }
+    /// Read the events addressed to the inbox identity after the stored token; if there are
+    /// none, poll the room for up to `wait` seconds, continuing from the last page read.
+    fn fetch(&self, inbox: &[u8], wait: u64) -> Result<Blobs, Box<dyn Error>> {
+        let recipient = Base64Url.encode(inbox);
+        let deadline = Instant::now() + Duration::from_secs(wait);
+        let start = self.cursor.lock().map_err(|e| e.to_string())?.clone();
+        let (mut pages, mut end) = self.read_pages(start, &recipient)?;
+        while pages.is_empty() && Instant::now() < deadline {
+            sleep(POLL_INTERVAL);
+            (pages, end) = self.read_pages(end, &recipient)?;
+        }
+        let fetched = pages.iter().map(|page| page.artifacts.len()).sum::<usize>();
+        tracing::info!("Fetched {fetched} events from {}", self.room_id);
+        let cursor = Arc::clone(&self.cursor);
+        let last = Arc::clone(&self.cursor);
+        // the token advances as pages are handed to processing: to the start of the page being
+        // processed, and past the last page once all are
+        let artifacts = pages.into_iter().flat_map(move |page| {
+            let cursor = Arc::clone(&cursor);
+            page.artifacts.into_iter().map(move |artifact| {
+                if let Some(from) = &page.from {
+                    *cursor.lock().map_err(|e| e.to_string())? = Some(from.clone());
+                }
+                Ok::<_, Box<dyn Error>>(artifact)
+            })
+        });
+        Ok(Box::new(artifacts.chain(from_fn(move || {
+            if let Some(end) = end.take()
+                && let Ok(mut cursor) = last.lock()
+            {
+                *cursor = Some(end);
+            }
+            None
+        }))))
+    }
+    fn resume(&mut self, cursors: &BTreeMap<String, Cursor>, _group_ids: &[Vec<u8>]) {
+        if let Some(Cursor::Token(token)) = cursors.get(&self.cursor_key())
+            && let Ok(mut cursor) = self.cursor.lock()
+        {
+            *cursor = Some(token.clone());
+        }
+    }
+    fn cursors(&self) -> BTreeMap<String, Cursor> {
+        self.cursor
+            .lock()
+            .ok()
+            .and_then(|cursor| cursor.clone())
+            .map(|token| BTreeMap::from([(self.cursor_key(), Cursor::Token(token))]))
+            .unwrap_or_default()
+    }
+    /// The agent's own events are in the room's timeline too.
+    fn echoes(&self) -> bool {
+        true
+    }
+}
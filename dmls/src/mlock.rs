@@ -0,0 +1,127 @@
+//! Locking secrets in memory (`--features mlock`).
+//!
+//! Long-running agents (`serve`, `serve-grpc`, `process --listen` or `--follow`) keep the private
+//! signing key and the group secrets in memory for as long as they run, where the OS may swap them
+//! to disk. `lock_secrets` locks the pages holding the private key and the values of all secret
+//! OpenMLS entries (see `openmls_kvstore::SECRET_LABELS`) into memory with `mlock` (`VirtualLock`
+//! on Windows) and, where supported, excludes them from core dumps.
+//!
+//! Secrets written after the call (e.g. the next epoch's) are locked as the store writes them
+//! (see `relock`), and the values they replace or that are deleted are zeroed and unlocked before
+//! they are freed. When a write copies entries shared with a snapshot (see
+//! `OpenMlsKeyValueStore::snapshot`), the copies are locked too, and the entries the snapshot keeps
+//! are zeroed and unlocked once the last share of them is dropped; so are a dropped store's.
+//! `unlock_secrets` zeroes and unlocks the private key of a state that is done with (e.g. after a
+//! gRPC request) and drops it. Locks cover whole pages, so unlocking a value also unlocks whatever
+//! shares its pages, and transient copies OpenMLS makes while decoding secrets aren't locked at
+//! all. Locking can fail, most often because of the limit on locked memory (`ulimit -l`); failures
+//! are warned about, not fatal.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let (state, format) = load_state(state_path)?;
+//! let failed = lock_secrets(&state);
+//! // ...
+//! unlock_secrets(state);
+//! ```
+
+use super::state::DmlsState;
+
+/// Lock the pages holding `bytes` into memory and return whether that succeeded.
+///
+/// Example:
+///
+/// ```ignore
+/// if !lock(skp.private_key_raw()) { tracing::warn!("Private key not locked"); }
+/// ```
+pub fn lock(bytes: &[u8]) -> bool {
+    if bytes.is_empty() {
+        return true;
+    }
+    // SAFETY: `mlock` only changes how the pages are paged, not the bytes, which stay allocated
+    // while borrowed
+    unsafe { memsec::mlock(bytes.as_ptr().cast_mut(), bytes.len()) }
+}
+
+/// Zero `bytes` and unlock the pages holding them, returning whether that succeeded.
+///
+/// Example:
+///
+/// ```ignore
+/// unlock(replaced.into_bytes());
+/// ```
+pub fn unlock(mut bytes: Vec<u8>) -> bool {
+    unlock_in_place(&mut bytes)
+}
+
+/// Zero `bytes` in place and unlock the pages holding them, returning whether that succeeded.
+///
+/// Example:
+///
+/// ```ignore
+/// unlock_in_place(&mut secret);
+/// ```
+pub fn unlock_in_place(bytes: &mut [u8]) -> bool {
+    if bytes.is_empty() {
+        return true;
+    }
+    // SAFETY: the bytes are borrowed mutably, so nothing else reads them while `munlock` zeroes
+    // them
+    unsafe { memsec::munlock(bytes.as_mut_ptr(), bytes.len()) }
+}
+
+/// Lock a secret value the store just wrote (if any) into memory, and zero and unlock the value
+/// it replaced or deleted (if any) before that is freed.
+///
+/// Example:
+///
+/// ```ignore
+/// let replaced = values.insert(key.clone(), value);
+/// relock(values.get(&key), replaced);
+/// ```
+pub fn relock(stored: Option<&String>, replaced: Option<String>) {
+    if let Some(replaced) = replaced {
+        unlock(replaced.into_bytes());
+    }
+    if stored.is_some_and(|value| !lock(value.as_bytes())) {
+        tracing::warn!("Could not lock a new secret in memory; check `ulimit -l`");
+    }
+}
+
+/// Lock the private key and secret OpenMLS values of a state into memory and return how many of
+/// them couldn't be locked (warning about them).
+///
+/// Example:
+///
+/// ```ignore
+/// lock_secrets(provider.state());
+/// ```
+pub fn lock_secrets(state: &DmlsState) -> usize {
+    let mut failed = usize::from(!lock(state.signature_key_pair().private_key_raw()));
+    state
+        .openmls_values()
+        .for_each_secret_value(|value| failed += usize::from(!lock(value.as_bytes())));
+    if failed > 0 {
+        tracing::warn!("Could not lock {failed} secrets in memory; check `ulimit -l`");
+    }
+    failed
+}
+
+/// Zero and unlock the private key of a state whose secrets were locked by `lock_secrets`, and
+/// drop the state, which zeroes and unlocks its secret OpenMLS values.
+///
+/// Example:
+///
+/// ```ignore
+/// persist_state(state_path, &state, format, journal, None)?;
+/// unlock_secrets(state);
+/// ```
+pub fn unlock_secrets(state: DmlsState) {
+    let private_key = state.signature_key_pair().private_key_raw();
+    if !private_key.is_empty() {
+        // SAFETY: the state is owned and dropped right after, so nothing reads the key after
+        // `munlock` zeroes it
+        unsafe { memsec::munlock(private_key.as_ptr().cast_mut(), private_key.len()) };
+    }
+}
@@ -0,0 +1,278 @@
+//! NATS JetStream transport (`--features nats`).
+//!
+//! `NatsTransport` exchanges artifacts through a NATS server with JetStream enabled, which keeps
+//! them in a stream, so agents that are offline catch up when they next run `process`. Group
+//! messages (commits, proposals, application messages) are published to one subject per group,
+//! `dmls.group.<group id>`, and everything else (Welcomes, key packages, GroupInfos, envelopes)
+//! to the inbox subject of every recipient, `dmls.inbox.<identity>`; ids are unpadded base64url.
+//! All subjects belong to the `DMLS` stream, created on connect if it doesn't exist yet.
+//!
+//! Fetching reads the agent's inbox subject and the subjects of all its groups with ordered
+//! consumers, starting after the cursors stored in the state: the JetStream sequence number of
+//! the last message handed to processing on each subject (see `transport::Transport::cursors`).
+//! A restarted `process` thus resumes from the last acknowledged message instead of reading the
+//! stream from its start; messages seen twice anyway, including the agent's own messages it
+//! fetches back from its groups' subjects, are skipped by deduplication. As all subjects share
+//! one stream, fetched messages are handed out in stream order, so a Welcome comes before the
+//! commits of its group that were published after it, whatever the subjects. The subjects of
+//! groups joined while processing are fetched by `process` once the fetched messages are done.
+//!
+//! The transport uses the `async-nats` client, the maintained one, on a private single-threaded
+//! Tokio runtime, so the rest of the agent stays synchronous.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let mut transport = NatsTransport::connect("nats://localhost:4222")?;
+//! transport.send(&commit_b64, &member_identities(&sg))?;
+//! transport.resume(provider.state().transport_cursors(), &group_ids);
+//! for blob in transport.fetch(&own_identity(&provider), 5)? { /* ... */ }
+//! provider.state_mut().update_transport_cursors(transport.cursors());
+//! ```
+
+use super::{
+    encoding::Blob,
+    helpers::bytes_to_mls_msg_in,
+    transport::{Blobs, Cursor, Transport},
+};
+use async_nats::jetstream::{
+    self, Context,
+    consumer::{DeliverPolicy, pull::OrderedConfig},
+    stream::Config as StreamConfig,
+};
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD as Base64Url};
+use core::{
+    error::Error,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    time::Duration,
+};
+use futures::StreamExt;
+use openmls::framing::{MlsMessageBodyIn, ProtocolMessage};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tokio::{
+    runtime::{Builder, Runtime},
+    time::timeout,
+};
+
+/// Name of the JetStream stream holding all DMLS subjects.
+pub const STREAM_NAME: &str = "DMLS";
+
+/// Prefix of all DMLS subjects.
+pub const SUBJECT_PREFIX: &str = "dmls";
+
+/// How long a subject may stay silent before all its messages count as fetched.
+const IDLE_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// Returns the subject of a group's messages.
+pub fn group_subject(group_id: &[u8]) -> String {
+    format!("{SUBJECT_PREFIX}.group.{}", Base64Url.encode(group_id))
+}
+
+/// Returns the inbox subject of a credential identity.
+pub fn inbox_subject(identity: &[u8]) -> String {
+    format!("{SUBJECT_PREFIX}.inbox.{}", Base64Url.encode(identity))
+}
+
+/// Transport over the subjects of a NATS JetStream stream.
+pub struct NatsTransport {
+    /// URL of the NATS server.
+    url: String,
+    /// Runtime driving the client.
+    runtime: Runtime,
+    /// JetStream context of the connection.
+    jetstream: Context,
+    /// Groups whose subjects are fetched along with the inbox.
+    group_ids: Vec<Vec<u8>>,
+    /// Sequence number of the last message handed out per subject; shared with fetched `Blobs`.
+    cursors: Arc<Mutex<BTreeMap<String, u64>>>,
+}
+
+impl Debug for NatsTransport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.debug_struct("NatsTransport")
+            .field("url", &self.url)
+            .field("group_ids", &self.group_ids.len())
+            .field("cursors", &self.cursors)
+            .finish_non_exhaustive()
+    }
+}
+
+impl NatsTransport {
+    /// Connects to the NATS server at `url` and creates the `DMLS` stream if needed.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let transport = NatsTransport::connect("nats://localhost:4222")?;
+    /// ```
+    pub fn connect(url: &str) -> Result<Self, Box<dyn Error>> {
+        let runtime = Builder::new_current_thread().enable_all().build()?;
+        let jetstream = runtime.block_on(async {
+            let jetstream = jetstream::new(async_nats::connect(url).await?);
+            jetstream
+                .get_or_create_stream(StreamConfig {
+                    name: STREAM_NAME.to_string(),
+                    subjects: vec![format!("{SUBJECT_PREFIX}.>")],
+                    ..Default::default()
+                })
+                .await?;
+            Ok::<_, Box<dyn Error>>(jetstream)
+        })?;
+        tracing::info!("Connected to {url}");
+        Ok(Self {
+            url: url.to_string(),
+            runtime,
+            jetstream,
+            group_ids: Vec::new(),
+            cursors: Arc::default(),
+        })
+    }
+
+    /// Returns the URL of the NATS server.
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// Returns the subject a message goes to: that of its group for group messages, none
+    /// (recipients' inboxes) otherwise.
+    fn message_subject(message: &[u8]) -> Option<String> {
+        let protocol_message = match bytes_to_mls_msg_in(message).ok()?.extract() {
+            MlsMessageBodyIn::PublicMessage(m) => ProtocolMessage::from(m),
+            MlsMessageBodyIn::PrivateMessage(m) => ProtocolMessage::from(m),
+            _ => return None,
+        };
+        Some(group_subject(protocol_message.group_id().as_slice()))
+    }
+
+    /// Publish a message to a subject and wait for the stream to store it.
+    fn publish(&self, subject: &str, message: &[u8]) -> Result<(), Box<dyn Error>> {
+        self.runtime.block_on(async {
+            self.jetstream
+                .publish(subject.to_string(), message.to_vec().into())
+                .await?
+                .await?;
+            Ok(())
+        })
+    }
+
+    /// Read the messages on a subject after its cursor, until it stays silent for
+    /// `IDLE_TIMEOUT`.
+    fn read_subject(&self, subject: &str) -> Result<Vec<(u64, Vec<u8>)>, Box<dyn Error>> {
+        let cursor = self
+            .cursors
+            .lock()
+            .map_err(|e| e.to_string())?
+            .get(subject)
+            .copied();
+        let deliver_policy = match cursor {
+            Some(cursor) => DeliverPolicy::ByStartSequence {
+                start_sequence: cursor + 1,
+            },
+            None => DeliverPolicy::All,
+        };
+        self.runtime.block_on(async {
+            let stream = self.jetstream.get_stream(STREAM_NAME).await?;
+            let consumer = stream
+                .create_consumer(OrderedConfig {
+                    filter_subject: subject.to_string(),
+                    deliver_policy,
+                    ..Default::default()
+                })
+                .await?;
+            let mut subscription = consumer.messages().await?;
+            let mut messages = Vec::new();
+            // a timeout means the subject has no further messages for now
+            while let Ok(Some(message)) = timeout(IDLE_TIMEOUT, subscription.next()).await {
+                let message = message?;
+                let sequence = message.info()?.stream_sequence;
+                messages.push((sequence, message.payload.to_vec()));
+            }
+            Ok(messages)
+        })
+    }
+}
+
+impl Transport for NatsTransport {
+    /// Publish group messages to the group's subject and everything else to the recipients'
+    /// inbox subjects.
+    fn send(&self, message: &str, recipients: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+        let bytes = Blob.decode(message)?;
+        match Self::message_subject(&bytes) {
+            Some(subject) => self.publish(&subject, &bytes)?,
+            None => {
+                for recipient in recipients {
+                    self.publish(&inbox_subject(recipient), &bytes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Read the inbox subject and the subjects of the groups passed to `resume`, in stream
+    /// order; if all are empty, keep polling for up to `wait` seconds.
+    fn fetch(&self, inbox: &[u8], wait: u64) -> Result<Blobs, Box<dyn Error>> {
+        let subjects = [inbox_subject(inbox)]
+            .into_iter()
+            .chain(
+                self.group_ids
+                    .iter()
+                    .map(|group_id| group_subject(group_id)),
+            )
+            .collect::<Vec<_>>();
+        let deadline = Instant::now() + Duration::from_secs(wait);
+        let mut fetched = Vec::new();
+        loop {
+            for subject in &subjects {
+                for (sequence, data) in self.read_subject(subject)? {
+                    fetched.push((subject.clone(), sequence, data));
+                }
+            }
+            if !fetched.is_empty() || Instant::now() >= deadline {
+                break;
+            }
+        }
+        // the subjects share one stream, whose sequence numbers order all messages
+        fetched.sort_by_key(|(_, sequence, _)| *sequence);
+        tracing::info!("Fetched {} messages from {}", fetched.len(), self.url);
+        let cursors = Arc::clone(&self.cursors);
+        // cursors advance as messages are handed to processing, not when they are fetched
+        Ok(Box::new(fetched.into_iter().map(
+            move |(subject, sequence, data)| {
+                cursors
+                    .lock()
+                    .map_err(|e| e.to_string())?
+                    .insert(subject, sequence);
+                Ok(data)
+            },
+        )))
+    }
+    fn resume(&mut self, cursors: &BTreeMap<String, Cursor>, group_ids: &[Vec<u8>]) {
+        self.group_ids = group_ids.to_vec();
+        if let Ok(mut own) = self.cursors.lock() {
+            own.extend(cursors.iter().filter_map(|(subject, cursor)| match cursor {
+                Cursor::Sequence(sequence) if subject.starts_with(SUBJECT_PREFIX) => {
+                    Some((subject.clone(), *sequence))
+                }
+                _ => None,
+            }));
+        }
+    }
+    fn cursors(&self) -> BTreeMap<String, Cursor> {
+        self.cursors
+            .lock()
+            .map(|cursors| {
+                cursors
+                    .iter()
+                    .map(|(subject, &sequence)| (subject.clone(), Cursor::Sequence(sequence)))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// Members fetch their own group messages from the group subjects.
+    fn echoes(&self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,147 @@
+//! Passive observation of groups from their public handshake traffic.
+//!
+//! Delivery servers and auditors may need to track a group's membership and epoch without being
+//! members. An observer starts from a GroupInfo of the group (see
+//! `helpers::export_group_info_base64`) and then follows the group through its `PublicMessage`
+//! proposals and commits, so the group must use public handshakes (see
+//! `helpers::HandshakeWireFormat`). Only the public group state (ratchet tree and group context)
+//! is kept, using OpenMLS' `PublicGroup`; nothing can be decrypted.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let group_id = start(&mut provider, group_info, None)?;
+//! let observation = process(&mut provider, public_message.into())?;
+//! println!("epoch {}", observation.epoch);
+//! ```
+
+use super::{encoding::Blob, provider::DmlsProvider};
+use core::error::Error;
+use openmls::{
+    framing::{ProcessedMessageContent, ProtocolMessage},
+    group::{GroupId, MlsGroup},
+    messages::group_info::VerifiableGroupInfo,
+    prelude::{ProposalStore, PublicGroup},
+    treesync::RatchetTreeIn,
+};
+use openmls_traits::OpenMlsProvider;
+
+/// The public state of an observed group.
+///
+/// Ids are encoded with the wire blob encoding (see `encoding`).
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct Observation {
+    /// The observed group.
+    pub group_id: String,
+    /// Current epoch of the group.
+    pub epoch: u64,
+    /// Credential identities of the members.
+    pub members: Vec<String>,
+}
+
+impl Observation {
+    /// Summarize a public group.
+    fn of(group: &PublicGroup) -> Self {
+        Self {
+            group_id: Blob.encode(group.group_id().as_slice()),
+            epoch: group.group_context().epoch().as_u64(),
+            members: group
+                .members()
+                .map(|m| Blob.encode(m.credential.serialized_content()))
+                .collect(),
+        }
+    }
+}
+
+/// Start observing the group described by a GroupInfo.
+///
+/// The ratchet tree is taken from the GroupInfo's ratchet_tree extension unless given. The agent
+/// must not be a member of the group, whose state would be overwritten.
+///
+/// Example:
+///
+/// ```ignore
+/// let group_id = start(&mut provider, group_info, None)?;
+/// ```
+pub fn start(
+    provider: &mut DmlsProvider,
+    group_info: VerifiableGroupInfo,
+    ratchet_tree: Option<RatchetTreeIn>,
+) -> Result<GroupId, Box<dyn Error>> {
+    let group_id = group_info.group_id().clone();
+    if MlsGroup::load(provider.storage(), &group_id)?.is_some() {
+        return Err("Already a member of the group; it can't be observed".into());
+    }
+    let ratchet_tree = match ratchet_tree {
+        Some(ratchet_tree) => ratchet_tree,
+        None => group_info
+            .extensions()
+            .ratchet_tree()
+            .map(|extension| extension.ratchet_tree().clone())
+            .ok_or("GroupInfo carries no ratchet tree; provide it out of band")?,
+    };
+    PublicGroup::from_external(
+        provider.crypto(),
+        provider.storage(),
+        ratchet_tree,
+        group_info,
+        ProposalStore::new(),
+    )?;
+    provider.state_mut().add_observed_group(&group_id);
+    Ok(group_id)
+}
+
+/// Apply a public proposal or commit to the observed group it belongs to and return the group's
+/// new public state.
+///
+/// Example:
+///
+/// ```ignore
+/// let observation = process(&mut provider, public_message.into())?;
+/// ```
+pub fn process(
+    provider: &mut DmlsProvider,
+    message: ProtocolMessage,
+) -> Result<Observation, Box<dyn Error>> {
+    let group_id = message.group_id().clone();
+    if !provider.state().is_observed(&group_id) {
+        return Err("Message belongs to a group that isn't observed".into());
+    }
+    let mut group = PublicGroup::load(provider.storage(), &group_id)?
+        .ok_or("Observed group not found in storage")?;
+    match group
+        .process_message(provider.crypto(), message)?
+        .into_content()
+    {
+        ProcessedMessageContent::StagedCommitMessage(staged_commit) => {
+            group.merge_commit(provider.storage(), *staged_commit)?;
+        }
+        ProcessedMessageContent::ProposalMessage(proposal) => {
+            group.add_proposal(provider.storage(), *proposal)?;
+        }
+        _ => return Err("Only proposals and commits can be observed".into()),
+    }
+    Ok(Observation::of(&group))
+}
+
+/// Return the public state of every observed group.
+///
+/// Example:
+///
+/// ```ignore
+/// for observation in status(&provider)? {
+///     println!("{} at epoch {}", observation.group_id, observation.epoch);
+/// }
+/// ```
+pub fn status(provider: &DmlsProvider) -> Result<Vec<Observation>, Box<dyn Error>> {
+    provider
+        .state()
+        .observed_groups()
+        .map(
+            |group_id| match PublicGroup::load(provider.storage(), &group_id)? {
+                Some(group) => Ok(Observation::of(&group)),
+                None => Err("Observed group not found in storage".into()),
+            },
+        )
+        .collect()
+}
@@ -6,7 +6,10 @@
 //!
 //! The `SignatureKeyPair` type supports creation from an `OpenMlsCrypto` impl and exposes
 //! the raw bytes for signing operations. The `SignaturePublicKey` type provides a compact
-//! serializable representation suitable for storage and lookup.
+//! serializable representation suitable for storage and lookup. Ed25519 signing seeds can be
+//! backed up and restored as 24-word BIP39 mnemonics (`to_mnemonic`, `from_mnemonic`), and
+//! public keys exported as PEM, OpenSSH or JWK, with a stable fingerprint (`fingerprint`), for
+//! use with existing PKI and SSH-based trust tooling.
 //!
 //! Example (pseudo-Rust):
 //!
@@ -16,15 +19,65 @@
 //! let pub_key = skp.public_key();
 //! ```
 
-use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use super::redact::RedactedDebug;
+use base64::{
+    Engine,
+    engine::general_purpose::{STANDARD as Base64, URL_SAFE_NO_PAD as Base64Url},
+};
+use bip39::Mnemonic;
+use core::error::Error;
+use ed25519_dalek::SigningKey;
 use openmls_traits::{
     crypto::OpenMlsCrypto,
     storage::{CURRENT_VERSION, Entity, Key, traits},
-    types::{CryptoError, SignatureScheme},
+    types::{CryptoError, HashType, SignatureScheme},
 };
 use serde::{Deserialize, Serialize};
+use serde_json::json;
 use serde_with::{base64::Base64, serde_as};
-use tls_codec::{TlsDeserialize, TlsDeserializeBytes, TlsSerialize, TlsSize};
+use tls_codec::{
+    Serialize as _, TlsDeserialize, TlsDeserializeBytes, TlsSerialize, TlsSize, VLBytes,
+};
+
+/// Parse a signature scheme name: `Ed25519`, `Ed448`, `P256`, `P384` or `P521`.
+///
+/// Whether keys can be generated for the scheme depends on the crypto backend (see
+/// `SignatureKeyPair::from_crypto`).
+///
+/// Example:
+///
+/// ```ignore
+/// let scheme = parse_signature_scheme("Ed448").ok_or("unknown signature scheme")?;
+/// ```
+pub fn parse_signature_scheme(name: &str) -> Option<SignatureScheme> {
+    match name {
+        "Ed25519" => Some(SignatureScheme::ED25519),
+        "Ed448" => Some(SignatureScheme::ED448),
+        "P256" => Some(SignatureScheme::ECDSA_SECP256R1_SHA256),
+        "P384" => Some(SignatureScheme::ECDSA_SECP384R1_SHA384),
+        "P521" => Some(SignatureScheme::ECDSA_SECP521R1_SHA512),
+        _ => None,
+    }
+}
+
+/// Returns the name of a signature scheme as accepted by `parse_signature_scheme`, or its debug
+/// representation for other schemes.
+///
+/// Example:
+///
+/// ```ignore
+/// println!("{} key", signature_scheme_name(skp.signature_scheme()));
+/// ```
+pub fn signature_scheme_name(signature_scheme: SignatureScheme) -> String {
+    match signature_scheme {
+        SignatureScheme::ED25519 => "Ed25519".to_string(),
+        SignatureScheme::ED448 => "Ed448".to_string(),
+        SignatureScheme::ECDSA_SECP256R1_SHA256 => "P256".to_string(),
+        SignatureScheme::ECDSA_SECP384R1_SHA384 => "P384".to_string(),
+        SignatureScheme::ECDSA_SECP521R1_SHA512 => "P521".to_string(),
+        other => format!("{other:?}"),
+    }
+}
 
 /// A public signature key to be used instead of the default provided data structure.
 ///
@@ -74,6 +127,40 @@ impl SignaturePublicKey {
     pub fn value(&self) -> &[u8] {
         self.value.as_slice()
     }
+    /// Verifies a signature by this key over a payload.
+    ///
+    /// MLS artifacts are signed over a labeled payload (see `labeled_payload`), not over their
+    /// content directly.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// public_key.verify(&crypto, SignatureScheme::ED25519, payload, &signature)?;
+    /// ```
+    pub fn verify<T: OpenMlsCrypto>(
+        &self,
+        crypto: &T,
+        signature_scheme: SignatureScheme,
+        payload: &[u8],
+        signature: &[u8],
+    ) -> Result<(), CryptoError> {
+        crypto.verify_signature(signature_scheme, payload, &self.value, signature)
+    }
+}
+
+/// Build the payload MLS signs for a label and content (RFC 9420 `SignWithLabel`): the label
+/// prefixed with `MLS 1.0 ` and the content, each with a variable-length size prefix.
+///
+/// Example:
+///
+/// ```ignore
+/// let payload = labeled_payload("LeafNodeTBS", &leaf_node_tbs)?;
+/// ```
+pub fn labeled_payload(label: &str, content: &[u8]) -> Result<Vec<u8>, tls_codec::Error> {
+    let mut payload =
+        VLBytes::new(format!("MLS 1.0 {label}").into_bytes()).tls_serialize_detached()?;
+    payload.extend(VLBytes::new(content.to_vec()).tls_serialize_detached()?);
+    Ok(payload)
 }
 
 /// Create a `SignaturePublicKey` from raw bytes.
@@ -105,7 +192,7 @@ pub struct SignatureKeyPair {
 impl core::fmt::Debug for SignatureKeyPair {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         f.debug_struct("SignatureKeyPair")
-            .field("private", &Base64.encode(&self.private).to_string())
+            .field("private", &RedactedDebug(&self.private))
             .field("public", &Base64.encode(&self.public).to_string())
             .field("signature_scheme", &self.signature_scheme)
             .finish()
@@ -144,7 +231,9 @@ impl SignatureKeyPair {
     ///
     /// # Returns
     ///
-    /// A result containing the new `SignatureKeyPair` instance or a `CryptoError`.
+    /// A result containing the new `SignatureKeyPair` instance or a `CryptoError`;
+    /// `CryptoError::UnsupportedSignatureScheme` if the provider can't generate keys for the
+    /// scheme (e.g. Ed448 or P-521 with a backend lacking them).
     pub fn from_crypto<T: OpenMlsCrypto>(
         crypto: &T,
         signature_scheme: SignatureScheme,
@@ -158,6 +247,220 @@ impl SignatureKeyPair {
     }
 }
 
+/// BIP39 mnemonic backup of Ed25519 signing keys.
+///
+/// The 32-byte Ed25519 seed is used as the mnemonic's entropy, so it round-trips through a
+/// 24-word phrase; the public key is re-derived from the seed.
+impl SignatureKeyPair {
+    /// Encode the signing seed as a 24-word BIP39 mnemonic (Ed25519 keys only).
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// println!("{}", skp.to_mnemonic()?);
+    /// ```
+    pub fn to_mnemonic(&self) -> Result<String, Box<dyn Error>> {
+        if self.signature_scheme != SignatureScheme::ED25519 {
+            return Err(format!(
+                "Mnemonic backups only support Ed25519 keys, not {:?}",
+                self.signature_scheme
+            )
+            .into());
+        }
+        Ok(Mnemonic::from_entropy(&self.private)?.to_string())
+    }
+    /// Restore an Ed25519 key pair from a 24-word BIP39 mnemonic made by `to_mnemonic`.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let skp = SignatureKeyPair::from_mnemonic("abandon abandon ... art")?;
+    /// ```
+    pub fn from_mnemonic(phrase: &str) -> Result<Self, Box<dyn Error>> {
+        let seed: [u8; 32] = Mnemonic::parse_normalized(phrase.trim())?
+            .to_entropy()
+            .try_into()
+            .map_err(|_| "Mnemonic must have 24 words")?;
+        let public = SigningKey::from_bytes(&seed)
+            .verifying_key()
+            .to_bytes()
+            .to_vec();
+        Ok(Self::from_raw(
+            seed.to_vec(),
+            public,
+            SignatureScheme::ED25519,
+        ))
+    }
+}
+
+/// Object identifier of EC public keys (`id-ecPublicKey`, RFC 5480), DER content bytes.
+const EC_PUBLIC_KEY_OID: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+
+/// Encode a DER type-length-value.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    match u8::try_from(content.len()) {
+        Ok(len) if len < 0x80 => out.push(len),
+        _ => {
+            let len = content.len().to_be_bytes();
+            let len = &len[len.iter().take_while(|b| **b == 0).count()..];
+            out.push(0x80 | len.len() as u8);
+            out.extend(len);
+        }
+    }
+    out.extend(content);
+    out
+}
+
+/// Encode an SSH wire-format string (RFC 4251).
+fn ssh_string(data: &[u8]) -> Vec<u8> {
+    let mut out = (data.len() as u32).to_be_bytes().to_vec();
+    out.extend(data);
+    out
+}
+
+/// Encode bytes as unpadded RFC 4648 base32.
+fn base32(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let (mut buffer, mut bits) = (0u16, 0);
+    for byte in data {
+        buffer = (buffer << 8) | u16::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            out.push(ALPHABET[usize::from((buffer >> bits) & 0x1f)] as char);
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[usize::from((buffer << (5 - bits)) & 0x1f)] as char);
+    }
+    out
+}
+
+/// Export of the public key in standard formats.
+///
+/// EdDSA keys are exported as-is; ECDSA keys are the uncompressed SEC1 points produced by the
+/// crypto backends. OpenSSH has no Ed448 key type.
+impl SignatureKeyPair {
+    /// Returns the DER-encoded `SubjectPublicKeyInfo` (RFC 5280) of the public key.
+    fn subject_public_key_info(&self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let curve: &[u8] = match self.signature_scheme {
+            SignatureScheme::ED25519 => &[0x2b, 0x65, 0x70],
+            SignatureScheme::ED448 => &[0x2b, 0x65, 0x71],
+            SignatureScheme::ECDSA_SECP256R1_SHA256 => {
+                &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07]
+            }
+            SignatureScheme::ECDSA_SECP384R1_SHA384 => &[0x2b, 0x81, 0x04, 0x00, 0x22],
+            SignatureScheme::ECDSA_SECP521R1_SHA512 => &[0x2b, 0x81, 0x04, 0x00, 0x23],
+            other => return Err(format!("Cannot export {other:?} keys").into()),
+        };
+        let algorithm = match self.signature_scheme {
+            SignatureScheme::ED25519 | SignatureScheme::ED448 => der(0x06, curve),
+            _ => [der(0x06, EC_PUBLIC_KEY_OID), der(0x06, curve)].concat(),
+        };
+        let mut bits = vec![0];
+        bits.extend(&self.public);
+        Ok(der(
+            0x30,
+            &[der(0x30, &algorithm), der(0x03, &bits)].concat(),
+        ))
+    }
+    /// Export the public key as a PEM `PUBLIC KEY` block.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// std::fs::write("agent.pub.pem", skp.public_key_pem()?)?;
+    /// ```
+    pub fn public_key_pem(&self) -> Result<String, Box<dyn Error>> {
+        let body = Base64.encode(self.subject_public_key_info()?);
+        let lines = body
+            .as_bytes()
+            .chunks(64)
+            .map(core::str::from_utf8)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(format!(
+            "-----BEGIN PUBLIC KEY-----\n{}\n-----END PUBLIC KEY-----",
+            lines.join("\n")
+        ))
+    }
+    /// Export the public key as an OpenSSH `authorized_keys` line (without comment).
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// println!("{} dmls", skp.public_key_openssh()?);
+    /// ```
+    pub fn public_key_openssh(&self) -> Result<String, Box<dyn Error>> {
+        let (key_type, curve) = match self.signature_scheme {
+            SignatureScheme::ED25519 => ("ssh-ed25519", None),
+            SignatureScheme::ECDSA_SECP256R1_SHA256 => ("ecdsa-sha2-nistp256", Some("nistp256")),
+            SignatureScheme::ECDSA_SECP384R1_SHA384 => ("ecdsa-sha2-nistp384", Some("nistp384")),
+            SignatureScheme::ECDSA_SECP521R1_SHA512 => ("ecdsa-sha2-nistp521", Some("nistp521")),
+            other => return Err(format!("OpenSSH has no key type for {other:?}").into()),
+        };
+        let mut blob = ssh_string(key_type.as_bytes());
+        if let Some(curve) = curve {
+            blob.extend(ssh_string(curve.as_bytes()));
+        }
+        blob.extend(ssh_string(&self.public));
+        Ok(format!("{key_type} {}", Base64.encode(blob)))
+    }
+    /// Export the public key as a JSON Web Key (RFC 8037 for EdDSA, RFC 7518 for ECDSA).
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let jwk: serde_json::Value = serde_json::from_str(&skp.public_key_jwk()?)?;
+    /// ```
+    pub fn public_key_jwk(&self) -> Result<String, Box<dyn Error>> {
+        let crv = match self.signature_scheme {
+            SignatureScheme::ED25519 => "Ed25519",
+            SignatureScheme::ED448 => "Ed448",
+            SignatureScheme::ECDSA_SECP256R1_SHA256 => "P-256",
+            SignatureScheme::ECDSA_SECP384R1_SHA384 => "P-384",
+            SignatureScheme::ECDSA_SECP521R1_SHA512 => "P-521",
+            other => return Err(format!("Cannot export {other:?} keys").into()),
+        };
+        let jwk = match self.signature_scheme {
+            SignatureScheme::ED25519 | SignatureScheme::ED448 => json!({
+                "kty": "OKP",
+                "crv": crv,
+                "x": Base64Url.encode(&self.public),
+            }),
+            _ => {
+                let point = self
+                    .public
+                    .strip_prefix(&[0x04])
+                    .ok_or("Expected an uncompressed EC point")?;
+                let (x, y) = point.split_at(point.len() / 2);
+                json!({
+                    "kty": "EC",
+                    "crv": crv,
+                    "x": Base64Url.encode(x),
+                    "y": Base64Url.encode(y),
+                })
+            }
+        };
+        Ok(jwk.to_string())
+    }
+    /// Returns a stable fingerprint of the public key: `SHA256:` followed by the unpadded base32
+    /// SHA-256 hash of the raw public key.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// println!("{}", skp.fingerprint(&crypto)?);
+    /// ```
+    pub fn fingerprint(&self, crypto: &impl OpenMlsCrypto) -> Result<String, CryptoError> {
+        Ok(format!(
+            "SHA256:{}",
+            base32(&crypto.hash(HashType::Sha2_256, &self.public)?)
+        ))
+    }
+}
+
 /// Additional ergonomic constructors and helpers for `SignatureKeyPair`.
 impl SignatureKeyPair {
     /// Create a keypair directly from the cryptographic provider and return its base64-encoded
@@ -10,6 +10,22 @@
 //! - Encoding everything as base64 keeps the map string-only and avoids issues with binary keys/values.
 //! - The implementation focuses on correctness and readability for learning; it's not optimized for
 //!   production use or large-scale storage.
+//! - Keys written or deleted since the last `take_changes` are tracked, so callers can persist only
+//!   the changed entries (see `journal`) instead of the whole store.
+//! - Keys are also indexed by label, so all entries of one kind (e.g. every group state) can be
+//!   enumerated with `keys_for_label` without decoding the whole store; `entry_sizes` walks all
+//!   entries with their stored sizes, e.g. to measure a group's storage footprint.
+//! - A panic while the lock is held (e.g. in a caller's tracing subscriber) doesn't make the store
+//!   unusable: every write swaps in a complete entry, so the map is consistent whenever the lock is
+//!   released, and poisoned locks are recovered.
+//! - `snapshot` takes a cheap point-in-time view of the store that `restore` rolls back to, e.g.
+//!   to try a commit speculatively. The entries are shared copy-on-write, so a snapshot costs nothing
+//!   until the next write, which copies them once. With the `mlock` feature, the copy's secret
+//!   values are locked into memory, and the last share of the entries zeroes and unlocks them as
+//!   it is dropped (see `mlock`).
+//! - With the `compression` feature, large values (ratchet trees, message secrets) are stored
+//!   zstd-compressed behind a magic prefix; uncompressed entries still load, and compressed ones load
+//!   in builds without the feature.
 //!
 //! Example use (pseudo-Rust):
 //!
@@ -21,29 +37,224 @@
 //! let gs = store.group_state(&group_id)?;
 //! ```
 
+use super::redact::RedactedDebug;
 use base64::{Engine, engine::general_purpose::STANDARD as Base64};
-// use log;
 use openmls_traits::storage::{CURRENT_VERSION, Entity, StorageProvider, traits};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
-// use serde_json;
-use std::{collections::HashMap, sync::RwLock};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet, hash_map::Entry},
+    ops::{Deref, DerefMut},
+    sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard},
+};
+
+/// The entries of a store, shared copy-on-write between the store and its clones and snapshots.
+///
+/// With the `mlock` feature, copying the entries locks the copied secret values (see
+/// `SECRET_LABELS`) into memory, and dropping them zeroes and unlocks the secret values.
+#[derive(Debug, Default)]
+struct Entries(HashMap<String, String>);
+
+impl Deref for Entries {
+    type Target = HashMap<String, String>;
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for Entries {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
+impl Clone for Entries {
+    fn clone(&self) -> Self {
+        let entries = Self(self.0.clone());
+        lock_secret_values(&entries);
+        entries
+    }
+}
+
+impl Drop for Entries {
+    fn drop(&mut self) {
+        unlock_secret_values(self);
+    }
+}
+
+/// A string map that records which keys were modified since changes were last taken, and indexes
+/// its keys by storage label.
+///
+/// Reads go through `Deref`; `insert`, `remove` and `entry` mark their key as dirty. The entries
+/// are shared copy-on-write with clones and snapshots.
+#[derive(Clone, Debug, Default)]
+struct TrackedMap {
+    /// The entries.
+    map: Arc<Entries>,
+    /// Keys written or deleted since changes were last taken.
+    dirty: HashSet<String>,
+    /// Keys present in the map, by storage label.
+    labels: HashMap<&'static [u8], HashSet<String>>,
+}
+
+impl Deref for TrackedMap {
+    type Target = HashMap<String, String>;
+    fn deref(&self) -> &Self::Target {
+        &self.map
+    }
+}
+
+impl TrackedMap {
+    /// Wrap a map, building the label index.
+    fn from_map(map: HashMap<String, String>) -> Self {
+        let mut tracked = Self::default();
+        tracked.replace(Arc::new(Entries(map)));
+        tracked.dirty.clear();
+        tracked
+    }
+    /// Replace all entries, marking the keys whose value changed as dirty and rebuilding the
+    /// label index.
+    fn replace(&mut self, map: Arc<Entries>) {
+        if Arc::ptr_eq(&self.map, &map) {
+            return;
+        }
+        let changed = self
+            .map
+            .iter()
+            .filter(|(key, value)| map.get(*key) != Some(value))
+            .map(|(key, _)| key)
+            .chain(map.keys().filter(|key| !self.map.contains_key(*key)))
+            .cloned()
+            .collect::<Vec<_>>();
+        self.dirty.extend(changed);
+        self.labels.clear();
+        for key in map.keys() {
+            self.index(key);
+        }
+        self.map = map;
+    }
+    /// Add a key to the label index.
+    fn index(&mut self, key: &str) {
+        if let Some(label) = label_of(key) {
+            self.labels
+                .entry(label)
+                .or_default()
+                .insert(key.to_string());
+        }
+    }
+    /// Insert an entry, marking its key as dirty.
+    fn insert(&mut self, key: String, value: String) -> Option<String> {
+        self.dirty.insert(key.clone());
+        self.insert_untracked(key, value)
+    }
+    /// Insert an entry without marking its key as dirty.
+    fn insert_untracked(&mut self, key: String, value: String) -> Option<String> {
+        self.index(&key);
+        Arc::make_mut(&mut self.map).insert(key, value)
+    }
+    /// Remove an entry, marking its key as dirty.
+    fn remove(&mut self, key: &str) -> Option<String> {
+        self.dirty.insert(key.to_string());
+        self.remove_untracked(key)
+    }
+    /// Remove an entry without marking its key as dirty.
+    fn remove_untracked(&mut self, key: &str) -> Option<String> {
+        if let Some(keys) = label_of(key).and_then(|label| self.labels.get_mut(label)) {
+            keys.remove(key);
+        }
+        Arc::make_mut(&mut self.map).remove(key)
+    }
+    /// Get an entry for in-place modification, marking its key as dirty.
+    fn entry(&mut self, key: String) -> Entry<'_, String, String> {
+        self.dirty.insert(key.clone());
+        self.index(&key);
+        Arc::make_mut(&mut self.map).entry(key)
+    }
+    /// Append a value to the list stored at a key, creating the list if it doesn't exist.
+    fn push_to_list(
+        &mut self,
+        key: String,
+        value: Vec<u8>,
+    ) -> Result<(), OpenMlsKeyValueStoreError> {
+        let list_bytes = self.entry(key).or_insert("[]".to_owned());
+        let mut list: Vec<Vec<u8>> = serde_json::from_slice(&decode_value(list_bytes)?)?;
+        list.push(value);
+        // write back only once the new value is complete, so a failure leaves the list untouched
+        *list_bytes = encode_value(&serde_json::to_vec(&list)?);
+        Ok(())
+    }
+    /// Remove a value from the list stored at a key, if both exist.
+    fn remove_from_list(
+        &mut self,
+        key: String,
+        value: &[u8],
+    ) -> Result<(), OpenMlsKeyValueStoreError> {
+        let list_bytes = self.entry(key).or_insert("[]".to_owned());
+        let mut list: Vec<Vec<u8>> = serde_json::from_slice(&decode_value(list_bytes)?)?;
+        if let Some(pos) = list.iter().position(|stored_item| stored_item == value) {
+            list.remove(pos);
+        }
+        // write back only once the new value is complete, so a failure leaves the list untouched
+        *list_bytes = encode_value(&serde_json::to_vec(&list)?);
+        Ok(())
+    }
+}
+
+/// A point-in-time view of an `OpenMlsKeyValueStore` (see `OpenMlsKeyValueStore::snapshot`).
+#[derive(Clone)]
+pub struct StoreSnapshot {
+    /// The entries, shared with the store until it is next written.
+    map: Arc<Entries>,
+}
 
 /// A key-value store for OpenMLS state, using base64 encoding for all keys and values.
 ///
 /// This store is thread-safe and serializable, and is intended for use as a backend for the
 /// OpenMLS `StorageProvider` trait. All data is stored in a `HashMap<String, String>`, where both
 /// keys and values are base64-encoded. This allows for safe storage of binary data in a string-based map.
-#[derive(Debug, Default)]
+#[derive(Default)]
 pub struct OpenMlsKeyValueStore {
     /// The underlying map of base64-encoded keys and values, protected by a read-write lock for thread safety.
-    values: RwLock<HashMap<String, String>>,
+    values: RwLock<TrackedMap>,
 }
 
-/// Implements deep cloning for the key-value store, duplicating all stored data.
+/// Format entries sorted by key, with the values of secret entries (see `SECRET_LABELS`) redacted.
+fn fmt_entries(
+    name: &str,
+    map: &HashMap<String, String>,
+    f: &mut core::fmt::Formatter<'_>,
+) -> core::fmt::Result {
+    let entries = map
+        .iter()
+        .map(|(key, value)| {
+            let value = if is_secret(key) {
+                RedactedDebug(&Base64.decode(value).unwrap_or_default()).to_string()
+            } else {
+                value.clone()
+            };
+            (key, value)
+        })
+        .collect::<BTreeMap<_, _>>();
+    f.debug_struct(name).field("values", &entries).finish()
+}
+
+impl core::fmt::Debug for StoreSnapshot {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_entries("StoreSnapshot", &self.map, f)
+    }
+}
+
+impl core::fmt::Debug for OpenMlsKeyValueStore {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        fmt_entries("OpenMlsKeyValueStore", &self.values(), f)
+    }
+}
+
+/// Implements cloning for the key-value store; the entries are shared copy-on-write.
 impl Clone for OpenMlsKeyValueStore {
-    /// Creates a deep clone of the key-value store, including all stored key-value pairs.
+    /// Creates a clone of the key-value store; the stored key-value pairs are only copied once
+    /// either store is written.
     fn clone(&self) -> Self {
-        let values = self.values.read().unwrap();
+        let values = self.values();
         Self {
             values: RwLock::new(values.clone()),
         }
@@ -57,8 +268,8 @@ impl Serialize for OpenMlsKeyValueStore {
     where
         S: Serializer,
     {
-        let values = self.values.read().unwrap();
-        values.serialize(serializer)
+        let values = self.values();
+        values.map.0.serialize(serializer)
     }
 }
 
@@ -69,14 +280,182 @@ impl<'de> Deserialize<'de> for OpenMlsKeyValueStore {
     where
         D: Deserializer<'de>,
     {
-        let values = HashMap::deserialize(deserializer)?;
+        let map = HashMap::deserialize(deserializer)?;
         Ok(Self {
-            values: RwLock::new(values),
+            values: RwLock::new(TrackedMap::from_map(map)),
         })
     }
 }
 
 impl OpenMlsKeyValueStore {
+    /// Locks the entries for reading, recovering the lock if it was poisoned.
+    fn values(&self) -> RwLockReadGuard<'_, TrackedMap> {
+        self.values.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Locks the entries for writing, recovering the lock if it was poisoned.
+    ///
+    /// Recovery is sound because writers only ever replace whole entries: a panicking writer
+    /// leaves each entry either untouched or completely written.
+    fn values_mut(&self) -> RwLockWriteGuard<'_, TrackedMap> {
+        self.values.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Returns a point-in-time view of the store, for rolling back with `restore`.
+    ///
+    /// The entries are shared copy-on-write, so this is cheap however large the store is.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let snapshot = store.snapshot();
+    /// if group.merge_staged_commit(&provider, staged).is_err() {
+    ///     store.restore(&snapshot);
+    /// }
+    /// ```
+    pub fn snapshot(&self) -> StoreSnapshot {
+        StoreSnapshot {
+            map: self.values().map.clone(),
+        }
+    }
+
+    /// Rolls the store back to a snapshot (see `snapshot`).
+    ///
+    /// The entries that differ from the snapshot are tracked as changed, so journals and storage
+    /// backends pick up the rollback like any other write.
+    pub fn restore(&self, snapshot: &StoreSnapshot) {
+        self.values_mut().replace(snapshot.map.clone());
+    }
+
+    /// Returns the entries changed since the last call, and stops tracking them.
+    ///
+    /// Keys are mapped to their current (base64-encoded) value, or `None` if they were deleted.
+    pub fn take_changes(&self) -> BTreeMap<String, Option<String>> {
+        let mut values = self.values_mut();
+        let dirty = std::mem::take(&mut values.dirty);
+        dirty
+            .into_iter()
+            .map(|key| {
+                let value = values.map.get(&key).cloned();
+                (key, value)
+            })
+            .collect()
+    }
+
+    /// Applies changes taken from another store (see `take_changes`) without tracking them.
+    pub fn apply_changes(&self, changes: BTreeMap<String, Option<String>>) {
+        let mut values = self.values_mut();
+        for (key, value) in changes {
+            match value {
+                Some(value) => values.insert_untracked(key, value),
+                None => values.remove_untracked(&key),
+            };
+        }
+    }
+
+    /// Returns all entries, decoded (see `decode_changes`).
+    pub fn raw_entries(&self) -> Result<Vec<RawEntry>, OpenMlsKeyValueStoreError> {
+        let values = self.values();
+        decode_changes(
+            values
+                .map
+                .iter()
+                .map(|(key, value)| (key.clone(), Some(value.clone())))
+                .collect(),
+        )
+    }
+
+    /// Calls `f` with the encoded value of every entry holding secrets (see `SECRET_LABELS`) in
+    /// place, e.g. to lock it in memory (see `mlock`).
+    pub fn for_each_secret_value(&self, mut f: impl FnMut(&str)) {
+        let values = self.values();
+        values
+            .iter()
+            .filter(|(key, _)| is_secret(key))
+            .for_each(|(_, value)| f(value));
+    }
+
+    /// Applies decoded entries (see `raw_entries`) without tracking them.
+    pub fn apply_raw_entries(&self, entries: Vec<RawEntry>) {
+        self.apply_changes(
+            entries
+                .into_iter()
+                .map(|(label, key, value)| {
+                    let key = Base64.encode([label, &key].concat());
+                    (key, value.map(|value| encode_value(&value)))
+                })
+                .collect(),
+        );
+    }
+
+    /// Inserts (or, without a value, deletes) decoded entries, tracking them like any other write.
+    pub fn insert_raw_entries(&self, entries: Vec<RawEntry>) {
+        let mut values = self.values_mut();
+        for (label, key, value) in entries {
+            let key = Base64.encode([label, &key].concat());
+            match value {
+                Some(value) => values.insert(key, encode_value(&value)),
+                None => values.remove(&key),
+            };
+        }
+    }
+
+    /// Returns the keys of all entries stored under `label` (e.g. `b"GroupState"`), sorted.
+    ///
+    /// Keys are returned as serialized by OpenMLS (e.g. the JSON-encoded group id), without the
+    /// label and version; values are not decoded. Uses the label index, so it doesn't scan the
+    /// whole store.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// for key in store.keys_for_label(b"GroupState") {
+    ///     let group_id: GroupId = serde_json::from_slice(&key)?;
+    /// }
+    /// ```
+    pub fn keys_for_label(&self, label: &[u8]) -> Vec<Vec<u8>> {
+        let values = self.values();
+        let Some(keys) = values.labels.get(label) else {
+            return Vec::new();
+        };
+        let mut keys = keys
+            .iter()
+            .filter_map(|key| Base64.decode(key).ok())
+            .filter_map(|key| {
+                let key = key.strip_prefix(label)?;
+                key.get(..key.len().checked_sub(2)?).map(<[u8]>::to_vec)
+            })
+            .collect::<Vec<_>>();
+        keys.sort();
+        keys
+    }
+
+    /// Returns the label, key and stored size (in bytes, as persisted) of every entry with a known
+    /// label, without decoding the values.
+    ///
+    /// Keys are returned like by `keys_for_label`, without the label and version.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let total: usize = store.entry_sizes().iter().map(|(_, _, size)| size).sum();
+    /// ```
+    pub fn entry_sizes(&self) -> Vec<(&'static [u8], Vec<u8>, usize)> {
+        let values = self.values();
+        values
+            .labels
+            .iter()
+            .flat_map(|(label, keys)| keys.iter().map(move |key| (*label, key)))
+            .filter_map(|(label, key)| {
+                let size = key.len() + values.map.get(key)?.len();
+                let key = Base64.decode(key).ok()?;
+                let key = key.strip_prefix(label)?;
+                let key = key.get(..key.len().checked_sub(2)?)?.to_vec();
+                Some((label, key, size))
+            })
+            .collect()
+    }
+
     /// Writes a single value to the store, encoding both key and value as base64.
     /// Internal helper to abstract write operations.
     #[inline(always)]
@@ -95,12 +474,17 @@ impl OpenMlsKeyValueStore {
         key: &[u8],
         value: Vec<u8>,
     ) -> Result<(), <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let mut values = self.values.write().unwrap();
+        let _span =
+            tracing::trace_span!("storage", op = "write", label = %String::from_utf8_lossy(label))
+                .entered();
+        let mut values = self.values_mut();
         let storage_key = build_key_from_vec::<VERSION>(label, key.to_vec());
 
-        log::trace!("{}", std::backtrace::Backtrace::capture());
+        tracing::trace!("{}", std::backtrace::Backtrace::capture());
 
-        values.insert(Base64.encode(storage_key), Base64.encode(value));
+        let storage_key = Base64.encode(storage_key);
+        let replaced = values.insert(storage_key.clone(), encode_value(&value));
+        relock_secret(label, values.get(&storage_key), replaced);
         Ok(())
     }
 
@@ -121,67 +505,15 @@ impl OpenMlsKeyValueStore {
         key: &[u8],
         value: Vec<u8>,
     ) -> Result<(), <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let mut values = self.values.write().unwrap();
+        let _span =
+            tracing::trace_span!("storage", op = "append", label = %String::from_utf8_lossy(label))
+                .entered();
+        let mut values = self.values_mut();
         let storage_key = build_key_from_vec::<VERSION>(label, key.to_vec());
 
-        log::trace!("{}", std::backtrace::Backtrace::capture());
-
-        // fetch value from db, falling back to an empty list if doens't exist
-        let list_bytes = values
-            .entry(Base64.encode(storage_key))
-            .or_insert("[]".to_owned());
-
-        // parse old value and push new data
-        let mut list: Vec<Vec<u8>> = serde_json::from_slice(&Base64.decode(&list_bytes).unwrap())?;
-        list.push(value);
-
-        // write back, reusing the old buffer
-        list_bytes.truncate(0);
-        let encoded = Base64.encode(serde_json::to_vec(&list)?);
-        list_bytes.push_str(&encoded);
+        tracing::trace!("{}", std::backtrace::Backtrace::capture());
 
-        Ok(())
-    }
-
-    /// Removes a specific value from a list stored at the given label and key, using the provided version.
-    ///
-    /// If the value is found in the list, it is removed. If the list does not exist, nothing happens.
-    ///
-    /// # Arguments
-    /// * `label` - A byte slice representing the label for the key.
-    /// * `key` - A byte slice representing the key.
-    /// * `value` - The value to remove, as a vector of bytes.
-    ///
-    /// # Returns
-    /// * `Result<(), ...>` - Returns Ok on success, or an error if the operation fails.
-    fn remove_item<const VERSION: u16>(
-        &self,
-        label: &[u8],
-        key: &[u8],
-        value: Vec<u8>,
-    ) -> Result<(), <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let mut values = self.values.write().unwrap();
-        let storage_key = build_key_from_vec::<VERSION>(label, key.to_vec());
-
-        log::trace!("{}", std::backtrace::Backtrace::capture());
-
-        // fetch value from db, falling back to an empty list if doens't exist
-        let list_bytes = values
-            .entry(Base64.encode(storage_key))
-            .or_insert("[]".to_owned());
-
-        // parse old value, find value to delete and remove it from list
-        let mut list: Vec<Vec<u8>> = serde_json::from_slice(&Base64.decode(&list_bytes).unwrap())?;
-        if let Some(pos) = list.iter().position(|stored_item| stored_item == &value) {
-            list.remove(pos);
-        }
-
-        // write back, reusing the old buffer
-        list_bytes.truncate(0);
-        let encoded = Base64.encode(serde_json::to_vec(&list)?);
-        list_bytes.push_str(&encoded);
-
-        Ok(())
+        values.push_to_list(Base64.encode(storage_key), value)
     }
 
     /// Internal helper to abstract read operations.
@@ -199,15 +531,18 @@ impl OpenMlsKeyValueStore {
         label: &[u8],
         key: &[u8],
     ) -> Result<Option<V>, <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let values = self.values.read().unwrap();
+        let _span =
+            tracing::trace_span!("storage", op = "read", label = %String::from_utf8_lossy(label))
+                .entered();
+        let values = self.values();
         let storage_key = build_key_from_vec::<VERSION>(label, key.to_vec());
 
-        log::trace!("{}", std::backtrace::Backtrace::capture());
+        tracing::trace!("{}", std::backtrace::Backtrace::capture());
 
         let value = values.get(&Base64.encode(storage_key));
 
         if let Some(value) = value {
-            serde_json::from_slice(&Base64.decode(value).unwrap())
+            serde_json::from_slice(&decode_value(value)?)
                 .map_err(|_| OpenMlsKeyValueStoreError::SerializationError)
                 .map(|v| Some(v))
         } else {
@@ -230,18 +565,22 @@ impl OpenMlsKeyValueStore {
         label: &[u8],
         key: &[u8],
     ) -> Result<Vec<V>, <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let values = self.values.read().unwrap();
+        let _span = tracing::trace_span!(
+            "storage",
+            op = "read_list",
+            label = %String::from_utf8_lossy(label)
+        )
+        .entered();
+        let values = self.values();
 
         let mut storage_key = label.to_vec();
         storage_key.extend_from_slice(key);
         storage_key.extend_from_slice(&u16::to_be_bytes(VERSION));
 
-        log::trace!("{}", std::backtrace::Backtrace::capture());
+        tracing::trace!("{}", std::backtrace::Backtrace::capture());
 
         let value: Vec<Vec<u8>> = match values.get(&Base64.encode(storage_key)) {
-            Some(list_bytes) => {
-                serde_json::from_slice(&Base64.decode(list_bytes).unwrap()).unwrap()
-            }
+            Some(list_bytes) => serde_json::from_slice(&decode_value(list_bytes)?)?,
             None => vec![],
         };
 
@@ -267,20 +606,92 @@ impl OpenMlsKeyValueStore {
         label: &[u8],
         key: &[u8],
     ) -> Result<(), <Self as StorageProvider<CURRENT_VERSION>>::Error> {
-        let mut values = self.values.write().unwrap();
+        let _span =
+            tracing::trace_span!("storage", op = "delete", label = %String::from_utf8_lossy(label))
+                .entered();
+        let mut values = self.values_mut();
 
         let mut storage_key = label.to_vec();
         storage_key.extend_from_slice(key);
         storage_key.extend_from_slice(&u16::to_be_bytes(VERSION));
 
-        log::trace!("{}", std::backtrace::Backtrace::capture());
+        tracing::trace!("{}", std::backtrace::Backtrace::capture());
 
-        values.remove(&Base64.encode(storage_key));
+        let removed = values.remove(&Base64.encode(storage_key));
+        relock_secret(label, None, removed);
 
         Ok(())
     }
 }
 
+/// With the `mlock` feature, lock a secret value just written (see `SECRET_LABELS`) into memory
+/// and zero and unlock the value it replaced (see `mlock::relock`); otherwise, do nothing.
+#[cfg(feature = "mlock")]
+fn relock_secret(label: &[u8], stored: Option<&String>, replaced: Option<String>) {
+    if SECRET_LABELS.contains(&label) {
+        super::mlock::relock(stored, replaced);
+    }
+}
+
+/// With the `mlock` feature, lock a secret value just written (see `SECRET_LABELS`) into memory
+/// and zero and unlock the value it replaced (see `mlock::relock`); otherwise, do nothing.
+#[cfg(not(feature = "mlock"))]
+fn relock_secret(_: &[u8], _: Option<&String>, _: Option<String>) {}
+
+/// With the `mlock` feature, lock the secret values of copied entries into memory (see
+/// `mlock::lock`); otherwise, do nothing.
+#[cfg(feature = "mlock")]
+fn lock_secret_values(entries: &Entries) {
+    let failed = entries
+        .iter()
+        .filter(|(key, value)| is_secret(key) && !super::mlock::lock(value.as_bytes()))
+        .count();
+    if failed > 0 {
+        tracing::warn!("Could not lock {failed} copied secrets in memory; check `ulimit -l`");
+    }
+}
+
+/// With the `mlock` feature, lock the secret values of copied entries into memory (see
+/// `mlock::lock`); otherwise, do nothing.
+#[cfg(not(feature = "mlock"))]
+fn lock_secret_values(_: &Entries) {}
+
+/// With the `mlock` feature, zero and unlock the secret values of dropped entries (see
+/// `mlock::unlock_in_place`); otherwise, do nothing.
+#[cfg(feature = "mlock")]
+fn unlock_secret_values(entries: &mut Entries) {
+    for (_, value) in entries.iter_mut().filter(|(key, _)| is_secret(key)) {
+        // SAFETY: zeroed bytes are valid UTF-8
+        super::mlock::unlock_in_place(unsafe { value.as_bytes_mut() });
+    }
+}
+
+/// With the `mlock` feature, zero and unlock the secret values of dropped entries (see
+/// `mlock::unlock_in_place`); otherwise, do nothing.
+#[cfg(not(feature = "mlock"))]
+fn unlock_secret_values(_: &mut Entries) {}
+
+/// A decoded store entry: its label, its key (the serialized OpenMLS key followed by the storage
+/// version) and its serialized value, or `None` for a deleted entry.
+pub type RawEntry = (&'static [u8], Vec<u8>, Option<Vec<u8>>);
+
+/// Decodes changes taken from the store (see `take_changes`) into labels, keys and plain values.
+///
+/// Entries without a known label are skipped.
+pub fn decode_changes(
+    changes: BTreeMap<String, Option<String>>,
+) -> Result<Vec<RawEntry>, OpenMlsKeyValueStoreError> {
+    changes
+        .into_iter()
+        .filter_map(|(key, value)| {
+            let label = label_of(&key)?;
+            let key = Base64.decode(&key).ok()?.split_off(label.len());
+            let value = value.as_deref().map(decode_value).transpose();
+            Some(value.map(|value| (label, key, value)))
+        })
+        .collect()
+}
+
 /// Errors thrown by the key store.
 /// Errors that can be returned by the OpenMlsKeyValueStore.
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
@@ -301,45 +712,95 @@ impl core::fmt::Display for OpenMlsKeyValueStoreError {
 impl core::error::Error for OpenMlsKeyValueStoreError {}
 
 /// Label for key package storage.
-const KEY_PACKAGE_LABEL: &[u8] = b"KeyPackage";
+pub(crate) const KEY_PACKAGE_LABEL: &[u8] = b"KeyPackage";
 /// Label for pre-shared key (PSK) storage.
-const PSK_LABEL: &[u8] = b"Psk";
+pub(crate) const PSK_LABEL: &[u8] = b"Psk";
 /// Label for encryption key pair storage.
-const ENCRYPTION_KEY_PAIR_LABEL: &[u8] = b"EncryptionKeyPair";
+pub(crate) const ENCRYPTION_KEY_PAIR_LABEL: &[u8] = b"EncryptionKeyPair";
 /// Label for signature key pair storage.
-const SIGNATURE_KEY_PAIR_LABEL: &[u8] = b"SignatureKeyPair";
+pub(crate) const SIGNATURE_KEY_PAIR_LABEL: &[u8] = b"SignatureKeyPair";
 /// Label for epoch key pairs storage.
-const EPOCH_KEY_PAIRS_LABEL: &[u8] = b"EpochKeyPairs";
+pub(crate) const EPOCH_KEY_PAIRS_LABEL: &[u8] = b"EpochKeyPairs";
 
 // related to PublicGroup
 /// Label for tree storage (related to PublicGroup).
-const TREE_LABEL: &[u8] = b"Tree";
+pub(crate) const TREE_LABEL: &[u8] = b"Tree";
 /// Label for group context storage (related to PublicGroup).
-const GROUP_CONTEXT_LABEL: &[u8] = b"GroupContext";
+pub(crate) const GROUP_CONTEXT_LABEL: &[u8] = b"GroupContext";
 /// Label for interim transcript hash storage (related to PublicGroup).
-const INTERIM_TRANSCRIPT_HASH_LABEL: &[u8] = b"InterimTranscriptHash";
+pub(crate) const INTERIM_TRANSCRIPT_HASH_LABEL: &[u8] = b"InterimTranscriptHash";
 /// Label for confirmation tag storage (related to PublicGroup).
-const CONFIRMATION_TAG_LABEL: &[u8] = b"ConfirmationTag";
+pub(crate) const CONFIRMATION_TAG_LABEL: &[u8] = b"ConfirmationTag";
 
 // related to MlsGroup
 /// Label for MLS group join config storage (related to MlsGroup).
-const JOIN_CONFIG_LABEL: &[u8] = b"MlsGroupJoinConfig";
+pub(crate) const JOIN_CONFIG_LABEL: &[u8] = b"MlsGroupJoinConfig";
 /// Label for own leaf nodes storage (related to MlsGroup).
-const OWN_LEAF_NODES_LABEL: &[u8] = b"OwnLeafNodes";
+pub(crate) const OWN_LEAF_NODES_LABEL: &[u8] = b"OwnLeafNodes";
 /// Label for group state storage (related to MlsGroup).
-const GROUP_STATE_LABEL: &[u8] = b"GroupState";
+pub(crate) const GROUP_STATE_LABEL: &[u8] = b"GroupState";
 /// Label for queued proposal storage (related to MlsGroup).
-const QUEUED_PROPOSAL_LABEL: &[u8] = b"QueuedProposal";
+pub(crate) const QUEUED_PROPOSAL_LABEL: &[u8] = b"QueuedProposal";
 /// Label for proposal queue references storage (related to MlsGroup).
-const PROPOSAL_QUEUE_REFS_LABEL: &[u8] = b"ProposalQueueRefs";
+pub(crate) const PROPOSAL_QUEUE_REFS_LABEL: &[u8] = b"ProposalQueueRefs";
 /// Label for own leaf node index storage (related to MlsGroup).
-const OWN_LEAF_NODE_INDEX_LABEL: &[u8] = b"OwnLeafNodeIndex";
+pub(crate) const OWN_LEAF_NODE_INDEX_LABEL: &[u8] = b"OwnLeafNodeIndex";
 /// Label for epoch secrets storage (related to MlsGroup).
-const EPOCH_SECRETS_LABEL: &[u8] = b"EpochSecrets";
+pub(crate) const EPOCH_SECRETS_LABEL: &[u8] = b"EpochSecrets";
 /// Label for resumption PSK store (related to MlsGroup).
-const RESUMPTION_PSK_STORE_LABEL: &[u8] = b"ResumptionPsk";
+pub(crate) const RESUMPTION_PSK_STORE_LABEL: &[u8] = b"ResumptionPsk";
 /// Label for message secrets storage (related to MlsGroup).
-const MESSAGE_SECRETS_LABEL: &[u8] = b"MessageSecrets";
+pub(crate) const MESSAGE_SECRETS_LABEL: &[u8] = b"MessageSecrets";
+
+/// All storage labels, for the label index.
+pub(crate) const LABELS: &[&[u8]] = &[
+    KEY_PACKAGE_LABEL,
+    PSK_LABEL,
+    ENCRYPTION_KEY_PAIR_LABEL,
+    SIGNATURE_KEY_PAIR_LABEL,
+    EPOCH_KEY_PAIRS_LABEL,
+    TREE_LABEL,
+    GROUP_CONTEXT_LABEL,
+    INTERIM_TRANSCRIPT_HASH_LABEL,
+    CONFIRMATION_TAG_LABEL,
+    JOIN_CONFIG_LABEL,
+    OWN_LEAF_NODES_LABEL,
+    GROUP_STATE_LABEL,
+    QUEUED_PROPOSAL_LABEL,
+    PROPOSAL_QUEUE_REFS_LABEL,
+    OWN_LEAF_NODE_INDEX_LABEL,
+    EPOCH_SECRETS_LABEL,
+    RESUMPTION_PSK_STORE_LABEL,
+    MESSAGE_SECRETS_LABEL,
+];
+
+/// Labels of entries holding secrets: private keys, PSKs, epoch and message secrets, key packages
+/// (whose bundles include the private init and encryption keys) and group states (a pending
+/// commit carries the next epoch's secrets).
+pub(crate) const SECRET_LABELS: &[&[u8]] = &[
+    KEY_PACKAGE_LABEL,
+    GROUP_STATE_LABEL,
+    PSK_LABEL,
+    ENCRYPTION_KEY_PAIR_LABEL,
+    SIGNATURE_KEY_PAIR_LABEL,
+    EPOCH_KEY_PAIRS_LABEL,
+    EPOCH_SECRETS_LABEL,
+    RESUMPTION_PSK_STORE_LABEL,
+    MESSAGE_SECRETS_LABEL,
+];
+
+/// Returns the label of a (base64-encoded) storage key, if it has a known one.
+///
+/// No label is a prefix of another, so the first match is the only one.
+fn label_of(key: &str) -> Option<&'static [u8]> {
+    let key = Base64.decode(key).ok()?;
+    LABELS.iter().copied().find(|label| key.starts_with(label))
+}
+
+/// Returns whether a (base64-encoded) storage key holds secrets (see `SECRET_LABELS`).
+fn is_secret(key: &str) -> bool {
+    label_of(key).is_some_and(|label| SECRET_LABELS.contains(&label))
+}
 
 impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
     type Error = OpenMlsKeyValueStoreError;
@@ -354,16 +815,22 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         proposal_ref: &ProposalRef,
         proposal: &QueuedProposal,
     ) -> Result<(), Self::Error> {
-        // write proposal to key (group_id, proposal_ref)
-        let key = serde_json::to_vec(&(group_id, proposal_ref))?;
-        let value = serde_json::to_vec(proposal)?;
-        self.write::<CURRENT_VERSION>(QUEUED_PROPOSAL_LABEL, &key, value)?;
-
-        // update proposal list for group_id
-        let key = serde_json::to_vec(group_id)?;
-        let value = serde_json::to_vec(proposal_ref)?;
-        self.append::<CURRENT_VERSION>(PROPOSAL_QUEUE_REFS_LABEL, &key, value)?;
-
+        // proposal at key (group_id, proposal_ref), and its ref in the list for group_id
+        let proposal_key = build_key_from_vec::<CURRENT_VERSION>(
+            QUEUED_PROPOSAL_LABEL,
+            serde_json::to_vec(&(group_id, proposal_ref))?,
+        );
+        let refs_key = build_key_from_vec::<CURRENT_VERSION>(
+            PROPOSAL_QUEUE_REFS_LABEL,
+            serde_json::to_vec(group_id)?,
+        );
+        let proposal = encode_value(&serde_json::to_vec(proposal)?);
+        let _span = tracing::trace_span!("storage", op = "queue_proposal").entered();
+        // both under one lock, so readers never see one without the other; the list update can
+        // fail, so it goes first
+        let mut values = self.values_mut();
+        values.push_to_list(Base64.encode(refs_key), serde_json::to_vec(proposal_ref)?)?;
+        values.insert(Base64.encode(proposal_key), proposal);
         Ok(())
     }
 
@@ -390,11 +857,11 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         group_id: &GroupId,
         interim_transcript_hash: &InterimTranscriptHash,
     ) -> Result<(), Self::Error> {
-        let mut values = self.values.write().unwrap();
+        let mut values = self.values_mut();
         let key = build_key::<CURRENT_VERSION, &GroupId>(INTERIM_TRANSCRIPT_HASH_LABEL, group_id);
         let value = serde_json::to_vec(&interim_transcript_hash).unwrap();
 
-        values.insert(Base64.encode(key), Base64.encode(value));
+        values.insert(Base64.encode(key), encode_value(&value));
         Ok(())
     }
 
@@ -406,11 +873,11 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         group_id: &GroupId,
         group_context: &GroupContext,
     ) -> Result<(), Self::Error> {
-        let mut values = self.values.write().unwrap();
+        let mut values = self.values_mut();
         let key = build_key::<CURRENT_VERSION, &GroupId>(GROUP_CONTEXT_LABEL, group_id);
         let value = serde_json::to_vec(&group_context).unwrap();
 
-        values.insert(Base64.encode(key), Base64.encode(value));
+        values.insert(Base64.encode(key), encode_value(&value));
         Ok(())
     }
 
@@ -422,11 +889,11 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         group_id: &GroupId,
         confirmation_tag: &ConfirmationTag,
     ) -> Result<(), Self::Error> {
-        let mut values = self.values.write().unwrap();
+        let mut values = self.values_mut();
         let key = build_key::<CURRENT_VERSION, &GroupId>(CONFIRMATION_TAG_LABEL, group_id);
         let value = serde_json::to_vec(&confirmation_tag).unwrap();
 
-        values.insert(Base64.encode(key), Base64.encode(value));
+        values.insert(Base64.encode(key), encode_value(&value));
         Ok(())
     }
 
@@ -438,12 +905,12 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         public_key: &SignaturePublicKey,
         signature_key_pair: &SignatureKeyPair,
     ) -> Result<(), Self::Error> {
-        let mut values = self.values.write().unwrap();
+        let mut values = self.values_mut();
         let key =
             build_key::<CURRENT_VERSION, &SignaturePublicKey>(SIGNATURE_KEY_PAIR_LABEL, public_key);
         let value = serde_json::to_vec(&signature_key_pair).unwrap();
 
-        values.insert(Base64.encode(key), Base64.encode(value));
+        values.insert(Base64.encode(key), encode_value(&value));
         Ok(())
     }
 
@@ -468,13 +935,18 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         let refs: Vec<ProposalRef> =
             self.read_list(PROPOSAL_QUEUE_REFS_LABEL, &serde_json::to_vec(group_id)?)?;
 
+        // a proposal removed concurrently may be gone while its ref was still listed; skip it
         refs.into_iter()
-            .map(|proposal_ref| -> Result<_, _> {
+            .filter_map(|proposal_ref| {
                 let key = (group_id, &proposal_ref);
-                let key = serde_json::to_vec(&key)?;
-
-                let proposal = self.read(QUEUED_PROPOSAL_LABEL, &key)?.unwrap();
-                Ok((proposal_ref, proposal))
+                let key = match serde_json::to_vec(&key) {
+                    Ok(key) => key,
+                    Err(e) => return Some(Err(e.into())),
+                };
+
+                self.read(QUEUED_PROPOSAL_LABEL, &key)
+                    .transpose()
+                    .map(|proposal| proposal.map(|proposal| (proposal_ref, proposal)))
             })
             .collect::<Result<Vec<_>, _>>()
     }
@@ -486,13 +958,13 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         &self,
         group_id: &GroupId,
     ) -> Result<Option<TreeSync>, Self::Error> {
-        let values = self.values.read().unwrap();
+        let values = self.values();
         let key = build_key::<CURRENT_VERSION, &GroupId>(TREE_LABEL, group_id);
 
         let Some(value) = values.get(&Base64.encode(key)) else {
             return Ok(None);
         };
-        let value = serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap();
+        let value = serde_json::from_slice(&decode_value(value)?).unwrap();
 
         Ok(value)
     }
@@ -504,13 +976,13 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         &self,
         group_id: &GroupId,
     ) -> Result<Option<GroupContext>, Self::Error> {
-        let values = self.values.read().unwrap();
+        let values = self.values();
         let key = build_key::<CURRENT_VERSION, &GroupId>(GROUP_CONTEXT_LABEL, group_id);
 
         let Some(value) = values.get(&Base64.encode(key)) else {
             return Ok(None);
         };
-        let value = serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap();
+        let value = serde_json::from_slice(&decode_value(value)?).unwrap();
 
         Ok(value)
     }
@@ -522,13 +994,13 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         &self,
         group_id: &GroupId,
     ) -> Result<Option<InterimTranscriptHash>, Self::Error> {
-        let values = self.values.read().unwrap();
+        let values = self.values();
         let key = build_key::<CURRENT_VERSION, &GroupId>(INTERIM_TRANSCRIPT_HASH_LABEL, group_id);
 
         let Some(value) = values.get(&Base64.encode(key)) else {
             return Ok(None);
         };
-        let value = serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap();
+        let value = serde_json::from_slice(&decode_value(value)?).unwrap();
 
         Ok(value)
     }
@@ -540,13 +1012,13 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         &self,
         group_id: &GroupId,
     ) -> Result<Option<ConfirmationTag>, Self::Error> {
-        let values = self.values.read().unwrap();
+        let values = self.values();
         let key = build_key::<CURRENT_VERSION, &GroupId>(CONFIRMATION_TAG_LABEL, group_id);
 
         let Some(value) = values.get(&Base64.encode(key)) else {
             return Ok(None);
         };
-        let value = serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap();
+        let value = serde_json::from_slice(&decode_value(value)?).unwrap();
 
         Ok(value)
     }
@@ -558,7 +1030,7 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         &self,
         public_key: &SignaturePublicKey,
     ) -> Result<Option<SignatureKeyPair>, Self::Error> {
-        let values = self.values.read().unwrap();
+        let values = self.values();
 
         let key =
             build_key::<CURRENT_VERSION, &SignaturePublicKey>(SIGNATURE_KEY_PAIR_LABEL, public_key);
@@ -566,7 +1038,7 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         let Some(value) = values.get(&Base64.encode(key)) else {
             return Ok(None);
         };
-        let value = serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap();
+        let value = serde_json::from_slice(&decode_value(value)?).unwrap();
 
         Ok(value)
     }
@@ -858,7 +1330,7 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
     ) -> Result<(), Self::Error> {
         let key = epoch_key_pairs_id(group_id, epoch, leaf_index)?;
         let value = serde_json::to_vec(key_pairs)?;
-        log::debug!("Writing encryption epoch key pairs");
+        tracing::debug!("Writing encryption epoch key pairs");
 
         self.write::<CURRENT_VERSION>(EPOCH_KEY_PAIRS_LABEL, &key, value)
     }
@@ -875,13 +1347,13 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
     ) -> Result<Vec<HpkeKeyPair>, Self::Error> {
         let key = epoch_key_pairs_id(group_id, epoch, leaf_index)?;
         let storage_key = build_key_from_vec::<CURRENT_VERSION>(EPOCH_KEY_PAIRS_LABEL, key);
-        log::debug!("Reading encryption epoch key pairs");
+        tracing::debug!("Reading encryption epoch key pairs");
 
-        let values = self.values.read().unwrap();
+        let values = self.values();
         let value = values.get(&Base64.encode(storage_key));
 
         if let Some(value) = value {
-            return Ok(serde_json::from_slice(&Base64.decode(value).unwrap()).unwrap());
+            return Ok(serde_json::from_slice(&decode_value(value)?).unwrap());
         }
 
         Ok(vec![])
@@ -910,7 +1382,7 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         // Get all proposal refs for this group.
         let proposal_refs: Vec<ProposalRef> =
             self.read_list(PROPOSAL_QUEUE_REFS_LABEL, &serde_json::to_vec(group_id)?)?;
-        let mut values = self.values.write().unwrap();
+        let mut values = self.values_mut();
         for proposal_ref in proposal_refs {
             // Delete all proposals.
             let key = serde_json::to_vec(&(group_id, proposal_ref))?;
@@ -1027,13 +1499,21 @@ impl StorageProvider<CURRENT_VERSION> for OpenMlsKeyValueStore {
         group_id: &GroupId,
         proposal_ref: &ProposalRef,
     ) -> Result<(), Self::Error> {
-        let key = serde_json::to_vec(group_id).unwrap();
-        let value = serde_json::to_vec(proposal_ref).unwrap();
-
-        self.remove_item::<CURRENT_VERSION>(PROPOSAL_QUEUE_REFS_LABEL, &key, value)?;
-
-        let key = serde_json::to_vec(&(group_id, proposal_ref)).unwrap();
-        self.delete::<CURRENT_VERSION>(QUEUED_PROPOSAL_LABEL, &key)
+        let refs_key = build_key_from_vec::<CURRENT_VERSION>(
+            PROPOSAL_QUEUE_REFS_LABEL,
+            serde_json::to_vec(group_id)?,
+        );
+        let proposal_key = build_key_from_vec::<CURRENT_VERSION>(
+            QUEUED_PROPOSAL_LABEL,
+            serde_json::to_vec(&(group_id, proposal_ref))?,
+        );
+        let _span = tracing::trace_span!("storage", op = "remove_proposal").entered();
+        // both under one lock, so readers never see one without the other; the list update can
+        // fail, so it goes first
+        let mut values = self.values_mut();
+        values.remove_from_list(Base64.encode(refs_key), &serde_json::to_vec(proposal_ref)?)?;
+        values.remove(&Base64.encode(proposal_key));
+        Ok(())
     }
 }
 
@@ -1087,6 +1567,48 @@ fn epoch_key_pairs_id(
     Ok(key)
 }
 
+/// Prefix marking a zstd-compressed value; JSON values never start with a NUL byte, so entries
+/// written without compression are still read as is.
+const COMPRESSED_PREFIX: &[u8] = b"\0zstd";
+
+/// Values shorter than this are stored uncompressed; compression doesn't pay off for them.
+#[cfg(feature = "compression")]
+const COMPRESSION_THRESHOLD: usize = 256;
+
+/// Encodes a serialized value for insertion into the map.
+///
+/// With the `compression` feature, large values are zstd-compressed and prefixed with
+/// `COMPRESSED_PREFIX` before being base64-encoded.
+fn encode_value(value: &[u8]) -> String {
+    #[cfg(feature = "compression")]
+    if value.len() >= COMPRESSION_THRESHOLD {
+        match zstd::encode_all(value, zstd::DEFAULT_COMPRESSION_LEVEL) {
+            Ok(compressed) if compressed.len() + COMPRESSED_PREFIX.len() < value.len() => {
+                return Base64.encode([COMPRESSED_PREFIX, &compressed].concat());
+            }
+            Ok(_) => {}
+            Err(e) => tracing::warn!("Storing value uncompressed: {e}"),
+        }
+    }
+    Base64.encode(value)
+}
+
+/// Decodes a value read from the map, decompressing it if it carries `COMPRESSED_PREFIX`.
+///
+/// Compressed values are read with or without the `compression` feature.
+fn decode_value(value: &str) -> Result<Vec<u8>, OpenMlsKeyValueStoreError> {
+    let bytes = Base64
+        .decode(value)
+        .map_err(|_| OpenMlsKeyValueStoreError::SerializationError)?;
+    let Some(compressed) = bytes.strip_prefix(COMPRESSED_PREFIX) else {
+        return Ok(bytes);
+    };
+    zstd::decode_all(compressed).map_err(|e| {
+        tracing::error!("Error decompressing value: {e}");
+        OpenMlsKeyValueStoreError::SerializationError
+    })
+}
+
 /// Converts Serde JSON errors into OpenMlsKeyValueStoreError::SerializationError.
 impl From<serde_json::Error> for OpenMlsKeyValueStoreError {
     fn from(_: serde_json::Error) -> Self {
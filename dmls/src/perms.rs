@@ -0,0 +1,153 @@
+//! Permissions of files holding secrets.
+//!
+//! A state file holds the agent's private signing key and all group secrets, so on Unix it is
+//! created with mode `0600` by `write_private`, as are all other files that hold secrets or
+//! vouch for them: the journal (`append_private`), the integrity MAC (see `integrity`), the files
+//! and database of a storage backend (see `dir_storage` and `redb_storage`, whose directories are
+//! created with mode `0700` by `create_private_dir`) and storage exports (see `archive`); these
+//! are opened through `private_options`. Loading a state file or storage backend that is readable
+//! or writable by its group or others (`check`) only warns by default, as it may have been
+//! created before or copied with a loose umask; with strict permissions (`set_strict_perms`, the
+//! CLI's global `--strict-perms` flag) it fails instead. Existing files keep their mode: a warning
+//! is the cue to `chmod` them. On other platforms, files are written with the default
+//! permissions and never checked.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! set_strict_perms(true);
+//! check(state_path)?; // fails if the state file is e.g. 0644
+//! write_private(state_path, &state.to_bytes(format)?)?;
+//! ```
+
+use core::{
+    error::Error,
+    sync::atomic::{AtomicBool, Ordering},
+};
+#[cfg(unix)]
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::{
+    fs::{DirBuilder, OpenOptions, metadata},
+    io::{ErrorKind, Result as IoResult, Write},
+    path::Path,
+};
+
+/// Mode of newly created private files.
+#[cfg(unix)]
+const PRIVATE_MODE: u32 = 0o600;
+
+/// Mode of newly created private directories.
+#[cfg(unix)]
+const PRIVATE_DIR_MODE: u32 = 0o700;
+
+/// Whether `check` fails instead of warning (see `set_strict_perms`).
+static STRICT_PERMS: AtomicBool = AtomicBool::new(false);
+
+/// Make `check` fail on group- or world-accessible files instead of warning (`--strict-perms`).
+///
+/// Example:
+///
+/// ```ignore
+/// set_strict_perms(true);
+/// ```
+pub fn set_strict_perms(strict: bool) {
+    STRICT_PERMS.store(strict, Ordering::Relaxed);
+}
+
+/// Returns new options opening a file for writing, creating it (on Unix with mode `0600`) if it
+/// doesn't exist.
+///
+/// Example:
+///
+/// ```ignore
+/// let file = private_options().truncate(true).open(path)?;
+/// ```
+pub fn private_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.create(true).write(true);
+    #[cfg(unix)]
+    options.mode(PRIVATE_MODE);
+    options
+}
+
+/// Write a file holding secrets, replacing its content; a new file is only accessible by its
+/// owner (on Unix).
+///
+/// Example:
+///
+/// ```ignore
+/// write_private(state_path, &state.to_bytes(format)?)?;
+/// ```
+pub fn write_private(path: &str, contents: &[u8]) -> IoResult<()> {
+    private_options()
+        .truncate(true)
+        .open(path)?
+        .write_all(contents)
+}
+
+/// Create a directory holding secrets and its missing parents; new directories are only
+/// accessible by their owner (on Unix).
+///
+/// Example:
+///
+/// ```ignore
+/// create_private_dir("./alice-storage")?;
+/// ```
+pub fn create_private_dir(path: impl AsRef<Path>) -> IoResult<()> {
+    let mut builder = DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    builder.mode(PRIVATE_DIR_MODE);
+    builder.create(path)
+}
+
+/// Append to a file holding secrets; a new file is only accessible by its owner (on Unix).
+///
+/// Example:
+///
+/// ```ignore
+/// append_private(&journal_path(state_path), &line)?;
+/// ```
+pub fn append_private(path: &str, contents: &[u8]) -> IoResult<()> {
+    private_options()
+        .append(true)
+        .open(path)?
+        .write_all(contents)
+}
+
+/// Check that a file (or directory) holding secrets isn't accessible by its group or others (on
+/// Unix), warning or, with strict permissions, failing otherwise; a missing file passes.
+///
+/// Example:
+///
+/// ```ignore
+/// check(state_path)?;
+/// ```
+pub fn check(path: &str) -> Result<(), Box<dyn Error>> {
+    let metadata = match metadata(path) {
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(()),
+        metadata => metadata?,
+    };
+    #[cfg(unix)]
+    {
+        let mode = metadata.permissions().mode() & 0o777;
+        if mode & 0o077 != 0 {
+            let private = if metadata.is_dir() {
+                PRIVATE_DIR_MODE
+            } else {
+                PRIVATE_MODE
+            };
+            let message = format!(
+                "{path} holds private keys but has mode {mode:04o}; restrict it with \
+                 `chmod {private:o} {path}`"
+            );
+            if STRICT_PERMS.load(Ordering::Relaxed) {
+                return Err(super::error::DmlsError::InsecurePermissions(message).into());
+            }
+            tracing::warn!("{message}");
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = metadata;
+    Ok(())
+}
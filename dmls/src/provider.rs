@@ -1,7 +1,8 @@
 //! DMLS provider implementation wiring OpenMLS crypto and storage to application state.
 //!
 //! `DmlsProvider` is the glue between the `DmlsState` (persistent application state), the
-//! `OpenMlsKeyValueStore` (storage backend), and the cryptographic provider (`RustCrypto`).
+//! `OpenMlsKeyValueStore` (storage backend), the cryptographic provider (`CryptoBackend`), and the
+//! random number provider (`DmlsRand`, which can be seeded for reproducible runs).
 //! It implements the `OpenMlsProvider` trait required by the OpenMLS library and the `Signer`
 //! trait used when producing credentials or signing commits.
 //!
@@ -13,14 +14,17 @@
 //! let signature = provider.sign(payload)?;
 //! ```
 
-use super::{openmls_kvstore::OpenMlsKeyValueStore, state::DmlsState};
-use openmls_rust_crypto::RustCrypto;
+use super::{
+    crypto::CryptoBackend, openmls_kvstore::OpenMlsKeyValueStore, rand::DmlsRand, state::DmlsState,
+    validator::CredentialValidator,
+};
 use openmls_traits::{
     OpenMlsProvider,
     crypto::OpenMlsCrypto,
     signatures::{Signer, SignerError},
     types::SignatureScheme,
 };
+use std::sync::Arc;
 
 /// The main provider struct for DMLS, implementing the OpenMLS provider interface.
 ///
@@ -35,13 +39,29 @@ use openmls_traits::{
 pub struct DmlsProvider {
     /// The persistent DMLS state, including protocol version and key-value store.
     state: DmlsState,
-    /// The cryptographic backend (RustCrypto) for OpenMLS operations.
-    crypto: RustCrypto,
+    /// The cryptographic backend for OpenMLS operations.
+    crypto: CryptoBackend,
+    /// The random number provider for OpenMLS operations.
+    rand: DmlsRand,
+    /// Whether helpers may discard a pending commit (see `helpers::discard_pending_commit`).
+    force: bool,
+    /// Seconds by which clocks may differ when checking lifetimes (see `helpers::check_lifetime`).
+    clock_skew: u64,
+    /// Whether lifetimes are not checked at all (see `helpers::check_lifetime`).
+    ignore_lifetime: bool,
+    /// Application-defined credential validation (see `validator`).
+    credential_validator: Option<Arc<dyn CredentialValidator>>,
 }
 
+/// Default tolerance for clock differences when checking lifetimes, in seconds.
+pub const DEFAULT_CLOCK_SKEW: u64 = 300;
+
 #[allow(clippy::from_over_into)]
 impl Into<DmlsState> for DmlsProvider {
-    fn into(self) -> DmlsState {
+    fn into(mut self) -> DmlsState {
+        if let Some(seed) = self.rand.next_seed() {
+            self.state.set_rng_seed(seed);
+        }
         self.state
     }
 }
@@ -51,12 +71,65 @@ impl DmlsProvider {
     ///
     /// # Arguments
     /// * `state` - The persistent DMLS state.
-    /// * `crypto` - The cryptographic backend (RustCrypto).
+    /// * `crypto` - The cryptographic backend (e.g. `RustCrypto`, see `CryptoBackend`).
     ///
     /// # Returns
-    /// A new `DmlsProvider` instance.
-    pub fn new(state: DmlsState, crypto: RustCrypto) -> Self {
-        Self { state, crypto }
+    /// A new `DmlsProvider` instance, drawing from a seeded RNG if the state is a deterministic
+    /// test identity (see `DmlsState::new_deterministic`).
+    pub fn new(state: DmlsState, crypto: impl Into<CryptoBackend>) -> Self {
+        let rand = DmlsRand::default();
+        #[cfg(feature = "deterministic-rng")]
+        let rand = state.rng_seed().map_or(rand, DmlsRand::from_seed);
+        Self {
+            state,
+            crypto: crypto.into(),
+            rand,
+            force: false,
+            clock_skew: DEFAULT_CLOCK_SKEW,
+            ignore_lifetime: false,
+            credential_validator: None,
+        }
+    }
+    /// Replaces the random number provider (e.g. with a seeded one).
+    pub fn with_rand(mut self, rand: DmlsRand) -> Self {
+        self.rand = rand;
+        self
+    }
+    /// Allows helpers to discard a pending commit that may already have been sent.
+    pub fn with_force(mut self, force: bool) -> Self {
+        self.force = force;
+        self
+    }
+    /// Returns whether helpers may discard a pending commit.
+    pub fn force(&self) -> bool {
+        self.force
+    }
+    /// Sets the tolerance for clock differences when checking lifetimes, in seconds.
+    pub fn with_clock_skew(mut self, clock_skew: u64) -> Self {
+        self.clock_skew = clock_skew;
+        self
+    }
+    /// Returns the tolerance for clock differences when checking lifetimes, in seconds.
+    pub fn clock_skew(&self) -> u64 {
+        self.clock_skew
+    }
+    /// Skips lifetime checks, e.g. for testing with old key packages.
+    pub fn with_ignore_lifetime(mut self, ignore_lifetime: bool) -> Self {
+        self.ignore_lifetime = ignore_lifetime;
+        self
+    }
+    /// Returns whether lifetime checks are skipped.
+    pub fn ignore_lifetime(&self) -> bool {
+        self.ignore_lifetime
+    }
+    /// Sets the validator credentials are checked with before they are accepted.
+    pub fn with_credential_validator(mut self, validator: Arc<dyn CredentialValidator>) -> Self {
+        self.credential_validator = Some(validator);
+        self
+    }
+    /// Returns the validator credentials are checked with, if any.
+    pub fn credential_validator(&self) -> Option<&dyn CredentialValidator> {
+        self.credential_validator.as_deref()
     }
     /// Returns a reference to the internal DMLS state.
     pub fn state(&self) -> &DmlsState {
@@ -70,20 +143,20 @@ impl DmlsProvider {
 
 /// Implements the OpenMLS provider trait for DMLS, wiring up crypto, random, and storage providers.
 impl OpenMlsProvider for DmlsProvider {
-    type CryptoProvider = RustCrypto;
-    type RandProvider = RustCrypto;
+    type CryptoProvider = CryptoBackend;
+    type RandProvider = DmlsRand;
     type StorageProvider = OpenMlsKeyValueStore;
     /// Returns a reference to the OpenMLS storage provider (key-value store).
     fn storage(&self) -> &Self::StorageProvider {
         self.state.openmls_values()
     }
-    /// Returns a reference to the cryptographic backend (RustCrypto).
+    /// Returns a reference to the cryptographic backend.
     fn crypto(&self) -> &Self::CryptoProvider {
         &self.crypto
     }
-    /// Returns a reference to the random provider (RustCrypto).
+    /// Returns a reference to the random provider (DmlsRand).
     fn rand(&self) -> &Self::RandProvider {
-        &self.crypto
+        &self.rand
     }
 }
 
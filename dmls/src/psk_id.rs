@@ -0,0 +1,81 @@
+//! Exporter PSK ids.
+//!
+//! Every member of a group derives the exporter PSK of an epoch and stores it under an external
+//! PSK id naming its source. Ids are domain-separated and versioned, so they can't collide with
+//! external PSKs of other namespaces: `"dmls-v1" || ciphersuite || group id || epoch`, with the
+//! ciphersuite (2 bytes) and epoch (8 bytes) big endian. Ids of the legacy format, the epoch
+//! followed by the group id, are still parsed, so PSKs stored and history recorded before the
+//! format changed keep their source. New ids always use the current format, which all members of
+//! a group must agree on to process each other's PSK injections.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let psk_id = ExporterPskId::new(group.ciphersuite(), group.group_id(), group.epoch()).to_bytes();
+//! assert_eq!(ExporterPskId::parse(&psk_id).map(|id| id.epoch), Some(group.epoch().as_u64()));
+//! ```
+
+use openmls::group::{GroupEpoch, GroupId};
+use openmls_traits::types::Ciphersuite;
+
+/// Domain separator and version prefixing exporter PSK ids.
+pub const EXPORTER_PSK_ID_DOMAIN: &[u8] = b"dmls-v1";
+
+/// Source of an exporter PSK, as named by its id.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ExporterPskId {
+    /// Ciphersuite of the source group; `None` for legacy ids, which don't name it.
+    pub ciphersuite: Option<u16>,
+    /// Id of the source group.
+    pub group_id: Vec<u8>,
+    /// Source epoch.
+    pub epoch: u64,
+}
+
+impl ExporterPskId {
+    /// Returns the id of the exporter PSK of a group's epoch.
+    pub fn new(ciphersuite: Ciphersuite, group_id: &GroupId, epoch: GroupEpoch) -> Self {
+        Self {
+            ciphersuite: Some(ciphersuite.into()),
+            group_id: group_id.to_vec(),
+            epoch: epoch.as_u64(),
+        }
+    }
+
+    /// Encodes the id: in the current format, or in the legacy one if it names no ciphersuite.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self.ciphersuite {
+            Some(ciphersuite) => [
+                EXPORTER_PSK_ID_DOMAIN,
+                &ciphersuite.to_be_bytes(),
+                &self.group_id,
+                &self.epoch.to_be_bytes(),
+            ]
+            .concat(),
+            None => [&self.epoch.to_be_bytes(), self.group_id.as_slice()].concat(),
+        }
+    }
+
+    /// Parses an id in the current or the legacy format; `None` if it is too short for either.
+    ///
+    /// Any id of at least 8 bytes parses as a legacy id, so callers need to know that the id
+    /// belongs to an exporter PSK.
+    pub fn parse(psk_id: &[u8]) -> Option<Self> {
+        if let Some(rest) = psk_id.strip_prefix(EXPORTER_PSK_ID_DOMAIN)
+            && let Some((ciphersuite, rest)) = rest.split_first_chunk::<2>()
+            && let Some((group_id, epoch)) = rest.split_last_chunk::<8>()
+        {
+            return Some(Self {
+                ciphersuite: Some(u16::from_be_bytes(*ciphersuite)),
+                group_id: group_id.to_vec(),
+                epoch: u64::from_be_bytes(*epoch),
+            });
+        }
+        let (epoch, group_id) = psk_id.split_first_chunk::<8>()?;
+        Some(Self {
+            ciphersuite: None,
+            group_id: group_id.to_vec(),
+            epoch: u64::from_be_bytes(*epoch),
+        })
+    }
+}
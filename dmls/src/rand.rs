@@ -0,0 +1,108 @@
+//! Random number providers for DMLS.
+//!
+//! `DmlsRand` is the `OpenMlsRand` implementation used by `DmlsProvider`. By default it draws from
+//! the crypto backend's (OS-seeded) RNG. With the `deterministic-rng` feature, a seeded ChaCha20
+//! RNG can be plugged in instead, so that runs (e.g. `simulate --seed`) draw the same OpenMLS-level
+//! randomness every time: group ids, epoch/path/leaf secrets, init keys, nonces and reuse guards.
+//!
+//! Randomness used internally by the crypto backend is not covered by this provider. HPKE
+//! encapsulation in particular draws from the backend's own RNG, so HPKE ciphertexts (in Welcomes
+//! and in commits with a path update) differ between runs even with a seed. The backend also
+//! generates signature keys; seeded identities derive theirs from the seed instead (see
+//! `DmlsState::new_deterministic`).
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let provider = DmlsProvider::new(state, RustCrypto::default()).with_rand(DmlsRand::from_seed(42));
+//! let bytes = provider.rand().random_vec(32)?;
+//! ```
+
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::random::OpenMlsRand;
+#[cfg(feature = "deterministic-rng")]
+use rand_chacha::{
+    ChaCha20Rng,
+    rand_core::{RngCore, SeedableRng},
+};
+#[cfg(feature = "deterministic-rng")]
+use std::sync::RwLock;
+
+/// Source of randomness for OpenMLS operations.
+#[derive(Debug)]
+pub enum DmlsRand {
+    /// The crypto backend's RNG.
+    Crypto(RustCrypto),
+    /// A ChaCha20 RNG seeded from a fixed value.
+    #[cfg(feature = "deterministic-rng")]
+    Seeded(RwLock<ChaCha20Rng>),
+}
+
+impl Default for DmlsRand {
+    fn default() -> Self {
+        Self::Crypto(RustCrypto::default())
+    }
+}
+
+impl DmlsRand {
+    /// Creates a deterministic RNG from the given seed.
+    #[cfg(feature = "deterministic-rng")]
+    pub fn from_seed(seed: u64) -> Self {
+        Self::Seeded(RwLock::new(ChaCha20Rng::seed_from_u64(seed)))
+    }
+
+    /// Draws a seed to continue from in a later run, if this RNG is deterministic.
+    pub fn next_seed(&self) -> Option<u64> {
+        match self {
+            Self::Crypto(_) => None,
+            #[cfg(feature = "deterministic-rng")]
+            Self::Seeded(rng) => rng.write().ok().map(|mut rng| rng.next_u64()),
+        }
+    }
+}
+
+/// Errors returned by `DmlsRand`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DmlsRandError(String);
+
+impl core::fmt::Display for DmlsRandError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "Random number generation failed: {}", self.0)
+    }
+}
+
+impl core::error::Error for DmlsRandError {}
+
+impl OpenMlsRand for DmlsRand {
+    type Error = DmlsRandError;
+    fn random_array<const N: usize>(&self) -> Result<[u8; N], Self::Error> {
+        match self {
+            Self::Crypto(crypto) => crypto
+                .random_array()
+                .map_err(|e| DmlsRandError(e.to_string())),
+            #[cfg(feature = "deterministic-rng")]
+            Self::Seeded(rng) => {
+                let mut out = [0u8; N];
+                rng.write()
+                    .map_err(|e| DmlsRandError(e.to_string()))?
+                    .fill_bytes(&mut out);
+                Ok(out)
+            }
+        }
+    }
+    fn random_vec(&self, len: usize) -> Result<Vec<u8>, Self::Error> {
+        match self {
+            Self::Crypto(crypto) => crypto
+                .random_vec(len)
+                .map_err(|e| DmlsRandError(e.to_string())),
+            #[cfg(feature = "deterministic-rng")]
+            Self::Seeded(rng) => {
+                let mut out = vec![0u8; len];
+                rng.write()
+                    .map_err(|e| DmlsRandError(e.to_string()))?
+                    .fill_bytes(&mut out);
+                Ok(out)
+            }
+        }
+    }
+}
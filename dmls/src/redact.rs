@@ -0,0 +1,150 @@
+//! Redacted state exports.
+//!
+//! Bug reports are much easier to act on with the reporter's state attached, but a state file
+//! holds every private key and epoch secret of the agent. `redact` renders a state as JSON that
+//! keeps the group structure (trees, group contexts, epochs, join configs, queued proposals) and
+//! all public keys, with the OpenMLS values decoded for readability, while every secret is
+//! replaced by `redacted:<SHA-256 of the secret, base64>`. The hashes let maintainers tell
+//! whether two dumps hold the same secret without learning it.
+//!
+//! Entries holding secrets (see `openmls_kvstore::SECRET_LABELS`) are redacted by allow-list:
+//! only fields that are public by name (`public`, `public_key`, ..., and the key package itself)
+//! survive, everything else is hashed.
+//!
+//! Logs get the same treatment: the `Debug` output of `DmlsState`, `SignatureKeyPair` and the
+//! OpenMLS key-value store shows secrets as `RedactedDebug`, i.e. hashed, unless full secrets were
+//! explicitly requested with `set_log_secrets` (`--unsafe-log-secrets`).
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! println!("{}", serde_json::to_string_pretty(&redact(provider.state())?)?);
+//! tracing::debug!("Private key: {:?}", RedactedDebug(&private_key));
+//! ```
+
+use super::{
+    openmls_kvstore::SECRET_LABELS,
+    state::{DmlsState, StateFormat},
+};
+use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use core::{
+    error::Error,
+    fmt::{Debug, Display, Formatter, Result as FmtResult},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{crypto::OpenMlsCrypto, types::HashType};
+use serde_json::{Map, Value};
+
+/// Whether `RedactedDebug` shows secrets in full (see `set_log_secrets`).
+static LOG_SECRETS: AtomicBool = AtomicBool::new(false);
+
+/// Show secrets in full instead of hashed in `Debug` output and thus logs (`--unsafe-log-secrets`).
+///
+/// Example:
+///
+/// ```ignore
+/// set_log_secrets(true);
+/// ```
+pub fn set_log_secrets(log_secrets: bool) {
+    LOG_SECRETS.store(log_secrets, Ordering::Relaxed);
+}
+
+/// Returns whether secrets are shown in full in `Debug` output (see `set_log_secrets`).
+pub fn log_secrets() -> bool {
+    LOG_SECRETS.load(Ordering::Relaxed)
+}
+
+/// A secret formatted for logs: `redacted:<SHA-256 of the secret, base64>`, or the secret in
+/// base64 if `set_log_secrets` was called.
+///
+/// Example:
+///
+/// ```ignore
+/// tracing::debug!("Exporter PSK: {}", RedactedDebug(&psk));
+/// ```
+pub struct RedactedDebug<'a>(pub &'a [u8]);
+
+impl Display for RedactedDebug<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        if log_secrets() {
+            return write!(f, "{}", Base64.encode(self.0));
+        }
+        match RustCrypto::default().hash(HashType::Sha2_256, self.0) {
+            Err(_) => write!(f, "redacted"),
+            Ok(hash) => write!(f, "redacted:{}", Base64.encode(hash)),
+        }
+    }
+}
+
+impl Debug for RedactedDebug<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(f, "\"{self}\"")
+    }
+}
+
+/// Replace a value by the hash of its serialization.
+fn hashed(value: &Value) -> Result<Value, Box<dyn Error>> {
+    let hash = RustCrypto::default().hash(HashType::Sha2_256, &serde_json::to_vec(value)?)?;
+    Ok(Value::String(format!("redacted:{}", Base64.encode(hash))))
+}
+
+/// Returns whether a field holds public data, judging by its name.
+fn is_public(field: &str) -> bool {
+    field.contains("public") || field == "key_package" || field == "signature_scheme"
+}
+
+/// Redact everything in a value except the fields that are public by name.
+fn redact_value(value: &Value) -> Result<Value, Box<dyn Error>> {
+    match value {
+        Value::Object(fields) => {
+            let mut redacted = Map::new();
+            for (field, value) in fields {
+                let value = if is_public(field) {
+                    value.clone()
+                } else {
+                    redact_value(value)?
+                };
+                redacted.insert(field.clone(), value);
+            }
+            Ok(Value::Object(redacted))
+        }
+        Value::Array(items) if items.iter().all(Value::is_object) => Ok(Value::Array(
+            items.iter().map(redact_value).collect::<Result<_, _>>()?,
+        )),
+        value => hashed(value),
+    }
+}
+
+/// Render a state as JSON with all private keys and secrets redacted (see the module docs).
+///
+/// Example:
+///
+/// ```ignore
+/// let redacted = redact(provider.state())?;
+/// ```
+pub fn redact(state: &DmlsState) -> Result<Value, Box<dyn Error>> {
+    // the agent's bookkeeping, with its private signature key hashed
+    let mut json: Value =
+        serde_json::from_slice(&state.without_openmls_values().to_bytes(StateFormat::Json)?)?;
+    if let Some(private) = json.pointer_mut("/signature_key_pair/private") {
+        *private = hashed(private)?;
+    }
+    // the OpenMLS values, decoded and keyed by label and (base64) key
+    let mut values = Map::new();
+    for (label, key, value) in state.openmls_values().raw_entries()? {
+        let Some(value) = value else {
+            continue;
+        };
+        let value: Value = serde_json::from_slice(&value)?;
+        let value = if SECRET_LABELS.contains(&label) {
+            redact_value(&value)?
+        } else {
+            value
+        };
+        let name = format!("{}/{}", String::from_utf8_lossy(label), Base64.encode(&key));
+        values.insert(name, value);
+    }
+    json["openmls_values"] = Value::Object(values);
+    Ok(json)
+}
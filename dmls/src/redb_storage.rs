@@ -0,0 +1,100 @@
+//! Embedded database storage on redb.
+//!
+//! `RedbStore` keeps the OpenMLS entities in a single redb database file, with one table per
+//! storage label (e.g. `GroupState`, `Tree`) mapping serialized keys to JSON values. Every write
+//! is its own ACID transaction, so the database survives crashes without a separate journal.
+//!
+//! On the command line, `use-state --storage redb:<path>` keeps the OpenMLS values of a state in
+//! such a database; values still in the state file are migrated into it on first use (see
+//! `byte_store::attach`).
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let store = RedbStore::open("./alice.redb")?;
+//! attach(&store, state.openmls_values())?;
+//! ```
+
+use super::{
+    byte_store::{ByteStore, StorageError},
+    perms::private_options,
+};
+use redb::{Database, ReadableTable, TableDefinition, TableError};
+use std::path::Path;
+
+/// A byte store in a redb database, with one table per storage label.
+#[derive(Debug)]
+pub struct RedbStore {
+    /// The database.
+    db: Database,
+}
+
+/// Returns the table holding the entries of a storage label.
+fn table(label: &str) -> TableDefinition<'_, &'static [u8], &'static [u8]> {
+    TableDefinition::new(label)
+}
+
+/// Converts redb errors into StorageError::Backend.
+fn backend(e: impl Into<redb::Error>) -> StorageError {
+    StorageError::Backend(e.into().to_string())
+}
+
+impl RedbStore {
+    /// Opens the database at the given path, creating it if necessary.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        // redb initializes an empty file, which is created private first
+        private_options().open(path.as_ref())?;
+        Ok(Self {
+            db: Database::create(path).map_err(backend)?,
+        })
+    }
+}
+
+impl ByteStore for RedbStore {
+    fn get(&self, label: &[u8], key: &[u8]) -> Result<Option<Vec<u8>>, StorageError> {
+        let label = String::from_utf8_lossy(label);
+        let txn = self.db.begin_read().map_err(backend)?;
+        let table = match txn.open_table(table(&label)) {
+            Err(TableError::TableDoesNotExist(_)) => return Ok(None),
+            table => table.map_err(backend)?,
+        };
+        Ok(table
+            .get(key)
+            .map_err(backend)?
+            .map(|value| value.value().to_vec()))
+    }
+    fn put(&self, label: &[u8], key: &[u8], value: &[u8]) -> Result<(), StorageError> {
+        let label = String::from_utf8_lossy(label);
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(table(&label))
+            .map_err(backend)?
+            .insert(key, value)
+            .map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+    fn delete(&self, label: &[u8], key: &[u8]) -> Result<(), StorageError> {
+        let label = String::from_utf8_lossy(label);
+        let txn = self.db.begin_write().map_err(backend)?;
+        txn.open_table(table(&label))
+            .map_err(backend)?
+            .remove(key)
+            .map_err(backend)?;
+        txn.commit().map_err(backend)
+    }
+    fn entries(&self, label: &[u8]) -> Result<Vec<(Vec<u8>, Vec<u8>)>, StorageError> {
+        let label = String::from_utf8_lossy(label);
+        let txn = self.db.begin_read().map_err(backend)?;
+        let table = match txn.open_table(table(&label)) {
+            Err(TableError::TableDoesNotExist(_)) => return Ok(Vec::new()),
+            table => table.map_err(backend)?,
+        };
+        table
+            .iter()
+            .map_err(backend)?
+            .map(|entry| {
+                let (key, value) = entry.map_err(backend)?;
+                Ok((key.value().to_vec(), value.value().to_vec()))
+            })
+            .collect()
+    }
+}
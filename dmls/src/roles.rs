@@ -0,0 +1,197 @@
+//! Admin roles stored in the group context.
+//!
+//! Only admins may change a group's membership. The admins are listed by credential identity in a
+//! private-use group context extension (`ADMINS_EXTENSION_TYPE`), so every member agrees on them;
+//! a group without the extension has its creator (leaf 0) as sole admin. `authorize_commit` is the
+//! validation hook `helpers::apply_commit` runs before merging a received commit: commits adding
+//! or removing members, or changing the group context extensions, are rejected unless their sender
+//! is an admin. `set_admin` grants or revokes the role by committing a new extension, which every
+//! member's leaf must support (see `helpers::capabilities`).
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let commit = set_admin(&mut provider, &mut sg, &identity, true, ciphersuite, 32)?;
+//! assert!(is_admin(&sg, &identity)?);
+//! ```
+
+use super::{
+    encoding::Blob,
+    error::DmlsError,
+    helpers::{
+        discard_pending_commit, merge_pending_commit, own_identity, send_group, store_exporter_psk,
+    },
+    provider::DmlsProvider,
+};
+use core::error::Error;
+use openmls::{
+    extensions::{Extension, UnknownExtension},
+    framing::{MlsMessageOut, Sender},
+    group::{MlsGroup, StagedCommit},
+    messages::proposals::Proposal,
+    prelude::LeafNodeIndex,
+};
+use openmls_traits::{OpenMlsProvider, types::Ciphersuite};
+use tls_codec::{Deserialize, Serialize, VLBytes};
+
+/// Extension type of the admin list (private-use range).
+pub const ADMINS_EXTENSION_TYPE: u16 = 0xff0a;
+
+/// Return the credential identities of the group's admins.
+///
+/// Example:
+///
+/// ```ignore
+/// for admin in admins(&group)? {
+///     println!("{}", Blob.encode(admin));
+/// }
+/// ```
+pub fn admins(group: &MlsGroup) -> Result<Vec<Vec<u8>>, Box<dyn Error>> {
+    for extension in group.extensions().iter() {
+        if let Extension::Unknown(ADMINS_EXTENSION_TYPE, UnknownExtension(bytes)) = extension {
+            return Ok(Vec::<VLBytes>::tls_deserialize_exact(bytes)?
+                .into_iter()
+                .map(|identity| identity.as_slice().to_vec())
+                .collect());
+        }
+    }
+    // without the extension, the creator is the only admin
+    Ok(group
+        .member(LeafNodeIndex::new(0))
+        .map(|credential| vec![credential.serialized_content().to_vec()])
+        .unwrap_or_default())
+}
+
+/// Return whether the credential identity is an admin of the group.
+///
+/// Example:
+///
+/// ```ignore
+/// let admin = is_admin(&group, &own_identity(&provider))?;
+/// ```
+pub fn is_admin(group: &MlsGroup, identity: &[u8]) -> Result<bool, Box<dyn Error>> {
+    Ok(admins(group)?.iter().any(|admin| admin == identity))
+}
+
+/// Reject a received commit that changes membership or group context extensions unless its
+/// sender is an admin of the group (before the commit is merged).
+///
+/// Example:
+///
+/// ```ignore
+/// authorize_commit(&group, &sender, &staged_commit)?;
+/// group.merge_staged_commit(&provider, staged_commit)?;
+/// ```
+pub fn authorize_commit(
+    group: &MlsGroup,
+    sender: &Sender,
+    commit: &StagedCommit,
+) -> Result<(), Box<dyn Error>> {
+    let restricted = commit.queued_proposals().any(|proposal| {
+        matches!(
+            proposal.proposal(),
+            Proposal::Add(_) | Proposal::Remove(_) | Proposal::GroupContextExtensions(_)
+        )
+    });
+    if !restricted {
+        return Ok(());
+    }
+    let Sender::Member(leaf_index) = sender else {
+        return Err("Membership changes must be committed by a member".into());
+    };
+    let identity = group
+        .member(*leaf_index)
+        .ok_or("Commit sender is not a member of the group")?
+        .serialized_content();
+    if !is_admin(group, identity)? {
+        return Err(format!(
+            "Rejected membership change committed by non-admin {}",
+            Blob.encode(identity)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Grant (`admin`) or revoke the admin role of a credential identity, merge the commit and return
+/// it.
+///
+/// Only admins can change roles, and the group always keeps at least one admin.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit = set_admin(&mut provider, &mut sg, &identity, false, ciphersuite, 32)?;
+/// ```
+pub fn set_admin(
+    provider: &mut DmlsProvider,
+    group: &mut MlsGroup,
+    identity: &[u8],
+    admin: bool,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<MlsMessageOut, Box<dyn Error>> {
+    let mut admins = admins(group)?;
+    if !admins.contains(&own_identity(provider)) {
+        return Err(DmlsError::NotAdmin.into());
+    }
+    if admin {
+        if admins.iter().any(|a| a == identity) {
+            return Err(format!("{} is already an admin", Blob.encode(identity)).into());
+        }
+        admins.push(identity.to_vec());
+    } else {
+        let count = admins.len();
+        admins.retain(|a| a != identity);
+        if admins.len() == count {
+            return Err(format!("{} is not an admin", Blob.encode(identity)).into());
+        }
+        if admins.is_empty() {
+            return Err("The group must keep at least one admin".into());
+        }
+    }
+    let list: Vec<VLBytes> = admins.into_iter().map(VLBytes::from).collect();
+    let mut extensions = group.extensions().clone();
+    extensions.add_or_replace(Extension::Unknown(
+        ADMINS_EXTENSION_TYPE,
+        UnknownExtension(list.tls_serialize_detached()?),
+    ));
+    discard_pending_commit(provider, group)?;
+    group.clear_pending_proposals(provider.storage())?;
+    let (commit, _, _) = group.update_group_context_extensions(provider, extensions, provider)?;
+    merge_pending_commit(provider, group)?;
+    drop(store_exporter_psk(
+        provider,
+        group,
+        ciphersuite,
+        exporter_length,
+    )?);
+    Ok(commit)
+}
+
+/// Grant or revoke the admin role of a credential identity in the send group (see `set_admin`)
+/// and return the commit as base64.
+///
+/// Example:
+///
+/// ```ignore
+/// let commit_b64 = send_group_set_admin_base64(&mut provider, &identity, true, ciphersuite, 32)?;
+/// ```
+pub fn send_group_set_admin_base64(
+    provider: &mut DmlsProvider,
+    identity: &[u8],
+    admin: bool,
+    ciphersuite: Ciphersuite,
+    exporter_length: usize,
+) -> Result<String, Box<dyn Error>> {
+    let mut sg = send_group(provider)?;
+    let commit = set_admin(
+        provider,
+        &mut sg,
+        identity,
+        admin,
+        ciphersuite,
+        exporter_length,
+    )?;
+    Ok(Blob.encode(commit.tls_serialize_detached()?))
+}
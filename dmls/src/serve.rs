@@ -0,0 +1,161 @@
+//! Unix domain socket daemon mode.
+//!
+//! `serve` keeps the provider loaded in memory and answers newline-delimited JSON requests on a
+//! Unix socket, so local integrations can drive the agent without paying the full state load/save
+//! cost of a CLI invocation per operation. Connections are handled one at a time; each line on a
+//! connection is one request and is answered with exactly one JSON response line.
+//!
+//! Requests are tagged by `op`, mirroring the CLI commands:
+//!
+//! - `{"op":"gen-kp"}`
+//! - `{"op":"encrypt","plaintexts":["hi"],"group_id":null}`
+//! - `{"op":"process","messages":["<base64>"]}`
+//! - `{"op":"commit"}`
+//! - `{"op":"shutdown"}` stops the daemon (the state is saved by the caller)
+//!
+//! Responses are `{"ok":true,"output":[...]}` or `{"ok":false,"error":"..."}`, where `output`
+//! holds the produced base64 artifacts or, for `process`, the decrypted plaintexts.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! serve("/tmp/alice.sock", |request| match request {
+//!     ServeRequest::GenKp {} => Ok(vec![gen_recorded_kp_base64(&mut provider, ciphersuite)?]),
+//!     ...
+//! })?;
+//! ```
+
+use core::error::Error;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str as json_decode, to_string as json_encode};
+use std::{
+    fs::{remove_file, symlink_metadata},
+    io::{BufRead, BufReader, Write},
+    os::unix::{fs::FileTypeExt, net::UnixListener},
+};
+
+/// A request received on the daemon socket.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum ServeRequest {
+    /// Generate a key package.
+    GenKp {},
+    /// Encrypt plaintexts into application messages.
+    Encrypt {
+        /// Plaintexts to encrypt, one message each.
+        plaintexts: Vec<String>,
+        /// Group to encrypt in, as base64 (defaults to the send group).
+        #[serde(default)]
+        group_id: Option<String>,
+    },
+    /// Process base64 MLS messages.
+    Process {
+        /// Messages to process, in order.
+        messages: Vec<String>,
+    },
+    /// Inject queued PSKs into the send group.
+    Commit {},
+    /// Stop the daemon.
+    Shutdown {},
+}
+
+/// A response sent on the daemon socket.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ServeResponse {
+    /// Whether the request succeeded.
+    pub ok: bool,
+    /// Produced artifacts or plaintexts.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output: Vec<String>,
+    /// Error description, if the request failed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ServeResponse {
+    /// Creates a successful response carrying the given output.
+    pub fn ok(output: Vec<String>) -> Self {
+        Self {
+            ok: true,
+            output,
+            error: None,
+        }
+    }
+    /// Creates a failed response carrying the given error.
+    pub fn error(error: impl core::fmt::Display) -> Self {
+        Self {
+            ok: false,
+            output: Vec::new(),
+            error: Some(error.to_string()),
+        }
+    }
+}
+
+/// Listen on the Unix socket at `path` and answer requests with `handle` until a `shutdown`
+/// request is received.
+///
+/// A stale socket left at `path` by a previous run is replaced; any other existing file is an
+/// error. Errors on individual connections are logged and do not stop the daemon.
+///
+/// Example:
+///
+/// ```ignore
+/// serve(&socket_path, |request| handle_request(&mut provider, request))?;
+/// ```
+pub fn serve(
+    path: &str,
+    mut handle: impl FnMut(ServeRequest) -> Result<Vec<String>, Box<dyn Error>>,
+) -> Result<(), Box<dyn Error>> {
+    if let Ok(meta) = symlink_metadata(path) {
+        if !meta.file_type().is_socket() {
+            return Err(format!("{path} exists and is not a socket").into());
+        }
+        remove_file(path)?;
+    }
+    let listener = UnixListener::bind(path)?;
+    tracing::warn!("Serving on {path}");
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Err(e) => {
+                tracing::error!("Error accepting connection: {e}");
+                continue;
+            }
+            Ok(stream) => stream,
+        };
+        let mut writer = match stream.try_clone() {
+            Err(e) => {
+                tracing::error!("Error accepting connection: {e}");
+                continue;
+            }
+            Ok(writer) => writer,
+        };
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Err(e) => {
+                    tracing::error!("Error reading request: {e}");
+                    break;
+                }
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+            };
+            let (response, shutdown) = match json_decode::<ServeRequest>(&line) {
+                Err(e) => (ServeResponse::error(e), false),
+                Ok(ServeRequest::Shutdown {}) => (ServeResponse::ok(Vec::new()), true),
+                Ok(request) => match handle(request) {
+                    Err(e) => (ServeResponse::error(e), false),
+                    Ok(output) => (ServeResponse::ok(output), false),
+                },
+            };
+            if let Err(e) = writeln!(writer, "{}", json_encode(&response)?) {
+                tracing::error!("Error writing response: {e}");
+                break;
+            }
+            if shutdown {
+                tracing::warn!("Shutting down");
+                remove_file(path)?;
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,393 @@
+//! In-process scenario simulation.
+//!
+//! `simulate` runs a whole DMLS deployment inside one process: every participant gets its own
+//! in-memory `DmlsState` and `DmlsProvider`, and artifacts produced by one agent are delivered
+//! directly to the intended recipients, which process them exactly like `process` would. A
+//! transcript of all steps and decrypted messages is produced, and at the end every send group is
+//! checked for convergence (all members at the same epoch with the same epoch authenticator and
+//! member set). This is useful for demos and as regression coverage of the PSK-injection flow.
+//! With a seed (feature `deterministic-rng`), every agent is a deterministic identity (see
+//! `DmlsState::new_deterministic`): its signing key and its OpenMLS randomness derive from the
+//! seed, so e.g. the credentials and created group ids are reproducible. HPKE ciphertexts are not,
+//! as the crypto backend encrypts with its own RNG (see `rand`).
+//!
+//! Scenarios are YAML (or JSON, which is valid YAML):
+//!
+//! ```text
+//! participants: [alice, bob, carol]
+//! steps:
+//!   - add: { by: alice, members: [bob, carol] }   # creates alice's send group if needed
+//!   - add: { by: bob, members: [alice] }
+//!   - message: { from: alice, text: "hello" }
+//!   - update: alice
+//!   - commit: bob                                 # inject bob's queued exporter PSKs
+//!   - remove: { by: alice, members: [carol] }
+//! ```
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let scenario: Scenario = serde_yaml::from_str(&text)?;
+//! let mut sim = Simulation::new(&scenario.participants, ciphersuite, 32, None)?;
+//! for step in &scenario.steps {
+//!     sim.step(step)?;
+//! }
+//! sim.check_convergence()?;
+//! ```
+
+use super::{
+    crypto::CryptoBackend,
+    encoding::Blob,
+    helpers::{
+        GroupConfigOptions, add_members_base64, gen_kp_base64, gen_send_group, load_group,
+        member_identities, own_identity, process_body_main, remove_members_base64, send_group,
+        send_group_inject_psks_base64, send_group_update_base64, stdin_base64_extract,
+        stdin_base64_to_kp, stdin_create_message_base64,
+    },
+    openmls_keys::SignatureKeyPair,
+    provider::DmlsProvider,
+    state::{DmlsState, ExporterParams},
+};
+use core::error::Error;
+use openmls_traits::types::Ciphersuite;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// A simulation scenario.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    /// Names of the participants.
+    pub participants: Vec<String>,
+    /// Steps to run, in order.
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+/// A single scenario step.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    /// Add members to a participant's send group, creating the group if necessary.
+    Add {
+        /// Owner of the send group.
+        by: String,
+        /// Participants to add.
+        members: Vec<String>,
+    },
+    /// Self-update in a participant's send group.
+    Update(String),
+    /// Inject a participant's queued PSKs into its send group.
+    Commit(String),
+    /// Send an application message in a participant's send group.
+    Message {
+        /// Sender (owner of the send group).
+        from: String,
+        /// Plaintext to send.
+        text: String,
+    },
+    /// Remove members from a participant's send group.
+    Remove {
+        /// Owner of the send group.
+        by: String,
+        /// Participants to remove.
+        members: Vec<String>,
+    },
+}
+
+/// A set of in-process agents running a scenario.
+#[derive(Debug)]
+pub struct Simulation {
+    /// Agents by participant name.
+    agents: BTreeMap<String, DmlsProvider>,
+    /// Ciphersuite to use.
+    ciphersuite: Ciphersuite,
+    /// Exporter length to use for DMLS exporter PSK.
+    exporter_length: usize,
+    /// Transcript of everything that happened so far.
+    transcript: Vec<String>,
+}
+
+impl Simulation {
+    /// Creates a simulation with a fresh agent per participant.
+    ///
+    /// With a seed, the `i`-th participant is the deterministic identity of `seed + i`; seeds
+    /// require the `deterministic-rng` feature and an Ed25519 ciphersuite.
+    pub fn new(
+        participants: &[String],
+        ciphersuite: Ciphersuite,
+        exporter_length: usize,
+        seed: Option<u64>,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut agents = BTreeMap::new();
+        for (i, name) in participants.iter().enumerate() {
+            let crypto = CryptoBackend::for_ciphersuite(ciphersuite)?;
+            let state = match seed.map(|seed| seed.wrapping_add(i as u64)) {
+                None => DmlsState::new(SignatureKeyPair::from_crypto(
+                    &crypto,
+                    ciphersuite.signature_algorithm(),
+                )?),
+                // deterministic identities have Ed25519 signing keys
+                #[cfg(feature = "deterministic-rng")]
+                Some(_)
+                    if ciphersuite.signature_algorithm()
+                        != openmls_traits::types::SignatureScheme::ED25519 =>
+                {
+                    return Err("Seeds require an Ed25519 ciphersuite".into());
+                }
+                #[cfg(feature = "deterministic-rng")]
+                Some(seed) => DmlsState::new_deterministic(seed),
+                #[cfg(not(feature = "deterministic-rng"))]
+                Some(_) => return Err("Seeds require the deterministic-rng feature".into()),
+            };
+            // a deterministic identity's provider draws from its seeded RNG
+            agents.insert(name.clone(), DmlsProvider::new(state, crypto));
+        }
+        Ok(Self {
+            agents,
+            ciphersuite,
+            exporter_length,
+            transcript: Vec::new(),
+        })
+    }
+    /// Returns the transcript recorded so far.
+    pub fn transcript(&self) -> &[String] {
+        &self.transcript
+    }
+    /// Returns the agent of the given participant.
+    fn agent(&mut self, name: &str) -> Result<&mut DmlsProvider, Box<dyn Error>> {
+        self.agents
+            .get_mut(name)
+            .ok_or_else(|| format!("Unknown participant: {name}").into())
+    }
+    /// Returns the agents by participant name.
+    pub fn agents(&self) -> &BTreeMap<String, DmlsProvider> {
+        &self.agents
+    }
+    /// Returns the names of the other members of a participant's send group.
+    ///
+    /// Empty if the participant is unknown or has no send group.
+    pub fn members(&self, name: &str) -> Vec<String> {
+        let identities = match self.agents.get(name).map(send_group) {
+            Some(Ok(sg)) => member_identities(&sg),
+            _ => Vec::new(),
+        };
+        self.agents
+            .iter()
+            .filter(|(_, agent)| identities.contains(&own_identity(agent)))
+            .map(|(n, _)| n.clone())
+            .collect()
+    }
+    /// Returns the names of the other members of a participant's send group.
+    fn recipients(&mut self, name: &str) -> Result<Vec<String>, Box<dyn Error>> {
+        self.agent(name)?;
+        Ok(self.members(name))
+    }
+    /// Record a transcript line.
+    fn record(&mut self, line: String) {
+        tracing::info!("{line}");
+        self.transcript.push(line);
+    }
+}
+
+impl Simulation {
+    /// Deliver a base64 artifact from `from` to each of the recipients and process it there.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// sim.deliver("alice", &recipients, &commit_b64)?;
+    /// ```
+    fn deliver(
+        &mut self,
+        from: &str,
+        recipients: &[String],
+        message: &str,
+    ) -> Result<(), Box<dyn Error>> {
+        let (ciphersuite, exporter_length) = (self.ciphersuite, self.exporter_length);
+        for recipient in recipients {
+            let body = stdin_base64_extract(Ok(message.to_string()))?;
+            let agent = self.agent(recipient)?;
+            if let Some(message) = process_body_main(agent, body, ciphersuite, exporter_length) {
+                self.record(format!("  {recipient} <- {from}: {}", message.plaintext));
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a single scenario step, delivering all produced artifacts.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// sim.step(&Step::Update("alice".to_string()))?;
+    /// ```
+    pub fn step(&mut self, step: &Step) -> Result<(), Box<dyn Error>> {
+        let (ciphersuite, exporter_length) = (self.ciphersuite, self.exporter_length);
+        match step {
+            Step::Add { by, members } => {
+                self.record(format!("{by} adds {}", members.join(", ")));
+                let mut kps = Vec::new();
+                for member in members {
+                    let kp = gen_kp_base64(self.agent(member)?, ciphersuite)?;
+                    kps.push(stdin_base64_to_kp(self.agent(by)?, Ok(kp))?);
+                }
+                let recipients = self.recipients(by)?;
+                let agent = self.agent(by)?;
+                let (mut sg, created) = match send_group(agent) {
+                    Ok(sg) => (sg, false),
+                    Err(_) => (
+                        gen_send_group(
+                            agent,
+                            ciphersuite,
+                            &GroupConfigOptions {
+                                exporter: ExporterParams::new(exporter_length),
+                                ..Default::default()
+                            },
+                        )?,
+                        true,
+                    ),
+                };
+                let (commit, welcome) =
+                    add_members_base64(agent, &mut sg, &kps, ciphersuite, exporter_length)?;
+                if created {
+                    let group_id = Blob.encode(sg.group_id().as_slice());
+                    self.record(format!("  {by} created group {group_id}"));
+                }
+                self.deliver(by, &recipients, &commit)?;
+                self.deliver(by, members, &welcome)?;
+            }
+            Step::Update(by) => {
+                self.record(format!("{by} updates"));
+                let recipients = self.recipients(by)?;
+                let commit =
+                    send_group_update_base64(self.agent(by)?, false, ciphersuite, exporter_length)?;
+                self.deliver(by, &recipients, &commit)?;
+            }
+            Step::Commit(by) => {
+                let agent = self.agent(by)?;
+                let queued = agent.state().exporter_psk_ids().len();
+                let commit = send_group_inject_psks_base64(agent, ciphersuite, exporter_length)?;
+                self.record(format!("{by} commits {queued} queued PSKs"));
+                let recipients = self.recipients(by)?;
+                self.deliver(by, &recipients, &commit)?;
+            }
+            Step::Message { from, text } => {
+                self.record(format!("{from} sends: {text}"));
+                let recipients = self.recipients(from)?;
+                let agent = self.agent(from)?;
+                let mut sg = send_group(agent)?;
+                let msg = stdin_create_message_base64(agent, &mut sg, Ok(text.clone()))?;
+                self.deliver(from, &recipients, &msg)?;
+            }
+            Step::Remove { by, members } => {
+                self.record(format!("{by} removes {}", members.join(", ")));
+                let mut identities = Vec::new();
+                for member in members {
+                    identities.push(own_identity(self.agent(member)?));
+                }
+                let recipients = self.recipients(by)?;
+                let agent = self.agent(by)?;
+                let mut sg = send_group(agent)?;
+                let commit = remove_members_base64(
+                    agent,
+                    &mut sg,
+                    &identities,
+                    ciphersuite,
+                    exporter_length,
+                )?;
+                self.deliver(by, &recipients, &commit)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Run a scenario step speculatively: if it fails, every agent (and the transcript) is rolled
+    /// back to where it was before the step, and the error is returned.
+    ///
+    /// Rolling back uses storage snapshots (see `OpenMlsKeyValueStore::snapshot`), so it doesn't
+    /// copy the agents' stores.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// if sim.try_step(&step).is_err() {
+    ///     // the simulation is unchanged; carry on with the next step
+    /// }
+    /// ```
+    pub fn try_step(&mut self, step: &Step) -> Result<(), Box<dyn Error>> {
+        let snapshots = self
+            .agents
+            .values()
+            .map(|agent| {
+                let state = agent.state();
+                (state.meta().clone(), state.openmls_values().snapshot())
+            })
+            .collect::<Vec<_>>();
+        let transcript_len = self.transcript.len();
+        let result = self.step(step);
+        if result.is_err() {
+            for (agent, (meta, snapshot)) in self.agents.values_mut().zip(snapshots) {
+                agent.state().openmls_values().restore(&snapshot);
+                agent.state_mut().set_meta(meta);
+            }
+            self.transcript.truncate(transcript_len);
+        }
+        result
+    }
+
+    /// Check that every member's copy of every send group agrees with the owner's copy.
+    ///
+    /// Records one transcript line per send group and fails if any group diverged.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// sim.check_convergence()?;
+    /// ```
+    pub fn check_convergence(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut diverged = Vec::new();
+        let mut lines = Vec::new();
+        for (owner, agent) in &self.agents {
+            let Ok(sg) = send_group(agent) else {
+                continue;
+            };
+            let mut members = sg
+                .members()
+                .map(|m| m.credential.serialized_content().to_vec())
+                .collect::<Vec<_>>();
+            members.sort();
+            for (name, other) in &self.agents {
+                if name == owner || !members.contains(&own_identity(other)) {
+                    continue;
+                }
+                let converged = load_group(other, sg.group_id()).is_ok_and(|g| {
+                    let mut other_members = g
+                        .members()
+                        .map(|m| m.credential.serialized_content().to_vec())
+                        .collect::<Vec<_>>();
+                    other_members.sort();
+                    g.epoch() == sg.epoch()
+                        && g.epoch_authenticator().as_slice() == sg.epoch_authenticator().as_slice()
+                        && other_members == members
+                });
+                if !converged {
+                    diverged.push(format!("{name} in {owner}'s group"));
+                }
+            }
+            lines.push(format!(
+                "{owner}'s group: epoch {}, {} members",
+                sg.epoch().as_u64(),
+                members.len()
+            ));
+        }
+        for line in lines {
+            self.record(line);
+        }
+        if diverged.is_empty() {
+            self.record("all members converged".to_string());
+            Ok(())
+        } else {
+            Err(format!("Not converged: {}", diverged.join(", ")).into())
+        }
+    }
+}
@@ -1,12 +1,23 @@
 //! Persistent application state for a DMLS agent.
 //!
-//! `DmlsState` stores the local signing key pair, an optional send-group id (the group this agent
-//! uses for sending application messages), a queue of exporter PSK identifiers produced during commits,
-//! and the `OpenMlsKeyValueStore` that holds all OpenMLS group state and secrets.
+//! `DmlsState` stores:
 //!
-//! The state is serializable and designed to be written to disk (as a JSON file) between runs of the
-//! example agent; the CLI demonstrates writing and reading this JSON file to persist identity and
-//! group membership across invocations.
+//! - the local signing key pair;
+//! - an optional send-group id (the group this agent uses for sending application messages);
+//! - a queue of exporter PSK identifiers produced during commits;
+//! - an address book of key packages collected from peers;
+//! - an outbox of produced artifacts awaiting delivery acknowledgement;
+//! - activity counters (`DmlsStats`);
+//! - a hash-chained audit log (see `audit`);
+//! - the unconsumed key packages of the published pool (see `kp_pool`);
+//! - records of the key packages it generated (see `helpers::gen_recorded_kp_base64`);
+//! - hashes of recently processed messages (for deduplication);
+//! - the `OpenMlsKeyValueStore` that holds all OpenMLS group state and secrets.
+//!
+//! The state is serializable and designed to be written to disk (as a JSON file by default, or as
+//! CBOR or MessagePack, see `StateFormat`) between runs of the example agent; the CLI demonstrates
+//! writing and reading this file to persist identity and group membership across invocations.
+//! The format of an existing file is detected automatically when it is loaded.
 //!
 //! Example (pseudo-Rust):
 //!
@@ -15,30 +26,432 @@
 //! let state = DmlsState::new(signature_key_pair);
 //! // set send group id after creating a group
 //! state.set_send_group_id(group.group_id().clone());
-//! // persist to disk using serde_json
-//! let json = serde_json::to_string(&state)?;
+//! // persist to disk
+//! let bytes = state.to_bytes(StateFormat::Cbor)?;
+//! let (state, format) = DmlsState::from_bytes(&bytes)?;
 //! ```
 
-use super::{openmls_keys::SignatureKeyPair, openmls_kvstore::OpenMlsKeyValueStore};
+use super::{
+    audit::AuditEntry, error::DmlsError, openmls_keys::SignatureKeyPair,
+    openmls_kvstore::OpenMlsKeyValueStore, psk_id::ExporterPskId, redact::RedactedDebug,
+    transport::Cursor,
+};
 use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use core::{error::Error, str::FromStr};
+#[cfg(feature = "deterministic-rng")]
+use ed25519_dalek::SigningKey;
 use openmls::group::GroupId;
+#[cfg(feature = "deterministic-rng")]
+use openmls_traits::types::SignatureScheme;
+#[cfg(feature = "deterministic-rng")]
+use rand_chacha::{
+    ChaCha20Rng,
+    rand_core::{RngCore, SeedableRng},
+};
 use serde::{Deserialize, Serialize};
 use serde_with::{base64::Base64, serde_as};
-use std::mem::take;
+use std::{
+    collections::BTreeMap,
+    mem::take,
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-/// The main persistent state struct for a DMLS agent.
+/// On-disk format of a state file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StateFormat {
+    /// JSON (human-readable).
+    #[default]
+    Json,
+    /// CBOR (RFC 8949).
+    Cbor,
+    /// MessagePack, with named fields.
+    MsgPack,
+}
+
+impl FromStr for StateFormat {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(Self::Json),
+            "cbor" => Ok(Self::Cbor),
+            "msgpack" => Ok(Self::MsgPack),
+            _ => Err(format!("Unknown state format: {s}")),
+        }
+    }
+}
+
+impl StateFormat {
+    /// Detect the format of a serialized state from its first byte.
+    ///
+    /// The state is a map, so a JSON state starts with `{` (possibly after whitespace), a CBOR
+    /// state with a major type 5 byte and a MessagePack state with a map marker.
+    pub fn detect(bytes: &[u8]) -> Option<Self> {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace())? {
+            b'{' => Some(Self::Json),
+            0xa0..=0xbb | 0xbf => Some(Self::Cbor),
+            0x80..=0x8f | 0xde | 0xdf => Some(Self::MsgPack),
+            _ => None,
+        }
+    }
+}
+
+/// Kind of artifact recorded in the outbox.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum OutboxKind {
+    /// A commit for existing group members.
+    Commit,
+    /// A welcome for new group members.
+    Welcome,
+    /// An application message.
+    Message,
+}
+
+/// An artifact produced by a command, kept until its delivery is acknowledged.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OutboxEntry {
+    /// Identifier used to resend or acknowledge the entry.
+    pub id: u64,
+    /// Kind of artifact.
+    pub kind: OutboxKind,
+    /// The artifact, exactly as printed when it was produced.
+    pub message: String,
+}
+
+/// Activity counters of a group.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GroupStats {
+    /// Application messages encrypted.
+    pub messages_encrypted: u64,
+    /// Application messages decrypted.
+    pub messages_decrypted: u64,
+    /// Commits merged, both own and received.
+    pub commits_applied: u64,
+    /// Exporter PSKs injected.
+    pub psks_injected: u64,
+    /// Unix time (in seconds) of the last activity.
+    pub last_activity: u64,
+}
+
+/// Activity counters of a DMLS agent, per group, e.g. for monitoring agent health.
 ///
-/// Holds the current OpenMLS protocol version and a key-value store for all OpenMLS-related values.
+/// Counters of states from before they were kept per group are dropped on load.
+#[serde_as]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct DmlsStats {
+    /// Counters keyed by group id.
+    #[serde_as(as = "BTreeMap<Base64, _>")]
+    pub groups: BTreeMap<Vec<u8>, GroupStats>,
+}
+
+impl DmlsStats {
+    /// Returns the counters of the given group, recording activity in it now.
+    fn touch(&mut self, group_id: &GroupId) -> &mut GroupStats {
+        let stats = self.groups.entry(group_id.to_vec()).or_default();
+        stats.last_activity = unix_now();
+        stats
+    }
+}
+
+/// Current Unix time in seconds.
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// When to re-key the send group automatically; a limit of `None` never triggers.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RekeyPolicy {
+    /// Application messages sent since the last self-update.
+    pub max_messages: Option<u64>,
+    /// Seconds since the last self-update.
+    pub max_age: Option<u64>,
+}
+
+/// Leaf node capabilities advertised in key packages, created groups and updated leaves, as
+/// protocol code points; an empty list means the OpenMLS default.
+///
+/// The extensions DMLS relies on (see `helpers::capabilities`) are always advertised in addition
+/// to `extensions`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CapabilitiesOptions {
+    /// Supported protocol versions.
+    pub versions: Vec<u16>,
+    /// Supported ciphersuites.
+    pub ciphersuites: Vec<u16>,
+    /// Supported extension types beyond the defaults.
+    pub extensions: Vec<u16>,
+    /// Supported credential types.
+    pub credentials: Vec<u16>,
+}
+
+/// Ciphersuites and signature schemes the agent accepts in key packages, Welcomes and groups it
+/// creates, as protocol code points; an empty list accepts all (see
+/// `helpers::check_suite_policy`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SuitePolicy {
+    /// Accepted ciphersuites.
+    pub ciphersuites: Vec<u16>,
+    /// Accepted signature schemes.
+    pub signature_schemes: Vec<u16>,
+}
+
+/// Limits on the membership of groups, protecting the agent from giant (malicious) commits; a
+/// limit of `None` is no limit (see `helpers::check_membership_policy`).
+#[serde_as]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MembershipPolicy {
+    /// Members a group may have.
+    pub max_members: Option<u64>,
+    /// Members a single commit may add.
+    pub max_adds_per_commit: Option<u64>,
+    /// Credential identities that must not be added.
+    #[serde_as(as = "Vec<Base64>")]
+    pub banned: Vec<Vec<u8>>,
+}
+
+/// What happens when an exporter PSK is queued while the queue is full (see `PskQueuePolicy`).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PskOverflow {
+    /// Drop the oldest queued PSKs.
+    #[default]
+    DropOldest,
+    /// Drop the queued PSKs of the new PSK's source group, which it supersedes, then the oldest
+    /// ones if the queue is still full.
+    Coalesce,
+    /// Refuse to queue the new PSK.
+    Error,
+}
+
+impl FromStr for PskOverflow {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "drop-oldest" => Ok(Self::DropOldest),
+            "coalesce" => Ok(Self::Coalesce),
+            "error" => Ok(Self::Error),
+            _ => Err(format!("Unknown PSK queue overflow policy: {s}")),
+        }
+    }
+}
+
+/// Cap on the exporter PSK queue, bounding the state of agents that receive many commits
+/// between their own; a cap of `None` is no cap (see `DmlsState::push_exporter_psk_id`).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PskQueuePolicy {
+    /// Exporter PSKs the queue may hold (at least 1).
+    pub max_len: Option<u64>,
+    /// What happens when the queue is full.
+    pub overflow: PskOverflow,
+}
+
+/// Activity of a group since the agent last updated its own leaf in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RekeyCounter {
+    /// Application messages sent since the last self-update.
+    pub messages: u64,
+    /// Unix time (in seconds) of the last self-update, or of the first message counted.
+    pub since: u64,
+}
+
+/// Number of generated key packages `DmlsState::record_generated_kp` keeps records of.
+pub const KP_RECORD_RETENTION: usize = 1024;
+
+/// A key package the agent generated, as recorded to explain Welcomes it can no longer process.
+#[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyPackageRecord {
+    /// Reference (hash) of the key package.
+    #[serde_as(as = "Base64")]
+    pub kp_ref: Vec<u8>,
+    /// Ciphersuite of the key package.
+    pub ciphersuite: u16,
+    /// Unix time (in seconds) the key package was generated.
+    pub generated_at: u64,
+}
+
+/// Number of processed messages remembered for deduplication, unless configured otherwise.
+pub const DEFAULT_DEDUPE_CAPACITY: u64 = 1024;
+
+/// Exporter label of exporter PSKs, unless a group recorded another one.
+pub const DEFAULT_EXPORTER_LABEL: &str = "exporter_psk";
+
+/// Parameters of a group's exporter PSKs; every member must derive them with the same ones.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ExporterParams {
+    /// Length of the exported secret in bytes.
+    pub length: usize,
+    /// Exporter label.
+    pub label: String,
+}
+
+impl ExporterParams {
+    /// Parameters with the given length and the default label.
+    pub fn new(length: usize) -> Self {
+        Self {
+            length,
+            label: DEFAULT_EXPORTER_LABEL.to_string(),
+        }
+    }
+}
+
+/// An exporter PSK injected into a group, as recorded in the PSK history.
 #[serde_as]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PskInjection {
+    /// Unix time (in seconds) of the injection.
+    pub time: u64,
+    /// Group the PSK was injected into.
+    #[serde_as(as = "Base64")]
+    pub group_id: Vec<u8>,
+    /// Epoch created by the injecting commit.
+    pub epoch: u64,
+    /// Group the PSK was exported from.
+    #[serde_as(as = "Base64")]
+    pub source_group_id: Vec<u8>,
+    /// Epoch of the source group the PSK was exported from.
+    pub source_epoch: u64,
+}
+
+/// The main persistent state struct for a DMLS agent.
+///
+/// Holds the agent's own bookkeeping (`DmlsStateMeta`) and a key-value store for all
+/// OpenMLS-related values.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct DmlsState {
+    /// Everything except the OpenMLS values; serialized inline.
+    #[serde(flatten)]
+    meta: DmlsStateMeta,
+    /// The in-memory, thread-safe key-value store for all OpenMLS values.
+    openmls_values: OpenMlsKeyValueStore,
+}
+
+/// The part of `DmlsState` that is not OpenMLS storage.
+///
+/// It is small compared to the OpenMLS values, so it is persisted as a whole whenever it changes
+/// (see `journal`).
+#[serde_as]
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DmlsStateMeta {
     #[serde_as(as = "Base64")]
     send_group_id: Vec<u8>,
     #[serde_as(as = "Vec<Base64>")]
     exporter_psk_queue: Vec<Vec<u8>>,
+    /// Cap on the exporter PSK queue.
+    #[serde(default)]
+    psk_queue_policy: PskQueuePolicy,
+    /// Whether joining a group from a Welcome doesn't queue the exporter PSK of the join epoch.
+    #[serde(default)]
+    welcome_psk_disabled: bool,
+    /// Ids of out-of-band external PSKs queued for injection into the send group.
+    #[serde_as(as = "Vec<Base64>")]
+    #[serde(default)]
+    external_psk_queue: Vec<Vec<u8>>,
+    /// Epochs of the send group whose resumption PSKs are queued for injection into it.
+    #[serde(default)]
+    resumption_psk_queue: Vec<u64>,
+    /// Key packages received from peers, keyed by credential identity (TLS-serialized).
+    #[serde_as(as = "BTreeMap<Base64, Vec<Base64>>")]
+    #[serde(default)]
+    address_book: BTreeMap<Vec<u8>, Vec<Vec<u8>>>,
+    /// Produced artifacts awaiting delivery acknowledgement.
+    #[serde(default)]
+    outbox: Vec<OutboxEntry>,
+    /// Identifier assigned to the next outbox entry.
+    #[serde(default)]
+    next_outbox_id: u64,
+    /// Sequence number of the next file dropped into an outbox directory.
+    #[serde(default)]
+    next_maildir_seq: u64,
+    /// Activity counters.
+    #[serde(default)]
+    stats: DmlsStats,
+    /// Append-only, hash-chained log of commits, membership changes and PSK injections.
+    #[serde(default)]
+    audit_log: Vec<AuditEntry>,
+    /// MAC of the audit log's last entry hash, if it was written with an integrity key (see
+    /// `integrity::audit_head_mac`).
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default)]
+    audit_head_mac: Option<Vec<u8>>,
+    /// References of the key packages in the published pool that weren't consumed yet (see
+    /// `kp_pool`).
+    #[serde_as(as = "Vec<Base64>")]
+    #[serde(default)]
+    kp_pool: Vec<Vec<u8>>,
+    /// The most recently generated key packages, oldest first, whether or not they were consumed
+    /// since (see `record_generated_kp`).
+    #[serde(default)]
+    generated_kps: Vec<KeyPackageRecord>,
+    /// Exporter PSK parameters recorded when a group was created or joined, keyed by group id.
+    #[serde_as(as = "BTreeMap<Base64, _>")]
+    #[serde(default)]
+    exporter_params: BTreeMap<Vec<u8>, ExporterParams>,
+    /// Automatic re-key policy for the send group.
+    #[serde(default)]
+    rekey_policy: RekeyPolicy,
+    /// Leaf node capabilities advertised by default.
+    #[serde(default)]
+    capabilities: CapabilitiesOptions,
+    /// Accepted ciphersuites and signature schemes.
+    #[serde(default)]
+    suite_policy: SuitePolicy,
+    /// Limits on group membership.
+    #[serde(default)]
+    membership_policy: MembershipPolicy,
+    /// Number of past epochs whose exporter PSKs and epoch key pairs are kept per group; `None`
+    /// keeps them all (see `helpers::purge_epochs`).
+    #[serde(default)]
+    epoch_retention: Option<u64>,
+    /// Number of processed messages remembered for deduplication; `None` uses
+    /// `DEFAULT_DEDUPE_CAPACITY` and 0 disables deduplication.
+    #[serde(default)]
+    dedupe_capacity: Option<u64>,
+    /// Hashes of the most recently processed messages, least recently seen first (see
+    /// `record_seen_message`).
+    #[serde_as(as = "Vec<Base64>")]
+    #[serde(default)]
+    seen_messages: Vec<Vec<u8>>,
+    /// Positions up to which messages were fetched from transports that keep them, keyed by
+    /// stream (see `transport::Transport::cursors`).
+    #[serde(default)]
+    transport_cursors: BTreeMap<String, Cursor>,
+    /// Re-key counters, keyed by group id.
+    #[serde_as(as = "BTreeMap<Base64, _>")]
+    #[serde(default)]
+    rekey_counters: BTreeMap<Vec<u8>, RekeyCounter>,
+    /// Exporter PSKs injected into groups, oldest first.
+    #[serde(default)]
+    psk_history: Vec<PskInjection>,
+    /// Exporter labels applications may export secrets with (see `export-secret`).
+    #[serde(default)]
+    export_labels: Vec<String>,
+    /// Send groups the exporter PSKs of each receive group may be injected into, keyed by source
+    /// group id; without any rules, exporter PSKs of all groups are bridged (see `bridges`).
+    #[serde_as(as = "BTreeMap<Base64, Vec<Base64>>")]
+    #[serde(default)]
+    bridging_rules: BTreeMap<Vec<u8>, Vec<Vec<u8>>>,
+    /// Groups followed as a passive observer (see `observe`).
+    #[serde_as(as = "Vec<Base64>")]
+    #[serde(default)]
+    observed_groups: Vec<Vec<u8>>,
+    /// Seed of the OpenMLS RNG of a deterministic test identity (see `new_deterministic`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rng_seed: Option<u64>,
+    /// Credential identity chosen with `update --new-identity`, replacing the one derived from
+    /// the signature public key.
+    #[serde_as(as = "Option<Base64>")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    identity: Option<Vec<u8>>,
     signature_key_pair: SignatureKeyPair,
-    /// The in-memory, thread-safe key-value store for all OpenMLS values.
-    openmls_values: OpenMlsKeyValueStore,
 }
 
 impl core::fmt::Debug for DmlsState {
@@ -46,17 +459,59 @@ impl core::fmt::Debug for DmlsState {
         f.debug_struct("DmlsState")
             .field(
                 "send_group_id",
-                &Base64.encode(&self.send_group_id).to_string(),
+                &Base64.encode(&self.meta.send_group_id).to_string(),
             )
             .field(
                 "exporter_psk_queue",
                 &self
+                    .meta
                     .exporter_psk_queue
                     .iter()
-                    .map(|v| Base64.encode(v).to_string())
-                    .collect::<Vec<String>>(),
+                    .map(|v| RedactedDebug(v))
+                    .collect::<Vec<_>>(),
+            )
+            .field("psk_queue_policy", &self.meta.psk_queue_policy)
+            .field("welcome_psk_disabled", &self.meta.welcome_psk_disabled)
+            .field(
+                "address_book",
+                &self
+                    .meta
+                    .address_book
+                    .iter()
+                    .map(|(k, v)| (Base64.encode(k).to_string(), v.len()))
+                    .collect::<BTreeMap<String, usize>>(),
             )
-            .field("signature_key_pair", &self.signature_key_pair)
+            .field("outbox", &self.meta.outbox)
+            .field("next_outbox_id", &self.meta.next_outbox_id)
+            .field("next_maildir_seq", &self.meta.next_maildir_seq)
+            .field("stats", &self.meta.stats)
+            .field("audit_log", &self.meta.audit_log.len())
+            .field("audit_head_mac", &self.meta.audit_head_mac.is_some())
+            .field("kp_pool", &self.meta.kp_pool.len())
+            .field("generated_kps", &self.meta.generated_kps.len())
+            .field("exporter_params", &self.meta.exporter_params.len())
+            .field("rekey_policy", &self.meta.rekey_policy)
+            .field("capabilities", &self.meta.capabilities)
+            .field("suite_policy", &self.meta.suite_policy)
+            .field("membership_policy", &self.meta.membership_policy)
+            .field("epoch_retention", &self.meta.epoch_retention)
+            .field("dedupe_capacity", &self.meta.dedupe_capacity)
+            .field("seen_messages", &self.meta.seen_messages.len())
+            .field("transport_cursors", &self.meta.transport_cursors)
+            .field("psk_history", &self.meta.psk_history.len())
+            .field("export_labels", &self.meta.export_labels)
+            .field("bridging_rules", &self.meta.bridging_rules.len())
+            .field("observed_groups", &self.meta.observed_groups.len())
+            .field("rng_seed", &self.meta.rng_seed)
+            .field(
+                "identity",
+                &self
+                    .meta
+                    .identity
+                    .as_ref()
+                    .map(|identity| Base64.encode(identity).to_string()),
+            )
+            .field("signature_key_pair", &self.meta.signature_key_pair)
             .field("openmls_values", &self.openmls_values)
             .finish()
     }
@@ -70,12 +525,113 @@ impl DmlsState {
     pub fn new(signature_key_pair: SignatureKeyPair) -> Self {
         // done
         Self {
-            exporter_psk_queue: Vec::new(),
-            send_group_id: Vec::new(),
-            signature_key_pair,
+            meta: DmlsStateMeta {
+                exporter_psk_queue: Vec::new(),
+                psk_queue_policy: PskQueuePolicy::default(),
+                welcome_psk_disabled: false,
+                external_psk_queue: Vec::new(),
+                resumption_psk_queue: Vec::new(),
+                send_group_id: Vec::new(),
+                address_book: BTreeMap::new(),
+                outbox: Vec::new(),
+                next_outbox_id: 0,
+                next_maildir_seq: 0,
+                stats: DmlsStats::default(),
+                audit_log: Vec::new(),
+                audit_head_mac: None,
+                kp_pool: Vec::new(),
+                generated_kps: Vec::new(),
+                exporter_params: BTreeMap::new(),
+                rekey_policy: RekeyPolicy::default(),
+                capabilities: CapabilitiesOptions::default(),
+                suite_policy: SuitePolicy::default(),
+                membership_policy: MembershipPolicy::default(),
+                epoch_retention: None,
+                dedupe_capacity: None,
+                seen_messages: Vec::new(),
+                transport_cursors: BTreeMap::new(),
+                rekey_counters: BTreeMap::new(),
+                psk_history: Vec::new(),
+                export_labels: Vec::new(),
+                bridging_rules: BTreeMap::new(),
+                observed_groups: Vec::new(),
+                rng_seed: None,
+                identity: None,
+                signature_key_pair,
+            },
             openmls_values: Default::default(),
         }
     }
+
+    /// Creates a test identity whose Ed25519 signature key pair and OpenMLS randomness are derived
+    /// from the seed, so tests and documentation examples produce the same artifacts every run.
+    ///
+    /// `DmlsProvider::new` draws from the seeded RNG and, when converted back into a state, stores
+    /// the seed to continue from, so successive runs don't repeat randomness. Never use such an
+    /// identity outside of tests: anyone knowing the seed knows all its secrets.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let provider = DmlsProvider::new(DmlsState::new_deterministic(42), RustCrypto::default());
+    /// ```
+    #[cfg(feature = "deterministic-rng")]
+    pub fn new_deterministic(seed: u64) -> Self {
+        let mut rng = ChaCha20Rng::seed_from_u64(seed);
+        let mut private = [0u8; 32];
+        rng.fill_bytes(&mut private);
+        let public = SigningKey::from_bytes(&private)
+            .verifying_key()
+            .to_bytes()
+            .to_vec();
+        let mut state = Self::new(SignatureKeyPair::from_raw(
+            private.to_vec(),
+            public,
+            SignatureScheme::ED25519,
+        ));
+        state.meta.rng_seed = Some(rng.next_u64());
+        state
+    }
+}
+
+impl DmlsState {
+    /// Serialize the state in the given format.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let bytes = state.to_bytes(StateFormat::MsgPack)?;
+    /// ```
+    pub fn to_bytes(&self, format: StateFormat) -> Result<Vec<u8>, Box<dyn Error>> {
+        Ok(match format {
+            StateFormat::Json => serde_json::to_vec(self)?,
+            StateFormat::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(self, &mut bytes)?;
+                bytes
+            }
+            StateFormat::MsgPack => rmp_serde::to_vec_named(self)?,
+        })
+    }
+
+    /// Deserialize a state, detecting its format.
+    ///
+    /// Returns the state together with the detected format, so it can be saved back unchanged.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let (state, format) = DmlsState::from_bytes(&std::fs::read(state_path)?)?;
+    /// ```
+    pub fn from_bytes(bytes: &[u8]) -> Result<(Self, StateFormat), Box<dyn Error>> {
+        let format = StateFormat::detect(bytes).ok_or("Unknown state format")?;
+        let state = match format {
+            StateFormat::Json => serde_json::from_slice(bytes)?,
+            StateFormat::Cbor => ciborium::from_reader(bytes)?,
+            StateFormat::MsgPack => rmp_serde::from_slice(bytes)?,
+        };
+        Ok((state, format))
+    }
 }
 
 impl DmlsState {
@@ -85,38 +641,581 @@ impl DmlsState {
     /// application messages. The id is stored as bytes in the state and will be used by
     /// `send_group()` to load the `MlsGroup` instance.
     pub fn set_send_group_id(&mut self, send_group_id: GroupId) {
-        self.send_group_id = send_group_id.as_slice().to_vec();
+        self.meta.send_group_id = send_group_id.as_slice().to_vec();
     }
 
-    /// Push an exporter PSK identifier onto the local queue.
+    /// Forget the send group id, e.g. because the group it refers to is gone.
+    pub fn clear_send_group_id(&mut self) {
+        self.meta.send_group_id = Vec::new();
+    }
+
+    /// Push an exporter PSK identifier onto the local queue and return how many queued ids were
+    /// dropped to make room for it.
     ///
     /// Exporter PSK ids are produced when handling commits that rotate keys. These ids are
-    /// queued for later injection into the group using `inject_psks` helpers.
-    pub fn push_exporter_psk_id(&mut self, psk: Vec<u8>) {
-        self.exporter_psk_queue.push(psk);
+    /// queued for later injection into the group using `inject_psks` helpers. If the queue is
+    /// full, the PSK queue policy decides which ids are dropped, or whether the new one is
+    /// refused with `DmlsError::PskQueueFull`.
+    pub fn push_exporter_psk_id(&mut self, psk: Vec<u8>) -> Result<usize, DmlsError> {
+        self.check_psk_queue()?;
+        let policy = &self.meta.psk_queue_policy;
+        let queue = &mut self.meta.exporter_psk_queue;
+        let mut dropped = 0;
+        if let Some(max) = policy.max_len
+            && queue.len() as u64 >= max
+        {
+            match policy.overflow {
+                PskOverflow::Error | PskOverflow::DropOldest => {}
+                PskOverflow::Coalesce => {
+                    let source = |psk_id: &[u8]| ExporterPskId::parse(psk_id).map(|s| s.group_id);
+                    if let Some(group_id) = source(&psk) {
+                        let len = queue.len();
+                        queue.retain(|queued| source(queued).as_ref() != Some(&group_id));
+                        dropped += len - queue.len();
+                    }
+                }
+            }
+            let excess = (queue.len() + 1).saturating_sub(max.max(1) as usize);
+            dropped += queue.drain(..excess).count();
+        }
+        queue.push(psk);
+        Ok(dropped)
+    }
+
+    /// Fail with `DmlsError::PskQueueFull` if the exporter PSK queue is full and its policy
+    /// refuses new PSKs.
+    pub fn check_psk_queue(&self) -> Result<(), DmlsError> {
+        match self.meta.psk_queue_policy {
+            PskQueuePolicy {
+                max_len: Some(max),
+                overflow: PskOverflow::Error,
+            } if self.meta.exporter_psk_queue.len() as u64 >= max => {
+                Err(DmlsError::PskQueueFull(max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Queue the id of an out-of-band external PSK for injection into the send group.
+    pub fn push_external_psk_id(&mut self, psk_id: Vec<u8>) {
+        if !self.meta.external_psk_queue.contains(&psk_id) {
+            self.meta.external_psk_queue.push(psk_id);
+        }
+    }
+
+    /// Queue the resumption PSK of an epoch of the send group for injection into it.
+    pub fn push_resumption_psk_epoch(&mut self, epoch: u64) {
+        if !self.meta.resumption_psk_queue.contains(&epoch) {
+            self.meta.resumption_psk_queue.push(epoch);
+        }
+    }
+
+    /// Clear the queued resumption PSK epochs, e.g. once they were injected.
+    pub fn clear_resumption_psk_epochs(&mut self) {
+        self.meta.resumption_psk_queue.clear();
+    }
+
+    /// Allow exporting secrets with the given exporter label.
+    pub fn allow_export_label(&mut self, label: String) {
+        if !self.meta.export_labels.contains(&label) {
+            self.meta.export_labels.push(label);
+        }
+    }
+
+    /// Stop allowing exports with the given exporter label.
+    pub fn revoke_export_label(&mut self, label: &str) {
+        self.meta.export_labels.retain(|allowed| allowed != label);
+    }
+
+    /// Allow exporter PSKs of the source group to be injected into the target send group.
+    pub fn allow_bridge(&mut self, source: Vec<u8>, target: Vec<u8>) {
+        let targets = self.meta.bridging_rules.entry(source).or_default();
+        if !targets.contains(&target) {
+            targets.push(target);
+        }
+    }
+
+    /// Stop bridging exporter PSKs of the source group into the target send group, keeping the
+    /// (possibly empty, thus blocking) rule, or remove the source group's rule altogether.
+    pub fn revoke_bridge(&mut self, source: &[u8], target: Option<&[u8]>) {
+        match target {
+            Some(target) => {
+                if let Some(targets) = self.meta.bridging_rules.get_mut(source) {
+                    targets.retain(|allowed| allowed != target);
+                }
+            }
+            None => {
+                self.meta.bridging_rules.remove(source);
+            }
+        }
+    }
+
+    /// Record a group as observed (see `observe`).
+    pub fn add_observed_group(&mut self, group_id: &GroupId) {
+        if !self.is_observed(group_id) {
+            self.meta.observed_groups.push(group_id.to_vec());
+        }
+    }
+
+    /// Record a key package published to the pool, by its reference.
+    pub fn push_kp_pool_ref(&mut self, kp_ref: Vec<u8>) {
+        self.meta.kp_pool.push(kp_ref);
+    }
+
+    /// Record a key package the agent generated, by its reference, keeping the last
+    /// `KP_RECORD_RETENTION` records.
+    pub fn record_generated_kp(&mut self, kp_ref: Vec<u8>, ciphersuite: u16) {
+        self.meta.generated_kps.push(KeyPackageRecord {
+            kp_ref,
+            ciphersuite,
+            generated_at: unix_now(),
+        });
+        let excess = self
+            .meta
+            .generated_kps
+            .len()
+            .saturating_sub(KP_RECORD_RETENTION);
+        self.meta.generated_kps.drain(..excess);
+    }
+
+    /// Keep only the pool key packages for which `keep` returns true; returns how many were
+    /// dropped.
+    pub fn retain_kp_pool(&mut self, keep: impl FnMut(&Vec<u8>) -> bool) -> usize {
+        let before = self.meta.kp_pool.len();
+        self.meta.kp_pool.retain(keep);
+        before - self.meta.kp_pool.len()
+    }
+
+    /// Record the exporter PSK parameters of a group.
+    pub fn set_exporter_params(&mut self, group_id: &GroupId, params: ExporterParams) {
+        self.meta.exporter_params.insert(group_id.to_vec(), params);
+    }
+
+    /// Forget the exporter PSK parameters of a group, e.g. once it was deleted.
+    pub fn remove_exporter_params(&mut self, group_id: &GroupId) {
+        self.meta.exporter_params.remove(group_id.as_slice());
+    }
+
+    /// Remove the given PSK identifiers from the exporter and external PSK queues, e.g. once they
+    /// were injected.
+    pub fn remove_exporter_psk_ids(&mut self, psk_ids: &[Vec<u8>]) {
+        self.meta
+            .exporter_psk_queue
+            .retain(|psk_id| !psk_ids.contains(psk_id));
+        self.meta
+            .external_psk_queue
+            .retain(|psk_id| !psk_ids.contains(psk_id));
     }
 
     /// Clear and return all queued exporter PSK identifiers.
     ///
     /// This consumes the queue and returns the queued PSK ids for processing or injection.
     pub fn clear_exporter_psk_ids(&mut self) -> Vec<Vec<u8>> {
-        take(&mut self.exporter_psk_queue)
+        take(&mut self.meta.exporter_psk_queue)
+    }
+
+    /// Add a serialized key package to the address book under the given credential identity.
+    ///
+    /// Key packages collected via `Process` are kept here until the creator consumes them
+    /// (e.g. with `gen-send-group --address-book`). Duplicate key packages are ignored.
+    pub fn push_address_book_kp(&mut self, identity: Vec<u8>, kp: Vec<u8>) {
+        let kps = self.meta.address_book.entry(identity).or_default();
+        if !kps.contains(&kp) {
+            kps.push(kp);
+        }
+    }
+
+    /// Record a produced artifact in the outbox and return its id.
+    pub fn push_outbox(&mut self, kind: OutboxKind, message: String) -> u64 {
+        let id = self.meta.next_outbox_id;
+        self.meta.next_outbox_id += 1;
+        self.meta.outbox.push(OutboxEntry { id, kind, message });
+        id
+    }
+
+    /// Return the sequence number for the next outbox directory file and advance it.
+    pub fn next_maildir_seq(&mut self) -> u64 {
+        let seq = self.meta.next_maildir_seq;
+        self.meta.next_maildir_seq += 1;
+        seq
+    }
+
+    /// Remove acknowledged entries from the outbox and return how many were removed.
+    pub fn ack_outbox(&mut self, ids: &[u64]) -> usize {
+        let len = self.meta.outbox.len();
+        self.meta.outbox.retain(|entry| !ids.contains(&entry.id));
+        len - self.meta.outbox.len()
+    }
+
+    /// Clear and return all outbox entries.
+    pub fn clear_outbox(&mut self) -> Vec<OutboxEntry> {
+        take(&mut self.meta.outbox)
+    }
+
+    /// Replace the address book, e.g. with a copy from which consumed key packages were removed.
+    pub fn set_address_book(&mut self, address_book: BTreeMap<Vec<u8>, Vec<Vec<u8>>>) {
+        self.meta.address_book = address_book;
+    }
+
+    /// Count an application message encrypted in the given group.
+    pub fn record_encrypted(&mut self, group_id: &GroupId) {
+        self.meta.stats.touch(group_id).messages_encrypted += 1;
+        self.meta
+            .rekey_counters
+            .entry(group_id.to_vec())
+            .or_insert_with(|| RekeyCounter {
+                messages: 0,
+                since: unix_now(),
+            })
+            .messages += 1;
+    }
+
+    /// Reset the re-key counter of a group after updating the agent's own leaf in it.
+    pub fn record_self_update(&mut self, group_id: &GroupId) {
+        self.meta.rekey_counters.insert(
+            group_id.to_vec(),
+            RekeyCounter {
+                messages: 0,
+                since: unix_now(),
+            },
+        );
+    }
+
+    /// Replace the local signature key pair (see `continuity::rotate_key`).
+    pub fn set_signature_key_pair(&mut self, signature_key_pair: SignatureKeyPair) {
+        self.meta.signature_key_pair = signature_key_pair;
+    }
+
+    /// Replace the credential identity (see `helpers::send_group_update_identity`).
+    pub fn set_identity(&mut self, identity: Vec<u8>) {
+        self.meta.identity = Some(identity);
+    }
+
+    /// Replace the automatic re-key policy.
+    pub fn set_rekey_policy(&mut self, policy: RekeyPolicy) {
+        self.meta.rekey_policy = policy;
+    }
+
+    /// Replace the default leaf node capabilities.
+    pub fn set_capabilities(&mut self, capabilities: CapabilitiesOptions) {
+        self.meta.capabilities = capabilities;
+    }
+
+    /// Replace the accepted ciphersuites and signature schemes.
+    pub fn set_suite_policy(&mut self, policy: SuitePolicy) {
+        self.meta.suite_policy = policy;
+    }
+
+    /// Replace the cap on the exporter PSK queue; it applies from the next queued PSK on.
+    pub fn set_psk_queue_policy(&mut self, policy: PskQueuePolicy) {
+        self.meta.psk_queue_policy = policy;
+    }
+
+    /// Set whether joining a group from a Welcome queues the exporter PSK of the join epoch.
+    pub fn set_welcome_psk(&mut self, enabled: bool) {
+        self.meta.welcome_psk_disabled = !enabled;
+    }
+
+    /// Replace the limits on group membership.
+    pub fn set_membership_policy(&mut self, policy: MembershipPolicy) {
+        self.meta.membership_policy = policy;
+    }
+
+    /// Set how many past epochs of secrets are kept per group (`None` keeps them all).
+    pub fn set_epoch_retention(&mut self, epochs: Option<u64>) {
+        self.meta.epoch_retention = epochs;
+    }
+
+    /// Set how many processed messages are remembered for deduplication (`None` for the default,
+    /// 0 disables it), forgetting the least recently seen ones beyond it.
+    pub fn set_dedupe_capacity(&mut self, capacity: Option<u64>) {
+        self.meta.dedupe_capacity = capacity;
+        self.trim_seen_messages();
+    }
+
+    /// Record the hash of a processed message; returns whether it was seen before.
+    ///
+    /// The hashes form an LRU: a message seen again becomes the most recently seen, and the least
+    /// recently seen ones are forgotten beyond the dedupe capacity.
+    pub fn record_seen_message(&mut self, digest: Vec<u8>) -> bool {
+        if self.dedupe_capacity() == 0 {
+            return false;
+        }
+        let seen = match self.meta.seen_messages.iter().position(|d| *d == digest) {
+            Some(i) => {
+                self.meta.seen_messages.remove(i);
+                true
+            }
+            None => false,
+        };
+        self.meta.seen_messages.push(digest);
+        self.trim_seen_messages();
+        seen
+    }
+
+    /// Forget all processed messages; returns how many were remembered.
+    pub fn clear_seen_messages(&mut self) -> usize {
+        take(&mut self.meta.seen_messages).len()
+    }
+
+    /// Record the positions up to which messages were fetched from a transport, keeping those of
+    /// other streams.
+    pub fn update_transport_cursors(&mut self, cursors: BTreeMap<String, Cursor>) {
+        self.meta.transport_cursors.extend(cursors);
+    }
+
+    fn trim_seen_messages(&mut self) {
+        let capacity = usize::try_from(self.dedupe_capacity()).unwrap_or(usize::MAX);
+        let excess = self.meta.seen_messages.len().saturating_sub(capacity);
+        self.meta.seen_messages.drain(..excess);
+    }
+
+    /// Count an application message decrypted in the given group.
+    pub fn record_decrypted(&mut self, group_id: &GroupId) {
+        self.meta.stats.touch(group_id).messages_decrypted += 1;
+    }
+
+    /// Count a commit merged into the given group.
+    pub fn record_commit(&mut self, group_id: &GroupId) {
+        self.meta.stats.touch(group_id).commits_applied += 1;
+    }
+
+    /// Count exporter PSKs injected into the given group by the commit that created `epoch`, and
+    /// record them in the PSK history. Must be called before the ids are removed from the queues.
+    ///
+    /// Exporter PSK ids name their source group and epoch (see `psk_id`).
+    pub fn record_psks_injected(&mut self, group_id: &GroupId, epoch: u64, psk_ids: &[Vec<u8>]) {
+        self.meta.stats.touch(group_id).psks_injected += psk_ids.len() as u64;
+        let time = unix_now();
+        // out-of-band external PSKs have no source group
+        for psk_id in psk_ids
+            .iter()
+            .filter(|psk_id| !self.meta.external_psk_queue.contains(psk_id))
+        {
+            let Some(source) = ExporterPskId::parse(psk_id) else {
+                continue;
+            };
+            self.meta.psk_history.push(PskInjection {
+                time,
+                group_id: group_id.to_vec(),
+                epoch,
+                source_group_id: source.group_id,
+                source_epoch: source.epoch,
+            });
+        }
+    }
+
+    /// Append an entry to the audit log with the MAC of its hash, if any (see `audit::append`).
+    pub fn push_audit_entry(&mut self, entry: AuditEntry, head_mac: Option<Vec<u8>>) {
+        self.meta.audit_log.push(entry);
+        self.meta.audit_head_mac = head_mac;
+    }
+
+    /// Clear and return all key packages held in the address book.
+    pub fn clear_address_book(&mut self) -> BTreeMap<Vec<u8>, Vec<Vec<u8>>> {
+        take(&mut self.meta.address_book)
     }
 }
 
 impl DmlsState {
+    /// Returns the id of the send group, if one has been created.
     pub fn send_group_id(&self) -> Option<GroupId> {
-        if self.send_group_id.is_empty() {
+        if self.meta.send_group_id.is_empty() {
             None
         } else {
-            Some(GroupId::from_slice(&self.send_group_id))
+            Some(GroupId::from_slice(&self.meta.send_group_id))
         }
     }
+    /// Returns the queued exporter PSK identifiers.
+    pub fn exporter_psk_ids(&self) -> &[Vec<u8>] {
+        &self.meta.exporter_psk_queue
+    }
+    /// Returns the queued out-of-band external PSK identifiers.
+    pub fn external_psk_ids(&self) -> &[Vec<u8>] {
+        &self.meta.external_psk_queue
+    }
+    /// Returns the queued resumption PSK epochs of the send group.
+    pub fn resumption_psk_epochs(&self) -> &[u64] {
+        &self.meta.resumption_psk_queue
+    }
+    /// Returns the references of the unconsumed key packages in the published pool.
+    pub fn kp_pool(&self) -> &[Vec<u8>] {
+        &self.meta.kp_pool
+    }
+    /// Returns the record of a generated key package, if it is among the last
+    /// `KP_RECORD_RETENTION` generated.
+    pub fn generated_kp(&self, kp_ref: &[u8]) -> Option<&KeyPackageRecord> {
+        self.meta
+            .generated_kps
+            .iter()
+            .find(|record| record.kp_ref == kp_ref)
+    }
+    /// Returns the automatic re-key policy.
+    pub fn rekey_policy(&self) -> &RekeyPolicy {
+        &self.meta.rekey_policy
+    }
+    /// Returns the default leaf node capabilities.
+    pub fn capabilities(&self) -> &CapabilitiesOptions {
+        &self.meta.capabilities
+    }
+    /// Returns the accepted ciphersuites and signature schemes.
+    pub fn suite_policy(&self) -> &SuitePolicy {
+        &self.meta.suite_policy
+    }
+    /// Returns the cap on the exporter PSK queue.
+    pub fn psk_queue_policy(&self) -> &PskQueuePolicy {
+        &self.meta.psk_queue_policy
+    }
+    /// Returns whether joining a group from a Welcome queues the exporter PSK of the join epoch
+    /// (the default).
+    pub fn welcome_psk(&self) -> bool {
+        !self.meta.welcome_psk_disabled
+    }
+    /// Returns the limits on group membership.
+    pub fn membership_policy(&self) -> &MembershipPolicy {
+        &self.meta.membership_policy
+    }
+    /// Returns how many past epochs of secrets are kept per group, if limited.
+    pub fn epoch_retention(&self) -> Option<u64> {
+        self.meta.epoch_retention
+    }
+    /// Returns how many processed messages are remembered for deduplication (0 if disabled).
+    pub fn dedupe_capacity(&self) -> u64 {
+        self.meta.dedupe_capacity.unwrap_or(DEFAULT_DEDUPE_CAPACITY)
+    }
+    /// Returns the hashes of the remembered processed messages, least recently seen first.
+    pub fn seen_messages(&self) -> &[Vec<u8>] {
+        &self.meta.seen_messages
+    }
+    /// Returns whether the hash of a message is among the remembered processed messages.
+    pub fn has_seen_message(&self, digest: &[u8]) -> bool {
+        self.meta.seen_messages.iter().any(|d| d == digest)
+    }
+    /// Returns the positions up to which messages were fetched from transports, keyed by stream.
+    pub fn transport_cursors(&self) -> &BTreeMap<String, Cursor> {
+        &self.meta.transport_cursors
+    }
+    /// Returns the re-key counter of a group, if it has seen activity.
+    pub fn rekey_counter(&self, group_id: &GroupId) -> Option<RekeyCounter> {
+        self.meta.rekey_counters.get(group_id.as_slice()).copied()
+    }
+    /// Returns whether the policy calls for re-keying the given group now.
+    pub fn rekey_due(&self, group_id: &GroupId) -> bool {
+        let (policy, Some(counter)) = (&self.meta.rekey_policy, self.rekey_counter(group_id))
+        else {
+            return false;
+        };
+        policy
+            .max_messages
+            .is_some_and(|max| counter.messages >= max)
+            || policy
+                .max_age
+                .is_some_and(|max| unix_now().saturating_sub(counter.since) >= max)
+    }
+    /// Returns the ids of the observed groups.
+    pub fn observed_groups(&self) -> impl Iterator<Item = GroupId> {
+        self.meta
+            .observed_groups
+            .iter()
+            .map(|group_id| GroupId::from_slice(group_id))
+    }
+    /// Returns whether a group is observed.
+    pub fn is_observed(&self, group_id: &GroupId) -> bool {
+        self.meta
+            .observed_groups
+            .iter()
+            .any(|observed| observed == group_id.as_slice())
+    }
+    /// Returns the bridging rules: the send groups exporter PSKs of each source group may be
+    /// injected into.
+    pub fn bridging_rules(&self) -> &BTreeMap<Vec<u8>, Vec<Vec<u8>>> {
+        &self.meta.bridging_rules
+    }
+    /// Returns whether exporter PSKs of the source group may be injected into the target send
+    /// group: always without any rules, otherwise only if the source group's rule lists it.
+    pub fn bridges(&self, source: &[u8], target: &[u8]) -> bool {
+        self.meta.bridging_rules.is_empty()
+            || self
+                .meta
+                .bridging_rules
+                .get(source)
+                .is_some_and(|targets| targets.iter().any(|allowed| allowed == target))
+    }
+    /// Returns the exporter labels applications may export secrets with.
+    pub fn export_labels(&self) -> &[String] {
+        &self.meta.export_labels
+    }
+    /// Returns whether a label is used for exporter PSKs, by default or by a recorded group.
+    pub fn is_exporter_psk_label(&self, label: &str) -> bool {
+        label == DEFAULT_EXPORTER_LABEL
+            || self
+                .meta
+                .exporter_params
+                .values()
+                .any(|params| params.label == label)
+    }
+    /// Returns the exporter PSK parameters recorded for a group, if any.
+    pub fn exporter_params(&self, group_id: &GroupId) -> Option<&ExporterParams> {
+        self.meta.exporter_params.get(group_id.as_slice())
+    }
+    /// Returns the local signature key pair.
     pub fn signature_key_pair(&self) -> &SignatureKeyPair {
-        &self.signature_key_pair
+        &self.meta.signature_key_pair
+    }
+    /// Returns the credential identity chosen with `update --new-identity`, if any.
+    pub fn identity(&self) -> Option<&[u8]> {
+        self.meta.identity.as_deref()
+    }
+    /// Returns the seed of the OpenMLS RNG of a deterministic test identity, if any.
+    pub fn rng_seed(&self) -> Option<u64> {
+        self.meta.rng_seed
+    }
+    /// Replace the seed the OpenMLS RNG continues from (see `new_deterministic`).
+    pub fn set_rng_seed(&mut self, seed: u64) {
+        self.meta.rng_seed = Some(seed);
+    }
+    /// Returns the key packages collected from peers, keyed by credential identity.
+    pub fn address_book(&self) -> &BTreeMap<Vec<u8>, Vec<Vec<u8>>> {
+        &self.meta.address_book
+    }
+    /// Returns the artifacts awaiting delivery acknowledgement, oldest first.
+    pub fn outbox(&self) -> &[OutboxEntry] {
+        &self.meta.outbox
+    }
+    /// Returns the activity counters of all groups.
+    pub fn stats(&self) -> &DmlsStats {
+        &self.meta.stats
+    }
+    /// Returns the exporter PSKs injected into groups, oldest first.
+    pub fn psk_history(&self) -> &[PskInjection] {
+        &self.meta.psk_history
+    }
+    /// Returns the audit log, oldest entry first.
+    pub fn audit_log(&self) -> &[AuditEntry] {
+        &self.meta.audit_log
+    }
+    /// Returns the MAC of the audit log's head, if it was written with an integrity key.
+    pub fn audit_head_mac(&self) -> Option<&[u8]> {
+        self.meta.audit_head_mac.as_deref()
     }
     /// Returns a reference to the internal OpenMLS key-value store.
     pub fn openmls_values(&self) -> &OpenMlsKeyValueStore {
         &self.openmls_values
     }
+    /// Returns the agent's bookkeeping, i.e. everything except the OpenMLS values.
+    pub fn meta(&self) -> &DmlsStateMeta {
+        &self.meta
+    }
+    /// Replace the agent's bookkeeping, e.g. with a copy replayed from a journal.
+    pub fn set_meta(&mut self, meta: DmlsStateMeta) {
+        self.meta = meta;
+    }
+    /// Returns a copy of the agent's bookkeeping with an empty OpenMLS store, for saving a state
+    /// whose OpenMLS values live in a separate storage backend (see `byte_store`).
+    pub fn without_openmls_values(&self) -> Self {
+        Self {
+            meta: self.meta.clone(),
+            openmls_values: Default::default(),
+        }
+    }
 }
@@ -0,0 +1,94 @@
+//! Redirectable standard input and output.
+//!
+//! The command-line agent reads incoming blobs from stdin and prints produced artifacts to stdout.
+//! The global `--in <file>` and `--out <file>` options redirect them to files with `set_input` and
+//! `set_output`, for platforms and orchestration tools where shell redirection is awkward. Output
+//! files are truncated unless appending is requested, and missing parent directories are created.
+//! Everything the agent prints or reads goes through `output` and `input`, so both honor the
+//! redirection; logs stay on stderr.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! set_output(Path::new("./out/commits.txt"), true)?;
+//! writeln!(output(), "{commit_b64}")?;
+//! for line in input().lines() { /* ... */ }
+//! ```
+
+use core::error::Error;
+use std::{
+    fs::{File, OpenOptions, create_dir_all},
+    io::{BufRead, BufReader, Write, stdin, stdout},
+    path::Path,
+    sync::OnceLock,
+};
+
+/// File standard input is redirected from, if any.
+static INPUT: OnceLock<File> = OnceLock::new();
+
+/// File standard output is redirected to, if any.
+static OUTPUT: OnceLock<File> = OnceLock::new();
+
+/// Read standard input from the file at `path` instead (once per process).
+///
+/// Example:
+///
+/// ```ignore
+/// set_input(Path::new("./in/kps.txt"))?;
+/// ```
+pub fn set_input(path: &Path) -> Result<(), Box<dyn Error>> {
+    INPUT
+        .set(File::open(path)?)
+        .map_err(|_| "Standard input is already redirected".into())
+}
+
+/// Write standard output to the file at `path` instead (once per process), appending to it or
+/// replacing its content, and creating missing parent directories.
+///
+/// Example:
+///
+/// ```ignore
+/// set_output(Path::new("./out/commits.txt"), true)?;
+/// ```
+pub fn set_output(path: &Path, append: bool) -> Result<(), Box<dyn Error>> {
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(append)
+        .truncate(!append)
+        .open(path)?;
+    OUTPUT
+        .set(file)
+        .map_err(|_| "Standard output is already redirected".into())
+}
+
+/// Returns the (possibly redirected) standard input.
+///
+/// Example:
+///
+/// ```ignore
+/// for line in input().lines() { /* ... */ }
+/// ```
+pub fn input() -> Box<dyn BufRead> {
+    match INPUT.get() {
+        Some(file) => Box::new(BufReader::new(file)),
+        None => Box::new(stdin().lock()),
+    }
+}
+
+/// Returns the (possibly redirected) standard output.
+///
+/// Example:
+///
+/// ```ignore
+/// writeln!(output(), "{commit_b64}")?;
+/// ```
+pub fn output() -> Box<dyn Write> {
+    match OUTPUT.get() {
+        Some(file) => Box::new(file),
+        None => Box::new(stdout().lock()),
+    }
+}
@@ -0,0 +1,275 @@
+//! Property-based convergence testing.
+//!
+//! This module (feature `testing`) provides an in-memory multi-agent `Harness` built on
+//! `simulate::Simulation` and `proptest` strategies generating random sequences of add, remove,
+//! update, PSK-injection commit and message operations. After running a sequence, the harness
+//! asserts that all members of every send group agree on the epoch, epoch authenticator, member
+//! set and the exporter PSK derived (and queued) for the current epoch.
+//!
+//! Operations whose preconditions do not hold (e.g. removing a non-member, or injecting a PSK
+//! that some recipient never derived) are skipped, so any sequence can be run. The harness is
+//! exported so that integrations built on this crate can run the same properties.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! proptest! {
+//!     #[test]
+//!     fn converges(ops in ops_strategy(3, 12)) {
+//!         let mut harness = Harness::new(3, ciphersuite, 32)?;
+//!         for op in &ops {
+//!             harness.apply(op)?;
+//!         }
+//!         harness.check_convergence()?;
+//!     }
+//! }
+//! ```
+
+use super::{
+    helpers::{exporter_psk, load_group, send_group},
+    simulate::{Simulation, Step},
+};
+use core::error::Error;
+use openmls_traits::types::Ciphersuite;
+use proptest::{collection::vec, prelude::*};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// An operation on a participant's send group; participants are referred to by index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Op {
+    /// `by` adds `member` to its send group, creating the group if necessary.
+    Add {
+        /// Owner of the send group.
+        by: usize,
+        /// Participant to add.
+        member: usize,
+    },
+    /// `by` removes `member` from its send group.
+    Remove {
+        /// Owner of the send group.
+        by: usize,
+        /// Participant to remove.
+        member: usize,
+    },
+    /// Self-update in a participant's send group.
+    Update(usize),
+    /// Inject a participant's queued PSKs into its send group.
+    Commit(usize),
+    /// Send an application message in a participant's send group.
+    Message {
+        /// Sender (owner of the send group).
+        from: usize,
+        /// Plaintext to send.
+        text: String,
+    },
+}
+
+/// Strategy for a single operation among `participants` participants.
+///
+/// Example:
+///
+/// ```ignore
+/// let op = op_strategy(3);
+/// ```
+pub fn op_strategy(participants: usize) -> impl Strategy<Value = Op> {
+    let p = 0..participants;
+    prop_oneof![
+        3 => (p.clone(), p.clone()).prop_map(|(by, member)| Op::Add { by, member }),
+        1 => (p.clone(), p.clone()).prop_map(|(by, member)| Op::Remove { by, member }),
+        2 => p.clone().prop_map(Op::Update),
+        2 => p.clone().prop_map(Op::Commit),
+        2 => (p, "[a-z ]{0,16}").prop_map(|(from, text)| Op::Message { from, text }),
+    ]
+}
+
+/// Strategy for a sequence of up to `max_ops` operations among `participants` participants.
+///
+/// Example:
+///
+/// ```ignore
+/// let ops = ops_strategy(3, 12);
+/// ```
+pub fn ops_strategy(participants: usize, max_ops: usize) -> impl Strategy<Value = Vec<Op>> {
+    vec(op_strategy(participants), 0..=max_ops)
+}
+
+/// An in-memory set of agents running generated operations.
+#[derive(Debug)]
+pub struct Harness {
+    /// The underlying simulation.
+    sim: Simulation,
+    /// Participant names, by index.
+    names: Vec<String>,
+    /// Participants holding each exporter PSK, by PSK id.
+    psk_holders: BTreeMap<Vec<u8>, BTreeSet<String>>,
+    /// Exporter length to use for DMLS exporter PSK.
+    exporter_length: usize,
+}
+
+impl Harness {
+    /// Creates a harness with `participants` fresh agents.
+    pub fn new(
+        participants: usize,
+        ciphersuite: Ciphersuite,
+        exporter_length: usize,
+    ) -> Result<Self, Box<dyn Error>> {
+        let names = (0..participants)
+            .map(|i| format!("p{i}"))
+            .collect::<Vec<_>>();
+        Ok(Self {
+            sim: Simulation::new(&names, ciphersuite, exporter_length, None)?,
+            names,
+            psk_holders: BTreeMap::new(),
+            exporter_length,
+        })
+    }
+    /// Returns the underlying simulation.
+    pub fn simulation(&self) -> &Simulation {
+        &self.sim
+    }
+    /// Returns the name of the participant with the given index.
+    fn name(&self, index: usize) -> Result<String, Box<dyn Error>> {
+        self.names
+            .get(index)
+            .cloned()
+            .ok_or_else(|| format!("No participant with index {index}").into())
+    }
+    /// Returns whether the participant has a send group.
+    fn has_send_group(&self, name: &str) -> bool {
+        self.sim
+            .agents()
+            .get(name)
+            .is_some_and(|agent| send_group(agent).is_ok())
+    }
+    /// Returns whether all current members of the participant's send group derived every PSK the
+    /// participant has queued, i.e. whether they can process its PSK-injection commit.
+    fn can_inject(&self, name: &str) -> bool {
+        let members = self.sim.members(name);
+        self.sim.agents()[name]
+            .state()
+            .exporter_psk_ids()
+            .iter()
+            .all(|psk_id| {
+                self.psk_holders
+                    .get(psk_id)
+                    .is_some_and(|holders| members.iter().all(|m| holders.contains(m)))
+            })
+    }
+}
+
+impl Harness {
+    /// Apply an operation, returning whether it was run (`false` if it was skipped).
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// harness.apply(&Op::Update(0))?;
+    /// ```
+    pub fn apply(&mut self, op: &Op) -> Result<bool, Box<dyn Error>> {
+        let step = match op {
+            Op::Add { by, member } => {
+                let (by, member) = (self.name(*by)?, self.name(*member)?);
+                if by == member || self.sim.members(&by).contains(&member) {
+                    return Ok(false);
+                }
+                Step::Add {
+                    by,
+                    members: vec![member],
+                }
+            }
+            Op::Remove { by, member } => {
+                let (by, member) = (self.name(*by)?, self.name(*member)?);
+                if !self.sim.members(&by).contains(&member) {
+                    return Ok(false);
+                }
+                Step::Remove {
+                    by,
+                    members: vec![member],
+                }
+            }
+            Op::Update(by) => {
+                let by = self.name(*by)?;
+                if !self.has_send_group(&by) {
+                    return Ok(false);
+                }
+                Step::Update(by)
+            }
+            Op::Commit(by) => {
+                let by = self.name(*by)?;
+                if !self.has_send_group(&by) || !self.can_inject(&by) {
+                    return Ok(false);
+                }
+                Step::Commit(by)
+            }
+            Op::Message { from, text } => {
+                let from = self.name(*from)?;
+                if !self.has_send_group(&from) {
+                    return Ok(false);
+                }
+                Step::Message {
+                    from,
+                    text: text.clone(),
+                }
+            }
+        };
+        let owner = match &step {
+            Step::Add { by, .. }
+            | Step::Remove { by, .. }
+            | Step::Update(by)
+            | Step::Commit(by) => by.clone(),
+            Step::Message { .. } => {
+                self.sim.step(&step)?;
+                return Ok(true);
+            }
+        };
+        let before = self.sim.members(&owner);
+        self.sim.step(&step)?;
+        // members that processed the commit derived the new epoch's exporter PSK, and so did new
+        // members joined via Welcome unless they disabled it; the owner only stores it for adds,
+        // removes and updates
+        let after = self.sim.members(&owner);
+        let mut holders = after
+            .into_iter()
+            .filter(|m| before.contains(m) || self.sim.agents()[m].state().welcome_psk())
+            .collect::<BTreeSet<_>>();
+        if !matches!(step, Step::Commit(_)) {
+            holders.insert(owner.clone());
+        }
+        let agent = &self.sim.agents()[&owner];
+        let (psk_id, _) = exporter_psk(agent, &send_group(agent)?, self.exporter_length)?;
+        self.psk_holders.insert(psk_id, holders);
+        Ok(true)
+    }
+
+    /// Check that all members of every send group converged.
+    ///
+    /// In addition to `Simulation::check_convergence` (epoch, epoch authenticator and members),
+    /// every member must derive the same exporter PSK id and secret for the current epoch.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// harness.check_convergence()?;
+    /// ```
+    pub fn check_convergence(&mut self) -> Result<(), Box<dyn Error>> {
+        self.sim.check_convergence()?;
+        for (owner, agent) in self.sim.agents() {
+            let Ok(sg) = send_group(agent) else {
+                continue;
+            };
+            let expected = exporter_psk(agent, &sg, self.exporter_length)?;
+            for name in self.sim.members(owner) {
+                let other = &self.sim.agents()[&name];
+                let actual = exporter_psk(
+                    other,
+                    &load_group(other, sg.group_id())?,
+                    self.exporter_length,
+                )?;
+                if actual != expected {
+                    return Err(format!("Exporter PSK of {name} in {owner}'s group differs").into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,227 @@
+//! Transports exchanging artifacts with other agents.
+//!
+//! Producing commands hand their encoded artifacts (commits, Welcomes, application messages) to a
+//! `Transport` to be delivered to the recipients, and `Process` fetches incoming messages from
+//! one. Every transport implements the same two operations, so the command-line agent selects
+//! one with `--transport` (see `parse_transport`) and is otherwise unaware of how messages
+//! travel. The default, `StdioTransport`, prints artifacts to the (possibly redirected) standard
+//! output and reads incoming messages from standard input, leaving delivery to whatever pipes
+//! them between agents; `HttpDeliveryService` (see `ds`) posts them to per-identity mailboxes,
+//! `NatsTransport` (see `nats`, `--features nats`) publishes them to per-group JetStream
+//! subjects, and `MatrixTransport` (see `matrix`, `--features matrix`) sends them as custom
+//! events into a Matrix room. Further transports (e.g. WebSocket or MQTT) implement the trait
+//! and add a specification prefix to `parse_transport`.
+//!
+//! Transports that keep messages, rather than handing each out once, report how far messages
+//! were fetched (`cursors`); the state stores these positions, and `resume` continues after them
+//! in the next run.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let transport = parse_transport("http:http://localhost:8080", Framing::Lines).unwrap();
+//! transport.send(&commit_b64, &member_identities(&sg))?;
+//! for blob in transport.fetch(&own_identity(&provider), 30)? {
+//!     process_body_main(&mut provider, bytes_extract(&blob?)?, ciphersuite, 32);
+//! }
+//! ```
+
+#[cfg(feature = "matrix")]
+use super::matrix::MatrixTransport;
+#[cfg(feature = "nats")]
+use super::nats::NatsTransport;
+use super::{
+    armor::Dearmor,
+    ds::HttpDeliveryService,
+    encoding::Blob,
+    framing::{Framing, read_frames, write_frame},
+    helpers::armor_base64,
+    stdio::{input, output},
+};
+use core::{error::Error, fmt::Debug};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+/// Incoming raw (decoded, TLS-encoded) messages, in the order they arrived.
+pub type Blobs = Box<dyn Iterator<Item = Result<Vec<u8>, Box<dyn Error>>>>;
+
+/// Position up to which messages were fetched from a stream of a transport.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Cursor {
+    /// Sequence number of the last message handed out, e.g. a JetStream stream sequence.
+    Sequence(u64),
+    /// Opaque token to continue from, e.g. a Matrix pagination token.
+    Token(String),
+}
+
+/// Delivers artifacts to, and fetches incoming messages from, other agents.
+pub trait Transport: Debug {
+    /// Deliver an encoded artifact to the recipients (credential identities).
+    fn send(&self, message: &str, recipients: &[Vec<u8>]) -> Result<(), Box<dyn Error>>;
+    /// Fetch the messages waiting in the inbox of a credential identity, waiting up to `wait`
+    /// seconds for some to arrive if the transport supports it.
+    fn fetch(&self, inbox: &[u8], wait: u64) -> Result<Blobs, Box<dyn Error>>;
+    /// Returns the key-package directory served along with the transport, if any.
+    fn directory(&self) -> Option<&dyn KeyPackageDirectory> {
+        None
+    }
+    /// Prepare fetching for the agent's groups, continuing after the positions stored in the
+    /// state (see `cursors`); transports that don't keep messages ignore this.
+    fn resume(&mut self, _cursors: &BTreeMap<String, Cursor>, _group_ids: &[Vec<u8>]) {}
+    /// Returns the positions up to which messages were fetched, keyed by stream, for the state
+    /// to store.
+    fn cursors(&self) -> BTreeMap<String, Cursor> {
+        BTreeMap::new()
+    }
+    /// Returns whether the sender of a message also fetches it, e.g. from a group's stream.
+    fn echoes(&self) -> bool {
+        false
+    }
+}
+
+/// Publishes key packages under, and hands them out by, credential identity.
+///
+/// A directory is separate from the `Transport` carrying messages; some transports serve one
+/// along (see `Transport::directory`), others leave key packages to `--url` or stdin.
+pub trait KeyPackageDirectory: Debug {
+    /// Publish base64 key packages under a credential identity.
+    fn publish_key_packages(
+        &self,
+        identity: &[u8],
+        key_packages: &[String],
+    ) -> Result<(), Box<dyn Error>>;
+    /// Fetch (and thereby consume) one base64 key package published for a credential identity;
+    /// `None` if there is none.
+    fn fetch_key_package(&self, identity: &[u8]) -> Result<Option<String>, Box<dyn Error>>;
+}
+
+/// Transport over the (possibly redirected) standard input and output.
+///
+/// Artifacts are written for all recipients alike and incoming messages are read regardless of
+/// the inbox, both with the framing.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct StdioTransport {
+    /// Framing of artifacts and incoming messages.
+    framing: Framing,
+}
+
+impl StdioTransport {
+    /// Creates a transport with the given framing.
+    pub fn new(framing: Framing) -> Self {
+        Self { framing }
+    }
+}
+
+impl Transport for StdioTransport {
+    fn send(&self, message: &str, _recipients: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+        emit(self.framing, message)
+    }
+    fn fetch(&self, _inbox: &[u8], _wait: u64) -> Result<Blobs, Box<dyn Error>> {
+        Ok(read_blobs(self.framing))
+    }
+}
+
+impl Transport for HttpDeliveryService {
+    /// Post the artifact to the mailbox of every recipient, even if some posts fail.
+    fn send(&self, message: &str, recipients: &[Vec<u8>]) -> Result<(), Box<dyn Error>> {
+        let mut failed = Vec::new();
+        for recipient in recipients {
+            if let Err(e) = self.post_messages(recipient, &[message.to_string()]) {
+                failed.push(e.to_string());
+            }
+        }
+        match failed.last() {
+            None => Ok(()),
+            Some(e) => Err(format!(
+                "{} of {} deliveries to {} failed: {e}",
+                failed.len(),
+                recipients.len(),
+                self.url()
+            )
+            .into()),
+        }
+    }
+    fn fetch(&self, inbox: &[u8], wait: u64) -> Result<Blobs, Box<dyn Error>> {
+        let messages = self.fetch_messages(inbox, wait)?;
+        Ok(Box::new(
+            messages.into_iter().map(|message| Blob.decode(message)),
+        ))
+    }
+    fn directory(&self) -> Option<&dyn KeyPackageDirectory> {
+        Some(self)
+    }
+}
+
+/// Strip a transport prefix from a specification, returning the URL: `<prefix>:<url>`, or the
+/// specification itself if it is a plain `<prefix>://` URL.
+fn transport_url<'a>(spec: &'a str, prefix: &str) -> Option<&'a str> {
+    let url = spec.strip_prefix(prefix)?.strip_prefix(':')?;
+    Some(if url.starts_with("//") { spec } else { url })
+}
+
+/// Parse a transport specification: `stdio`, `http:<url>` (or a plain `http://` URL) of a
+/// delivery service, `nats:<url>` (or a plain `nats://` URL) of a NATS server with JetStream
+/// (`--features nats`), connecting to it, or `matrix:<homeserver url>?room=<room id>` of a
+/// Matrix room (`--features matrix`).
+///
+/// Example:
+///
+/// ```ignore
+/// let transport = parse_transport(&args.transport, framing)?;
+/// ```
+pub fn parse_transport(spec: &str, framing: Framing) -> Result<Box<dyn Transport>, Box<dyn Error>> {
+    if spec == "stdio" {
+        return Ok(Box::new(StdioTransport::new(framing)));
+    }
+    if let Some(url) = transport_url(spec, "http") {
+        return Ok(Box::new(HttpDeliveryService::new(url)));
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = transport_url(spec, "nats") {
+        return Ok(Box::new(NatsTransport::connect(url)?));
+    }
+    #[cfg(feature = "matrix")]
+    if let Some(spec) = spec.strip_prefix("matrix:") {
+        return Ok(Box::new(MatrixTransport::from_spec(spec)?));
+    }
+    Err(format!("Unknown transport {spec}").into())
+}
+
+/// Write an encoded artifact to the output (stdout or `--out`) using the framing.
+///
+/// With `raw-len32`, the artifact is decoded and written as a binary frame.
+///
+/// Example:
+///
+/// ```ignore
+/// emit(Framing::Armor, &kp_b64)?;
+/// ```
+pub fn emit(framing: Framing, message: &str) -> Result<(), Box<dyn Error>> {
+    match framing {
+        Framing::Lines => writeln!(output(), "{message}")?,
+        Framing::Armor => writeln!(output(), "{}", armor_base64(message)?)?,
+        Framing::RawLen32 => write_frame(&mut output(), &Blob.decode(message)?)?,
+    }
+    Ok(())
+}
+
+/// Read incoming blobs from the input (stdin or `--in`) using the framing.
+///
+/// With `lines` or `armor`, every line (or armored block) is decoded; with `raw-len32`, frames
+/// are returned as they are.
+///
+/// Example:
+///
+/// ```ignore
+/// for blob in read_blobs(Framing::Lines) { /* ... */ }
+/// ```
+pub fn read_blobs(framing: Framing) -> Blobs {
+    match framing {
+        Framing::Lines | Framing::Armor => Box::new(Dearmor::new(input().lines())),
+        Framing::RawLen32 => Box::new(read_frames(input()).map(|frame| Ok(frame?))),
+    }
+}
@@ -0,0 +1,239 @@
+//! Ratchet tree visualization.
+//!
+//! `TreeView` captures the shape of a group's ratchet tree (using the array representation of
+//! RFC 9420, appendix C: leaves at even node indices, parents at odd ones) together with the
+//! credential identity at every occupied leaf, and renders it as ASCII art or as a Graphviz DOT
+//! graph. This is meant for teaching and for debugging how the tree evolves after commits.
+//!
+//! `TreeStats` summarizes the tree's health instead: its depth and how many of its nodes are
+//! blank or carry unmerged leaves. Both make commits larger and are fixed by full-path updates.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! let view = TreeView::new(&group);
+//! println!("{}", view.to_ascii());
+//! std::fs::write("tree.dot", view.to_dot())?;
+//! println!("{:?}", TreeStats::new(&group)?);
+//! ```
+
+use super::encoding::Blob;
+use core::error::Error;
+use openmls::group::MlsGroup;
+use tls_codec::{Deserialize, Serialize as _, VLBytes};
+
+/// The leaves of a ratchet tree, with the identity of each occupied leaf.
+#[derive(Clone, Debug)]
+pub struct TreeView {
+    /// Credential identity per leaf (`None` for blank leaves); the length is a power of two.
+    leaves: Vec<Option<Vec<u8>>>,
+    /// Index of the local member's leaf.
+    own_leaf: usize,
+}
+
+impl TreeView {
+    /// Captures the ratchet tree of the group.
+    pub fn new(group: &MlsGroup) -> Self {
+        let members = group.members().collect::<Vec<_>>();
+        let width = members
+            .iter()
+            .map(|m| m.index.usize() + 1)
+            .max()
+            .unwrap_or(1)
+            .next_power_of_two();
+        let mut leaves = vec![None; width];
+        for member in members {
+            leaves[member.index.usize()] = Some(member.credential.serialized_content().to_vec());
+        }
+        Self {
+            leaves,
+            own_leaf: group.own_leaf_index().usize(),
+        }
+    }
+    /// Returns the node index of the root.
+    fn root(&self) -> usize {
+        self.leaves.len() - 1
+    }
+    /// Returns the label of the node with the given index.
+    fn label(&self, node: usize) -> String {
+        if node % 2 == 1 {
+            return format!("node {node}");
+        }
+        let leaf = node / 2;
+        let identity = match &self.leaves[leaf] {
+            None => "(blank)".to_string(),
+            Some(identity) => Blob.encode(identity),
+        };
+        if leaf == self.own_leaf {
+            format!("leaf {leaf}: {identity} (you)")
+        } else {
+            format!("leaf {leaf}: {identity}")
+        }
+    }
+    /// Returns the children of a parent node.
+    fn children(node: usize) -> (usize, usize) {
+        let k = node.trailing_ones();
+        (node ^ (1 << (k - 1)), node ^ (3 << (k - 1)))
+    }
+
+    /// Render the tree sideways as ASCII art, root on the left and leaf 0 at the top.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// println!("{}", TreeView::new(&group).to_ascii());
+    /// ```
+    pub fn to_ascii(&self) -> String {
+        let mut out = String::new();
+        self.ascii_node(self.root(), "", "", &mut out);
+        out
+    }
+    /// Append the subtree rooted at `node` to `out`.
+    fn ascii_node(&self, node: usize, first: &str, rest: &str, out: &mut String) {
+        out.push_str(first);
+        out.push_str(&self.label(node));
+        out.push('\n');
+        if node % 2 == 1 {
+            let (left, right) = Self::children(node);
+            self.ascii_node(left, &format!("{rest}+-- "), &format!("{rest}|   "), out);
+            self.ascii_node(right, &format!("{rest}`-- "), &format!("{rest}    "), out);
+        }
+    }
+
+    /// Render the tree as a Graphviz DOT graph.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// std::fs::write("tree.dot", TreeView::new(&group).to_dot())?;
+    /// ```
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph ratchet_tree {\n    node [shape=box];\n");
+        for node in 0..2 * self.leaves.len() - 1 {
+            // blank leaves are dashed
+            let style = if node % 2 == 0 && self.leaves[node / 2].is_none() {
+                ", style=dashed"
+            } else {
+                ""
+            };
+            out.push_str(&format!(
+                "    n{node} [label=\"{}\"{style}];\n",
+                self.label(node)
+            ));
+            if node % 2 == 1 {
+                let (left, right) = Self::children(node);
+                out.push_str(&format!(
+                    "    n{node} -> n{left};\n    n{node} -> n{right};\n"
+                ));
+            }
+        }
+        out.push_str("}\n");
+        out
+    }
+}
+
+/// Shape and health of a ratchet tree.
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize)]
+pub struct TreeStats {
+    /// Number of levels below the root.
+    pub depth: u32,
+    /// Number of leaves, including blank ones.
+    pub leaves: usize,
+    /// Number of blank (leaf and parent) nodes.
+    pub blank_nodes: usize,
+    /// Number of unmerged leaves recorded in parent nodes.
+    pub unmerged_leaves: usize,
+}
+
+/// A non-blank node of the RFC 9420 TLS encoding of a ratchet tree.
+enum TreeNode {
+    /// A leaf node.
+    Leaf,
+    /// A parent node with the number of its unmerged leaves.
+    Parent(usize),
+}
+
+impl TreeNode {
+    /// Read a node, skipping over the fields that aren't needed.
+    fn read(bytes: &mut &[u8]) -> Result<Self, tls_codec::Error> {
+        match u8::tls_deserialize(bytes)? {
+            1 => {
+                // encryption key, signature key, credential
+                VLBytes::tls_deserialize(bytes)?;
+                VLBytes::tls_deserialize(bytes)?;
+                u16::tls_deserialize(bytes)?;
+                VLBytes::tls_deserialize(bytes)?;
+                // capabilities: versions, ciphersuites, extensions, proposals, credentials
+                for _ in 0..5 {
+                    VLBytes::tls_deserialize(bytes)?;
+                }
+                // leaf node source: key package (lifetime), update or commit (parent hash)
+                match u8::tls_deserialize(bytes)? {
+                    1 => {
+                        u64::tls_deserialize(bytes)?;
+                        u64::tls_deserialize(bytes)?;
+                    }
+                    2 => {}
+                    3 => {
+                        VLBytes::tls_deserialize(bytes)?;
+                    }
+                    source => {
+                        return Err(tls_codec::Error::DecodingError(format!(
+                            "Unknown leaf node source {source}"
+                        )));
+                    }
+                }
+                // extensions, signature
+                VLBytes::tls_deserialize(bytes)?;
+                VLBytes::tls_deserialize(bytes)?;
+                Ok(Self::Leaf)
+            }
+            2 => {
+                // encryption key, parent hash, unmerged leaves (u32 each)
+                VLBytes::tls_deserialize(bytes)?;
+                VLBytes::tls_deserialize(bytes)?;
+                let unmerged_leaves = VLBytes::tls_deserialize(bytes)?;
+                Ok(Self::Parent(unmerged_leaves.as_slice().len() / 4))
+            }
+            node_type => Err(tls_codec::Error::DecodingError(format!(
+                "Unknown node type {node_type}"
+            ))),
+        }
+    }
+}
+
+impl TreeStats {
+    /// Computes the statistics of the group's ratchet tree.
+    ///
+    /// Example:
+    ///
+    /// ```ignore
+    /// let stats = TreeStats::new(&group)?;
+    /// println!("{} of {} nodes are blank", stats.blank_nodes, 2 * stats.leaves - 1);
+    /// ```
+    pub fn new(group: &MlsGroup) -> Result<Self, Box<dyn Error>> {
+        let encoded = group.export_ratchet_tree().tls_serialize_detached()?;
+        let nodes = VLBytes::tls_deserialize_exact(&encoded)?;
+        let mut bytes = nodes.as_slice();
+        let (mut count, mut blank_nodes, mut unmerged_leaves) = (0, 0, 0);
+        while !bytes.is_empty() {
+            count += 1;
+            if u8::tls_deserialize(&mut bytes)? == 0 {
+                blank_nodes += 1;
+                continue;
+            }
+            if let TreeNode::Parent(unmerged) = TreeNode::read(&mut bytes)? {
+                unmerged_leaves += unmerged;
+            }
+        }
+        // trailing blank nodes are left out of the encoding
+        let leaves = count.div_ceil(2).next_power_of_two();
+        blank_nodes += (2 * leaves - 1).saturating_sub(count);
+        Ok(Self {
+            depth: leaves.trailing_zeros(),
+            leaves,
+            blank_nodes,
+            unmerged_leaves,
+        })
+    }
+}
@@ -0,0 +1,94 @@
+//! Application-defined credential validation.
+//!
+//! DMLS only checks that credentials are well-formed and signed; whether an identity is who it
+//! claims to be is up to the application. A library user plugs in a `CredentialValidator` with
+//! `DmlsProvider::with_credential_validator`, and `validate` then hands it every credential the
+//! agent is about to accept: those of key packages read or taken from the address book, those of
+//! all members of a group joined from a Welcome, and those of members added or leaf nodes changed
+//! by received commits. A rejection is a `DmlsError::CredentialRejected`: the key package is
+//! dropped, the Welcome isn't joined (the group is deleted again), or the commit isn't merged.
+//! Without a validator, all credentials are accepted.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! #[derive(Debug)]
+//! struct Directory(HashSet<Vec<u8>>);
+//!
+//! impl CredentialValidator for Directory {
+//!     fn validate(&self, credential: &Credential, _: &[u8], _: CredentialSource)
+//!         -> Result<(), Box<dyn Error>> {
+//!         if self.0.contains(credential.serialized_content()) { Ok(()) }
+//!         else { Err("unknown identity".into()) }
+//!     }
+//! }
+//!
+//! let provider = DmlsProvider::new(state, crypto).with_credential_validator(Arc::new(directory));
+//! ```
+
+use super::{error::DmlsError, provider::DmlsProvider};
+use core::{error::Error, fmt::Debug};
+use openmls::{credentials::Credential, treesync::LeafNode};
+
+/// Where a credential being validated was seen.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CredentialSource {
+    /// A key package, read or taken from the address book.
+    KeyPackage,
+    /// A member of a group joined from a Welcome.
+    Welcome,
+    /// A member added, or a leaf node changed, by a received commit.
+    Commit,
+}
+
+/// Verifies credentials before the agent accepts them, e.g. against an identity provider.
+pub trait CredentialValidator: Debug + Send + Sync {
+    /// Accept the credential, bound to the given signature public key, or reject it with a reason.
+    fn validate(
+        &self,
+        credential: &Credential,
+        signature_key: &[u8],
+        source: CredentialSource,
+    ) -> Result<(), Box<dyn Error>>;
+}
+
+/// Pass a credential to the provider's validator, if any.
+///
+/// Example:
+///
+/// ```ignore
+/// validate(&provider, &m.credential, &m.signature_key, CredentialSource::Welcome)?;
+/// ```
+pub fn validate(
+    provider: &DmlsProvider,
+    credential: &Credential,
+    signature_key: &[u8],
+    source: CredentialSource,
+) -> Result<(), Box<dyn Error>> {
+    match provider.credential_validator() {
+        None => Ok(()),
+        Some(validator) => validator
+            .validate(credential, signature_key, source)
+            .map_err(|e| DmlsError::CredentialRejected(e.to_string()).into()),
+    }
+}
+
+/// Pass the credential of a leaf node to the provider's validator, if any.
+///
+/// Example:
+///
+/// ```ignore
+/// validate_leaf_node(&provider, kp.leaf_node(), CredentialSource::KeyPackage)?;
+/// ```
+pub fn validate_leaf_node(
+    provider: &DmlsProvider,
+    leaf_node: &LeafNode,
+    source: CredentialSource,
+) -> Result<(), Box<dyn Error>> {
+    validate(
+        provider,
+        leaf_node.credential(),
+        leaf_node.signature_key().as_slice(),
+        source,
+    )
+}
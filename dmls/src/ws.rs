@@ -0,0 +1,77 @@
+//! WebSocket message stream.
+//!
+//! This module connects to a WebSocket endpoint and hands every received MLS message to a
+//! callback as it arrives, which lets `Process` run as a long-running agent instead of a batch
+//! command. Text frames are expected to carry base64 messages (one or more, newline-separated,
+//! exactly as printed by the agent); binary frames carry a single raw TLS-encoded MLS message.
+//!
+//! Control frames are handled by the WebSocket implementation; the stream ends when the server
+//! closes the connection or the callback returns `ControlFlow::Break`.
+//!
+//! Example (pseudo-Rust):
+//!
+//! ```ignore
+//! listen("ws://localhost:9000/alice", |frame| {
+//!     match frame {
+//!         WsFrame::Text(line) => { /* base64 message */ }
+//!         WsFrame::Binary(bytes) => { /* raw message */ }
+//!     }
+//!     ControlFlow::Continue(())
+//! })?;
+//! ```
+
+use core::{error::Error, ops::ControlFlow};
+use tungstenite::{Message, connect, error::Error as WsError};
+
+/// A single MLS message received over the WebSocket.
+#[derive(Clone, Debug)]
+pub enum WsFrame {
+    /// A base64-encoded message (one line of a text frame).
+    Text(String),
+    /// A raw TLS-encoded message (a binary frame).
+    Binary(Vec<u8>),
+}
+
+/// Connect to the WebSocket endpoint at `url` and call `on_frame` for every received message.
+///
+/// Blocks until the server closes the connection or `on_frame` breaks (then the connection is
+/// closed); connection and protocol errors are returned.
+///
+/// Example:
+///
+/// ```ignore
+/// listen(&url, |frame| handle(&mut provider, frame))?;
+/// ```
+pub fn listen(
+    url: &str,
+    mut on_frame: impl FnMut(WsFrame) -> ControlFlow<()>,
+) -> Result<(), Box<dyn Error>> {
+    let (mut socket, _) = connect(url)?;
+    tracing::info!("Connected to {url}");
+    loop {
+        let flow = match socket.read() {
+            Ok(Message::Text(text)) => text
+                .as_str()
+                .lines()
+                .filter(|l| !l.trim().is_empty())
+                .try_for_each(|line| on_frame(WsFrame::Text(line.trim().to_string()))),
+            Ok(Message::Binary(bytes)) => on_frame(WsFrame::Binary(bytes.to_vec())),
+            Ok(Message::Close(_)) | Err(WsError::ConnectionClosed) => {
+                tracing::info!("Connection to {url} closed");
+                return Ok(());
+            }
+            Ok(_) => {
+                // ping/pong and raw frames; replies are queued by tungstenite
+                ControlFlow::Continue(())
+            }
+            Err(e) => {
+                return Err(e.into());
+            }
+        };
+        if flow.is_break() {
+            tracing::info!("Closing connection to {url}");
+            socket.close(None)?;
+            return Ok(());
+        }
+    }
+}
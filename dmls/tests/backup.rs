@@ -0,0 +1,24 @@
+//! Backups are sealed only under keys long enough to resist guessing, and open with the same key.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::{
+    backup::{MIN_KEY_LEN, open, seal},
+    openmls_keys::SignatureKeyPair,
+    state::DmlsState,
+};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::SignatureScheme;
+
+#[test]
+fn short_backup_keys_are_rejected() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ED25519).unwrap();
+    let state = DmlsState::new(skp);
+    assert!(seal(&crypto, &state, b"", false).is_err());
+    assert!(seal(&crypto, &state, &[7; MIN_KEY_LEN - 1], false).is_err());
+    let key = [7; MIN_KEY_LEN];
+    let snapshot = seal(&crypto, &state, &key, false).unwrap();
+    assert!(open(&crypto, &snapshot, &[8; MIN_KEY_LEN]).is_err());
+    open(&crypto, &snapshot, &key).unwrap();
+}
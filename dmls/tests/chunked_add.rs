@@ -0,0 +1,58 @@
+//! Commit and Welcome sizes when adding many members at once or in chunks.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use dmls::{
+    encoding::Blob,
+    helpers::{GroupConfigOptions, add_members, bytes_to_kp, gen_kp_base64, gen_send_group},
+};
+use openmls::key_packages::KeyPackage;
+use tls_codec::Serialize;
+
+/// Adds `members` key packages to a new group at once and in chunks of `chunk_size`, checking
+/// that every chunk's commit stays within 5/4 of its proportional share of the single commit
+/// (the rest being the framing every commit carries) and its Welcome below the single Welcome.
+fn check_chunked_add(members: usize, chunk_size: usize) {
+    let kps: Vec<KeyPackage> = (0..members)
+        .map(|_| {
+            let joiner = agent();
+            let kp = gen_kp_base64(&joiner, CIPHERSUITE).unwrap();
+            bytes_to_kp(&joiner, &Blob.decode(kp).unwrap()).unwrap()
+        })
+        .collect();
+    let options = GroupConfigOptions::default();
+    // everyone at once
+    let mut creator = agent();
+    let mut group = gen_send_group(&mut creator, CIPHERSUITE, &options).unwrap();
+    let (commit, welcome) = add_members(&mut creator, &mut group, &kps, CIPHERSUITE, 32).unwrap();
+    assert_eq!(group.members().count(), members + 1);
+    let commit_size = commit.tls_serialized_len();
+    let welcome_size = welcome.tls_serialized_len();
+    // in chunks
+    let mut creator = agent();
+    let mut group = gen_send_group(&mut creator, CIPHERSUITE, &options).unwrap();
+    for chunk in kps.chunks(chunk_size) {
+        let (commit, welcome) =
+            add_members(&mut creator, &mut group, chunk, CIPHERSUITE, 32).unwrap();
+        // each commit carries only its chunk's key packages
+        assert!(commit.tls_serialized_len() * members * 4 <= commit_size * chunk.len() * 5);
+        // each Welcome carries only its chunk's group secrets
+        assert!(welcome.tls_serialized_len() < welcome_size);
+    }
+    assert_eq!(group.members().count(), members + 1);
+    assert_eq!(group.epoch().as_u64(), members.div_ceil(chunk_size) as u64);
+}
+
+#[test]
+fn chunked_add_keeps_commits_and_welcomes_small() {
+    check_chunked_add(40, 10);
+}
+
+#[test]
+#[ignore = "slow: generates and adds 500 key packages twice"]
+fn chunked_add_keeps_commits_and_welcomes_small_at_500_members() {
+    check_chunked_add(500, 50);
+}
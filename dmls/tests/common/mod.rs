@@ -0,0 +1,15 @@
+//! Fixtures shared by the integration tests.
+
+use dmls::{openmls_keys::SignatureKeyPair, provider::DmlsProvider, state::DmlsState};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::Ciphersuite;
+
+/// Ciphersuite of the tests' key packages and groups.
+pub const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+/// Returns the provider of a new agent with a fresh signing key.
+pub fn agent() -> DmlsProvider {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, CIPHERSUITE.signature_algorithm()).unwrap();
+    DmlsProvider::new(DmlsState::new(skp), crypto)
+}
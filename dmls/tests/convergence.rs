@@ -0,0 +1,23 @@
+//! Property-based convergence tests (requires the `testing` feature).
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::testing::{Harness, ops_strategy};
+use openmls_traits::types::Ciphersuite;
+use proptest::prelude::*;
+
+const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+proptest! {
+    // every case runs a full set of MLS agents, so keep the number of cases small
+    #![proptest_config(ProptestConfig::with_cases(16))]
+
+    #[test]
+    fn random_operations_converge(ops in ops_strategy(3, 12)) {
+        let mut harness = Harness::new(3, CIPHERSUITE, 32).unwrap();
+        for op in &ops {
+            harness.apply(op).unwrap();
+        }
+        harness.check_convergence().unwrap();
+    }
+}
@@ -0,0 +1,59 @@
+//! Key packages with credentials the application's validator rejects aren't accepted.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use core::error::Error;
+use dmls::{
+    encoding::Blob,
+    error::DmlsError,
+    helpers::{bytes_to_kp, gen_kp_base64, own_identity},
+    validator::{CredentialSource, CredentialValidator},
+};
+use openmls::credentials::Credential;
+use std::sync::Arc;
+
+/// Accepts only the listed identities.
+#[derive(Debug)]
+struct Directory(Vec<Vec<u8>>);
+
+impl CredentialValidator for Directory {
+    fn validate(
+        &self,
+        credential: &Credential,
+        _signature_key: &[u8],
+        source: CredentialSource,
+    ) -> Result<(), Box<dyn Error>> {
+        assert_eq!(source, CredentialSource::KeyPackage);
+        if self
+            .0
+            .iter()
+            .any(|id| id == credential.serialized_content())
+        {
+            Ok(())
+        } else {
+            Err("unknown identity".into())
+        }
+    }
+}
+
+#[test]
+fn validator_rejects_unknown_key_packages() {
+    let (mut known, mut unknown) = (agent(), agent());
+    let creator =
+        agent().with_credential_validator(Arc::new(Directory(vec![own_identity(&known)])));
+    let kp = Blob
+        .decode(gen_kp_base64(&known, CIPHERSUITE).unwrap())
+        .unwrap();
+    assert!(bytes_to_kp(&creator, &kp).is_ok());
+    let kp = Blob
+        .decode(gen_kp_base64(&unknown, CIPHERSUITE).unwrap())
+        .unwrap();
+    let e = bytes_to_kp(&creator, &kp).unwrap_err();
+    assert_eq!(
+        e.downcast_ref::<DmlsError>(),
+        Some(&DmlsError::CredentialRejected("unknown identity".into()))
+    );
+}
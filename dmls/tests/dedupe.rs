@@ -0,0 +1,59 @@
+//! Repeated deliveries of a message are recognized, within the dedupe capacity, and only once it
+//! was processed successfully.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use dmls::{
+    encoding::Blob,
+    helpers::{
+        GroupCache, GroupConfigOptions, add_members_base64, bytes_to_kp, check_duplicate,
+        decode_blob, gen_kp_base64, gen_send_group, load_group, process_decoded,
+    },
+    provider::DmlsProvider,
+};
+
+#[test]
+fn duplicates_are_recognized_until_forgotten() {
+    let mut provider = agent();
+    provider.state_mut().set_dedupe_capacity(Some(2));
+    assert!(!check_duplicate(&mut provider, b"a").unwrap());
+    assert!(!check_duplicate(&mut provider, b"b").unwrap());
+    // seeing "a" again makes "b" the least recently seen
+    assert!(check_duplicate(&mut provider, b"a").unwrap());
+    assert!(!check_duplicate(&mut provider, b"c").unwrap());
+    assert!(check_duplicate(&mut provider, b"a").unwrap());
+    assert!(!check_duplicate(&mut provider, b"b").unwrap());
+    provider.state_mut().set_dedupe_capacity(Some(0));
+    assert!(!check_duplicate(&mut provider, b"b").unwrap());
+}
+
+#[test]
+fn failed_message_is_processed_when_delivered_again() {
+    let (mut owner, mut early, mut late) = (agent(), agent(), agent());
+    let mut sg = gen_send_group(&mut owner, CIPHERSUITE, &GroupConfigOptions::default()).unwrap();
+    let mut add = |owner: &mut DmlsProvider, member: &mut DmlsProvider| {
+        let kp = Blob
+            .decode(gen_kp_base64(member, CIPHERSUITE).unwrap())
+            .unwrap();
+        let kps = vec![bytes_to_kp(owner, &kp).unwrap()];
+        add_members_base64(owner, &mut sg, &kps, CIPHERSUITE, 32).unwrap()
+    };
+    let (_, welcome) = add(&mut owner, &mut early);
+    let (commit, _) = add(&mut owner, &mut late);
+    let mut groups = GroupCache::new();
+    let mut deliver = |to: &mut DmlsProvider, message: &str| {
+        let decoded = decode_blob(&Blob.decode(message).unwrap(), false).unwrap();
+        process_decoded(to, &mut groups, decoded, None, CIPHERSUITE, 32)
+    };
+    // the commit overtakes the Welcome of its group and fails
+    assert!(deliver(&mut early, &commit).is_err());
+    deliver(&mut early, &welcome).unwrap();
+    // redelivered, it is processed this time, and skipped from then on
+    deliver(&mut early, &commit).unwrap();
+    deliver(&mut early, &commit).unwrap();
+    let group = load_group(&early, sg.group_id()).unwrap();
+    assert_eq!(group.epoch(), sg.epoch());
+}
@@ -0,0 +1,35 @@
+//! Deterministic test identities (requires the `deterministic-rng` feature).
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::{provider::DmlsProvider, state::DmlsState};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{OpenMlsProvider, random::OpenMlsRand};
+
+#[test]
+fn same_seed_gives_same_identity_and_randomness() {
+    let a = DmlsProvider::new(DmlsState::new_deterministic(42), RustCrypto::default());
+    let b = DmlsProvider::new(DmlsState::new_deterministic(42), RustCrypto::default());
+    let c = DmlsProvider::new(DmlsState::new_deterministic(43), RustCrypto::default());
+    assert_eq!(
+        a.state().signature_key_pair().public_key_raw(),
+        b.state().signature_key_pair().public_key_raw()
+    );
+    assert_ne!(
+        a.state().signature_key_pair().public_key_raw(),
+        c.state().signature_key_pair().public_key_raw()
+    );
+    assert_eq!(
+        a.rand().random_vec(32).unwrap(),
+        b.rand().random_vec(32).unwrap()
+    );
+}
+
+#[test]
+fn reloaded_state_continues_the_random_stream() {
+    let provider = DmlsProvider::new(DmlsState::new_deterministic(42), RustCrypto::default());
+    let first = provider.rand().random_vec(32).unwrap();
+    let state: DmlsState = provider.into();
+    let reloaded = DmlsProvider::new(state, RustCrypto::default());
+    assert_ne!(reloaded.rand().random_vec(32).unwrap(), first);
+}
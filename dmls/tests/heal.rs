@@ -0,0 +1,55 @@
+//! A member rejoining a group with an external commit (`heal`) is accepted by the owner and
+//! queues the new epoch's exporter PSK.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use dmls::{
+    encoding::Blob,
+    helpers::{
+        GroupConfigOptions, add_members_base64, bytes_to_kp, export_group_info_base64,
+        gen_kp_base64, gen_send_group, heal_base64, load_group, process_body_main, send_group,
+        stdin_base64_extract,
+    },
+    provider::DmlsProvider,
+};
+
+fn deliver(to: &mut DmlsProvider, message: &str) {
+    let body = stdin_base64_extract(Ok(message.to_string())).unwrap();
+    process_body_main(to, body, CIPHERSUITE, 32);
+}
+
+#[test]
+fn healed_member_rejoins_and_queues_psk() {
+    let (mut owner, mut member) = (agent(), agent());
+    let mut sg = gen_send_group(&mut owner, CIPHERSUITE, &GroupConfigOptions::default()).unwrap();
+    let kp = Blob
+        .decode(gen_kp_base64(&member, CIPHERSUITE).unwrap())
+        .unwrap();
+    let kps = vec![bytes_to_kp(&owner, &kp).unwrap()];
+    let (_, welcome) = add_members_base64(&mut owner, &mut sg, &kps, CIPHERSUITE, 32).unwrap();
+    deliver(&mut member, &welcome);
+    let group_id = sg.group_id().clone();
+    assert!(load_group(&member, &group_id).is_ok());
+    // the member's copy of the group is compromised; rejoin from a fresh GroupInfo
+    let group_info = export_group_info_base64(&owner, &sg, true).unwrap();
+    let queued = member.state().exporter_psk_ids().len();
+    let commit = heal_base64(
+        &mut member,
+        &Blob.encode(group_id.as_slice()),
+        &Blob.decode(group_info).unwrap(),
+        None,
+        32,
+    )
+    .unwrap();
+    assert_eq!(member.state().exporter_psk_ids().len(), queued + 1);
+    deliver(&mut owner, &commit);
+    let sg = send_group(&owner).unwrap();
+    let healed = load_group(&member, &group_id).unwrap();
+    assert_eq!(sg.epoch(), healed.epoch());
+    assert_eq!(sg.epoch_authenticator(), healed.epoch_authenticator());
+    // the commit replaced the old leaf
+    assert_eq!(sg.members().count(), 2);
+}
@@ -0,0 +1,35 @@
+//! Group lifecycle under the hybrid X25519 + ML-KEM ciphersuite (requires the `testing` and `pq`
+//! features).
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::testing::{Harness, Op};
+use openmls_traits::types::Ciphersuite;
+
+const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_256_XWING_CHACHA20POLY1305_SHA256_Ed25519;
+
+#[test]
+fn hybrid_group_lifecycle_converges() {
+    let mut harness = Harness::new(3, CIPHERSUITE, 32).unwrap();
+    let ops = [
+        Op::Add { by: 0, member: 1 },
+        Op::Add { by: 0, member: 2 },
+        Op::Add { by: 1, member: 0 },
+        Op::Message {
+            from: 0,
+            text: "hello".to_string(),
+        },
+        Op::Update(0),
+        Op::Commit(1),
+        Op::Message {
+            from: 1,
+            text: "hi".to_string(),
+        },
+        Op::Remove { by: 0, member: 2 },
+        Op::Update(0),
+    ];
+    for op in &ops {
+        harness.apply(op).unwrap();
+    }
+    harness.check_convergence().unwrap();
+}
@@ -0,0 +1,28 @@
+//! The checked-in test vectors pass.
+//!
+//! `vectors/tree-math.json` follows the format of the mls-implementations vectors and was
+//! generated with the reference code of RFC 9420, Appendix C, for trees of 1 to 64 leaves.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::interop::verify_file;
+use openmls_rust_crypto::RustCrypto;
+
+#[test]
+fn tree_math_vectors_pass() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors/tree-math.json");
+    assert_eq!(verify_file(path, &RustCrypto::default()).unwrap(), 7);
+}
+
+#[test]
+fn tampered_tree_math_vectors_fail() {
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/vectors/tree-math.json");
+    let mut vectors: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    vectors[3]["parent"][0] = serde_json::json!(2);
+    let tampered = std::env::temp_dir().join(format!("dmls-tree-math-{}.json", std::process::id()));
+    std::fs::write(&tampered, vectors.to_string()).unwrap();
+    let result = verify_file(tampered.to_str().unwrap(), &RustCrypto::default());
+    std::fs::remove_file(&tampered).unwrap();
+    assert!(result.is_err());
+}
@@ -0,0 +1,157 @@
+//! Concurrency tests for `OpenMlsKeyValueStore`: list updates from many threads must not lose or
+//! corrupt entries, and a panic while the store is locked must not make it unusable.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::openmls_kvstore::OpenMlsKeyValueStore;
+use openmls_traits::storage::{CURRENT_VERSION, Entity, Key, StorageProvider, traits};
+use serde::{Deserialize, Serialize};
+use std::{sync::Arc, thread};
+use tracing::{
+    Event, Metadata, Subscriber,
+    span::{Attributes, Id as SpanId, Record},
+};
+
+const THREADS: u32 = 8;
+const ITEMS: u32 = 50;
+
+/// Stand-in for the OpenMLS types; the store only sees their JSON serialization.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Id(u32);
+
+impl Key<CURRENT_VERSION> for Id {}
+impl Entity<CURRENT_VERSION> for Id {}
+impl traits::GroupId<CURRENT_VERSION> for Id {}
+impl traits::ProposalRef<CURRENT_VERSION> for Id {}
+impl traits::QueuedProposal<CURRENT_VERSION> for Id {}
+impl traits::LeafNode<CURRENT_VERSION> for Id {}
+
+const GROUP: Id = Id(0);
+
+/// Runs `f(thread index)` on `THREADS` threads sharing `store`.
+fn on_threads(store: &Arc<OpenMlsKeyValueStore>, f: impl Fn(&OpenMlsKeyValueStore, u32) + Sync) {
+    thread::scope(|scope| {
+        for t in 0..THREADS {
+            let (store, f) = (store.clone(), &f);
+            scope.spawn(move || f(&store, t));
+        }
+    });
+}
+
+#[test]
+fn concurrent_appends_are_not_lost() {
+    let store = Arc::new(OpenMlsKeyValueStore::default());
+    on_threads(&store, |store, t| {
+        for i in 0..ITEMS {
+            store
+                .append_own_leaf_node(&GROUP, &Id(t * ITEMS + i))
+                .unwrap();
+        }
+    });
+    let mut leaves: Vec<Id> = store.own_leaf_nodes(&GROUP).unwrap();
+    leaves.sort();
+    assert_eq!(leaves, (0..THREADS * ITEMS).map(Id).collect::<Vec<_>>());
+}
+
+#[test]
+fn concurrent_queue_and_remove_keep_lists_consistent() {
+    let store = Arc::new(OpenMlsKeyValueStore::default());
+    // even threads queue proposals, odd threads remove the ones queued by their even neighbour,
+    // while readers keep parsing the list
+    on_threads(&store, |store, t| {
+        for i in 0..ITEMS {
+            let proposal_ref = Id((t & !1) * ITEMS + i);
+            if t % 2 == 0 {
+                store
+                    .queue_proposal(&GROUP, &proposal_ref, &proposal_ref)
+                    .unwrap();
+            } else {
+                // wait until the proposal was queued, then remove it
+                while !store
+                    .queued_proposal_refs::<Id, Id>(&GROUP)
+                    .unwrap()
+                    .contains(&proposal_ref)
+                {
+                    thread::yield_now();
+                }
+                store.remove_proposal(&GROUP, &proposal_ref).unwrap();
+            }
+        }
+    });
+    assert!(
+        store
+            .queued_proposal_refs::<Id, Id>(&GROUP)
+            .unwrap()
+            .is_empty()
+    );
+    assert!(
+        store
+            .queued_proposals::<Id, Id, Id>(&GROUP)
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn readers_see_complete_lists() {
+    let store = Arc::new(OpenMlsKeyValueStore::default());
+    on_threads(&store, |store, t| {
+        for i in 0..ITEMS {
+            if t == 0 {
+                store.append_own_leaf_node(&GROUP, &Id(i)).unwrap();
+            } else {
+                // every read must parse and be a prefix of what the writer appends
+                let leaves: Vec<Id> = store.own_leaf_nodes(&GROUP).unwrap();
+                assert!(leaves.iter().copied().eq((0..leaves.len() as u32).map(Id)));
+            }
+        }
+    });
+    assert_eq!(
+        store.own_leaf_nodes::<Id, Id>(&GROUP).unwrap().len(),
+        ITEMS as usize
+    );
+}
+
+/// Panics on every event, e.g. the trace events the store logs while it holds its lock.
+struct PanickingSubscriber;
+
+impl Subscriber for PanickingSubscriber {
+    fn enabled(&self, _: &Metadata<'_>) -> bool {
+        true
+    }
+    fn new_span(&self, _: &Attributes<'_>) -> SpanId {
+        SpanId::from_u64(1)
+    }
+    fn record(&self, _: &SpanId, _: &Record<'_>) {}
+    fn record_follows_from(&self, _: &SpanId, _: &SpanId) {}
+    fn event(&self, _: &Event<'_>) {
+        panic!("subscriber failure");
+    }
+    fn enter(&self, _: &SpanId) {}
+    fn exit(&self, _: &SpanId) {}
+}
+
+#[test]
+fn panic_while_locked_leaves_store_usable() {
+    let store = OpenMlsKeyValueStore::default();
+    store.append_own_leaf_node(&GROUP, &Id(0)).unwrap();
+    // the append panics while holding the lock, poisoning it
+    let panicked = thread::scope(|scope| {
+        scope
+            .spawn(|| {
+                tracing::subscriber::with_default(PanickingSubscriber, || {
+                    store.append_own_leaf_node(&GROUP, &Id(1))
+                })
+            })
+            .join()
+            .is_err()
+    });
+    assert!(panicked);
+    // the interrupted append left the list untouched, and the store can still be read and written
+    assert_eq!(store.own_leaf_nodes::<Id, Id>(&GROUP).unwrap(), vec![Id(0)]);
+    store.append_own_leaf_node(&GROUP, &Id(2)).unwrap();
+    assert_eq!(
+        store.own_leaf_nodes::<Id, Id>(&GROUP).unwrap(),
+        vec![Id(0), Id(2)]
+    );
+}
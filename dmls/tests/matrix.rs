@@ -0,0 +1,55 @@
+//! Room ids are encoded in Matrix API paths, and events fetched from a room resume after the
+//! stored pagination token.
+//!
+//! The round trip needs a room at `DMLS_TEST_MATRIX_SPEC` (`<homeserver url>?room=<room id>`)
+//! joined by the account of `DMLS_MATRIX_TOKEN`; it passes without doing anything otherwise.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::{
+    encoding::Blob,
+    matrix::{MatrixTransport, encode_path_segment, parse_spec},
+    transport::Transport,
+};
+use std::env::var;
+
+#[test]
+fn room_ids_are_encoded_in_paths() {
+    let (homeserver, room_id) =
+        parse_spec("https://matrix.example.org/?room=!abc:example.org").unwrap();
+    assert_eq!(room_id, "!abc:example.org");
+    assert!(parse_spec("https://matrix.example.org").is_err());
+    assert_eq!(
+        encode_path_segment("#dmls:example.org"),
+        "%23dmls%3Aexample.org"
+    );
+    let transport = MatrixTransport::new(homeserver, room_id, String::new());
+    assert_eq!(
+        transport.room_url("messages"),
+        "https://matrix.example.org/_matrix/client/v3/rooms/%21abc%3Aexample.org/messages"
+    );
+}
+
+#[test]
+fn fetching_resumes_after_the_stored_token() {
+    let Ok(spec) = var("DMLS_TEST_MATRIX_SPEC") else {
+        return;
+    };
+    let identity = b"matrix-test-recipient".to_vec();
+    let mut transport = MatrixTransport::from_spec(&spec).unwrap();
+    // catch up with the room first
+    transport.fetch(&identity, 0).unwrap().for_each(drop);
+    let artifact = vec![0, 1, 0, 2];
+    transport
+        .send(&Blob.encode(&artifact), &[identity.clone()])
+        .unwrap();
+    let fetched = transport
+        .fetch(&identity, 5)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(fetched, [artifact]);
+    let mut resumed = MatrixTransport::from_spec(&spec).unwrap();
+    resumed.resume(&transport.cursors(), &[]);
+    assert_eq!(resumed.fetch(&identity, 0).unwrap().count(), 0);
+}
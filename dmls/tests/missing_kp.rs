@@ -0,0 +1,40 @@
+//! A Welcome addressed to a key package that is no longer stored names the key package.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use dmls::{
+    encoding::Blob,
+    error::DmlsError,
+    helpers::{
+        GroupConfigOptions, add_members_base64, bytes_to_kp, gen_recorded_kp_base64,
+        gen_send_group, process_welcome, stdin_base64_extract,
+    },
+};
+use openmls::framing::MlsMessageBodyIn;
+
+#[test]
+fn consumed_key_package_is_reported() {
+    let (mut owner, mut member) = (agent(), agent());
+    let mut sg = gen_send_group(&mut owner, CIPHERSUITE, &GroupConfigOptions::default()).unwrap();
+    let kp = Blob
+        .decode(gen_recorded_kp_base64(&mut member, CIPHERSUITE).unwrap())
+        .unwrap();
+    let kps = vec![bytes_to_kp(&owner, &kp).unwrap()];
+    let (_, welcome) = add_members_base64(&mut owner, &mut sg, &kps, CIPHERSUITE, 32).unwrap();
+    let welcome = || match stdin_base64_extract(Ok(welcome.clone())).unwrap() {
+        MlsMessageBodyIn::Welcome(welcome) => welcome,
+        _ => panic!("not a Welcome"),
+    };
+    process_welcome(&mut member, welcome(), None, 32).unwrap();
+    // joining consumed the key package
+    let e = process_welcome(&mut member, welcome(), None, 32).unwrap_err();
+    match e.downcast_ref::<DmlsError>() {
+        Some(DmlsError::KeyPackageMissing(described)) => {
+            assert!(described.contains("generated at"));
+        }
+        other => panic!("unexpected error: {other:?}"),
+    }
+}
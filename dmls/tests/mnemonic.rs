@@ -0,0 +1,55 @@
+//! Round-trip tests for BIP39 mnemonic backups of signing keys.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::openmls_keys::SignatureKeyPair;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::{crypto::OpenMlsCrypto, types::SignatureScheme};
+
+#[test]
+fn ed25519_key_round_trips_through_mnemonic() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ED25519).unwrap();
+    let phrase = skp.to_mnemonic().unwrap();
+    assert_eq!(phrase.split_whitespace().count(), 24);
+    let restored = SignatureKeyPair::from_mnemonic(&phrase).unwrap();
+    assert_eq!(restored.private_key_raw(), skp.private_key_raw());
+    assert_eq!(restored.public_key_raw(), skp.public_key_raw());
+    // the restored key signs for the original public key
+    let signature = crypto
+        .sign(
+            SignatureScheme::ED25519,
+            b"payload",
+            restored.private_key_raw(),
+        )
+        .unwrap();
+    crypto
+        .verify_signature(
+            SignatureScheme::ED25519,
+            b"payload",
+            skp.public_key_raw(),
+            &signature,
+        )
+        .unwrap();
+}
+
+#[test]
+fn invalid_mnemonics_are_rejected() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ED25519).unwrap();
+    let phrase = skp.to_mnemonic().unwrap();
+    // wrong length
+    let short = phrase
+        .split_whitespace()
+        .take(12)
+        .collect::<Vec<_>>()
+        .join(" ");
+    assert!(SignatureKeyPair::from_mnemonic(&short).is_err());
+    // unknown word
+    let unknown = phrase.replacen(phrase.split_whitespace().next().unwrap(), "dmls", 1);
+    assert!(SignatureKeyPair::from_mnemonic(&unknown).is_err());
+    // only Ed25519 keys have mnemonics
+    let p256 =
+        SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ECDSA_SECP256R1_SHA256).unwrap();
+    assert!(p256.to_mnemonic().is_err());
+}
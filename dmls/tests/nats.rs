@@ -0,0 +1,65 @@
+//! Messages fetched from NATS JetStream come in stream order across subjects, and fetching
+//! resumes after the last message handed out.
+//!
+//! Needs a NATS server with JetStream (`nats-server -js`) at `DMLS_TEST_NATS_URL`; the test
+//! passes without doing anything if the variable isn't set.
+
+#![allow(unused_crate_dependencies)]
+
+mod common;
+
+use common::{CIPHERSUITE, agent};
+use dmls::{
+    encoding::Blob,
+    helpers::{
+        GroupConfigOptions, add_members_base64, bytes_to_kp, gen_kp_base64, gen_send_group,
+        own_identity,
+    },
+    nats::NatsTransport,
+    provider::DmlsProvider,
+    transport::Transport,
+};
+use std::env::var;
+
+#[test]
+fn fetches_in_stream_order_and_resumes() {
+    let Ok(url) = var("DMLS_TEST_NATS_URL") else {
+        return;
+    };
+    let (mut owner, mut member, mut other) = (agent(), agent(), agent());
+    let mut sg = gen_send_group(&mut owner, CIPHERSUITE, &GroupConfigOptions::default()).unwrap();
+    let mut add = |owner: &mut DmlsProvider, member: &mut DmlsProvider| {
+        let kp = Blob
+            .decode(gen_kp_base64(member, CIPHERSUITE).unwrap())
+            .unwrap();
+        let kps = vec![bytes_to_kp(owner, &kp).unwrap()];
+        add_members_base64(owner, &mut sg, &kps, CIPHERSUITE, 32).unwrap()
+    };
+    add(&mut owner, &mut member);
+    let (commit, _) = add(&mut owner, &mut other);
+    let key_package = gen_kp_base64(&mut owner, CIPHERSUITE).unwrap();
+    let identity = own_identity(&member);
+    let sender = NatsTransport::connect(&url).unwrap();
+    // the commit goes to the group's subject, the key package to the member's inbox after it
+    sender.send(&commit, &[identity.clone()]).unwrap();
+    sender.send(&key_package, &[identity.clone()]).unwrap();
+    let mut receiver = NatsTransport::connect(&url).unwrap();
+    receiver.resume(&Default::default(), &[sg.group_id().as_slice().to_vec()]);
+    let fetched = receiver
+        .fetch(&identity, 0)
+        .unwrap()
+        .collect::<Result<Vec<_>, _>>()
+        .unwrap();
+    assert_eq!(
+        fetched,
+        [
+            Blob.decode(&commit).unwrap(),
+            Blob.decode(&key_package).unwrap()
+        ]
+    );
+    // a new transport resuming from the cursors fetches nothing again
+    let cursors = receiver.cursors();
+    let mut resumed = NatsTransport::connect(&url).unwrap();
+    resumed.resume(&cursors, &[sg.group_id().as_slice().to_vec()]);
+    assert_eq!(resumed.fetch(&identity, 0).unwrap().count(), 0);
+}
@@ -0,0 +1,26 @@
+//! Exporter PSK ids in the current and the legacy format parse to their source.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::psk_id::{EXPORTER_PSK_ID_DOMAIN, ExporterPskId};
+use openmls::group::{GroupEpoch, GroupId};
+use openmls_traits::types::Ciphersuite;
+
+const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+#[test]
+fn psk_ids_round_trip_in_both_formats() {
+    let id = ExporterPskId::new(
+        CIPHERSUITE,
+        &GroupId::from_slice(b"group"),
+        GroupEpoch::from(7),
+    );
+    let bytes = id.to_bytes();
+    assert!(bytes.starts_with(EXPORTER_PSK_ID_DOMAIN));
+    assert_eq!(ExporterPskId::parse(&bytes), Some(id.clone()));
+    let legacy = [&7u64.to_be_bytes(), b"group".as_slice()].concat();
+    let parsed = ExporterPskId::parse(&legacy).unwrap();
+    assert_eq!(parsed.ciphersuite, None);
+    assert_eq!((parsed.group_id, parsed.epoch), (id.group_id, id.epoch));
+    assert_eq!(ExporterPskId::parse(&[0; 7]), None);
+}
@@ -0,0 +1,46 @@
+//! Public key export formats and fingerprints.
+
+#![allow(unused_crate_dependencies)]
+
+use dmls::openmls_keys::SignatureKeyPair;
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::SignatureScheme;
+
+#[test]
+fn ed25519_public_key_exports() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ED25519).unwrap();
+    // the DER prefix of an Ed25519 SubjectPublicKeyInfo is always the same
+    let pem = skp.public_key_pem().unwrap();
+    assert!(pem.starts_with("-----BEGIN PUBLIC KEY-----\nMCowBQYDK2VwAyEA"));
+    assert!(pem.ends_with("-----END PUBLIC KEY-----"));
+    assert!(
+        skp.public_key_openssh()
+            .unwrap()
+            .starts_with("ssh-ed25519 AAAAC3NzaC1lZDI1NTE5AAAAI")
+    );
+    let jwk: serde_json::Value = serde_json::from_str(&skp.public_key_jwk().unwrap()).unwrap();
+    assert_eq!(jwk["kty"], "OKP");
+    assert_eq!(jwk["crv"], "Ed25519");
+    // 256-bit hash in unpadded base32
+    let fingerprint = skp.fingerprint(&crypto).unwrap();
+    assert_eq!(fingerprint.len(), "SHA256:".len() + 52);
+    assert_eq!(fingerprint, skp.fingerprint(&crypto).unwrap());
+}
+
+#[test]
+fn p256_public_key_exports() {
+    let crypto = RustCrypto::default();
+    let skp =
+        SignatureKeyPair::from_crypto(&crypto, SignatureScheme::ECDSA_SECP256R1_SHA256).unwrap();
+    assert!(
+        skp.public_key_openssh()
+            .unwrap()
+            .starts_with("ecdsa-sha2-nistp256 ")
+    );
+    let jwk: serde_json::Value = serde_json::from_str(&skp.public_key_jwk().unwrap()).unwrap();
+    assert_eq!(jwk["kty"], "EC");
+    assert_eq!(jwk["crv"], "P-256");
+    assert_eq!(jwk["x"].as_str().unwrap().len(), 43);
+    assert_eq!(jwk["y"].as_str().unwrap().len(), 43);
+}
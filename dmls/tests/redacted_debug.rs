@@ -0,0 +1,63 @@
+//! Secrets in `Debug` output (and thus logs) are hashed unless explicitly requested.
+
+#![allow(unused_crate_dependencies)]
+
+use base64::{Engine, engine::general_purpose::STANDARD as Base64};
+use dmls::{
+    helpers::{GroupConfigOptions, gen_kp_base64, gen_send_group, send_group_stage_update_base64},
+    openmls_keys::SignatureKeyPair,
+    provider::DmlsProvider,
+    redact::{redact, set_log_secrets},
+    state::DmlsState,
+};
+use openmls_rust_crypto::RustCrypto;
+use openmls_traits::types::Ciphersuite;
+
+const CIPHERSUITE: Ciphersuite = Ciphersuite::MLS_128_DHKEMX25519_AES128GCM_SHA256_Ed25519;
+
+#[test]
+fn debug_output_redacts_secrets_unless_requested() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, CIPHERSUITE.signature_algorithm()).unwrap();
+    let private = Base64.encode(skp.private_key_raw());
+    let provider = DmlsProvider::new(DmlsState::new(skp), crypto);
+    // stores the key package's private keys
+    gen_kp_base64(&provider, CIPHERSUITE).unwrap();
+    let redacted = format!("{provider:?}");
+    assert!(!redacted.contains(&private));
+    assert!(redacted.contains("redacted:"));
+    set_log_secrets(true);
+    let full = format!("{provider:?}");
+    set_log_secrets(false);
+    assert!(full.contains(&private));
+}
+
+#[test]
+fn pending_commits_are_redacted() {
+    let crypto = RustCrypto::default();
+    let skp = SignatureKeyPair::from_crypto(&crypto, CIPHERSUITE.signature_algorithm()).unwrap();
+    let mut provider = DmlsProvider::new(DmlsState::new(skp), crypto);
+    gen_send_group(&mut provider, CIPHERSUITE, &GroupConfigOptions::default()).unwrap();
+    // the pending commit is stored in the group state, with the next epoch's secrets
+    send_group_stage_update_base64(&provider, false).unwrap();
+    let group_state = provider
+        .state()
+        .openmls_values()
+        .raw_entries()
+        .unwrap()
+        .into_iter()
+        .find(|(label, _, _)| *label == b"GroupState")
+        .and_then(|(_, _, value)| value)
+        .unwrap();
+    assert!(String::from_utf8_lossy(&group_state).contains("PendingCommit"));
+    assert!(!format!("{provider:?}").contains(&Base64.encode(&group_state)));
+    let redacted = redact(provider.state()).unwrap();
+    let (_, value) = redacted["openmls_values"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .find(|(name, _)| name.starts_with("GroupState/"))
+        .unwrap();
+    assert_ne!(serde_json::to_vec(value).unwrap(), group_state);
+    assert!(value.to_string().contains("redacted:"));
+}